@@ -1,12 +1,16 @@
+import os
+from io import BytesIO
 from typing import Optional
 from angle_emb import AnglE, Prompts
+import requests
 import uvicorn
 import torch
 import numpy as np
 from fastapi import FastAPI
 from fastapi.responses import JSONResponse
+from PIL import Image
 from pydantic import BaseModel
-from transformers import AutoModelForMaskedLM, AutoTokenizer
+from transformers import AutoModelForMaskedLM, AutoTokenizer, CLIPModel, CLIPProcessor
 from sentence_transformers.cross_encoder import CrossEncoder
 
 # Create a Flask app
@@ -20,8 +24,28 @@ query_model_id = "naver/efficient-splade-VI-BT-large-query"
 query_tokenizer = AutoTokenizer.from_pretrained(query_model_id)
 query_model = AutoModelForMaskedLM.from_pretrained(query_model_id)
 
+# SPLADE models which a dataset's SPLADE_MODEL may select to encode both docs and queries with,
+# loaded on first use
+allowed_splade_model_ids = {
+    model_id.strip()
+    for model_id in os.environ.get("ALLOWED_SPLADE_MODELS", "").split(",")
+    if model_id.strip()
+}
+splade_models = {}
+
 cross_encoder_model_id = "cross-encoder/ms-marco-MiniLM-L-4-v2"
-cross_encoder_model = CrossEncoder(cross_encoder_model_id)
+cross_encoder_models = {cross_encoder_model_id: CrossEncoder(cross_encoder_model_id)}
+# Other cross-encoders which a dataset's RERANKER_MODEL may select, loaded on first use
+allowed_cross_encoder_model_ids = {cross_encoder_model_id} | {
+    model_id.strip()
+    for model_id in os.environ.get("ALLOWED_RERANKER_MODELS", "").split(",")
+    if model_id.strip()
+}
+
+# Produces 512 dimensional vectors, which the server stores under the clip_vectors named vector
+clip_model_id = "openai/clip-vit-base-patch32"
+clip_model = CLIPModel.from_pretrained(clip_model_id)
+clip_processor = CLIPProcessor.from_pretrained(clip_model_id)
 
 
 angle = AnglE.from_pretrained("WhereIsAI/UAE-Large-V1", pooling_strategy="cls")
@@ -35,6 +59,7 @@ angle.set_prompt(Prompts.C)
 # Tokenize sentences
 query_model.to(device)
 doc_model.to(device)
+clip_model.to(device)
 
 
 @app.get("/")
@@ -99,31 +124,110 @@ async def encode(encodingRequest: EncodeRequest):
 class SparseEncodeRequest(BaseModel):
     input: str
     encode_type: str
+    model: Optional[str] = None
+    # Also return the vocabulary token of each index, used to read keywords off the vector
+    return_tokens: bool = False
+
+
+def get_splade_model(model_id):
+    if model_id not in splade_models:
+        model = AutoModelForMaskedLM.from_pretrained(model_id)
+        model.to(device)
+        splade_models[model_id] = (AutoTokenizer.from_pretrained(model_id), model)
+    return splade_models[model_id]
 
 
 @app.post("/sparse_encode")
 async def sparse_encode(encodingRequest: SparseEncodeRequest):
     vec = []
-    if encodingRequest.encode_type == "doc":
+    if encodingRequest.encode_type not in ["doc", "query"]:
+        return JSONResponse(
+            content={
+                "embeddings": [],
+                "status": 400,
+            }
+        )
+    if encodingRequest.model is not None:
+        if encodingRequest.model not in allowed_splade_model_ids:
+            return JSONResponse(
+                content={
+                    "embeddings": [],
+                    "status": 400,
+                },
+                status_code=400,
+            )
+        tokenizer, model = get_splade_model(encodingRequest.model)
+        vec = compute_vector(encodingRequest.input, model=model, tokenizer=tokenizer)
+    elif encodingRequest.encode_type == "doc":
+        tokenizer = doc_tokenizer
         vec = compute_vector(
             encodingRequest.input, model=doc_model, tokenizer=doc_tokenizer
         )
-    elif encodingRequest.encode_type == "query":
+    else:
+        tokenizer = query_tokenizer
         vec = compute_vector(
             encodingRequest.input, model=query_model, tokenizer=query_tokenizer
         )
+    indices = vec.nonzero().squeeze().cpu().tolist()
+    if isinstance(indices, int):
+        indices = [indices]
+    values = vec[indices].cpu().tolist()
+    content = {
+        "embeddings": list(zip(indices, values)),
+        "status": 200,
+    }
+    if encodingRequest.return_tokens:
+        content["tokens"] = tokenizer.convert_ids_to_tokens(indices)
+    return JSONResponse(content=content)
+
+
+class ClipEncodeRequest(BaseModel):
+    inputs: list[str]
+    encode_type: str
+
+
+@app.post("/clip_encode")
+async def clip_encode(encodingRequest: ClipEncodeRequest):
+    # image inputs are urls which are downloaded, text inputs are embedded as is
+    if encodingRequest.encode_type == "image":
+        try:
+            images = [
+                Image.open(BytesIO(requests.get(url, timeout=10).content)).convert("RGB")
+                for url in encodingRequest.inputs
+            ]
+        except Exception:
+            return JSONResponse(
+                content={
+                    "embeddings": [],
+                    "status": 400,
+                },
+                status_code=400,
+            )
+        clip_inputs = clip_processor(images=images, return_tensors="pt").to(device)
+        with torch.no_grad():
+            embeddings = clip_model.get_image_features(**clip_inputs)
+    elif encodingRequest.encode_type == "text":
+        clip_inputs = clip_processor(
+            text=encodingRequest.inputs,
+            return_tensors="pt",
+            padding=True,
+            truncation=True,
+        ).to(device)
+        with torch.no_grad():
+            embeddings = clip_model.get_text_features(**clip_inputs)
     else:
         return JSONResponse(
             content={
                 "embeddings": [],
                 "status": 400,
-            }
+            },
+            status_code=400,
         )
-    indices = vec.nonzero().squeeze().cpu().tolist()
-    values = vec[indices].cpu().tolist()
+    # normalize so that image and text embeddings are compared by cosine similarity
+    embeddings = embeddings / embeddings.norm(dim=-1, keepdim=True)
     return JSONResponse(
         content={
-            "embeddings": list(zip(indices, values)),
+            "embeddings": embeddings.cpu().tolist(),
             "status": 200,
         }
     )
@@ -132,18 +236,33 @@ async def sparse_encode(encodingRequest: SparseEncodeRequest):
 class ReRankRequest(BaseModel):
     query: str
     docs: list[str]
+    model: Optional[str] = None
 
 
 @app.post("/rerank")
 async def rerank(rerankRequest: ReRankRequest):
-    
+    model_id = rerankRequest.model or cross_encoder_model_id
+    if model_id not in allowed_cross_encoder_model_ids:
+        return JSONResponse(
+            content={
+                "docs": [],
+                "scores": [],
+                "status": 400,
+            },
+            status_code=400,
+        )
+    if model_id not in cross_encoder_models:
+        cross_encoder_models[model_id] = CrossEncoder(model_id)
+
     combined_docs = [[rerankRequest.query, doc] for doc in rerankRequest.docs]
-    doc_scores = cross_encoder_model.predict(combined_docs)
-    sim_scores_argsort = reversed(np.argsort(doc_scores))
+    doc_scores = cross_encoder_models[model_id].predict(combined_docs)
+    sim_scores_argsort = list(reversed(np.argsort(doc_scores)))
     reranked_docs = [rerankRequest.docs[i] for i in sim_scores_argsort]
+    reranked_scores = [float(doc_scores[i]) for i in sim_scores_argsort]
     return JSONResponse(
         content={
             "docs": reranked_docs,
+            "scores": reranked_scores,
             "status": 200,
         }
     )
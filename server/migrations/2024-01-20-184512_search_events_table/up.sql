@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE search_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    query TEXT NOT NULL,
+    search_type TEXT NOT NULL,
+    result_count INTEGER NOT NULL DEFAULT 0,
+    top_score FLOAT8,
+    latency_ms INTEGER NOT NULL DEFAULT 0,
+    user_hash TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX search_events_dataset_id_created_at_idx ON search_events (dataset_id, created_at);
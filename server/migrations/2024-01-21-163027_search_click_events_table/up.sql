@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE search_click_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    query_id UUID NOT NULL,
+    chunk_id UUID NOT NULL,
+    position INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX search_click_events_query_id_idx ON search_click_events (query_id);
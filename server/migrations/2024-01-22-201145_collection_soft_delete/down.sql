@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE chunk_collection DROP COLUMN IF EXISTS deleted_at;
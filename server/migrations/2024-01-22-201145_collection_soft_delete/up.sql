@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE chunk_collection ADD COLUMN deleted_at TIMESTAMP NULL;
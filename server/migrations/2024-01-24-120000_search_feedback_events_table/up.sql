@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE search_feedback_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    dataset_id UUID NOT NULL,
+    result_id UUID NOT NULL,
+    query_id UUID,
+    chunk_id UUID NOT NULL,
+    positive BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX search_feedback_events_result_id_idx ON search_feedback_events (result_id);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE chunk_versions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    chunk_id UUID NOT NULL,
+    dataset_id UUID NOT NULL,
+    version INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    chunk_html TEXT,
+    link TEXT,
+    tag_set TEXT,
+    metadata JSONB,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (chunk_id) REFERENCES chunk_metadata(id) ON DELETE CASCADE,
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE,
+    UNIQUE (chunk_id, version)
+);
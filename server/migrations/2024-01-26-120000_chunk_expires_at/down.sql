@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS chunk_metadata_expires_at_idx;
+ALTER TABLE chunk_metadata DROP COLUMN IF EXISTS expires_at;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE chunk_metadata ADD COLUMN expires_at TIMESTAMP NULL;
+CREATE INDEX chunk_metadata_expires_at_idx ON chunk_metadata (expires_at) WHERE expires_at IS NOT NULL;
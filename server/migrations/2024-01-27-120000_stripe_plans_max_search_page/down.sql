@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE stripe_plans DROP COLUMN IF EXISTS max_search_page;
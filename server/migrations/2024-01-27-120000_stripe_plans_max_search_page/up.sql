@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE stripe_plans ADD COLUMN max_search_page INTEGER NOT NULL DEFAULT 1000;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS chunk_files_file_id_chunk_index_idx;
+ALTER TABLE chunk_files DROP COLUMN IF EXISTS chunk_index;
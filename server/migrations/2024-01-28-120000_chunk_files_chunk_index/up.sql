@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE chunk_files ADD COLUMN chunk_index INTEGER NULL;
+CREATE INDEX chunk_files_file_id_chunk_index_idx ON chunk_files (file_id, chunk_index);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS search_feedback_events_dataset_id_chunk_id_idx;
+DROP TABLE IF EXISTS chunk_feedback_weights;
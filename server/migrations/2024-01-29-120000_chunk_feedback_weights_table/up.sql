@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE chunk_feedback_weights (
+    chunk_id UUID PRIMARY KEY,
+    dataset_id UUID NOT NULL,
+    base_weight DOUBLE PRECISION NOT NULL,
+    multiplier DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (chunk_id) REFERENCES chunk_metadata(id) ON DELETE CASCADE,
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX chunk_feedback_weights_dataset_id_idx ON chunk_feedback_weights (dataset_id);
+CREATE INDEX search_feedback_events_dataset_id_chunk_id_idx ON search_feedback_events (dataset_id, chunk_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS update_dataset_words_trigger ON chunk_metadata;
+DROP FUNCTION IF EXISTS update_dataset_words();
+DROP INDEX IF EXISTS dataset_words_word_trgm_idx;
+DROP TABLE IF EXISTS dataset_words;
@@ -0,0 +1,62 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS dataset_words (
+    dataset_id UUID NOT NULL,
+    word TEXT NOT NULL,
+    chunk_count INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (dataset_id, word),
+    FOREIGN KEY (dataset_id) REFERENCES datasets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX dataset_words_word_trgm_idx ON dataset_words USING gin (word gin_trgm_ops);
+
+-- Function to keep the words of each dataset's chunks and how many chunks contain them up to date
+CREATE OR REPLACE FUNCTION update_dataset_words()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND OLD.content IS NOT DISTINCT FROM NEW.content THEN
+        RETURN NULL;
+    END IF;
+
+    IF TG_OP = 'DELETE' OR TG_OP = 'UPDATE' THEN
+        UPDATE dataset_words
+        SET chunk_count = dataset_words.chunk_count - 1
+        FROM (
+            SELECT DISTINCT regexp_split_to_table(lower(OLD.content), '[^[:alnum:]]+') AS word
+        ) AS old_words
+        WHERE dataset_words.dataset_id = OLD.dataset_id AND dataset_words.word = old_words.word;
+
+        DELETE FROM dataset_words
+        WHERE dataset_id = OLD.dataset_id AND chunk_count <= 0;
+    END IF;
+
+    IF TG_OP = 'INSERT' OR TG_OP = 'UPDATE' THEN
+        -- Words are inserted in order so concurrent chunk inserts lock them in the same order
+        INSERT INTO dataset_words (dataset_id, word, chunk_count)
+        SELECT NEW.dataset_id, word, 1
+        FROM (
+            SELECT DISTINCT regexp_split_to_table(lower(NEW.content), '[^[:alnum:]]+') AS word
+        ) AS new_words
+        WHERE word <> ''
+        ORDER BY word
+        ON CONFLICT (dataset_id, word) DO UPDATE
+        SET chunk_count = dataset_words.chunk_count + 1;
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER update_dataset_words_trigger
+AFTER INSERT OR DELETE OR UPDATE OF content ON chunk_metadata
+FOR EACH ROW
+EXECUTE FUNCTION update_dataset_words();
+
+INSERT INTO dataset_words (dataset_id, word, chunk_count)
+SELECT dataset_id, word, COUNT(DISTINCT id)
+FROM (
+    SELECT id, dataset_id, regexp_split_to_table(lower(content), '[^[:alnum:]]+') AS word
+    FROM chunk_metadata
+) AS chunk_words
+WHERE word <> ''
+GROUP BY dataset_id, word
+ON CONFLICT (dataset_id, word) DO NOTHING;
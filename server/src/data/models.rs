@@ -201,8 +201,11 @@ pub struct ChunkMetadata {
     pub content: String,
     pub link: Option<String>,
     pub author_id: uuid::Uuid,
+    /// Id of the chunk's point in qdrant. This is null for collisions (duplicates), which share the point of the chunk they collided with instead of having one of their own.
     pub qdrant_point_id: Option<uuid::Uuid>,
+    /// When the chunk was created, in UTC. Unlike time_stamp this is set by the server and never changes.
     pub created_at: chrono::NaiveDateTime,
+    /// When the chunk was last updated, in UTC. Equal to created_at until the chunk is first updated.
     pub updated_at: chrono::NaiveDateTime,
     pub tag_set: Option<String>,
     pub chunk_html: Option<String>,
@@ -211,6 +214,8 @@ pub struct ChunkMetadata {
     pub time_stamp: Option<NaiveDateTime>,
     pub dataset_id: uuid::Uuid,
     pub weight: f64,
+    /// When the chunk expires, in UTC. Expired chunks are left out of search and are deleted by a background job. Null for chunks which never expire.
+    pub expires_at: Option<NaiveDateTime>,
 }
 
 impl ChunkMetadata {
@@ -243,6 +248,7 @@ impl ChunkMetadata {
             time_stamp,
             dataset_id,
             weight,
+            expires_at: None,
         }
     }
 }
@@ -278,6 +284,41 @@ impl ChunkMetadata {
             time_stamp,
             dataset_id,
             weight,
+            expires_at: None,
+        }
+    }
+}
+
+/// A snapshot of a chunk's content and metadata from before an update. Versions are numbered from 1
+/// in the order the chunk was updated.
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[diesel(table_name = chunk_versions)]
+pub struct ChunkVersion {
+    pub id: uuid::Uuid,
+    pub chunk_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub version: i32,
+    pub content: String,
+    pub chunk_html: Option<String>,
+    pub link: Option<String>,
+    pub tag_set: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl ChunkVersion {
+    pub fn from_chunk(chunk: &ChunkMetadata, version: i32) -> Self {
+        ChunkVersion {
+            id: uuid::Uuid::new_v4(),
+            chunk_id: chunk.id,
+            dataset_id: chunk.dataset_id,
+            version,
+            content: chunk.content.clone(),
+            chunk_html: chunk.chunk_html.clone(),
+            link: chunk.link.clone(),
+            tag_set: chunk.tag_set.clone(),
+            metadata: chunk.metadata.clone(),
+            created_at: chrono::Utc::now().naive_local(),
         }
     }
 }
@@ -311,6 +352,7 @@ pub struct ChunkMetadataWithFileData {
     pub content: String,
     pub chunk_html: Option<String>,
     pub link: Option<String>,
+    /// Id of the qdrant point the chunk is searched by. For collisions (duplicates) this is the point of the chunk they collided with, since collisions do not have a point of their own.
     pub qdrant_point_id: uuid::Uuid,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
@@ -323,6 +365,29 @@ pub struct ChunkMetadataWithFileData {
     pub weight: f64,
 }
 
+impl ChunkMetadataWithFileData {
+    /// An empty chunk standing in for a qdrant point whose chunk could not be found in postgres.
+    pub fn placeholder(qdrant_point_id: uuid::Uuid) -> Self {
+        ChunkMetadataWithFileData {
+            id: uuid::Uuid::default(),
+            author: None,
+            qdrant_point_id,
+            created_at: chrono::Utc::now().naive_local(),
+            updated_at: chrono::Utc::now().naive_local(),
+            file_id: None,
+            file_name: None,
+            content: "".to_string(),
+            chunk_html: Some("".to_string()),
+            link: Some("".to_string()),
+            tag_set: Some("".to_string()),
+            metadata: None,
+            tracking_id: None,
+            time_stamp: None,
+            weight: 1.0,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct SlimUser {
     pub id: uuid::Uuid,
@@ -376,6 +441,7 @@ pub struct ChunkCollection {
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub dataset_id: uuid::Uuid,
+    pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
 impl ChunkCollection {
@@ -393,6 +459,7 @@ impl ChunkCollection {
             dataset_id,
             created_at: chrono::Utc::now().naive_local(),
             updated_at: chrono::Utc::now().naive_local(),
+            deleted_at: None,
         }
     }
 }
@@ -416,6 +483,46 @@ pub struct ChunkCollectionAndFile {
     pub file_id: Option<uuid::Uuid>,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ChunkCollectionWithBookmarkCount {
+    pub id: uuid::Uuid,
+    pub author_id: uuid::Uuid,
+    pub name: String,
+    pub description: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub dataset_id: uuid::Uuid,
+    pub bookmark_count: i64,
+    pub last_updated_at: chrono::NaiveDateTime,
+}
+
+impl ChunkCollectionWithBookmarkCount {
+    pub fn from_details(
+        collection: ChunkCollection,
+        bookmark_count: i64,
+        last_bookmarked_at: Option<chrono::NaiveDateTime>,
+    ) -> Self {
+        let last_updated_at = match last_bookmarked_at {
+            Some(last_bookmarked_at) if last_bookmarked_at > collection.updated_at => {
+                last_bookmarked_at
+            }
+            _ => collection.updated_at,
+        };
+
+        ChunkCollectionWithBookmarkCount {
+            id: collection.id,
+            author_id: collection.author_id,
+            name: collection.name,
+            description: collection.description,
+            created_at: collection.created_at,
+            updated_at: collection.updated_at,
+            dataset_id: collection.dataset_id,
+            bookmark_count,
+            last_updated_at,
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize, Queryable)]
 pub struct ChunkCollectionAndFileWithCount {
     pub id: uuid::Uuid,
@@ -674,16 +781,23 @@ pub struct ChunkFile {
     pub file_id: uuid::Uuid,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
+    /// The position of the chunk in the file's reading order.
+    pub chunk_index: Option<i32>,
 }
 
 impl ChunkFile {
-    pub fn from_details(chunk_id: uuid::Uuid, file_id: uuid::Uuid) -> Self {
+    pub fn from_details(
+        chunk_id: uuid::Uuid,
+        file_id: uuid::Uuid,
+        chunk_index: Option<i32>,
+    ) -> Self {
         ChunkFile {
             id: uuid::Uuid::new_v4(),
             chunk_id,
             file_id,
             created_at: chrono::Utc::now().naive_local(),
             updated_at: chrono::Utc::now().naive_local(),
+            chunk_index,
         }
     }
 }
@@ -847,10 +961,43 @@ pub struct ServerDatasetConfiguration {
     pub DOCUMENT_DOWNLOAD_FEATURE: Option<bool>,
     pub LLM_BASE_URL: Option<String>,
     pub EMBEDDING_BASE_URL: Option<String>,
+    pub EMBEDDING_PROVIDER: Option<String>,
+    pub EMBEDDING_MODEL_NAME: Option<String>,
     pub RAG_PROMPT: Option<String>,
     pub N_RETRIEVALS_TO_INCLUDE: Option<usize>,
     pub DUPLICATE_DISTANCE_THRESHOLD: Option<f32>,
     pub EMBEDDING_SIZE: Option<usize>,
+    pub EMBEDDING_DIMENSIONS: Option<usize>,
+    pub EMBEDDING_TEXT_TEMPLATE: Option<String>,
+    pub LLM_STREAM_TIMEOUT_SECS: Option<u64>,
+    pub LLM_STREAM_IDLE_TIMEOUT_SECS: Option<u64>,
+    pub SEARCH_ANALYTICS_ENABLED: Option<bool>,
+    pub IMAGE_EMBEDDING_ENABLED: Option<bool>,
+    pub RERANKER_MODEL: Option<String>,
+    pub FULLTEXT_STEMMING_LANGUAGE: Option<String>,
+    pub METADATA_SCHEMA: Option<serde_json::Value>,
+    pub NORMALIZE_CONTENT: Option<bool>,
+    pub LOWERCASE_CONTENT: Option<bool>,
+    pub MAX_COLLISIONS_PER_CHUNK: Option<u64>,
+    pub COLLISION_OVERFLOW_POLICY: Option<String>,
+    pub DEFAULT_SEARCH_TYPE: Option<String>,
+    pub COLLISION_WINDOW_SECS: Option<u64>,
+    pub COLLISION_SCOPE: Option<String>,
+    pub FULLTEXT_UNACCENT: Option<bool>,
+    pub EMBEDDING_PRICE_PER_MILLION_TOKENS: Option<f64>,
+    pub LLM_PRICE_PER_MILLION_TOKENS: Option<f64>,
+    pub NORMALIZE_EMBEDDINGS: Option<bool>,
+    pub MIN_RESULTS_TO_RERANK: Option<usize>,
+    pub SPLADE_MODEL: Option<String>,
+    pub FEEDBACK_WEIGHT_RATE: Option<f64>,
+    pub FEEDBACK_WEIGHT_MAX_MULTIPLIER: Option<f64>,
+    pub COLLISION_EMBEDDING_MODEL_CHECK: Option<String>,
+    /// The dataset's own key for the LLM provider, encrypted. Never serialized so it cannot leak through config responses.
+    #[serde(skip_serializing)]
+    pub LLM_API_KEY: Option<String>,
+    /// The dataset's own key for the embedding provider, encrypted. Never serialized so it cannot leak through config responses.
+    #[serde(skip_serializing)]
+    pub EMBEDDING_API_KEY: Option<String>,
 }
 
 impl ServerDatasetConfiguration {
@@ -860,6 +1007,21 @@ impl ServerDatasetConfiguration {
             .as_object()
             .unwrap_or(default_config.as_object().unwrap());
 
+        let embedding_provider = configuration
+            .get("EMBEDDING_PROVIDER")
+            .and_then(|provider| provider.as_str())
+            .unwrap_or("openai")
+            .to_lowercase();
+        let (embedding_provider_default_base_url, embedding_provider_default_model) =
+            match embedding_provider.as_str() {
+                "cohere" => (Some("https://api.cohere.ai/v1"), Some("embed-english-v3.0")),
+                "local" => (None, None),
+                _ => (
+                    Some("https://api.openai.com/v1"),
+                    Some("text-embedding-ada-002"),
+                ),
+            };
+
         ServerDatasetConfiguration {
             DOCUMENT_UPLOAD_FEATURE: configuration
                 .get("DOCUMENT_UPLOAD_FEATURE")
@@ -876,7 +1038,13 @@ impl ServerDatasetConfiguration {
                 .map(|s| s.to_string()),
             EMBEDDING_BASE_URL: configuration
                 .get("EMBEDDING_BASE_URL")
-                .unwrap_or(&json!("https://api.openai.com/v1".to_string()))
+                .unwrap_or(&json!(embedding_provider_default_base_url))
+                .as_str()
+                .map(|s| s.to_string()),
+            EMBEDDING_PROVIDER: Some(embedding_provider),
+            EMBEDDING_MODEL_NAME: configuration
+                .get("EMBEDDING_MODEL_NAME")
+                .unwrap_or(&json!(embedding_provider_default_model))
                 .as_str()
                 .map(|s| s.to_string()),
             RAG_PROMPT: configuration
@@ -899,7 +1067,147 @@ impl ServerDatasetConfiguration {
                 .unwrap_or(&json!(1536))
                 .as_u64()
                 .map(|u| u as usize),
+            EMBEDDING_DIMENSIONS: configuration
+                .get("EMBEDDING_DIMENSIONS")
+                .and_then(|dimensions| dimensions.as_u64())
+                .map(|u| u as usize),
+            EMBEDDING_TEXT_TEMPLATE: configuration
+                .get("EMBEDDING_TEXT_TEMPLATE")
+                .unwrap_or(&json!("{{content}}".to_string()))
+                .as_str()
+                .map(|s| s.to_string()),
+            LLM_STREAM_TIMEOUT_SECS: configuration
+                .get("LLM_STREAM_TIMEOUT_SECS")
+                .unwrap_or(&json!(60))
+                .as_u64(),
+            LLM_STREAM_IDLE_TIMEOUT_SECS: configuration
+                .get("LLM_STREAM_IDLE_TIMEOUT_SECS")
+                .unwrap_or(&json!(20))
+                .as_u64(),
+            SEARCH_ANALYTICS_ENABLED: configuration
+                .get("SEARCH_ANALYTICS_ENABLED")
+                .unwrap_or(&json!(false))
+                .as_bool(),
+            IMAGE_EMBEDDING_ENABLED: configuration
+                .get("IMAGE_EMBEDDING_ENABLED")
+                .unwrap_or(&json!(false))
+                .as_bool(),
+            RERANKER_MODEL: configuration
+                .get("RERANKER_MODEL")
+                .and_then(|model| model.as_str())
+                .map(|s| s.to_string()),
+            FULLTEXT_STEMMING_LANGUAGE: configuration
+                .get("FULLTEXT_STEMMING_LANGUAGE")
+                .and_then(|language| language.as_str())
+                .map(|s| s.to_string()),
+            METADATA_SCHEMA: configuration
+                .get("METADATA_SCHEMA")
+                .filter(|schema| !schema.is_null())
+                .cloned(),
+            NORMALIZE_CONTENT: configuration
+                .get("NORMALIZE_CONTENT")
+                .unwrap_or(&json!(false))
+                .as_bool(),
+            LOWERCASE_CONTENT: configuration
+                .get("LOWERCASE_CONTENT")
+                .unwrap_or(&json!(false))
+                .as_bool(),
+            MAX_COLLISIONS_PER_CHUNK: configuration
+                .get("MAX_COLLISIONS_PER_CHUNK")
+                .and_then(|max_collisions| max_collisions.as_u64()),
+            COLLISION_OVERFLOW_POLICY: configuration
+                .get("COLLISION_OVERFLOW_POLICY")
+                .unwrap_or(&json!("reject".to_string()))
+                .as_str()
+                .map(|s| s.to_string()),
+            DEFAULT_SEARCH_TYPE: configuration
+                .get("DEFAULT_SEARCH_TYPE")
+                .unwrap_or(&json!("semantic".to_string()))
+                .as_str()
+                .map(|s| s.to_string()),
+            COLLISION_WINDOW_SECS: configuration
+                .get("COLLISION_WINDOW_SECS")
+                .and_then(|window| window.as_u64()),
+            COLLISION_SCOPE: configuration
+                .get("COLLISION_SCOPE")
+                .and_then(|scope| scope.as_str())
+                .map(|s| s.to_string())
+                .or(Some("global".to_string())),
+            FULLTEXT_UNACCENT: configuration
+                .get("FULLTEXT_UNACCENT")
+                .and_then(|unaccent| unaccent.as_bool())
+                .or(Some(false)),
+            EMBEDDING_PRICE_PER_MILLION_TOKENS: configuration
+                .get("EMBEDDING_PRICE_PER_MILLION_TOKENS")
+                .and_then(|price| price.as_f64()),
+            LLM_PRICE_PER_MILLION_TOKENS: configuration
+                .get("LLM_PRICE_PER_MILLION_TOKENS")
+                .and_then(|price| price.as_f64()),
+            NORMALIZE_EMBEDDINGS: configuration
+                .get("NORMALIZE_EMBEDDINGS")
+                .and_then(|normalize| normalize.as_bool())
+                .or(Some(false)),
+            MIN_RESULTS_TO_RERANK: configuration
+                .get("MIN_RESULTS_TO_RERANK")
+                .unwrap_or(&json!(0))
+                .as_u64()
+                .map(|u| u as usize),
+            SPLADE_MODEL: configuration
+                .get("SPLADE_MODEL")
+                .and_then(|model| model.as_str())
+                .map(|s| s.to_string()),
+            FEEDBACK_WEIGHT_RATE: configuration
+                .get("FEEDBACK_WEIGHT_RATE")
+                .and_then(|rate| rate.as_f64()),
+            FEEDBACK_WEIGHT_MAX_MULTIPLIER: configuration
+                .get("FEEDBACK_WEIGHT_MAX_MULTIPLIER")
+                .and_then(|multiplier| multiplier.as_f64())
+                .or(Some(2.0)),
+            COLLISION_EMBEDDING_MODEL_CHECK: configuration
+                .get("COLLISION_EMBEDDING_MODEL_CHECK")
+                .and_then(|check| check.as_str())
+                .map(|s| s.to_string())
+                .or(Some("same_model".to_string())),
+            LLM_API_KEY: configuration
+                .get("LLM_API_KEY")
+                .and_then(|key| key.as_str())
+                .map(|s| s.to_string()),
+            EMBEDDING_API_KEY: configuration
+                .get("EMBEDDING_API_KEY")
+                .and_then(|key| key.as_str())
+                .map(|s| s.to_string()),
+        }
+    }
+}
 
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[allow(non_snake_case)]
+pub struct PublicDatasetConfiguration {
+    pub DOCUMENT_UPLOAD_FEATURE: Option<bool>,
+    pub DOCUMENT_DOWNLOAD_FEATURE: Option<bool>,
+    pub EMBEDDING_PROVIDER: Option<String>,
+    pub EMBEDDING_MODEL_NAME: Option<String>,
+    pub N_RETRIEVALS_TO_INCLUDE: Option<usize>,
+    pub DUPLICATE_DISTANCE_THRESHOLD: Option<f32>,
+    pub EMBEDDING_SIZE: Option<usize>,
+    pub EMBEDDING_DIMENSIONS: Option<usize>,
+    pub IMAGE_EMBEDDING_ENABLED: Option<bool>,
+    pub RERANKER_MODEL: Option<String>,
+}
+
+impl From<ServerDatasetConfiguration> for PublicDatasetConfiguration {
+    fn from(config: ServerDatasetConfiguration) -> Self {
+        PublicDatasetConfiguration {
+            DOCUMENT_UPLOAD_FEATURE: config.DOCUMENT_UPLOAD_FEATURE,
+            DOCUMENT_DOWNLOAD_FEATURE: config.DOCUMENT_DOWNLOAD_FEATURE,
+            EMBEDDING_PROVIDER: config.EMBEDDING_PROVIDER,
+            EMBEDDING_MODEL_NAME: config.EMBEDDING_MODEL_NAME,
+            N_RETRIEVALS_TO_INCLUDE: config.N_RETRIEVALS_TO_INCLUDE,
+            DUPLICATE_DISTANCE_THRESHOLD: config.DUPLICATE_DISTANCE_THRESHOLD,
+            EMBEDDING_SIZE: config.EMBEDDING_SIZE,
+            EMBEDDING_DIMENSIONS: config.EMBEDDING_DIMENSIONS,
+            IMAGE_EMBEDDING_ENABLED: config.IMAGE_EMBEDDING_ENABLED,
+            RERANKER_MODEL: config.RERANKER_MODEL,
         }
     }
 }
@@ -1079,6 +1387,8 @@ pub struct StripePlan {
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub name: String,
+    /// The deepest page of results a search of the organization's datasets can fetch. Searches for later pages are rejected with a 403 so that datasets cannot be scraped by paging through every result.
+    pub max_search_page: i32,
 }
 
 impl StripePlan {
@@ -1092,6 +1402,7 @@ impl StripePlan {
         message_count: i32,
         amount: i64,
         name: String,
+        max_search_page: i32,
     ) -> Self {
         StripePlan {
             id: uuid::Uuid::new_v4(),
@@ -1105,6 +1416,7 @@ impl StripePlan {
             created_at: chrono::Utc::now().naive_local(),
             updated_at: chrono::Utc::now().naive_local(),
             name,
+            max_search_page,
         }
     }
 
@@ -1121,6 +1433,7 @@ impl StripePlan {
             created_at: chrono::Utc::now().naive_local(),
             updated_at: chrono::Utc::now().naive_local(),
             name: "Free".to_string(),
+            max_search_page: 10,
         }
     }
 }
@@ -1305,3 +1618,115 @@ impl From<UserApiKey> for ApiKeyDTO {
         }
     }
 }
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[diesel(table_name = search_events)]
+pub struct SearchEvent {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub query: String,
+    pub search_type: String,
+    pub result_count: i32,
+    pub top_score: Option<f64>,
+    pub latency_ms: i32,
+    pub user_hash: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl SearchEvent {
+    pub fn from_details(
+        id: uuid::Uuid,
+        dataset_id: uuid::Uuid,
+        query: String,
+        search_type: String,
+        result_count: i32,
+        top_score: Option<f64>,
+        latency_ms: i32,
+        user_hash: Option<String>,
+    ) -> Self {
+        SearchEvent {
+            id,
+            dataset_id,
+            query,
+            search_type,
+            result_count,
+            top_score,
+            latency_ms,
+            user_hash,
+            created_at: chrono::Utc::now().naive_local(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[diesel(table_name = search_click_events)]
+pub struct SearchClickEvent {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub query_id: uuid::Uuid,
+    pub chunk_id: uuid::Uuid,
+    pub position: i32,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl SearchClickEvent {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        query_id: uuid::Uuid,
+        chunk_id: uuid::Uuid,
+        position: i32,
+    ) -> Self {
+        SearchClickEvent {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            query_id,
+            chunk_id,
+            position,
+            created_at: chrono::Utc::now().naive_local(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone, ToSchema)]
+#[diesel(table_name = search_feedback_events)]
+pub struct SearchFeedbackEvent {
+    pub id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub result_id: uuid::Uuid,
+    pub query_id: Option<uuid::Uuid>,
+    pub chunk_id: uuid::Uuid,
+    pub positive: bool,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// The feedback adjustment applied to a chunk's weight. The chunk's weight is its base_weight
+/// times the multiplier for as long as it has not been changed by an update.
+#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Selectable, Clone)]
+#[diesel(table_name = chunk_feedback_weights)]
+pub struct ChunkFeedbackWeight {
+    pub chunk_id: uuid::Uuid,
+    pub dataset_id: uuid::Uuid,
+    pub base_weight: f64,
+    pub multiplier: f64,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl SearchFeedbackEvent {
+    pub fn from_details(
+        dataset_id: uuid::Uuid,
+        result_id: uuid::Uuid,
+        query_id: Option<uuid::Uuid>,
+        chunk_id: uuid::Uuid,
+        positive: bool,
+    ) -> Self {
+        SearchFeedbackEvent {
+            id: uuid::Uuid::new_v4(),
+            dataset_id,
+            result_id,
+            query_id,
+            chunk_id,
+            positive,
+            created_at: chrono::Utc::now().naive_local(),
+        }
+    }
+}
@@ -9,6 +9,7 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         dataset_id -> Uuid,
+        deleted_at -> Nullable<Timestamp>,
     }
 }
 
@@ -32,6 +33,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    chunk_feedback_weights (chunk_id) {
+        chunk_id -> Uuid,
+        dataset_id -> Uuid,
+        base_weight -> Float8,
+        multiplier -> Float8,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     chunk_files (id) {
         id -> Uuid,
@@ -39,6 +50,7 @@ diesel::table! {
         file_id -> Uuid,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        chunk_index -> Nullable<Int4>,
     }
 }
 
@@ -58,6 +70,22 @@ diesel::table! {
         time_stamp -> Nullable<Timestamp>,
         dataset_id -> Uuid,
         weight -> Float8,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    chunk_versions (id) {
+        id -> Uuid,
+        chunk_id -> Uuid,
+        dataset_id -> Uuid,
+        version -> Int4,
+        content -> Text,
+        chunk_html -> Nullable<Text>,
+        link -> Nullable<Text>,
+        tag_set -> Nullable<Text>,
+        metadata -> Nullable<Jsonb>,
+        created_at -> Timestamp,
     }
 }
 
@@ -89,6 +117,14 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    dataset_words (dataset_id, word) {
+        dataset_id -> Uuid,
+        word -> Text,
+        chunk_count -> Int4,
+    }
+}
+
 diesel::table! {
     datasets (id) {
         id -> Uuid,
@@ -181,6 +217,43 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    search_click_events (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        query_id -> Uuid,
+        chunk_id -> Uuid,
+        position -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    search_events (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        query -> Text,
+        search_type -> Text,
+        result_count -> Int4,
+        top_score -> Nullable<Float8>,
+        latency_ms -> Int4,
+        user_hash -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    search_feedback_events (id) {
+        id -> Uuid,
+        dataset_id -> Uuid,
+        result_id -> Uuid,
+        query_id -> Nullable<Uuid>,
+        chunk_id -> Uuid,
+        positive -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     stripe_plans (id) {
         id -> Uuid,
@@ -194,6 +267,7 @@ diesel::table! {
         created_at -> Timestamp,
         updated_at -> Timestamp,
         name -> Text,
+        max_search_page -> Int4,
     }
 }
 
@@ -278,14 +352,19 @@ diesel::joinable!(chunk_collection -> datasets (dataset_id));
 diesel::joinable!(chunk_collection -> users (author_id));
 diesel::joinable!(chunk_collection_bookmarks -> chunk_collection (collection_id));
 diesel::joinable!(chunk_collection_bookmarks -> chunk_metadata (chunk_metadata_id));
+diesel::joinable!(chunk_feedback_weights -> chunk_metadata (chunk_id));
+diesel::joinable!(chunk_feedback_weights -> datasets (dataset_id));
 diesel::joinable!(chunk_files -> chunk_metadata (chunk_id));
 diesel::joinable!(chunk_files -> files (file_id));
 diesel::joinable!(chunk_metadata -> datasets (dataset_id));
 diesel::joinable!(chunk_metadata -> users (author_id));
+diesel::joinable!(chunk_versions -> chunk_metadata (chunk_id));
+diesel::joinable!(chunk_versions -> datasets (dataset_id));
 diesel::joinable!(collections_from_files -> chunk_collection (collection_id));
 diesel::joinable!(collections_from_files -> files (file_id));
 diesel::joinable!(cut_chunks -> users (user_id));
 diesel::joinable!(dataset_usage_counts -> datasets (dataset_id));
+diesel::joinable!(dataset_words -> datasets (dataset_id));
 diesel::joinable!(datasets -> organizations (organization_id));
 diesel::joinable!(file_upload_completed_notifications -> chunk_collection (collection_uuid));
 diesel::joinable!(file_upload_completed_notifications -> datasets (dataset_id));
@@ -294,6 +373,9 @@ diesel::joinable!(files -> users (user_id));
 diesel::joinable!(messages -> datasets (dataset_id));
 diesel::joinable!(messages -> topics (topic_id));
 diesel::joinable!(organization_usage_counts -> organizations (org_id));
+diesel::joinable!(search_click_events -> datasets (dataset_id));
+diesel::joinable!(search_events -> datasets (dataset_id));
+diesel::joinable!(search_feedback_events -> datasets (dataset_id));
 diesel::joinable!(stripe_subscriptions -> organizations (organization_id));
 diesel::joinable!(stripe_subscriptions -> stripe_plans (plan_id));
 diesel::joinable!(topics -> datasets (dataset_id));
@@ -308,11 +390,14 @@ diesel::allow_tables_to_appear_in_same_query!(
     chunk_collection,
     chunk_collection_bookmarks,
     chunk_collisions,
+    chunk_feedback_weights,
     chunk_files,
     chunk_metadata,
+    chunk_versions,
     collections_from_files,
     cut_chunks,
     dataset_usage_counts,
+    dataset_words,
     datasets,
     file_upload_completed_notifications,
     files,
@@ -320,6 +405,9 @@ diesel::allow_tables_to_appear_in_same_query!(
     messages,
     organization_usage_counts,
     organizations,
+    search_click_events,
+    search_events,
+    search_feedback_events,
     stripe_plans,
     stripe_subscriptions,
     topics,
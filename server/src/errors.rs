@@ -33,6 +33,15 @@ pub enum ServiceError {
 
     #[display(fmt = "Not Found")]
     NotFound,
+
+    #[display(fmt = "Service Unavailable: {_0}")]
+    ServiceUnavailable(String),
+
+    #[display(fmt = "Payload Too Large: {_0}")]
+    PayloadTooLarge(String),
+
+    #[display(fmt = "Too Many Requests: {_0}")]
+    TooManyRequests(String),
 }
 
 // impl ResponseError trait allows to convert our errors into http responses with appropriate data
@@ -51,6 +60,20 @@ impl ResponseError for ServiceError {
             ServiceError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
             ServiceError::Forbidden => HttpResponse::Forbidden().json("Forbidden"),
             ServiceError::NotFound => HttpResponse::NotFound().json("Record not found"),
+            ServiceError::ServiceUnavailable(ref message) => HttpResponse::ServiceUnavailable()
+                .json(ErrorResponseBody {
+                    message: message.to_string(),
+                }),
+            ServiceError::PayloadTooLarge(ref message) => {
+                HttpResponse::PayloadTooLarge().json(ErrorResponseBody {
+                    message: message.to_string(),
+                })
+            }
+            ServiceError::TooManyRequests(ref message) => {
+                HttpResponse::TooManyRequests().json(ErrorResponseBody {
+                    message: message.to_string(),
+                })
+            }
         }
     }
 }
@@ -0,0 +1,171 @@
+use super::auth_handler::{AdminOnly, LoggedUser};
+use crate::{
+    data::models::{
+        DatasetAndOrgWithSubAndPlan, Pool, SearchClickEvent, SearchFeedbackEvent,
+        ServerDatasetConfiguration,
+    },
+    errors::ServiceError,
+    operators::analytics_operator::{
+        create_search_click_event_query, create_search_feedback_event_query, get_top_queries_query,
+    },
+};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct TopQueriesQuery {
+    /// Number of days to look back over. Defaults to 7.
+    pub days: Option<i64>,
+    /// Maximum number of queries to return in each list. Defaults to 10.
+    pub limit: Option<i64>,
+}
+
+/// get_top_queries
+///
+/// Get the most frequent search queries for the dataset along with the most frequent queries which returned no results over the given window. Searches are only logged for datasets with SEARCH_ANALYTICS_ENABLED set in their server configuration. Auth'ed user must be an admin or owner of the dataset's organization.
+#[utoipa::path(
+    get,
+    path = "/dataset/analytics/top_queries",
+    context_path = "/api",
+    tag = "dataset",
+    responses(
+        (status = 200, description = "The most frequent queries and zero-result queries for the dataset", body = TopQueriesResult),
+        (status = 400, description = "Service error relating to getting the top queries for the dataset", body = DefaultError),
+    ),
+    params(TopQueriesQuery),
+)]
+pub async fn get_top_queries(
+    query: web::Query<TopQueriesQuery>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let since = chrono::Utc::now().naive_local() - chrono::Duration::days(query.days.unwrap_or(7));
+    let limit = query.limit.unwrap_or(10);
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+
+    let top_queries = web::block(move || get_top_queries_query(dataset_id, since, limit, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(top_queries))
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SearchClickData {
+    /// The query_id returned in the search response which the clicked result belongs to.
+    pub query_id: uuid::Uuid,
+    /// The id of the chunk which was clicked.
+    pub chunk_id: uuid::Uuid,
+    /// The zero-indexed position of the clicked chunk in the search results.
+    pub position: i32,
+}
+
+/// record_search_click
+///
+/// Record that a user clicked a search result. The query_id from the search response is used to correlate clicks with the search that produced them, which allows computing click-through rates by position. Clicks are only recorded for datasets with SEARCH_ANALYTICS_ENABLED set in their server configuration.
+#[utoipa::path(
+    post,
+    path = "/analytics/click",
+    context_path = "/api",
+    tag = "analytics",
+    request_body(content = SearchClickData, description = "JSON request payload describing the clicked search result", content_type = "application/json"),
+    responses(
+        (status = 204, description = "Confirmation that the click was recorded"),
+        (status = 400, description = "Service error relating to recording the click", body = DefaultError),
+    ),
+)]
+pub async fn record_search_click(
+    data: web::Json<SearchClickData>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _user: LoggedUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let search_analytics_enabled =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration)
+            .SEARCH_ANALYTICS_ENABLED
+            .unwrap_or(false);
+
+    if !search_analytics_enabled {
+        return Err(ServiceError::BadRequest(
+            "Search analytics are not enabled for this dataset".into(),
+        )
+        .into());
+    }
+
+    let search_click_event = SearchClickEvent::from_details(
+        dataset_org_plan_sub.dataset.id,
+        data.query_id,
+        data.chunk_id,
+        data.position,
+    );
+
+    web::block(move || create_search_click_event_query(search_click_event, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SearchFeedbackData {
+    /// The result_id of the search result the feedback is for.
+    pub result_id: uuid::Uuid,
+    /// The query_id returned in the search response which the result belongs to, if it is known.
+    pub query_id: Option<uuid::Uuid>,
+    /// The id of the chunk the feedback is for.
+    pub chunk_id: uuid::Uuid,
+    /// True for a thumbs-up and false for a thumbs-down.
+    pub positive: bool,
+}
+
+/// record_search_feedback
+///
+/// Record a thumbs-up or thumbs-down on a search result. The result_id from the search response ties the feedback to the query and chunk it was given on, and is the same every time the search is repeated, so feedback can be aggregated per query and chunk. Feedback is only recorded for datasets with SEARCH_ANALYTICS_ENABLED or FEEDBACK_WEIGHT_RATE set in their server configuration. If FEEDBACK_WEIGHT_RATE is set, a background job periodically multiplies the weight of each chunk with feedback by `1 + FEEDBACK_WEIGHT_RATE` raised to its number of positive minus negative feedback events, bounded by FEEDBACK_WEIGHT_MAX_MULTIPLIER (2 by default) and its reciprocal. This changes the chunk's ranking in every search of the dataset, not only for the query the feedback was given on. Removing FEEDBACK_WEIGHT_RATE or setting it to 0 restores the weights the chunks had before they were adjusted the next time the job runs, except for chunks whose weight was changed by an update since.
+#[utoipa::path(
+    post,
+    path = "/analytics/feedback",
+    context_path = "/api",
+    tag = "analytics",
+    request_body(content = SearchFeedbackData, description = "JSON request payload describing the feedback on a search result", content_type = "application/json"),
+    responses(
+        (status = 204, description = "Confirmation that the feedback was recorded"),
+        (status = 400, description = "Service error relating to recording the feedback", body = DefaultError),
+    ),
+)]
+pub async fn record_search_feedback(
+    data: web::Json<SearchFeedbackData>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _user: LoggedUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    let feedback_enabled = dataset_config.SEARCH_ANALYTICS_ENABLED.unwrap_or(false)
+        || dataset_config
+            .FEEDBACK_WEIGHT_RATE
+            .is_some_and(|rate| rate > 0.0);
+
+    if !feedback_enabled {
+        return Err(ServiceError::BadRequest(
+            "Search analytics are not enabled for this dataset".into(),
+        )
+        .into());
+    }
+
+    let search_feedback_event = SearchFeedbackEvent::from_details(
+        dataset_org_plan_sub.dataset.id,
+        data.result_id,
+        data.query_id,
+        data.chunk_id,
+        data.positive,
+    );
+
+    web::block(move || create_search_feedback_event_query(search_feedback_event, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
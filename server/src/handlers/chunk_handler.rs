@@ -1,29 +1,45 @@
 use super::auth_handler::{AdminOnly, LoggedUser};
 use crate::data::models::{
     ChatMessageProxy, ChunkCollection, ChunkCollectionBookmark, ChunkMetadata,
-    ChunkMetadataWithFileData, DatasetAndOrgWithSubAndPlan, Pool, ServerDatasetConfiguration,
-    StripePlan,
+    ChunkMetadataWithFileData, ChunkVersion, Dataset, DatasetAndOrgWithSubAndPlan, Pool,
+    SearchEvent, ServerDatasetConfiguration, StripePlan, UserRole,
 };
 use crate::errors::{DefaultError, ServiceError};
-use crate::get_env;
+use crate::operators::analytics_operator::{anonymize_user_id, create_search_event_query};
 use crate::operators::chunk_operator::get_metadata_from_id_query;
 use crate::operators::chunk_operator::*;
 use crate::operators::collection_operator::{
-    create_chunk_bookmark_query, get_collection_by_id_query,
+    create_chunk_bookmark_query, get_bookmark_collection_ids_query, get_collection_by_id_query,
+};
+use crate::operators::model_operator::{
+    count_tokens, create_embedding, cross_encoder, get_clip_embedding, get_embedding_model_id,
+    get_embedding_price_per_million_tokens, get_llm_api_key, get_splade_doc_embedding,
+    get_splade_query_embedding, l2_normalize, normalize_content, stem_query, stem_text,
+    unaccent_text, warm_query_embedding,
 };
-use crate::operators::model_operator::create_embedding;
 use crate::operators::qdrant_operator::update_qdrant_point_query;
 use crate::operators::qdrant_operator::{
-    create_new_qdrant_point_query, delete_qdrant_point_id_query, recommend_qdrant_query,
+    create_new_qdrant_point_query, delete_qdrant_point_id_query, embedding_model_condition,
+    filter_point_ids_by_acl_query, has_other_embedding_model_points_query,
+    qdrant_error_to_service_error, recommend_qdrant_query, recommend_weighted_qdrant_query,
+    validate_distance_metric, CLIP_VECTOR_NAME,
 };
 use crate::operators::search_operator::{
-    global_unfiltered_top_match_query, search_full_text_chunks, search_full_text_collections,
-    search_hybrid_chunks, search_semantic_chunks, search_semantic_collections,
+    add_embedding_models, add_matched_tags, add_neighbor_chunks, add_relevance_percents,
+    add_result_ids, add_semantic_highlights, boost_by_metadata_field, browse_chunks,
+    diversify_score_chunks, explain_chunk_filters_query, explain_chunk_ranking,
+    get_collision_scope_conditions, get_metadata_aggregations_query,
+    global_unfiltered_top_match_query, group_score_chunks, recent_top_match_query,
+    search_full_text_chunks, search_full_text_collections, search_hybrid_chunks,
+    search_image_chunks, search_semantic_chunks, search_semantic_collections, SearchResult,
+    METADATA_AGGREGATION_OPS,
 };
 use actix_web::web::Bytes;
 use actix_web::{web, HttpResponse};
 use chrono::NaiveDateTime;
 use dateparser::DateTimeUtc;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
 use openai_dive::v1::api::Client;
 use openai_dive::v1::resources::chat::{
     ChatCompletionParameters, ChatMessage, ChatMessageContent, Role,
@@ -31,7 +47,10 @@ use openai_dive::v1::resources::chat::{
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::process::Command;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
 use tokio_stream::StreamExt;
 use utoipa::{IntoParams, ToSchema};
 
@@ -80,9 +99,9 @@ pub struct CreateChunkData {
     pub tag_set: Option<String>,
     /// File_uuid is the uuid of the file that the chunk is associated with. This is used to associate chunks with files. This is useful for when you want to delete a file and all of its associated chunks.
     pub file_uuid: Option<uuid::Uuid>,
-    /// Metadata is a JSON object which can be used to filter chunks. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata.
+    /// Metadata is a JSON object which can be used to filter chunks. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata. If the dataset has a METADATA_SCHEMA, the metadata must match it or the request will fail, with no metadata being checked as an empty object. If the dataset's EMBEDDING_TEXT_TEMPLATE refers to metadata keys, such as `{title}` in `"{title}\n\n{content}"`, the metadata must have them unless a chunk_vector is provided.
     pub metadata: Option<serde_json::Value>,
-    /// Chunk_vector is a vector of floats which can be used instead of generating a new embedding. This is useful for when you are using a pre-embedded dataset. If this is not provided, the innerText of the chunk_html will be used to create the embedding.
+    /// Chunk_vector is a vector of floats which can be used instead of generating a new embedding. This is useful for when you are using a pre-embedded dataset. If this is not provided, the innerText of the chunk_html will be used to create the embedding. If the dataset has NORMALIZE_EMBEDDINGS set, the vector is scaled to unit length like generated embeddings are.
     pub chunk_vector: Option<Vec<f32>>,
     /// Tracking_id is a string which can be used to identify a chunk. This is useful for when you are coordinating with an external system and want to use the tracking_id to identify the chunk.
     pub tracking_id: Option<String>,
@@ -90,14 +109,201 @@ pub struct CreateChunkData {
     pub collection_id: Option<uuid::Uuid>,
     /// Time_stamp should be an ISO 8601 combined date and time without timezone. It is used for time window filtering and recency-biasing search results.
     pub time_stamp: Option<String>,
-    /// Weight is a float which can be used to bias search results. This is useful for when you want to bias search results for a chunk. The magnitude only matters relative to other chunks in the chunk's dataset dataset.
+    /// Weight is a float which can be used to bias search results. This is useful for when you want to bias search results for a chunk. The magnitude only matters relative to other chunks in the chunk's dataset dataset. Weights below 1.0 down-rank the chunk, and a weight of 0 effectively hides it from search rankings while keeping it retrievable by id. The default weight is 1.0.
     pub weight: Option<f64>,
+    /// Image_urls is a list of urls of images which belong to the chunk. They are stored under the `image_urls` key of the chunk's metadata. If IMAGE_EMBEDDING_ENABLED is set for the dataset, the images are also embedded with a CLIP model so the chunk can be found with a search_type of "image".
+    pub image_urls: Option<Vec<String>>,
+    /// Acl is a list of group ids which are allowed to retrieve the chunk, whether from search or from any other route which returns its content. Other users get the same error as for a missing chunk, or the chunk is left out of lists. A user's group ids are their user id and the ids of the organizations they belong to. Admins and owners of the dataset's organization can always retrieve the chunk. If not provided or empty, the chunk is visible to everyone with access to the dataset. If the chunk collides with an existing chunk, the existing chunk's acl applies.
+    pub acl: Option<Vec<String>>,
+    /// Expires_at should be an ISO 8601 combined date and time, treated as UTC if it has no timezone. Once it has passed the chunk is left out of search results, and it is deleted along with its qdrant point by a background job shortly after. It must be in the future. If not provided, the chunk never expires.
+    pub expires_at: Option<String>,
+    /// Chunk_index is the position of the chunk in the file given by file_uuid, starting at 0. The chunks of a file are returned in order of their chunk_index, which lets a document viewer reassemble the file in its reading order. Chunks created from an uploaded file are given their position automatically. Requires file_uuid. Updating the chunk does not change its position.
+    pub chunk_index: Option<i32>,
+}
+
+/// Returns the acl group ids a user searches with, or None if they are an admin or owner of the
+/// dataset's organization and may see every chunk.
+pub fn get_acl_groups(user: &LoggedUser, dataset: &Dataset) -> Option<Vec<String>> {
+    let is_admin = user.user_orgs.iter().any(|user_org| {
+        user_org.organization_id == dataset.organization_id
+            && user_org.role >= UserRole::Admin as i32
+    });
+    if is_admin {
+        return None;
+    }
+
+    Some(
+        std::iter::once(user.id.to_string())
+            .chain(
+                user.user_orgs
+                    .iter()
+                    .map(|user_org| user_org.organization_id.to_string()),
+            )
+            .collect(),
+    )
 }
 
-pub fn convert_html(html: &str) -> Result<String, DefaultError> {
-    let html_parse_result = Command::new("./server-python/html-converter.py")
+/// The largest chunk_html, in bytes, which will be converted. Set by the
+/// MAX_HTML_CONVERSION_BYTES env var and 100,000 bytes by default. The html is passed to the
+/// converter as an argument, so this must stay below the OS limit on a single argument.
+static MAX_HTML_CONVERSION_BYTES: Lazy<usize> = Lazy::new(|| {
+    std::env::var("MAX_HTML_CONVERSION_BYTES")
+        .ok()
+        .and_then(|max_bytes| max_bytes.parse::<usize>().ok())
+        .unwrap_or(100_000)
+});
+
+/// How long the html converter may run before it is killed. Set by the
+/// HTML_CONVERSION_TIMEOUT_SECS env var and 10 seconds by default.
+static HTML_CONVERSION_TIMEOUT: Lazy<std::time::Duration> = Lazy::new(|| {
+    std::time::Duration::from_secs(
+        std::env::var("HTML_CONVERSION_TIMEOUT_SECS")
+            .ok()
+            .and_then(|timeout| timeout.parse::<u64>().ok())
+            .unwrap_or(10),
+    )
+});
+
+/// Runs the html converter, killing it if it does not finish within HTML_CONVERSION_TIMEOUT.
+/// Its stdout is drained on the blocking pool so the converter cannot stall on a full pipe and the
+/// executor is never blocked waiting for it.
+async fn run_html_converter(html: &str) -> Result<std::process::Output, DefaultError> {
+    let mut child = Command::new("./server-python/html-converter.py")
         .arg(html)
-        .output();
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|_| DefaultError {
+            message: "Could not parse html",
+        })?;
+
+    let mut stdout = child.stdout.take().ok_or(DefaultError {
+        message: "Could not parse html",
+    })?;
+    let read_stdout = web::block(move || {
+        let mut output = vec![];
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    // The pipe closes when the converter exits, so reading it to the end waits for the converter
+    let stdout = match tokio::time::timeout(*HTML_CONVERSION_TIMEOUT, read_stdout).await {
+        Ok(Ok(Ok(stdout))) => stdout,
+        Ok(_) => {
+            let _ = child.kill();
+            let _ = web::block(move || child.wait()).await;
+            return Err(DefaultError {
+                message: "Could not parse html",
+            });
+        }
+        Err(_) => {
+            let _ = child.kill();
+            let _ = web::block(move || child.wait()).await;
+            return Err(DefaultError {
+                message: "Timed out converting html, the chunk_html may be too large or malformed",
+            });
+        }
+    };
+
+    let status = web::block(move || child.wait())
+        .await
+        .ok()
+        .and_then(|status| status.ok())
+        .ok_or(DefaultError {
+            message: "Could not parse html",
+        })?;
+
+    Ok(std::process::Output {
+        status,
+        stdout,
+        stderr: vec![],
+    })
+}
+
+/// Returns which of the given chunks are visible to a user with the given acl groups. A collision
+/// is visible when the chunk it collided with is.
+pub async fn get_visible_chunk_ids(
+    chunk_ids: Vec<uuid::Uuid>,
+    acl_groups: Vec<String>,
+    pool: web::Data<Pool>,
+) -> Result<HashSet<uuid::Uuid>, ServiceError> {
+    let chunk_point_ids =
+        web::block(move || get_qdrant_point_ids_by_chunk_ids_query(chunk_ids, pool))
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let visible_point_ids: HashSet<uuid::Uuid> = filter_point_ids_by_acl_query(
+        chunk_point_ids
+            .iter()
+            .filter_map(|(_, point_id)| *point_id)
+            .unique()
+            .collect(),
+        acl_groups,
+    )
+    .await
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?
+    .into_iter()
+    .collect();
+
+    Ok(chunk_point_ids
+        .into_iter()
+        .filter(|(_, point_id)| {
+            point_id.is_some_and(|point_id| visible_point_ids.contains(&point_id))
+        })
+        .map(|(chunk_id, _)| chunk_id)
+        .collect())
+}
+
+/// Keeps the chunks which are visible to a user with the given acl groups, or all of them if
+/// acl_groups is None.
+pub async fn filter_chunks_by_acl<T>(
+    chunks: Vec<T>,
+    chunk_id: fn(&T) -> uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
+    pool: web::Data<Pool>,
+) -> Result<Vec<T>, ServiceError> {
+    let Some(acl_groups) = acl_groups else {
+        return Ok(chunks);
+    };
+
+    let visible_chunk_ids =
+        get_visible_chunk_ids(chunks.iter().map(chunk_id).collect(), acl_groups, pool).await?;
+
+    Ok(chunks
+        .into_iter()
+        .filter(|chunk| visible_chunk_ids.contains(&chunk_id(chunk)))
+        .collect())
+}
+
+/// Fails with the same error as a missing chunk if the chunk is not visible to a user with the
+/// given acl groups, so that hidden chunks cannot be told apart from missing ones.
+pub async fn check_chunk_acl(
+    chunk_id: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    let Some(acl_groups) = acl_groups else {
+        return Ok(());
+    };
+
+    if !get_visible_chunk_ids(vec![chunk_id], acl_groups, pool)
+        .await?
+        .contains(&chunk_id)
+    {
+        return Err(ServiceError::BadRequest("Failed to load metadata".into()));
+    }
+
+    Ok(())
+}
+
+pub async fn convert_html(html: &str) -> Result<String, DefaultError> {
+    if html.len() > *MAX_HTML_CONVERSION_BYTES {
+        return Err(DefaultError {
+            message: "chunk_html is larger than MAX_HTML_CONVERSION_BYTES",
+        });
+    }
+
+    let html_parse_result = run_html_converter(html).await;
 
     let content = match html_parse_result {
         Ok(result) => {
@@ -117,10 +323,8 @@ pub fn convert_html(html: &str) -> Result<String, DefaultError> {
                 });
             }
         }
-        Err(_) => {
-            return Err(DefaultError {
-                message: "Could not parse html",
-            });
+        Err(err) => {
+            return Err(err);
         }
     };
 
@@ -135,11 +339,51 @@ pub fn convert_html(html: &str) -> Result<String, DefaultError> {
 pub struct ReturnCreatedChunk {
     pub chunk_metadata: ChunkMetadata,
     pub duplicate: bool,
+    /// Which existing chunk the new chunk was linked to as a duplicate and why. Only set when duplicate is true.
+    pub collision: Option<ChunkCollisionDetails>,
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct ChunkCollisionDetails {
+    /// The qdrant point shared by the new chunk and the chunk it collided with.
+    pub point_id: uuid::Uuid,
+    /// Id of the root chunk the new chunk collided with.
+    pub chunk_id: uuid::Uuid,
+    /// The similarity between the new chunk and the chunk it collided with. A chunk is a duplicate when this is at least the dataset's DUPLICATE_DISTANCE_THRESHOLD.
+    pub score: f32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ChunkQuotaExceeded {
+    pub message: String,
+    /// The number of chunks in the dataset.
+    pub chunk_count: i32,
+    /// The number of chunks the organization's plan allows per dataset.
+    pub chunk_limit: i32,
+    /// The deepest page of results the organization's plan allows searches to fetch.
+    pub max_search_page: i32,
+    /// The name of the organization which owns the dataset.
+    pub organization_name: String,
+    /// The name of the organization's plan.
+    pub plan_name: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SearchPageLimitExceeded {
+    pub message: String,
+    /// The page which was requested.
+    pub page: u64,
+    /// The deepest page the organization's plan allows searches to fetch.
+    pub max_search_page: i32,
+    /// The name of the organization which owns the dataset.
+    pub organization_name: String,
+    /// The name of the organization's plan.
+    pub plan_name: String,
 }
 
 /// create_chunk
 ///
-/// Create a new chunk. If the chunk has the same tracking_id as an existing chunk, the request will fail. Once a chunk is created, it can be searched for using the search endpoint.
+/// Create a new chunk. If the chunk has the same tracking_id as an existing chunk, the request will fail. Once a chunk is created, it can be searched for using the search endpoint. Duplicates of an existing chunk are stored as collisions of it, subject to the dataset's MAX_COLLISIONS_PER_CHUNK.
 #[utoipa::path(
     post,
     path = "/chunk",
@@ -149,6 +393,8 @@ pub struct ReturnCreatedChunk {
     responses(
         (status = 200, description = "JSON response payload containing the created chunk", body = ReturnCreatedChunk),
         (status = 400, description = "Service error relating to to creating a chunk, likely due to conflicting tracking_id", body = DefaultError),
+        (status = 409, description = "The chunk is a duplicate of a chunk which already has the maximum number of collisions", body = DefaultError),
+        (status = 426, description = "The dataset has as many chunks as the organization's plan allows", body = ChunkQuotaExceeded),
     )
 )]
 pub async fn create_chunk(
@@ -162,22 +408,107 @@ pub async fn create_chunk(
     let pool3 = pool.clone();
     let count_pool = pool.clone();
     let count_dataset_id = dataset_org_plan_sub.dataset.id;
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    validate_chunk_metadata(
+        chunk.metadata.as_ref().unwrap_or(&json!({})),
+        &dataset_config,
+    )?;
+    if chunk.chunk_vector.is_none() {
+        validate_embedding_text_metadata(&chunk.metadata, &dataset_config)?;
+    }
+    let expires_at = chunk
+        .expires_at
+        .clone()
+        .map(|expires_at| -> Result<NaiveDateTime, ServiceError> {
+            Ok(expires_at
+                .parse::<DateTimeUtc>()
+                .map_err(|_| ServiceError::BadRequest("Invalid expires_at format".to_string()))?
+                .0
+                .naive_utc())
+        })
+        .transpose()?;
+    if expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now().naive_utc()) {
+        return Err(ServiceError::BadRequest("expires_at must be in the future".into()).into());
+    }
+    if let Some(chunk_index) = chunk.chunk_index {
+        if chunk.file_uuid.is_none() {
+            return Err(ServiceError::BadRequest("chunk_index requires file_uuid".into()).into());
+        }
+        if chunk_index < 0 {
+            return Err(ServiceError::BadRequest("chunk_index must not be negative".into()).into());
+        }
+    }
 
-    let chunk_count =
-        web::block(move || get_row_count_for_dataset_id_query(count_dataset_id, count_pool))
-            .await?
-            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    // The plan quota lookup and the html parse + embedding are independent, so they run
+    // together and this step costs the slower of the two rather than their sum. If the quota
+    // lookup finishes first and the plan is full, the embedding is dropped before it completes so
+    // a rejected chunk does not pay for an embedding. Nothing is written until the quota check
+    // below has passed.
+    let prepare_started_at = std::time::Instant::now();
+    let chunk_count_future = Box::pin(web::block(move || {
+        get_row_count_for_dataset_id_query(count_dataset_id, count_pool)
+    }));
+    let chunk_html = chunk.chunk_html.clone().unwrap_or_default();
+    let content_and_embedding_future = Box::pin(async {
+        let content = convert_html(&chunk_html).await.map_err(|err| {
+            ServiceError::BadRequest(format!("Could not parse html: {}", err.message))
+        })?;
+        let embedding_vector = if let Some(embedding_vector) = chunk.chunk_vector.clone() {
+            if dataset_config.NORMALIZE_EMBEDDINGS.unwrap_or(false) {
+                l2_normalize(embedding_vector)
+            } else {
+                embedding_vector
+            }
+        } else {
+            let embedding_text = get_embedding_text(&content, &chunk.metadata, &dataset_config);
+            create_embedding(&embedding_text, dataset_config.clone()).await?
+        };
 
-    if chunk_count
-        >= dataset_org_plan_sub
-            .organization
-            .plan
-            .unwrap_or(StripePlan::default())
-            .chunk_count
-    {
-        return Ok(HttpResponse::UpgradeRequired()
-            .json(json!({"message": "Must upgrade your plan to add more chunks"})));
+        Ok::<(String, Vec<f32>), actix_web::Error>((content, embedding_vector))
+    });
+
+    let (chunk_count, content_and_embedding) =
+        match futures::future::select(chunk_count_future, content_and_embedding_future).await {
+            futures::future::Either::Left((chunk_count, content_and_embedding_future)) => (
+                chunk_count,
+                futures::future::Either::Left(content_and_embedding_future),
+            ),
+            futures::future::Either::Right((content_and_embedding, chunk_count_future)) => (
+                chunk_count_future.await,
+                futures::future::Either::Right(content_and_embedding),
+            ),
+        };
+
+    let chunk_count = chunk_count?.map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let plan = dataset_org_plan_sub
+        .organization
+        .plan
+        .clone()
+        .unwrap_or(StripePlan::default());
+    if chunk_count >= plan.chunk_count {
+        return Ok(HttpResponse::UpgradeRequired().json(ChunkQuotaExceeded {
+            message: "Must upgrade your plan to add more chunks".to_string(),
+            chunk_count,
+            chunk_limit: plan.chunk_count,
+            max_search_page: plan.max_search_page,
+            organization_name: dataset_org_plan_sub.organization.name.clone(),
+            plan_name: plan.name,
+        }));
     }
+    let chunk_quota_remaining = plan.chunk_count - chunk_count - 1;
+
+    let (content, embedding_vector) = match content_and_embedding {
+        futures::future::Either::Left(content_and_embedding_future) => {
+            content_and_embedding_future.await?
+        }
+        futures::future::Either::Right(content_and_embedding) => content_and_embedding?,
+    };
+    log::debug!(
+        "create_chunk quota check and embedding took {}ms",
+        prepare_started_at.elapsed().as_millis()
+    );
 
     let chunk_tracking_id = chunk
         .tracking_id
@@ -187,31 +518,117 @@ pub async fn create_chunk(
 
     let mut collision: Option<uuid::Uuid> = None;
 
-    let content =
-        convert_html(chunk.chunk_html.as_ref().unwrap_or(&"".to_string())).map_err(|err| {
-            ServiceError::BadRequest(format!("Could not parse html: {}", err.message))
-        })?;
-    let dataset_config =
-        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
-    let embedding_vector = if let Some(embedding_vector) = chunk.chunk_vector.clone() {
-        embedding_vector
+    let image_urls = chunk.image_urls.clone().unwrap_or_default();
+    let chunk_metadata_json = if image_urls.is_empty() {
+        chunk.metadata.clone()
     } else {
-        create_embedding(&content, dataset_config.clone()).await?
+        let mut metadata = match chunk.metadata.clone() {
+            Some(serde_json::Value::Object(metadata)) => metadata,
+            Some(_) => {
+                return Err(ServiceError::BadRequest(
+                    "Metadata must be a JSON object to be used with image_urls".into(),
+                )
+                .into())
+            }
+            None => serde_json::Map::new(),
+        };
+        metadata.insert("image_urls".to_string(), json!(image_urls));
+        Some(serde_json::Value::Object(metadata))
     };
 
-    let first_semantic_result = global_unfiltered_top_match_query(
-        embedding_vector.clone(),
+    let duplicate_distance_threshold = dataset_config.DUPLICATE_DISTANCE_THRESHOLD.unwrap_or(0.95);
+
+    // Vectors of different sizes or embedding models are not comparable, so chunks embedded with a
+    // previous model during a migration must not be mistaken for duplicates of the new chunk
+    let collision_model_check = dataset_config
+        .COLLISION_EMBEDDING_MODEL_CHECK
+        .clone()
+        .unwrap_or("same_model".to_string());
+    if !["same_model", "skip", "any"].contains(&collision_model_check.as_str()) {
+        return Err(ServiceError::BadRequest(format!(
+            "Invalid COLLISION_EMBEDDING_MODEL_CHECK: {}. Must be same_model, skip or any",
+            collision_model_check
+        ))
+        .into());
+    }
+    // The model of a client provided chunk_vector is not known, so it is compared with every chunk
+    let collision_embedding_model = chunk
+        .chunk_vector
+        .is_none()
+        .then(|| get_embedding_model_id(&dataset_config));
+    let skip_collision_check =
+        if embedding_vector.len() != dataset_config.EMBEDDING_SIZE.unwrap_or(1536) {
+            true
+        } else {
+            match (
+                collision_model_check.as_str(),
+                collision_embedding_model.clone(),
+            ) {
+                ("skip", Some(embedding_model)) => has_other_embedding_model_points_query(
+                    dataset_org_plan_sub.dataset.id,
+                    embedding_model,
+                )
+                .await
+                .map_err(|err| ServiceError::BadRequest(err.message.into()))?,
+                _ => false,
+            }
+        };
+
+    // COLLISION_SCOPE limits which chunks count as duplicates, e.g. only chunks from the same file
+    let mut scope_conditions = get_collision_scope_conditions(
+        dataset_config
+            .COLLISION_SCOPE
+            .as_deref()
+            .unwrap_or("global"),
+        chunk.tag_set.clone(),
+        chunk_metadata_json.clone(),
+        chunk.file_uuid,
         dataset_org_plan_sub.dataset.id,
+        pool.clone(),
     )
-    .await
-    .map_err(|err| {
-        ServiceError::BadRequest(format!(
+    .await?;
+    if let ("same_model", Some(embedding_model)) =
+        (collision_model_check.as_str(), collision_embedding_model)
+    {
+        scope_conditions.push(embedding_model_condition(embedding_model));
+    }
+
+    // With a COLLISION_WINDOW_SECS, only roots created or updated within the window can absorb the
+    // new chunk, so fresh content is not swallowed by a stale duplicate
+    let first_semantic_result = match dataset_config.COLLISION_WINDOW_SECS {
+        _ if skip_collision_check => Ok(SearchResult {
+            score: 0.0,
+            point_id: uuid::Uuid::nil(),
+        }),
+        Some(window_secs) => {
+            recent_top_match_query(
+                embedding_vector.clone(),
+                dataset_org_plan_sub.dataset.id,
+                duplicate_distance_threshold,
+                chrono::Duration::seconds(window_secs as i64),
+                scope_conditions,
+                pool.clone(),
+            )
+            .await
+        }
+        None => {
+            global_unfiltered_top_match_query(
+                embedding_vector.clone(),
+                dataset_org_plan_sub.dataset.id,
+                scope_conditions,
+            )
+            .await
+        }
+    }
+    .map_err(|err| match qdrant_error_to_service_error(err) {
+        ServiceError::BadRequest(message) => ServiceError::BadRequest(format!(
             "Could not get semantic similarity for collision check: {}",
-            err.message
-        ))
+            message
+        )),
+        err => err,
     })?;
 
-    let duplicate_distance_threshold = dataset_config.DUPLICATE_DISTANCE_THRESHOLD.unwrap_or(0.95);
+    let mut collision_details: Option<ChunkCollisionDetails> = None;
 
     if first_semantic_result.score >= duplicate_distance_threshold {
         //Sets collision to collided chunk id
@@ -222,7 +639,7 @@ pub async fn create_chunk(
         })
         .await?;
 
-        match score_chunk_result {
+        let collided_chunk = match score_chunk_result {
             Ok(chunk_results) => {
                 if chunk_results.is_empty() {
                     delete_qdrant_point_id_query(
@@ -247,19 +664,62 @@ pub async fn create_chunk(
                 return Err(ServiceError::BadRequest(err.message.into()).into());
             }
         };
+
+        collision_details = Some(ChunkCollisionDetails {
+            point_id: first_semantic_result.point_id,
+            chunk_id: collided_chunk.id,
+            score: first_semantic_result.score,
+        });
     }
 
     let mut chunk_metadata: ChunkMetadata;
     let mut duplicate: bool = false;
 
     //if collision is not nil, insert chunk with collision
-    if collision.is_some() {
+    if let Some(collision_qdrant_id) = collision {
+        if let Some(max_collisions) = dataset_config.MAX_COLLISIONS_PER_CHUNK {
+            let collision_count_pool = pool.clone();
+            let collision_count = web::block(move || {
+                get_collision_count_query(collision_qdrant_id, collision_count_pool)
+            })
+            .await?
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+            if collision_count as u64 >= max_collisions {
+                let evict_oldest = dataset_config.COLLISION_OVERFLOW_POLICY.as_deref()
+                    == Some("evict_oldest")
+                    && max_collisions > 0;
+                if !evict_oldest {
+                    return Ok(HttpResponse::Conflict().json(json!({
+                        "message": "This chunk is a duplicate of a chunk which already has the maximum number of collisions"
+                    })));
+                }
+
+                let evict_pool = pool.clone();
+                let evict_dataset_id = dataset_org_plan_sub.dataset.id;
+                web::block(move || {
+                    delete_oldest_collisions_query(
+                        collision_qdrant_id,
+                        collision_count - max_collisions as i64 + 1,
+                        evict_dataset_id,
+                        evict_pool,
+                    )
+                })
+                .await?
+                .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+            }
+        }
+
         update_qdrant_point_query(
             None,
-            collision.expect("Collision must be some"),
+            collision_qdrant_id,
             Some(user.0.id),
             None,
             dataset_org_plan_sub.dataset.id,
+            dataset_config.FULLTEXT_STEMMING_LANGUAGE.clone(),
+            dataset_config.FULLTEXT_UNACCENT.unwrap_or(false),
+            dataset_config.SPLADE_MODEL.clone(),
+            None,
         )
         .await?;
 
@@ -270,7 +730,7 @@ pub async fn create_chunk(
             &chunk.tag_set,
             user.0.id,
             None,
-            chunk.metadata.clone(),
+            chunk_metadata_json,
             chunk_tracking_id,
             chunk
                 .time_stamp
@@ -290,11 +750,13 @@ pub async fn create_chunk(
             dataset_org_plan_sub.dataset.id,
             0.0,
         );
+        chunk_metadata.expires_at = expires_at;
         chunk_metadata = web::block(move || {
             insert_duplicate_chunk_metadata_query(
                 chunk_metadata,
-                collision.expect("Collision should must be some"),
+                collision_qdrant_id,
                 chunk.file_uuid,
+                chunk.chunk_index,
                 pool1,
             )
         })
@@ -314,7 +776,7 @@ pub async fn create_chunk(
             &chunk.tag_set,
             user.0.id,
             Some(qdrant_point_id),
-            chunk.metadata.clone(),
+            chunk_metadata_json,
             chunk_tracking_id,
             chunk
                 .time_stamp
@@ -333,10 +795,19 @@ pub async fn create_chunk(
             dataset_org_plan_sub.dataset.id,
             0.0,
         );
+        chunk_metadata.expires_at = expires_at;
 
-        chunk_metadata = insert_chunk_metadata_query(chunk_metadata, chunk.file_uuid, pool1)
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+        chunk_metadata =
+            insert_chunk_metadata_query(chunk_metadata, chunk.file_uuid, chunk.chunk_index, pool1)
+                .await
+                .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+        let image_vector =
+            if dataset_config.IMAGE_EMBEDDING_ENABLED.unwrap_or(false) && !image_urls.is_empty() {
+                Some(get_clip_embedding(image_urls, "image").await?)
+            } else {
+                None
+            };
 
         create_new_qdrant_point_query(
             qdrant_point_id,
@@ -344,6 +815,16 @@ pub async fn create_chunk(
             chunk_metadata.clone(),
             Some(user.0.id),
             dataset_org_plan_sub.dataset.id,
+            image_vector,
+            dataset_config.FULLTEXT_STEMMING_LANGUAGE.clone(),
+            dataset_config.FULLTEXT_UNACCENT.unwrap_or(false),
+            dataset_config.SPLADE_MODEL.clone(),
+            chunk.acl.clone(),
+            // A client provided chunk_vector may come from any model
+            chunk
+                .chunk_vector
+                .is_none()
+                .then(|| get_embedding_model_id(&dataset_config)),
         )
         .await?;
     }
@@ -356,10 +837,14 @@ pub async fn create_chunk(
             .await?;
     }
 
-    Ok(HttpResponse::Ok().json(ReturnCreatedChunk {
-        chunk_metadata,
-        duplicate,
-    }))
+    Ok(HttpResponse::Ok()
+        .insert_header(("X-Chunk-Quota-Remaining", chunk_quota_remaining.to_string()))
+        .insert_header(("X-Max-Search-Page", plan.max_search_page.to_string()))
+        .json(ReturnCreatedChunk {
+            chunk_metadata,
+            duplicate,
+            collision: collision_details,
+        }))
 }
 
 /// delete_chunk
@@ -453,13 +938,13 @@ pub struct UpdateChunkData {
     link: Option<String>,
     /// HTML content of the chunk you want to update. This can also be plaintext. The innerText of the HTML will be used to create the embedding vector. The point of using HTML is for convienience, as some users have applications where users submit HTML content. If no chunk_html is provided, the existing chunk_html will be used.
     chunk_html: Option<String>,
-    /// The metadata is a JSON object which can be used to filter chunks. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata. If no metadata is provided, the existing metadata will be used.
+    /// The metadata is a JSON object which can be used to filter chunks. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata. If no metadata is provided, the existing metadata will be used. Provided metadata must match the dataset's METADATA_SCHEMA if it has one, and the chunk's metadata must have the keys which the dataset's EMBEDDING_TEXT_TEMPLATE refers to.
     metadata: Option<serde_json::Value>,
     /// Tracking_id is a string which can be used to identify a chunk. This is useful for when you are coordinating with an external system and want to use the tracking_id to identify the chunk. If no tracking_id is provided, the existing tracking_id will be used.
     tracking_id: Option<String>,
     /// Time_stamp should be an ISO 8601 combined date and time without timezone. It is used for time window filtering and recency-biasing search results. If no time_stamp is provided, the existing time_stamp will be used.
     time_stamp: Option<String>,
-    /// Weight is a float which can be used to bias search results. This is useful for when you want to bias search results for a chunk. The magnitude only matters relative to other chunks in the chunk's dataset dataset. If no weight is provided, the existing weight will be used.
+    /// Weight is a float which can be used to bias search results. This is useful for when you want to bias search results for a chunk. The magnitude only matters relative to other chunks in the chunk's dataset dataset. Weights below 1.0 down-rank the chunk, and a weight of 0 effectively hides it from search rankings while keeping it retrievable by id. If no weight is provided, the existing weight will be used.
     weight: Option<f64>,
 }
 #[derive(Serialize, Deserialize, Clone, ToSchema)]
@@ -503,15 +988,24 @@ pub async fn update_chunk(
         .filter(|chunk_tracking| !chunk_tracking.is_empty());
 
     let new_content = convert_html(chunk.chunk_html.as_ref().unwrap_or(&chunk_metadata.content))
+        .await
         .map_err(|err| {
             ServiceError::BadRequest(format!("Could not parse html: {}", err.message))
         })?;
 
-    let embedding_vector = create_embedding(
-        &new_content,
-        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration),
-    )
-    .await?;
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    if let Some(metadata) = chunk.metadata.as_ref() {
+        validate_chunk_metadata(metadata, &dataset_config)?;
+    }
+    let embedding_metadata = chunk.metadata.clone().or(chunk_metadata.metadata.clone());
+    validate_embedding_text_metadata(&embedding_metadata, &dataset_config)?;
+    let embedding_text = get_embedding_text(&new_content, &embedding_metadata, &dataset_config);
+    let stemming_language = dataset_config.FULLTEXT_STEMMING_LANGUAGE.clone();
+    let unaccent = dataset_config.FULLTEXT_UNACCENT.unwrap_or(false);
+    let splade_model = dataset_config.SPLADE_MODEL.clone();
+    let embedding_model = get_embedding_model_id(&dataset_config);
+    let embedding_vector = create_embedding(&embedding_text, dataset_config).await?;
 
     let chunk_html = match chunk.chunk_html.clone() {
         Some(chunk_html) => Some(chunk_html),
@@ -523,7 +1017,7 @@ pub async fn update_chunk(
         .await?
         .map_err(|_| ServiceError::BadRequest("chunk not found".into()))?;
 
-    let metadata = ChunkMetadata::from_details_with_id(
+    let mut metadata = ChunkMetadata::from_details_with_id(
         chunk.chunk_uuid,
         &new_content,
         &chunk_html,
@@ -549,8 +1043,10 @@ pub async fn update_chunk(
             .transpose()?
             .or(chunk_metadata.time_stamp),
         dataset_id,
-        chunk.weight.unwrap_or(1.0),
+        chunk.weight.unwrap_or(chunk_metadata.weight),
     );
+    metadata.created_at = chunk_metadata.created_at;
+    metadata.expires_at = chunk_metadata.expires_at;
     let metadata1 = metadata.clone();
     update_chunk_metadata_query(metadata, None, dataset_id, pool2)
         .await
@@ -567,6 +1063,10 @@ pub async fn update_chunk(
         Some(user.0.id),
         Some(embedding_vector),
         dataset_id,
+        stemming_language,
+        unaccent,
+        splade_model,
+        Some(embedding_model),
     )
     .await?;
 
@@ -581,11 +1081,11 @@ pub struct UpdateChunkByTrackingIdData {
     link: Option<String>,
     /// HTML content of the chunk you want to update. This can also be plaintext. The innerText of the HTML will be used to create the embedding vector. The point of using HTML is for convienience, as some users have applications where users submit HTML content. If no chunk_html is provided, the existing chunk_html will be used.
     chunk_html: Option<String>,
-    /// The metadata is a JSON object which can be used to filter chunks. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata. If no metadata is provided, the existing metadata will be used.
+    /// The metadata is a JSON object which can be used to filter chunks. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata. If no metadata is provided, the existing metadata will be used. Provided metadata must match the dataset's METADATA_SCHEMA if it has one, and the chunk's metadata must have the keys which the dataset's EMBEDDING_TEXT_TEMPLATE refers to.
     metadata: Option<serde_json::Value>,
     /// Time_stamp should be an ISO 8601 combined date and time without timezone. It is used for time window filtering and recency-biasing search results. If no time_stamp is provided, the existing time_stamp will be used.
     time_stamp: Option<String>,
-    /// Weight is a float which can be used to bias search results. This is useful for when you want to bias search results for a chunk. The magnitude only matters relative to other chunks in the chunk's dataset dataset. If no weight is provided, the existing weight will be used.
+    /// Weight is a float which can be used to bias search results. This is useful for when you want to bias search results for a chunk. The magnitude only matters relative to other chunks in the chunk's dataset dataset. Weights below 1.0 down-rank the chunk, and a weight of 0 effectively hides it from search rankings while keeping it retrievable by id. If no weight is provided, the existing weight will be used.
     weight: Option<f64>,
 }
 
@@ -634,15 +1134,24 @@ pub async fn update_chunk_by_tracking_id(
         .unwrap_or_else(|| chunk_metadata.link.clone().unwrap_or_default());
 
     let new_content = convert_html(chunk.chunk_html.as_ref().unwrap_or(&chunk_metadata.content))
+        .await
         .map_err(|err| {
             ServiceError::BadRequest(format!("Could not parse html: {}", err.message))
         })?;
 
-    let embedding_vector = create_embedding(
-        &new_content,
-        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration),
-    )
-    .await?;
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    if let Some(metadata) = chunk.metadata.as_ref() {
+        validate_chunk_metadata(metadata, &dataset_config)?;
+    }
+    let embedding_metadata = chunk.metadata.clone().or(chunk_metadata.metadata.clone());
+    validate_embedding_text_metadata(&embedding_metadata, &dataset_config)?;
+    let embedding_text = get_embedding_text(&new_content, &embedding_metadata, &dataset_config);
+    let stemming_language = dataset_config.FULLTEXT_STEMMING_LANGUAGE.clone();
+    let unaccent = dataset_config.FULLTEXT_UNACCENT.unwrap_or(false);
+    let splade_model = dataset_config.SPLADE_MODEL.clone();
+    let embedding_model = get_embedding_model_id(&dataset_config);
+    let embedding_vector = create_embedding(&embedding_text, dataset_config).await?;
 
     let chunk_html = match chunk.chunk_html.clone() {
         Some(chunk_html) => Some(chunk_html),
@@ -654,7 +1163,7 @@ pub async fn update_chunk_by_tracking_id(
         .await?
         .map_err(|_| ServiceError::BadRequest("chunk not found".into()))?;
 
-    let metadata = ChunkMetadata::from_details_with_id(
+    let mut metadata = ChunkMetadata::from_details_with_id(
         chunk_metadata.id,
         &new_content,
         &chunk_html,
@@ -680,8 +1189,10 @@ pub async fn update_chunk_by_tracking_id(
             .transpose()?
             .or(chunk_metadata.time_stamp),
         dataset_org_plan_sub.dataset.id,
-        chunk.weight.unwrap_or(1.0),
+        chunk.weight.unwrap_or(chunk_metadata.weight),
     );
+    metadata.created_at = chunk_metadata.created_at;
+    metadata.expires_at = chunk_metadata.expires_at;
     let metadata1 = metadata.clone();
     update_chunk_metadata_query(metadata, None, dataset_org_plan_sub.dataset.id, pool2)
         .await
@@ -698,19 +1209,25 @@ pub async fn update_chunk_by_tracking_id(
         Some(user.0.id),
         Some(embedding_vector),
         dataset_org_plan_sub.dataset.id,
+        stemming_language,
+        unaccent,
+        splade_model,
+        Some(embedding_model),
     )
     .await?;
 
     Ok(HttpResponse::NoContent().finish())
 }
 
-#[derive(Serialize, Deserialize, Clone, ToSchema)]
+#[derive(Serialize, Deserialize, Clone, ToSchema, Default)]
 pub struct SearchChunkData {
-    /// Can be either "semantic", "fulltext", or "hybrid". "hybrid" will pull in one page (10 chunks) of both semantic and full-text results then re-rank them using reciprocal rank fusion using the specified weights or BAAI/bge-reranker-large. "semantic" will pull in one page (10 chunks) of the nearest cosine distant vectors. "fulltext" will pull in one page (10 chunks) of full-text results based on SPLADE.
+    /// Can be either "semantic", "fulltext", "hybrid" or "image", and defaults to the dataset's DEFAULT_SEARCH_TYPE. "hybrid" will pull in one page (10 chunks) of both semantic and full-text results then re-rank them using reciprocal rank fusion using the specified weights or BAAI/bge-reranker-large. "semantic" will pull in one page (10 chunks) of the nearest cosine distant vectors. "fulltext" will pull in one page (10 chunks) of full-text results based on SPLADE. "image" searches the CLIP embeddings of chunks created with image_urls.
+    #[serde(default)]
     pub search_type: String,
-    /// Query is the search query. This can be any string. The query will be used to create an embedding vector and/or SPLADE vector which will be used to find the result set.
-    pub query: String,
-    /// Page of chunks to fetch. Each page is 10 chunks. Support for custom page size is coming soon.
+    /// Query is the search query. This can be any string, or a list of up to 5 phrasings of the same search whose semantic results are merged. It may be empty if a filter is provided, in which case the matching chunks are returned newest first. Words can be boosted for full-text search as `word^weight`.
+    #[serde(default)]
+    pub query: SearchQuery,
+    /// Page of chunks to fetch. Each page is 10 chunks. Pages past the max_search_page of the organization's plan are rejected with a 403.
     pub page: Option<u64>,
     /// Link set is a comma separated list of links. This can be used to filter chunks by link. HNSW indices do not exist for links, so there is a performance hit for filtering on them.
     pub link: Option<Vec<String>>,
@@ -718,26 +1235,296 @@ pub struct SearchChunkData {
     pub tag_set: Option<Vec<String>>,
     /// Time_range is a tuple of two ISO 8601 combined date and time without timezone. The first value is the start of the time range and the second value is the end of the time range. This can be used to filter chunks by time range. HNSW indices do not exist for time range, so there is a performance hit for filtering on them.
     pub time_range: Option<(String, String)>,
+    /// Created_range is a tuple of two ISO 8601 combined date and time without timezone which filters chunks by when they were created in the system rather than by their time_stamp. Either value can be "null" to leave that end of the range open.
+    pub created_range: Option<(String, String)>,
+    /// Updated_range is a tuple of two ISO 8601 combined date and time without timezone which filters chunks by when they were last updated in the system rather than by their time_stamp. Either value can be "null" to leave that end of the range open.
+    pub updated_range: Option<(String, String)>,
+    /// Since is an ISO 8601 combined date and time without timezone, in UTC. Only chunks created after it are returned, so the server_time of the previous response can be passed to poll for new chunks.
+    pub since: Option<NaiveDateTime>,
     /// Filters is a JSON object which can be used to filter chunks. The values on each key in the object will be used to check for an exact substring match on the metadata values for each existing chunk. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata.
     pub filters: Option<serde_json::Value>,
+    /// Metadata filters are matched case-insensitively by default, so a filter value of "active" will match a metadata value of "Active". Set case_sensitive_filters to true to only match values with the same casing.
+    pub case_sensitive_filters: Option<bool>,
     /// Set date_bias to true to bias search results towards more recent chunks. This will work best in hybrid search mode.
     pub date_bias: Option<bool>,
-    /// Set cross_encoder to true to use the BAAI/bge-reranker-large model to re-rank search results. This will only apply if in hybrid search mode. If no weighs are specified, the re-ranker will be used by default.
+    /// Set cross_encoder to true to use the BAAI/bge-reranker-large model to re-rank search results. This will only apply if in hybrid search mode. If no weighs are specified, the re-ranker will be used by default. The re-ranker is skipped when fewer chunks than the dataset's MIN_RESULTS_TO_RERANK were retrieved.
     pub cross_encoder: Option<bool>,
     /// Weights are a tuple of two floats. The first value is the weight for the semantic search results and the second value is the weight for the full-text search results. This can be used to bias search results towards semantic or full-text results. This will only apply if in hybrid search mode and cross_encoder is set to false.
     pub weights: Option<(f64, f64)>,
+    /// Rrf_k is the rank smoothing constant of reciprocal rank fusion in hybrid search, where each result scores `weight / (rrf_k + rank)`. Defaults to 60.
+    pub rrf_k: Option<f64>,
+    /// Fusion is how hybrid search combines semantic and full-text results, either "rrf" for reciprocal rank fusion or "weighted_norm" to sum the min-max normalized scores using the weights. Defaults to "rrf".
+    pub fusion: Option<String>,
+    /// Highlight_max_num is the maximum number of phrases to highlight with `<b>` tags in each result's chunk_html. If not specified, 2 phrases will be highlighted for short chunks and 3 for longer ones.
+    pub highlight_max_num: Option<usize>,
+    /// Highlight_window is the number of words of context to keep on either side of each highlighted phrase. If specified, the chunk_html of each result will be trimmed down to snippets around the highlights joined by "...". If not specified, the full chunk_html will be returned.
+    pub highlight_window: Option<usize>,
+    /// Highlight_strategy can be either "lexical", to highlight the phrases sharing the most words with the query, or "semantic", to highlight the sentences whose embeddings are closest to the query. "semantic" embeds up to 20 sentences of every result, so it is slower. Defaults to "lexical".
+    pub highlight_strategy: Option<String>,
+    /// Exclude_chunk_ids is a list of chunk ids which should not be returned in the search results. This is useful for infinite scroll or "don't show again" experiences where a user has already seen certain results. It applies to all search types.
+    pub exclude_chunk_ids: Option<Vec<uuid::Uuid>>,
+    /// Group_by is a metadata key to bucket the results by. If specified, the response will be a GroupedSearchChunkQueryResponseBody whose groups are ordered by their best scoring result.
+    pub group_by: Option<String>,
+    /// Set include_relevance_percent to true to add a relevance_percent from 0 to 100 to each result, which is the result's score min-max scaled against the other results on the page. This is meant for displaying a "match %" and does not change the ranking or the raw score. Defaults to false.
+    pub include_relevance_percent: Option<bool>,
+    /// Set include_embedding_model to true to add the embedding_model which produced each result's vector. This is useful for debugging a migration to a new embedding model or for checking that a re-embedding has reached every chunk. Defaults to false.
+    pub include_embedding_model: Option<bool>,
+    /// Set include_neighbors to N to attach up to N chunks before and after each result from the same file, in the order of the file's chunks given by their chunk_index. This lets a reader expand the context around a match without extra requests. Results which did not come from a file get no neighbors. Can be at most 10.
+    pub include_neighbors: Option<u32>,
+    /// Set suggest_on_empty to true to include did_you_mean suggestions in the response when the search returns no results. Suggestions are built by replacing each word of the query which does not appear in the dataset with the trigram-closest words that do. Defaults to false.
+    pub suggest_on_empty: Option<bool>,
+    /// Set relax_filters_on_empty to true to retry the search once without the filters, tag_set, link, time_range, created_range and updated_range when it returns no results. The response's filters_relaxed will be true if the returned results came from the retry. Has no effect if the query is empty. Defaults to false.
+    pub relax_filters_on_empty: Option<bool>,
+    /// Set prefix_match to true to treat the last word of the query as a prefix for fulltext searches, so "vector embed" will match "embedding". The prefix is matched without an index, so it is slower on large datasets.
+    pub prefix_match: Option<bool>,
+    /// Sort can be either "relevance" or "recency". "recency" returns the chunks matching the filters and the words of the query newest first with a score of 0. Defaults to "relevance".
+    pub sort: Option<String>,
+    /// Set require_all_terms to true to only return fulltext results containing every word of the query. Has no effect for other search types.
+    pub require_all_terms: Option<bool>,
+    /// Set ignore_missing_filter_keys to true to drop filters on metadata keys which no chunk in the dataset has instead of matching nothing, so a filter on an absent key matches every chunk. The response's missing_filter_keys lists such keys either way. Defaults to false.
+    pub ignore_missing_filter_keys: Option<bool>,
+    /// Set include_query_vector to true to return the embedding which the query was searched with as the response's query_vector. Has no effect for fulltext searches.
+    pub include_query_vector: Option<bool>,
+    /// Soft_deadline_ms is a deadline in milliseconds after which a hybrid search drops its slow stages and returns the results it has, with partial set to true in the response. Has no effect for other search types.
+    pub soft_deadline_ms: Option<u64>,
+    /// Aggregations are up to 10 min, max, avg or sum statistics of numeric metadata keys, computed over every chunk matching the search's filters rather than only the returned page.
+    pub aggregations: Option<Vec<AggSpec>>,
+    /// Boost_field names a numeric metadata key, such as a view count, whose log-scaled value boosts the score of each result on the returned page.
+    pub boost_field: Option<String>,
+    /// Explain_chunk_id is the id of a chunk to explain the presence or absence of in the results. It runs an extra query per filter, so it is meant for debugging rather than production traffic.
+    pub explain_chunk_id: Option<uuid::Uuid>,
+    /// Diversity between 0 and 1 re-ranks the returned page with maximal marginal relevance so that near-duplicate chunks do not crowd out other results. Defaults to 0.
+    pub diversity: Option<f32>,
+    /// Distance_metric is the metric the search expects the dense vectors to be scored by, either "cosine" or "dot". The search fails with a 400 if the dataset's collection scores by the other one.
+    pub distance_metric: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+pub struct ChunkExplanation {
+    pub chunk_id: uuid::Uuid,
+    /// Why the chunk is or is not in the results. Can be "in_results", "not_in_page", "not_found", "expired", "excluded", "filtered" or "acl".
+    pub status: String,
+    /// A readable description of the status.
+    pub reason: String,
+    /// The filters the chunk does not match when the status is "filtered". Can contain "link", "tag_set", "time_range", "created_range", "updated_range", "since", "filters", "quoted_words", "negated_words" and "prefix".
+    pub failed_filters: Vec<String>,
+    /// The 1-based position of the chunk's result on the returned page, only set when the status is "in_results".
+    pub rank: Option<usize>,
+    /// The score of the chunk's result, only set when the status is "in_results".
+    pub score: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+pub struct AggSpec {
+    /// The metadata key whose values are aggregated.
+    pub key: String,
+    /// The aggregation to compute, either "min", "max", "avg" or "sum".
+    pub op: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+pub struct AggregationResult {
+    pub key: String,
+    pub op: String,
+    /// The result of the aggregation. Null if no matching chunk has a numeric value for the key.
+    pub value: Option<f64>,
+    /// The number of matching chunks with a numeric value for the key which were aggregated.
+    pub count: i64,
+}
+
+pub const MAX_AGGREGATIONS: usize = 10;
+
+pub const MAX_SEARCH_QUERIES: usize = 5;
+pub const MAX_NEIGHBORS: u32 = 10;
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
+#[serde(untagged)]
+pub enum SearchQuery {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        SearchQuery::Single("".to_string())
+    }
+}
+
+impl SearchQuery {
+    /// The first query, which is used wherever a single query is needed.
+    pub fn primary(&self) -> String {
+        match self {
+            SearchQuery::Single(query) => query.clone(),
+            SearchQuery::Multiple(queries) => queries.first().cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Every distinct query, in the order they were given.
+    pub fn all(&self) -> Vec<String> {
+        match self {
+            SearchQuery::Single(query) => vec![query.clone()],
+            SearchQuery::Multiple(queries) => queries.iter().unique().cloned().collect(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
 pub struct ScoreChunkDTO {
     pub metadata: Vec<ChunkMetadataWithFileData>,
     pub score: f64,
+    /// The score rescaled to 0-100 relative to the other results on the page. Only set when include_relevance_percent is true on the search request.
+    pub relevance_percent: Option<f64>,
+    /// The embedding model which produced the vector of the result's qdrant point, such as `openai/text-embedding-3-small`. Only set when include_embedding_model is true on the search request. Null for chunks created with a chunk_vector or before the model was recorded.
+    pub embedding_model: Option<String>,
+    /// The chunks surrounding the result in the file it came from. Only set when include_neighbors is set on the search request. Neighbors are not scored.
+    pub neighbors: Option<ChunkNeighbors>,
+    /// A stable id for this result of this search, derived from the query, the chunk and the page. Repeating a search produces the same ids. Send it to /analytics/feedback to record whether the result was relevant. Only set on results of the search route.
+    pub result_id: Option<uuid::Uuid>,
+    /// The tags of the result's chunk which are in the tag_set of the search request, compared case-insensitively. Useful for rendering badges for the tags a result matched on. Empty when the search had no tag_set filter.
+    pub matched_tags: Vec<String>,
+    /// The ids of the searched collections which the result's chunk is bookmarked in. Only set on results of the collection search route.
+    pub collection_ids: Option<Vec<uuid::Uuid>>,
 }
 
-#[derive(Serialize, Deserialize, ToSchema)]
+impl ScoreChunkDTO {
+    /// A result without any of the fields which are only set for some requests.
+    pub fn new(metadata: Vec<ChunkMetadataWithFileData>, score: f64) -> Self {
+        ScoreChunkDTO {
+            metadata,
+            score,
+            relevance_percent: None,
+            embedding_model: None,
+            neighbors: None,
+            result_id: None,
+            matched_tags: vec![],
+            collection_ids: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ChunkNeighbors {
+    /// The chunks before the result, closest last.
+    pub previous: Vec<ChunkMetadata>,
+    /// The chunks after the result, closest first.
+    pub next: Vec<ChunkMetadata>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Default)]
 pub struct SearchChunkQueryResponseBody {
     pub score_chunks: Vec<ScoreChunkDTO>,
     pub total_chunk_pages: i64,
+    /// The number of chunks matching the search's filters across all pages.
+    pub total_results: i64,
+    /// The page of results which was returned.
+    pub page: u64,
+    /// Whether there is another page of results after this one.
+    pub has_more: bool,
+    /// Id of the search which can be sent along with clicks to /analytics/click to correlate them with this search.
+    pub query_id: Option<uuid::Uuid>,
+    /// Alternative queries to try, only set if suggest_on_empty was true and the search returned no results.
+    pub did_you_mean: Option<Vec<String>>,
+    /// Whether the results came from retrying the search without its filters, only set if relax_filters_on_empty was true.
+    pub filters_relaxed: Option<bool>,
+    /// The keys of the request's filters which no chunk in the dataset has, usually because of a typo. Only set if the search had filters.
+    pub missing_filter_keys: Option<Vec<String>>,
+    /// The embedding of the query which the search was run with, only set if include_query_vector was true.
+    pub query_vector: Option<Vec<f32>>,
+    /// Whether a stage of the search was dropped because it missed the soft deadline, only set if soft_deadline_ms was set on a hybrid search.
+    pub partial: Option<bool>,
+    /// The time in UTC at which the search was run. Pass it as since on the next request to get only the chunks added after this search. Only set on responses of the search route.
+    pub server_time: Option<NaiveDateTime>,
+    /// The results of the request's aggregations in the order they were requested, only set if aggregations were requested.
+    pub aggregations: Option<Vec<AggregationResult>>,
+    /// Why the chunk of explain_chunk_id is or is not in the results, only set if explain_chunk_id was set.
+    pub explanation: Option<ChunkExplanation>,
+}
+
+impl SearchChunkQueryResponseBody {
+    /// A page of results without any of the fields which are only set for some requests.
+    pub fn new(
+        score_chunks: Vec<ScoreChunkDTO>,
+        total_chunk_pages: i64,
+        total_results: i64,
+        page: u64,
+    ) -> Self {
+        SearchChunkQueryResponseBody {
+            score_chunks,
+            total_chunk_pages,
+            total_results,
+            page,
+            has_more: (page as i64) < total_chunk_pages,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ScoreChunkGroup {
+    /// The value of the group_by metadata key shared by every chunk in this group. Null for chunks which do not have the key.
+    pub group_key: Option<String>,
+    pub score_chunks: Vec<ScoreChunkDTO>,
+}
+
+/// The body of a search response. It is a GroupedSearchChunkQueryResponseBody when the request set group_by and a SearchChunkQueryResponseBody otherwise.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum SearchChunkResponseTypes {
+    Chunks(SearchChunkQueryResponseBody),
+    GroupedChunks(GroupedSearchChunkQueryResponseBody),
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct GroupedSearchChunkQueryResponseBody {
+    pub groups: Vec<ScoreChunkGroup>,
+    pub total_chunk_pages: i64,
+    /// The number of chunks matching the search's filters across all pages.
+    pub total_results: i64,
+    /// The page of results which was returned.
+    pub page: u64,
+    /// Whether there is another page of results after this one.
+    pub has_more: bool,
+    /// Id of the search which can be sent along with clicks to /analytics/click to correlate them with this search.
+    pub query_id: Option<uuid::Uuid>,
+    /// Alternative queries to try, only set if suggest_on_empty was true and the search returned no results.
+    pub did_you_mean: Option<Vec<String>>,
+    /// Whether the results came from retrying the search without its filters, only set if relax_filters_on_empty was true.
+    pub filters_relaxed: Option<bool>,
+    /// The keys of the request's filters which no chunk in the dataset has, usually because of a typo. Only set if the search had filters.
+    pub missing_filter_keys: Option<Vec<String>>,
+    /// The embedding of the query which the search was run with, only set if include_query_vector was true.
+    pub query_vector: Option<Vec<f32>>,
+    /// Whether a stage of the search was dropped because it missed the soft deadline, only set if soft_deadline_ms was set on a hybrid search.
+    pub partial: Option<bool>,
+    /// The time in UTC at which the search was run. Pass it as since on the next request to get only the chunks added after this search. Only set on responses of the search route.
+    pub server_time: Option<NaiveDateTime>,
+    /// The results of the request's aggregations in the order they were requested, only set if aggregations were requested.
+    pub aggregations: Option<Vec<AggregationResult>>,
+    /// Why the chunk of explain_chunk_id is or is not in the results, only set if explain_chunk_id was set.
+    pub explanation: Option<ChunkExplanation>,
+}
+
+impl GroupedSearchChunkQueryResponseBody {
+    /// Groups the results of a search by the value of the group_by metadata key, keeping the rest of
+    /// the response as is.
+    pub fn from_results(result_chunks: SearchChunkQueryResponseBody, group_by: &str) -> Self {
+        GroupedSearchChunkQueryResponseBody {
+            groups: group_score_chunks(result_chunks.score_chunks, group_by),
+            total_chunk_pages: result_chunks.total_chunk_pages,
+            total_results: result_chunks.total_results,
+            page: result_chunks.page,
+            has_more: result_chunks.has_more,
+            query_id: result_chunks.query_id,
+            did_you_mean: result_chunks.did_you_mean,
+            filters_relaxed: result_chunks.filters_relaxed,
+            missing_filter_keys: result_chunks.missing_filter_keys,
+            query_vector: result_chunks.query_vector,
+            partial: result_chunks.partial,
+            server_time: result_chunks.server_time,
+            aggregations: result_chunks.aggregations,
+            explanation: result_chunks.explanation,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -745,37 +1532,173 @@ pub struct ParsedQuery {
     pub query: String,
     pub quote_words: Option<Vec<String>>,
     pub negated_words: Option<Vec<String>>,
+    /// Whether the query was unaccented, in which case quoted and negated words are matched against
+    /// the unaccented content of the chunks.
+    pub unaccented: bool,
+    /// A word which the chunks must contain a word starting with, set for prefix_match searches.
+    pub prefix: Option<String>,
+    /// The terms of the query written as `term^weight` and their weights. The weight of each
+    /// term's tokens in the SPLADE query vector is multiplied by its weight for fulltext searches.
+    pub boosts: Vec<(String, f32)>,
+    /// The dataset's SPLADE_MODEL which the query is encoded with for fulltext searches.
+    pub splade_model: Option<String>,
 }
-fn parse_query(query: String) -> ParsedQuery {
-    let re = Regex::new(r#""(.*?)""#).unwrap();
-    let quote_words: Vec<String> = re
-        .captures_iter(&query.replace('\\', ""))
-        .map(|capture| capture[1].to_string())
-        .filter(|word| !word.is_empty())
-        .collect::<Vec<String>>();
 
-    let quote_words = if quote_words.is_empty() {
-        None
-    } else {
-        Some(quote_words)
-    };
+impl ParsedQuery {
+    /// Turns the query into a pure filter for recency sorted searches. Every loose word of the query
+    /// must be contained in the chunk like a quoted phrase and the query is cleared so that the
+    /// matching chunks are browsed newest first instead of being ranked.
+    pub fn into_recency_filter(self) -> Self {
+        ParsedQuery {
+            query: String::new(),
+            prefix: None,
+            ..self.require_loose_words()
+        }
+    }
 
-    let negated_words: Vec<String> = query
-        .split_whitespace()
-        .filter(|word| word.starts_with('-'))
-        .map(|word| word.strip_prefix('-').unwrap().to_string())
-        .collect::<Vec<String>>();
+    /// Requires every loose word of the query to be contained in the chunk like a quoted phrase,
+    /// while keeping the query to rank the results by.
+    pub fn require_loose_words(self) -> Self {
+        let mut quote_words = self.quote_words.unwrap_or_default();
+        quote_words.extend(get_loose_words(&self.query));
 
-    let negated_words = if negated_words.is_empty() {
-        None
-    } else {
-        Some(negated_words)
-    };
+        ParsedQuery {
+            quote_words: if quote_words.is_empty() {
+                None
+            } else {
+                Some(quote_words)
+            },
+            ..self
+        }
+    }
 
-    ParsedQuery {
-        query,
-        quote_words,
+    pub fn unaccent(self) -> Self {
+        let unaccent_words = |words: Option<Vec<String>>| {
+            words.map(|words| words.iter().map(|word| unaccent_text(word)).collect())
+        };
+
+        ParsedQuery {
+            query: unaccent_text(&self.query),
+            quote_words: unaccent_words(self.quote_words),
+            negated_words: unaccent_words(self.negated_words),
+            unaccented: true,
+            prefix: self.prefix.map(|prefix| unaccent_text(&prefix)),
+            boosts: self
+                .boosts
+                .into_iter()
+                .map(|(term, weight)| (unaccent_text(&term), weight))
+                .collect(),
+            splade_model: self.splade_model,
+        }
+    }
+
+    /// Stems the query and its boosted terms, leaving quoted phrases as they were written.
+    pub fn stem(self, language: &str) -> Result<Self, ServiceError> {
+        Ok(ParsedQuery {
+            query: stem_query(&self.query, language)?,
+            boosts: self
+                .boosts
+                .into_iter()
+                .map(|(term, weight)| Ok((stem_text(&term, language)?, weight)))
+                .collect::<Result<Vec<(String, f32)>, ServiceError>>()?,
+            ..self
+        })
+    }
+}
+
+/// Splits the `term^weight` boosts out of a query, returning the query with the `^weight` suffixes
+/// removed along with the boosted terms and their weights. Negated words are never boosted and a
+/// query without boosts is returned unchanged.
+pub fn parse_query_boosts(query: &str) -> (String, Vec<(String, f32)>) {
+    let boost_re =
+        Regex::new(r#"([^\s"^]+)\^(\d+(?:\.\d+)?)"#).expect("Boost regex should be valid");
+
+    let mut stripped_query = String::new();
+    let mut boosts = vec![];
+    let mut last_end = 0;
+    for captures in boost_re.captures_iter(query) {
+        let boost = captures.get(0).expect("Capture 0 should always exist");
+        let term = &captures[1];
+        let starts_word = query[..boost.start()]
+            .chars()
+            .last()
+            .map_or(true, |c| c.is_whitespace());
+        let ends_word = query[boost.end()..]
+            .chars()
+            .next()
+            .map_or(true, |c| c.is_whitespace());
+        if !starts_word || !ends_word || term.starts_with('-') {
+            continue;
+        }
+        let Ok(weight) = captures[2].parse::<f32>() else {
+            continue;
+        };
+
+        stripped_query.push_str(&query[last_end..boost.start()]);
+        stripped_query.push_str(term);
+        last_end = boost.end();
+        boosts.push((term.to_string(), weight));
+    }
+    stripped_query.push_str(&query[last_end..]);
+
+    (stripped_query, boosts)
+}
+
+/// The words of the query which are neither quoted nor negated, stripped to their letters and
+/// numbers so they can be used in a tsquery.
+fn get_loose_words(query: &str) -> Vec<String> {
+    let quote_re = Regex::new(r#""(.*?)""#).expect("Quote regex should be valid");
+    let non_word_re = Regex::new(r"[^\p{L}\p{N}]").expect("Non-word regex should be valid");
+
+    quote_re
+        .replace_all(query, " ")
+        .split_whitespace()
+        .filter(|word| !word.starts_with('-'))
+        .map(|word| non_word_re.replace_all(word, "").to_string())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The last word of the query which is neither quoted nor negated.
+fn get_prefix_word(query: &str) -> Option<String> {
+    get_loose_words(query).pop()
+}
+
+fn parse_query(query: String) -> ParsedQuery {
+    let (query, boosts) = parse_query_boosts(&query);
+    let re = Regex::new(r#""(.*?)""#).unwrap();
+    let quote_words: Vec<String> = re
+        .captures_iter(&query.replace('\\', ""))
+        .map(|capture| capture[1].to_string())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<String>>();
+
+    let quote_words = if quote_words.is_empty() {
+        None
+    } else {
+        Some(quote_words)
+    };
+
+    let negated_words: Vec<String> = query
+        .split_whitespace()
+        .filter(|word| word.starts_with('-'))
+        .map(|word| word.strip_prefix('-').unwrap().to_string())
+        .collect::<Vec<String>>();
+
+    let negated_words = if negated_words.is_empty() {
+        None
+    } else {
+        Some(negated_words)
+    };
+
+    ParsedQuery {
+        query,
+        quote_words,
         negated_words,
+        unaccented: false,
+        prefix: None,
+        boosts,
+        splade_model: None,
     }
 }
 
@@ -789,34 +1712,532 @@ fn parse_query(query: String) -> ParsedQuery {
     tag = "chunk",
     request_body(content = SearchChunkData, description = "JSON request payload to semantically search for chunks (chunks)", content_type = "application/json"),
     responses(
-        (status = 200, description = "chunks which are similar to the embedding vector of the search query", body = SearchChunkQueryResponseBody),
+        (status = 200, description = "chunks which are similar to the embedding vector of the search query. If group_by is specified, the body will be a GroupedSearchChunkQueryResponseBody instead of a SearchChunkQueryResponseBody", body = SearchChunkResponseTypes),
         (status = 400, description = "Service error relating to searching", body = DefaultError),
+        (status = 403, description = "The page is past the deepest page the organization's plan allows searches to fetch", body = SearchPageLimitExceeded),
     ),
 )]
-#[allow(clippy::too_many_arguments)]
 pub async fn search_chunk(
-    data: web::Json<SearchChunkData>,
-    _user: LoggedUser,
+    mut data: web::Json<SearchChunkData>,
+    user: LoggedUser,
     pool: web::Data<Pool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let page = data.page.unwrap_or(1);
     let dataset_id = dataset_org_plan_sub.dataset.id;
-    let parsed_query = parse_query(data.query.clone());
+    check_search_page(data.page.unwrap_or(1), &dataset_org_plan_sub)?;
+    let dataset_config = ServerDatasetConfiguration::from_json(
+        dataset_org_plan_sub.dataset.server_configuration.clone(),
+    );
+    let search_analytics_enabled = dataset_config.SEARCH_ANALYTICS_ENABLED.unwrap_or(false);
+    let query = data.query.primary();
+    let search_type = resolve_search_type(&data.search_type, &dataset_config)?;
+    let search_started_at = std::time::Instant::now();
+    let analytics_pool = pool.clone();
+    let group_by = data.group_by.clone();
+    let include_relevance_percent = data.include_relevance_percent.unwrap_or(false);
+    let include_embedding_model = data.include_embedding_model.unwrap_or(false);
+    let include_neighbors = data.include_neighbors.unwrap_or(0);
+    if include_neighbors > MAX_NEIGHBORS {
+        return Err(ServiceError::BadRequest(format!(
+            "include_neighbors can be at most {}",
+            MAX_NEIGHBORS
+        ))
+        .into());
+    }
+    let suggest_on_empty = data.suggest_on_empty.unwrap_or(false);
+    let relax_filters_on_empty = data.relax_filters_on_empty.unwrap_or(false);
 
-    let result_chunks = match data.search_type.as_str() {
-        "fulltext" => search_full_text_chunks(data, parsed_query, page, pool, dataset_id).await?,
-        "hybrid" => {
-            search_hybrid_chunks(data, parsed_query, page, pool, dataset_org_plan_sub.dataset)
+    let filter_keys = match &data.filters {
+        Some(serde_json::Value::Object(obj)) => obj.keys().cloned().collect::<Vec<String>>(),
+        _ => vec![],
+    };
+    let missing_filter_keys = if filter_keys.is_empty() {
+        None
+    } else {
+        let missing_keys_pool = pool.clone();
+        Some(
+            web::block(move || {
+                get_missing_metadata_keys_query(filter_keys, dataset_id, missing_keys_pool)
+            })
+            .await?
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?,
+        )
+    };
+    if data.ignore_missing_filter_keys.unwrap_or(false) {
+        if let (Some(serde_json::Value::Object(obj)), Some(missing_filter_keys)) =
+            (data.filters.as_mut(), missing_filter_keys.as_ref())
+        {
+            obj.retain(|key, _| !missing_filter_keys.contains(key));
+        }
+    }
+
+    let relaxed_data = if relax_filters_on_empty && !query.trim().is_empty() {
+        Some(SearchChunkData {
+            link: None,
+            tag_set: None,
+            time_range: None,
+            created_range: None,
+            updated_range: None,
+            filters: None,
+            ..data.clone()
+        })
+    } else {
+        None
+    };
+
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let neighbors_acl_groups = acl_groups.clone();
+    let neighbors_pool = pool.clone();
+
+    // Taken before searching so chunks created while the search runs are caught by the next poll
+    let server_time = chrono::Utc::now().naive_utc();
+    let mut result_chunks = search_dataset_chunks(
+        data,
+        pool.clone(),
+        dataset_org_plan_sub.dataset.clone(),
+        acl_groups.clone(),
+    )
+    .await?;
+
+    if relax_filters_on_empty {
+        result_chunks.filters_relaxed = Some(false);
+    }
+
+    if result_chunks.score_chunks.is_empty() {
+        if let Some(relaxed_data) = relaxed_data {
+            result_chunks = search_dataset_chunks(
+                web::Json(relaxed_data),
+                pool.clone(),
+                dataset_org_plan_sub.dataset.clone(),
+                acl_groups,
+            )
+            .await?;
+            result_chunks.filters_relaxed = Some(true);
+        }
+    }
+
+    if suggest_on_empty && result_chunks.score_chunks.is_empty() && !query.trim().is_empty() {
+        let suggestion_query = query.clone();
+        let did_you_mean =
+            web::block(move || get_did_you_mean_query(suggestion_query, dataset_id, pool))
                 .await?
+                .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+        result_chunks.did_you_mean = Some(did_you_mean);
+    }
+
+    result_chunks.missing_filter_keys = missing_filter_keys;
+    result_chunks.server_time = Some(server_time);
+
+    let query_id = uuid::Uuid::new_v4();
+    result_chunks.query_id = Some(query_id);
+
+    add_result_ids(&mut result_chunks.score_chunks, &query, result_chunks.page);
+
+    if include_relevance_percent {
+        add_relevance_percents(&mut result_chunks.score_chunks);
+    }
+
+    if include_embedding_model {
+        add_embedding_models(&mut result_chunks.score_chunks).await?;
+    }
+
+    if include_neighbors > 0 {
+        add_neighbor_chunks(
+            &mut result_chunks.score_chunks,
+            include_neighbors as usize,
+            dataset_id,
+            neighbors_acl_groups,
+            neighbors_pool,
+        )
+        .await?;
+    }
+
+    if search_analytics_enabled {
+        let search_event = SearchEvent::from_details(
+            query_id,
+            dataset_id,
+            query,
+            search_type,
+            result_chunks.score_chunks.len() as i32,
+            result_chunks.score_chunks.first().map(|chunk| chunk.score),
+            search_started_at.elapsed().as_millis() as i32,
+            Some(anonymize_user_id(user.id)),
+        );
+
+        actix_web::rt::spawn(async move {
+            let result =
+                web::block(move || create_search_event_query(search_event, analytics_pool)).await;
+
+            if let Ok(Err(err)) = result {
+                log::error!("Failed to log search event: {}", err.message);
+            }
+        });
+    }
+
+    if let Some(group_by) = group_by {
+        return Ok(
+            HttpResponse::Ok().json(SearchChunkResponseTypes::GroupedChunks(
+                GroupedSearchChunkQueryResponseBody::from_results(result_chunks, &group_by),
+            )),
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(SearchChunkResponseTypes::Chunks(result_chunks)))
+}
+
+pub const MAX_WARM_QUERIES: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct WarmQueriesData {
+    /// The queries to embed ahead of time, written the same way they will be searched for. Boosts are stripped like they are for search. At most 100 queries can be warmed at once.
+    pub queries: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct WarmQueriesResponse {
+    /// The number of queries which were embedded and added to the cache.
+    pub warmed: usize,
+    /// The number of queries which were already cached and were skipped.
+    pub already_cached: usize,
+}
+
+/// warm_queries
+///
+/// Embed a list of search queries ahead of time so that semantic and hybrid searches for them skip the call to the embedding model. This is useful for the popular queries of a high-traffic search box. Query embeddings are cached in redis for QUERY_EMBEDDING_CACHE_TTL_SECS (a day by default) whether they were warmed or embedded by a search, and warming a query which is already cached does not extend its expiry. Duplicate queries are only embedded once.
+#[utoipa::path(
+    post,
+    path = "/chunk/warm_queries",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = WarmQueriesData, description = "JSON request payload with the queries to embed ahead of time", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The number of queries which were warmed and which were already cached", body = WarmQueriesResponse),
+        (status = 400, description = "Service error relating to embedding the queries", body = DefaultError),
+    ),
+)]
+pub async fn warm_queries(
+    data: web::Json<WarmQueriesData>,
+    _user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data.queries.is_empty() || data.queries.len() > MAX_WARM_QUERIES {
+        return Err(ServiceError::BadRequest(format!(
+            "queries must contain between 1 and {} queries",
+            MAX_WARM_QUERIES
+        ))
+        .into());
+    }
+
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    let queries = data
+        .queries
+        .iter()
+        .map(|query| parse_query_boosts(query).0)
+        .filter(|query| !query.trim().is_empty())
+        .unique()
+        .collect::<Vec<String>>();
+
+    let warmed_queries = futures::future::try_join_all(
+        queries
+            .iter()
+            .map(|query| warm_query_embedding(query, dataset_config.clone())),
+    )
+    .await?;
+
+    let warmed = warmed_queries.iter().filter(|warmed| **warmed).count();
+    Ok(HttpResponse::Ok().json(WarmQueriesResponse {
+        warmed,
+        already_cached: warmed_queries.len() - warmed,
+    }))
+}
+
+pub const SEARCH_TYPES: [&str; 4] = ["semantic", "fulltext", "hybrid", "image"];
+
+/// Falls back to the dataset's DEFAULT_SEARCH_TYPE when no search_type was given and rejects
+/// unknown search types rather than running a semantic search for them.
+pub fn resolve_search_type(
+    search_type: &str,
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<String, ServiceError> {
+    let search_type = if search_type.trim().is_empty() {
+        dataset_config
+            .DEFAULT_SEARCH_TYPE
+            .clone()
+            .unwrap_or("semantic".to_string())
+    } else {
+        search_type.to_string()
+    };
+
+    if !SEARCH_TYPES.contains(&search_type.as_str()) {
+        return Err(ServiceError::BadRequest(format!(
+            "Invalid search_type \"{}\". Must be one of {}",
+            search_type,
+            SEARCH_TYPES.join(", ")
+        )));
+    }
+
+    Ok(search_type)
+}
+
+/// The deepest page of results the organization's plan allows searches to fetch.
+pub fn get_max_search_page(dataset_org_plan_sub: &DatasetAndOrgWithSubAndPlan) -> u64 {
+    dataset_org_plan_sub
+        .organization
+        .plan
+        .as_ref()
+        .map_or(StripePlan::default().max_search_page, |plan| {
+            plan.max_search_page
+        })
+        .max(1) as u64
+}
+
+/// Rejects a search for a page past the max_search_page of the organization's plan with a 403
+/// carrying a SearchPageLimitExceeded body. Every route which pages through search results checks
+/// this so deep pages cannot be scraped through a route other than search.
+pub fn check_search_page(
+    page: u64,
+    dataset_org_plan_sub: &DatasetAndOrgWithSubAndPlan,
+) -> Result<(), actix_web::Error> {
+    if page <= get_max_search_page(dataset_org_plan_sub) {
+        return Ok(());
+    }
+
+    let plan = dataset_org_plan_sub
+        .organization
+        .plan
+        .clone()
+        .unwrap_or(StripePlan::default());
+    Err(actix_web::error::InternalError::from_response(
+        "Search page limit exceeded",
+        HttpResponse::Forbidden().json(SearchPageLimitExceeded {
+            message: "Must upgrade your plan to fetch deeper pages of search results".to_string(),
+            page,
+            max_search_page: plan.max_search_page,
+            organization_name: dataset_org_plan_sub.organization.name.clone(),
+            plan_name: plan.name,
+        }),
+    )
+    .into())
+}
+
+/// Runs a search over the dataset's chunks for the requested search_type. Shared by the search
+/// and search_and_generate routes so both retrieve results the same way.
+pub async fn search_dataset_chunks(
+    mut data: web::Json<SearchChunkData>,
+    pool: web::Data<Pool>,
+    dataset: Dataset,
+    acl_groups: Option<Vec<String>>,
+) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
+    let page = data.page.unwrap_or(1);
+    let dataset_id = dataset.id;
+
+    if let SearchQuery::Multiple(queries) = &data.query {
+        if queries.is_empty() || queries.len() > MAX_SEARCH_QUERIES {
+            return Err(ServiceError::BadRequest(format!(
+                "A list of queries must contain between 1 and {} queries",
+                MAX_SEARCH_QUERIES
+            ))
+            .into());
+        }
+    }
+
+    if let Some(aggregations) = &data.aggregations {
+        if aggregations.is_empty() || aggregations.len() > MAX_AGGREGATIONS {
+            return Err(ServiceError::BadRequest(format!(
+                "aggregations must contain between 1 and {} aggregations",
+                MAX_AGGREGATIONS
+            ))
+            .into());
+        }
+        if let Some(aggregation) = aggregations
+            .iter()
+            .find(|aggregation| !METADATA_AGGREGATION_OPS.contains(&aggregation.op.as_str()))
+        {
+            return Err(ServiceError::BadRequest(format!(
+                "Invalid aggregation op \"{}\", must be one of min, max, avg or sum",
+                aggregation.op
+            ))
+            .into());
+        }
+    }
+
+    if let Some(diversity) = data.diversity {
+        if !(0.0..=1.0).contains(&diversity) {
+            return Err(
+                ServiceError::BadRequest("diversity must be between 0 and 1".into()).into(),
+            );
+        }
+    }
+
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset.server_configuration.clone());
+    data.search_type = resolve_search_type(&data.search_type, &dataset_config)?;
+
+    if let Some(distance_metric) = &data.distance_metric {
+        if matches!(data.search_type.as_str(), "semantic" | "hybrid") {
+            validate_distance_metric(distance_metric, &dataset_config).await?;
         }
+    }
+
+    let sort_by_recency = match data.sort.as_deref().unwrap_or("relevance") {
+        "relevance" => false,
+        "recency" => true,
         _ => {
-            search_semantic_chunks(data, parsed_query, page, pool, dataset_org_plan_sub.dataset)
-                .await?
+            return Err(ServiceError::BadRequest(
+                "sort must be either \"relevance\" or \"recency\"".into(),
+            )
+            .into())
         }
     };
 
-    Ok(HttpResponse::Ok().json(result_chunks))
+    let mut parsed_query = parse_query(data.query.primary());
+    parsed_query.splade_model = dataset_config.SPLADE_MODEL.clone();
+    if data.prefix_match.unwrap_or(false) && data.search_type == "fulltext" {
+        parsed_query.prefix = get_prefix_word(&parsed_query.query);
+    }
+    if data.require_all_terms.unwrap_or(false) && data.search_type == "fulltext" {
+        parsed_query = parsed_query.require_loose_words();
+    }
+    if sort_by_recency {
+        parsed_query = parsed_query.into_recency_filter();
+    }
+    if dataset_config.FULLTEXT_UNACCENT.unwrap_or(false) {
+        parsed_query = parsed_query.unaccent();
+    }
+    if let Some(language) = dataset_config.FULLTEXT_STEMMING_LANGUAGE {
+        parsed_query = parsed_query.stem(&language)?;
+    }
+
+    let is_browse = data.query.primary().trim().is_empty();
+    if is_browse
+        && data.time_range.is_none()
+        && data.created_range.is_none()
+        && data.updated_range.is_none()
+        && data.since.is_none()
+        && data.filters.is_none()
+        && data.tag_set.is_none()
+        && data.link.is_none()
+    {
+        return Err(ServiceError::BadRequest(
+            "Query must not be empty unless a time_range, created_range, updated_range, since, filters, tag_set or link is provided"
+                .into(),
+        )
+        .into());
+    }
+
+    let semantic_highlights = match data.highlight_strategy.as_deref().unwrap_or("lexical") {
+        "lexical" => false,
+        "semantic" => !is_browse,
+        _ => {
+            return Err(ServiceError::BadRequest(
+                "highlight_strategy must be either \"lexical\" or \"semantic\"".into(),
+            )
+            .into())
+        }
+    };
+    let highlight_query = parse_query_boosts(&data.query.primary()).0;
+    let tag_set = data.tag_set.clone();
+    let highlight_max_num = data.highlight_max_num;
+    let highlight_window = data.highlight_window;
+    let include_query_vector = data.include_query_vector.unwrap_or(false);
+    let boost_field = data
+        .boost_field
+        .clone()
+        .filter(|_| !is_browse && !sort_by_recency);
+    let diversity = data
+        .diversity
+        .filter(|diversity| *diversity > 0.0 && !is_browse && !sort_by_recency);
+    let diversity_vector_name = match data.search_type.as_str() {
+        "image" => CLIP_VECTOR_NAME.to_string(),
+        _ => format!("{}_vectors", dataset_config.EMBEDDING_SIZE.unwrap_or(1536)),
+    };
+
+    let aggregations = match data.aggregations.clone() {
+        Some(aggregations) => Some(
+            get_metadata_aggregations_query(
+                aggregations,
+                &data,
+                &parsed_query,
+                acl_groups.clone(),
+                dataset_id,
+                pool.clone(),
+            )
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?,
+        ),
+        None => None,
+    };
+
+    let filter_explanation = match data.explain_chunk_id {
+        Some(explain_chunk_id) => explain_chunk_filters_query(
+            explain_chunk_id,
+            &data,
+            &parsed_query,
+            acl_groups.clone(),
+            dataset_id,
+            pool.clone(),
+        )
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?,
+        None => None,
+    };
+    let explain_chunk_id = data.explain_chunk_id;
+
+    let mut result_chunks = match data.search_type.as_str() {
+        _ if is_browse || sort_by_recency => {
+            browse_chunks(data, parsed_query, page, pool, dataset_id, acl_groups).await?
+        }
+        "image" => search_image_chunks(data, parsed_query, page, pool, dataset, acl_groups).await?,
+        "fulltext" => {
+            search_full_text_chunks(data, parsed_query, page, pool, dataset_id, acl_groups).await?
+        }
+        "hybrid" => {
+            search_hybrid_chunks(data, parsed_query, page, pool, dataset, acl_groups).await?
+        }
+        _ => search_semantic_chunks(data, parsed_query, page, pool, dataset, acl_groups).await?,
+    };
+
+    if let Some(boost_field) = boost_field {
+        result_chunks.score_chunks =
+            boost_by_metadata_field(result_chunks.score_chunks, &boost_field);
+    }
+    if let Some(diversity) = diversity {
+        result_chunks.score_chunks = diversify_score_chunks(
+            result_chunks.score_chunks,
+            diversity,
+            &diversity_vector_name,
+        )
+        .await?;
+    }
+    add_matched_tags(&mut result_chunks.score_chunks, &tag_set);
+    result_chunks.aggregations = aggregations;
+    if let Some(explain_chunk_id) = explain_chunk_id {
+        result_chunks.explanation = Some(filter_explanation.unwrap_or_else(|| {
+            explain_chunk_ranking(explain_chunk_id, &result_chunks.score_chunks)
+        }));
+    }
+
+    if semantic_highlights {
+        // Semantic and hybrid searches return the vector they searched with so it is not embedded again
+        let query_vector = if include_query_vector {
+            result_chunks.query_vector.clone()
+        } else {
+            result_chunks.query_vector.take()
+        };
+        add_semantic_highlights(
+            &mut result_chunks.score_chunks,
+            query_vector,
+            &highlight_query,
+            highlight_max_num,
+            highlight_window,
+            &dataset_config,
+        )
+        .await?;
+    }
+
+    Ok(result_chunks)
 }
 
 #[derive(Serialize, Deserialize, Clone, ToSchema, IntoParams)]
@@ -824,7 +2245,7 @@ pub async fn search_chunk(
 pub struct SearchCollectionsData {
     /// The query is the search query. This can be any string. The query will be used to create an embedding vector and/or SPLADE vector which will be used to find the result set.
     pub query: String,
-    /// The page of chunks to fetch. Each page is 10 chunks. Support for custom page size is coming soon.
+    /// The page of chunks to fetch. Each page is 10 chunks. Support for custom page size is coming soon. Pages past the max_search_page of the organization's plan, 10 on the free plan, are rejected with a 403.
     pub page: Option<u64>,
     /// The link set is a comma separated list of links. This can be used to filter chunks by link. HNSW indices do not exist for links, so there is a performance hit for filtering on them.
     pub link: Option<Vec<String>>,
@@ -832,8 +2253,12 @@ pub struct SearchCollectionsData {
     pub tag_set: Option<Vec<String>>,
     /// Filters is a JSON object which can be used to filter chunks. The values on each key in the object will be used to check for an exact substring match on the metadata values for each existing chunk. This is useful for when you want to filter chunks by arbitrary metadata. Unlike with tag filtering, there is a performance hit for filtering on metadata.
     pub filters: Option<serde_json::Value>,
-    /// Collection_id specifies the collection to search within. Results will only consist of chunks which are bookmarks within the specified collection.
-    pub collection_id: uuid::Uuid,
+    /// Metadata filters are matched case-insensitively by default. Set case_sensitive_filters to true to only match values with the same casing.
+    pub case_sensitive_filters: Option<bool>,
+    /// Collection_id specifies the collection to search within. Results will only consist of chunks which are bookmarks within the specified collection. Either collection_id or collection_ids must be provided.
+    pub collection_id: Option<uuid::Uuid>,
+    /// Collection_ids specifies several collections to search within at once. Results will consist of chunks which are bookmarks within any of the collections, merged by score. If collection_id is also provided, it is searched along with these. At most 100 collections can be searched at once.
+    pub collection_ids: Option<Vec<uuid::Uuid>>,
     #[param(inline)]
     /// Search_type can be either "semantic", "fulltext", or "hybrid". "hybrid" will pull in one page (10 chunks) of both semantic and full-text results then re-rank them using BAAI/bge-reranker-large. "semantic" will pull in one page (10 chunks) of the nearest cosine distant vectors. "fulltext" will pull in one page (10 chunks) of full-text results based on SPLADE.
     pub search_type: String,
@@ -844,30 +2269,46 @@ pub struct SearchCollectionsData {
 impl From<SearchCollectionsData> for SearchChunkData {
     fn from(data: SearchCollectionsData) -> Self {
         Self {
-            query: data.query,
+            query: SearchQuery::Single(data.query),
             page: data.page,
             link: data.link,
             tag_set: data.tag_set,
             time_range: None,
+            created_range: None,
+            updated_range: None,
             filters: data.filters,
-            cross_encoder: None,
-            weights: None,
+            case_sensitive_filters: data.case_sensitive_filters,
             search_type: data.search_type,
             date_bias: data.date_bias,
+            ..Default::default()
         }
     }
 }
 
+impl SearchCollectionsData {
+    /// The collection_id and collection_ids of the request combined, without duplicates and in the order they were given.
+    pub fn get_collection_ids(&self) -> Vec<uuid::Uuid> {
+        self.collection_id
+            .into_iter()
+            .chain(self.collection_ids.clone().unwrap_or_default())
+            .unique()
+            .collect()
+    }
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct SearchCollectionsResult {
     pub bookmarks: Vec<ScoreChunkDTO>,
+    /// The first collection which was searched. Kept for requests which only search a single collection_id.
     pub collection: ChunkCollection,
+    /// All of the collections which were searched.
+    pub collections: Vec<ChunkCollection>,
     pub total_pages: i64,
 }
 
 /// collection_search
 ///
-/// This route allows you to search only within a collection. This is useful for when you only want search results to contain chunks which are members of a specific group. Think about this like searching within a playlist or bookmark folder.
+/// This route allows you to search only within a collection. This is useful for when you only want search results to contain chunks which are members of a specific group. Think about this like searching within a playlist or bookmark folder. Several collections can be searched at once with collection_ids, in which case each result lists the collections it is bookmarked in.
 #[utoipa::path(
     post,
     path = "/chunk_collection/search",
@@ -877,41 +2318,75 @@ pub struct SearchCollectionsResult {
     responses(
         (status = 200, description = "Collection chunks which are similar to the embedding vector of the search query", body = SearchCollectionsResult),
         (status = 400, description = "Service error relating to getting the collections that the chunk is in", body = DefaultError),
+        (status = 403, description = "The page is past the deepest page the organization's plan allows searches to fetch", body = SearchPageLimitExceeded),
     ),
 )]
 #[allow(clippy::too_many_arguments)]
 pub async fn search_collections(
     data: web::Json<SearchCollectionsData>,
     pool: web::Data<Pool>,
-    _required_user: LoggedUser,
+    required_user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
     //search over the links as well
     let page = data.page.unwrap_or(1);
-    let collection_id = data.collection_id;
+    check_search_page(page, &dataset_org_plan_sub)?;
+    let acl_groups = get_acl_groups(&required_user, &dataset_org_plan_sub.dataset);
+    let collection_ids = data.get_collection_ids();
+    if collection_ids.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Either collection_id or collection_ids must be provided".into(),
+        )
+        .into());
+    }
+    if collection_ids.len() > 100 {
+        return Err(ServiceError::BadRequest(
+            "At most 100 collections can be searched at once".into(),
+        )
+        .into());
+    }
     let dataset_id = dataset_org_plan_sub.dataset.id;
     let full_text_search_pool: web::Data<
         r2d2::Pool<diesel::r2d2::ConnectionManager<diesel::prelude::PgConnection>>,
     > = pool.clone();
-
-    let collection = {
-        web::block(move || get_collection_by_id_query(collection_id, dataset_id, pool))
-            .await
-            .map_err(|err| ServiceError::BadRequest(err.to_string()))?
-            .map_err(|err| ServiceError::BadRequest(err.message.into()))?
+    let bookmarks_pool = pool.clone();
+
+    let collections = {
+        web::block(move || {
+            collection_ids
+                .into_iter()
+                .map(|collection_id| {
+                    get_collection_by_id_query(collection_id, dataset_id, pool.clone())
+                })
+                .collect::<Result<Vec<ChunkCollection>, DefaultError>>()
+        })
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?
     };
 
-    let parsed_query = parse_query(data.query.clone());
+    let dataset_config = ServerDatasetConfiguration::from_json(
+        dataset_org_plan_sub.dataset.server_configuration.clone(),
+    );
+    let mut parsed_query = parse_query(data.query.clone());
+    parsed_query.splade_model = dataset_config.SPLADE_MODEL.clone();
+    if dataset_config.FULLTEXT_UNACCENT.unwrap_or(false) {
+        parsed_query = parsed_query.unaccent();
+    }
+    if let Some(language) = dataset_config.FULLTEXT_STEMMING_LANGUAGE {
+        parsed_query = parsed_query.stem(&language)?;
+    }
 
-    let result_chunks = match data.search_type.as_str() {
+    let mut result_chunks = match data.search_type.as_str() {
         "fulltext" => {
             search_full_text_collections(
                 data,
                 parsed_query,
-                collection,
+                collections,
                 page,
                 full_text_search_pool,
                 dataset_id,
+                acl_groups,
             )
             .await?
         }
@@ -919,17 +2394,50 @@ pub async fn search_collections(
             search_semantic_collections(
                 data,
                 parsed_query,
-                collection,
+                collections,
                 page,
                 full_text_search_pool,
                 dataset_org_plan_sub.dataset,
+                acl_groups,
             )
             .await?
         }
     };
 
-    Ok(HttpResponse::Ok().json(result_chunks))
-}
+    let chunk_ids = result_chunks
+        .bookmarks
+        .iter()
+        .flat_map(|score_chunk| score_chunk.metadata.iter().map(|chunk| chunk.id))
+        .collect::<Vec<uuid::Uuid>>();
+    let searched_collection_ids = result_chunks
+        .collections
+        .iter()
+        .map(|collection| collection.id)
+        .collect::<Vec<uuid::Uuid>>();
+    let bookmark_collection_ids = web::block(move || {
+        get_bookmark_collection_ids_query(chunk_ids, searched_collection_ids, bookmarks_pool)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    for score_chunk in result_chunks.bookmarks.iter_mut() {
+        let chunk_ids = score_chunk
+            .metadata
+            .iter()
+            .map(|chunk| chunk.id)
+            .collect::<Vec<uuid::Uuid>>();
+        score_chunk.collection_ids = Some(
+            bookmark_collection_ids
+                .iter()
+                .filter(|(chunk_id, _)| chunk_ids.contains(chunk_id))
+                .map(|(_, collection_id)| *collection_id)
+                .unique()
+                .collect(),
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(result_chunks))
+}
 
 /// get_chunk
 ///
@@ -949,19 +2457,315 @@ pub async fn search_collections(
 )]
 pub async fn get_chunk_by_id(
     chunk_id: web::Path<uuid::Uuid>,
-    _user: LoggedUser,
+    user: LoggedUser,
     pool: web::Data<Pool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let acl_pool = pool.clone();
     let chunk = web::block(move || {
         get_metadata_from_id_query(chunk_id.into_inner(), dataset_org_plan_sub.dataset.id, pool)
     })
     .await?
     .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    check_chunk_acl(chunk.id, acl_groups, acl_pool).await?;
 
     Ok(HttpResponse::Ok().json(chunk))
 }
 
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollisionCountResponse {
+    pub chunk_id: uuid::Uuid,
+    pub collision_count: i64,
+}
+
+/// get_collision_count
+///
+/// Get the number of collisions (duplicates) in the collision group of a chunk. If the chunk is itself a collision, the collisions of the chunk it collided with are counted. Use this to see how close a chunk is to the dataset's MAX_COLLISIONS_PER_CHUNK.
+#[utoipa::path(
+    get,
+    path = "/chunk/{chunk_id}/collision_count",
+    context_path = "/api",
+    tag = "chunk",
+    responses(
+        (status = 200, description = "The number of collisions in the chunk's collision group", body = CollisionCountResponse),
+        (status = 400, description = "Service error relating to counting the collisions of the chunk", body = DefaultError),
+    ),
+    params(
+        ("chunk_id" = uuid, Path, description = "Id of the chunk to count the collisions of")
+    ),
+)]
+pub async fn get_collision_count(
+    chunk_id: web::Path<uuid::Uuid>,
+    _user: LoggedUser,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let chunk_id = chunk_id.into_inner();
+
+    let collision_count = web::block(move || {
+        get_metadata_from_id_query(chunk_id, dataset_org_plan_sub.dataset.id, pool.clone())?;
+        let qdrant_point_id = get_qdrant_id_from_chunk_id_query(chunk_id, pool.clone())?;
+        get_collision_count_query(qdrant_point_id, pool)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(CollisionCountResponse {
+        chunk_id,
+        collision_count,
+    }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollisionGroupResponse {
+    /// The chunk which owns the collision group's qdrant point and is returned by searches.
+    pub root: ChunkMetadataWithFileData,
+    /// The collisions (duplicates) linked to the root chunk, oldest first.
+    pub collisions: Vec<ChunkMetadataWithFileData>,
+}
+
+/// get_chunk_collisions
+///
+/// Get the root chunk of a chunk's collision group and all of the collisions (duplicates) linked to it, each with its author and creation time. If the chunk is itself a collision, the group of the chunk it collided with is returned. Use this to audit how chunks were deduplicated and to find duplicates to clean up.
+#[utoipa::path(
+    get,
+    path = "/chunk/{chunk_id}/collisions",
+    context_path = "/api",
+    tag = "chunk",
+    responses(
+        (status = 200, description = "The root chunk and the collisions of the chunk's collision group", body = CollisionGroupResponse),
+        (status = 400, description = "Service error relating to getting the collisions of the chunk", body = DefaultError),
+    ),
+    params(
+        ("chunk_id" = uuid, Path, description = "Id of the chunk to get the collision group of")
+    ),
+)]
+pub async fn get_chunk_collisions(
+    chunk_id: web::Path<uuid::Uuid>,
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let chunk_id = chunk_id.into_inner();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    // Every chunk of a collision group shares the root chunk's qdrant point and so its acl
+    check_chunk_acl(
+        chunk_id,
+        get_acl_groups(&user, &dataset_org_plan_sub.dataset),
+        pool.clone(),
+    )
+    .await?;
+
+    let (root, collisions) = web::block(move || {
+        get_metadata_from_id_query(chunk_id, dataset_id, pool.clone())?;
+        let qdrant_point_id = get_qdrant_id_from_chunk_id_query(chunk_id, pool.clone())?;
+        get_collision_group_query(qdrant_point_id, dataset_id, pool)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(CollisionGroupResponse { root, collisions }))
+}
+
+/// get_chunk_history
+///
+/// Get the previous versions of a chunk, newest first. A version is recorded each time the chunk's content, chunk_html, link, tag_set or metadata is updated and only the most recent 20 versions are kept.
+#[utoipa::path(
+    get,
+    path = "/chunk/{chunk_id}/history",
+    context_path = "/api",
+    tag = "chunk",
+    responses(
+        (status = 200, description = "The previous versions of the chunk, newest first", body = Vec<ChunkVersion>),
+        (status = 400, description = "Service error relating to getting the history of the chunk", body = DefaultError),
+    ),
+    params(
+        ("chunk_id" = uuid, Path, description = "Id of the chunk to get the history of")
+    ),
+)]
+pub async fn get_chunk_history(
+    chunk_id: web::Path<uuid::Uuid>,
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let chunk_id = chunk_id.into_inner();
+    check_chunk_acl(
+        chunk_id,
+        get_acl_groups(&user, &dataset_org_plan_sub.dataset),
+        pool.clone(),
+    )
+    .await?;
+
+    let versions = web::block(move || {
+        get_metadata_from_id_query(chunk_id, dataset_org_plan_sub.dataset.id, pool.clone())?;
+        get_chunk_versions_query(chunk_id, dataset_org_plan_sub.dataset.id, pool)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct ChunkDiffQuery {
+    /// The version of the chunk to diff from.
+    pub from: i32,
+    /// The version of the chunk to diff to. If not specified, the diff is against the current chunk.
+    pub to: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ChunkDiffChange {
+    /// One of "equal", "insert" or "delete".
+    pub tag: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ChunkDiffResponse {
+    pub chunk_id: uuid::Uuid,
+    pub from: i32,
+    /// The version diffed to, or null if the diff is against the current chunk.
+    pub to: Option<i32>,
+    /// Word level changes to the content of the chunk. Concatenating the values of the equal and delete changes gives the old content and the equal and insert changes gives the new content.
+    pub changes: Vec<ChunkDiffChange>,
+    pub metadata_changed: bool,
+    pub tag_set_changed: bool,
+    pub link_changed: bool,
+}
+
+/// get_chunk_diff
+///
+/// Get the text diff between two versions of a chunk. Versions come from the chunk's history and the diff defaults to being against the current chunk when `to` is not specified.
+#[utoipa::path(
+    get,
+    path = "/chunk/{chunk_id}/diff",
+    context_path = "/api",
+    tag = "chunk",
+    responses(
+        (status = 200, description = "The diff between the two versions of the chunk", body = ChunkDiffResponse),
+        (status = 400, description = "Service error relating to diffing the versions of the chunk", body = DefaultError),
+    ),
+    params(
+        ("chunk_id" = uuid, Path, description = "Id of the chunk to diff the versions of"),
+        ChunkDiffQuery,
+    ),
+)]
+pub async fn get_chunk_diff(
+    chunk_id: web::Path<uuid::Uuid>,
+    query: web::Query<ChunkDiffQuery>,
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let chunk_id = chunk_id.into_inner();
+    check_chunk_acl(
+        chunk_id,
+        get_acl_groups(&user, &dataset_org_plan_sub.dataset),
+        pool.clone(),
+    )
+    .await?;
+    let query = query.into_inner();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+
+    let (from, to) = web::block(move || {
+        let from = get_chunk_version_query(chunk_id, query.from, dataset_id, pool.clone())?;
+        let to = match query.to {
+            Some(to) => get_chunk_version_query(chunk_id, to, dataset_id, pool)?,
+            None => {
+                let chunk = get_metadata_from_id_query(chunk_id, dataset_id, pool)?;
+                ChunkVersion::from_chunk(&chunk, 0)
+            }
+        };
+        Ok::<(ChunkVersion, ChunkVersion), DefaultError>((from, to))
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(ChunkDiffResponse {
+        chunk_id,
+        from: from.version,
+        to: query.to,
+        changes: diff_chunk_content(&from.content, &to.content),
+        metadata_changed: from.metadata != to.metadata,
+        tag_set_changed: from.tag_set != to.tag_set,
+        link_changed: from.link != to.link,
+    }))
+}
+
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct ChunkKeywordsQuery {
+    /// The number of keywords to return. Defaults to 10 and can be at most 50.
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ChunkKeyword {
+    /// The keyword, lowercased.
+    pub term: String,
+    /// The weight of the keyword in the SPLADE vector of the chunk's content, how strongly the full-text model associates it with the chunk. Only comparable between keywords of the same chunk.
+    pub score: f64,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ChunkKeywordsResponse {
+    pub chunk_id: uuid::Uuid,
+    /// The keywords of the chunk, highest scoring first.
+    pub keywords: Vec<ChunkKeyword>,
+}
+
+/// get_chunk_keywords
+///
+/// Get the most salient terms of a chunk's content, ranked by their weight in the SPLADE vector the dataset's full-text model produces for it. These make good suggestions for the chunk's tag_set or for related searches, and can include closely related terms which do not appear in the content. Terms shorter than 3 characters and numbers are never returned.
+#[utoipa::path(
+    get,
+    path = "/chunk/{chunk_id}/keywords",
+    context_path = "/api",
+    tag = "chunk",
+    responses(
+        (status = 200, description = "The keywords of the chunk, highest scoring first", body = ChunkKeywordsResponse),
+        (status = 400, description = "Service error relating to getting the keywords of the chunk", body = DefaultError),
+    ),
+    params(
+        ("chunk_id" = uuid, Path, description = "Id of the chunk to get the keywords of"),
+        ChunkKeywordsQuery,
+    ),
+)]
+pub async fn get_chunk_keywords(
+    chunk_id: web::Path<uuid::Uuid>,
+    query: web::Query<ChunkKeywordsQuery>,
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let chunk_id = chunk_id.into_inner();
+    let limit = query.limit.unwrap_or(10);
+    if !(1..=50).contains(&limit) {
+        return Err(ServiceError::BadRequest("limit must be between 1 and 50".into()).into());
+    }
+    check_chunk_acl(
+        chunk_id,
+        get_acl_groups(&user, &dataset_org_plan_sub.dataset),
+        pool.clone(),
+    )
+    .await?;
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+
+    let chunk = web::block(move || get_metadata_from_id_query(chunk_id, dataset_id, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    let keywords =
+        get_chunk_keywords_query(chunk.content, dataset_config.SPLADE_MODEL, limit).await?;
+
+    Ok(HttpResponse::Ok().json(ChunkKeywordsResponse { chunk_id, keywords }))
+}
+
 /// get_chunk_by_tracking_id
 ///
 /// Get a singular chunk by tracking_id. This is useful for when you are coordinating with an external system and want to use your own id as the primary reference for a chunk.
@@ -980,11 +2784,13 @@ pub async fn get_chunk_by_id(
 )]
 pub async fn get_chunk_by_tracking_id(
     tracking_id: web::Path<String>,
-    _user: LoggedUser,
+    user: LoggedUser,
     pool: web::Data<Pool>,
     _required_user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let acl_pool = pool.clone();
     let chunk = web::block(move || {
         get_metadata_from_tracking_id_query(
             tracking_id.into_inner(),
@@ -994,14 +2800,380 @@ pub async fn get_chunk_by_tracking_id(
     })
     .await?
     .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    check_chunk_acl(chunk.id, acl_groups, acl_pool).await?;
+
+    Ok(HttpResponse::Ok().json(chunk))
+}
+
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct ChunksForFileQuery {
+    /// The page of chunks to fetch. Defaults to 1.
+    pub page: Option<u64>,
+    /// The number of chunks to return per page. Defaults to 10 and can be at most 100.
+    pub page_size: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ChunksForFileResponse {
+    pub chunks: Vec<ChunkMetadata>,
+    pub total_pages: i64,
+}
+
+/// get_chunks_for_file
+///
+/// Get the chunks which were created from a file, in the order of their chunk_index. Chunks without a chunk_index come after the ones with one, in the order they were created. This is useful for rendering a viewer for the original document.
+#[utoipa::path(
+    get,
+    path = "/chunk/file/{file_id}",
+    context_path = "/api",
+    tag = "chunk",
+    responses(
+        (status = 200, description = "The page of chunks which were created from the file", body = ChunksForFileResponse),
+        (status = 400, description = "Service error relating to getting the chunks for the file", body = DefaultError),
+    ),
+    params(
+        ("file_id" = uuid, Path, description = "Id of the file to fetch the chunks for"),
+        ChunksForFileQuery,
+    ),
+)]
+pub async fn get_chunks_for_file(
+    file_id: web::Path<uuid::Uuid>,
+    query: web::Query<ChunksForFileQuery>,
+    user: LoggedUser,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let acl_pool = pool.clone();
+    let query = query.into_inner();
+    let page_size = query.page_size.unwrap_or(10);
+    if !(1..=100).contains(&page_size) {
+        return Err(ServiceError::BadRequest("page_size must be between 1 and 100".into()).into());
+    }
+
+    let (chunks, total_chunks) = web::block(move || {
+        get_chunks_for_file_query(
+            file_id.into_inner(),
+            query.page.unwrap_or(1),
+            page_size,
+            dataset_org_plan_sub.dataset.id,
+            pool,
+        )
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    let chunks = filter_chunks_by_acl(chunks, |chunk| chunk.id, acl_groups, acl_pool).await?;
+
+    Ok(HttpResponse::Ok().json(ChunksForFileResponse {
+        chunks,
+        total_pages: (total_chunks as f64 / page_size as f64).ceil() as i64,
+    }))
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct TrackingIdsExistData {
+    /// The tracking_ids to check for in the dataset. At most 1000 tracking_ids can be checked at once.
+    pub tracking_ids: Vec<String>,
+}
+
+pub const MAX_TRACKING_IDS_TO_CHECK: usize = 1000;
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct TrackingIdsExistResponse {
+    /// The requested tracking_ids which belong to a chunk in the dataset.
+    pub existing_tracking_ids: Vec<String>,
+    /// The requested tracking_ids which do not belong to any chunk in the dataset.
+    pub missing_tracking_ids: Vec<String>,
+}
+
+/// check_tracking_ids_exist
+///
+/// Check which of a list of tracking_ids already belong to chunks in the dataset. This is useful before a large import to decide whether each item should be created or updated without attempting the creates one by one.
+#[utoipa::path(
+    post,
+    path = "/chunk/tracking_ids/exists",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = TrackingIdsExistData, description = "JSON request payload listing the tracking_ids to check", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The tracking_ids split into those which exist in the dataset and those which do not", body = TrackingIdsExistResponse),
+        (status = 400, description = "Service error relating to checking the tracking_ids", body = DefaultError),
+    ),
+)]
+pub async fn check_tracking_ids_exist(
+    data: web::Json<TrackingIdsExistData>,
+    pool: web::Data<Pool>,
+    _user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data.tracking_ids.len() > MAX_TRACKING_IDS_TO_CHECK {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} tracking_ids can be checked at once",
+            MAX_TRACKING_IDS_TO_CHECK
+        ))
+        .into());
+    }
+    let tracking_ids = data.tracking_ids.clone();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+
+    let existing_tracking_ids =
+        web::block(move || get_existing_tracking_ids_query(tracking_ids, dataset_id, pool))
+            .await?
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let missing_tracking_ids = data
+        .tracking_ids
+        .iter()
+        .filter(|tracking_id| !existing_tracking_ids.contains(tracking_id))
+        .unique()
+        .cloned()
+        .collect();
+
+    Ok(HttpResponse::Ok().json(TrackingIdsExistResponse {
+        existing_tracking_ids,
+        missing_tracking_ids,
+    }))
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct DeleteTrackingIdsData {
+    /// The tracking_ids of the chunks to delete. At most 1000 tracking_ids can be deleted at once.
+    pub tracking_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct DeleteTrackingIdsResponse {
+    /// The requested tracking_ids whose chunks were deleted.
+    pub deleted_tracking_ids: Vec<String>,
+    /// The requested tracking_ids which do not belong to any chunk in the dataset.
+    pub missing_tracking_ids: Vec<String>,
+}
+
+pub const MAX_TRACKING_IDS_TO_DELETE: usize = 1000;
+const DELETE_TRACKING_IDS_BATCH_SIZE: usize = 100;
+
+/// delete_chunks_by_tracking_ids
+///
+/// Delete many chunks at once by their tracking_ids. This is useful for sync jobs with an external system which tracks deletions by its own ids. Chunks are deleted in batches of 100, each with a single delete, and as with the single tracking_id delete, if a root chunk which has a collision is deleted, the most recently created collision will become a new root chunk. If any of the chunks were not created by the requesting user, nothing is deleted.
+#[utoipa::path(
+    post,
+    path = "/chunk/delete/tracking_ids",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = DeleteTrackingIdsData, description = "JSON request payload listing the tracking_ids of the chunks to delete", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The tracking_ids split into those whose chunks were deleted and those which were not found", body = DeleteTrackingIdsResponse),
+        (status = 400, description = "Service error relating to deleting the chunks", body = DefaultError),
+    ),
+)]
+pub async fn delete_chunks_by_tracking_ids(
+    data: web::Json<DeleteTrackingIdsData>,
+    pool: web::Data<Pool>,
+    user: AdminOnly,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let tracking_ids = data
+        .into_inner()
+        .tracking_ids
+        .into_iter()
+        .unique()
+        .collect::<Vec<String>>();
+    if tracking_ids.len() > MAX_TRACKING_IDS_TO_DELETE {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} tracking_ids can be deleted at once",
+            MAX_TRACKING_IDS_TO_DELETE
+        ))
+        .into());
+    }
+
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    let lookup_pool = pool.clone();
+    let lookup_tracking_ids = tracking_ids.clone();
+    let chunks = web::block(move || {
+        get_metadata_from_tracking_ids_query(lookup_tracking_ids, dataset_id, lookup_pool)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    if chunks.iter().any(|chunk| chunk.author_id != user.0.id) {
+        return Err(ServiceError::Forbidden.into());
+    }
+
+    let mut deleted_tracking_ids = vec![];
+    for batch in chunks.chunks(DELETE_TRACKING_IDS_BATCH_SIZE) {
+        delete_chunk_metadata_batch_query(
+            batch.to_vec(),
+            dataset_org_plan_sub.dataset.clone(),
+            pool.clone(),
+        )
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+        deleted_tracking_ids.extend(batch.iter().filter_map(|chunk| chunk.tracking_id.clone()));
+    }
+
+    let missing_tracking_ids = tracking_ids
+        .into_iter()
+        .filter(|tracking_id| !deleted_tracking_ids.contains(tracking_id))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(DeleteTrackingIdsResponse {
+        deleted_tracking_ids,
+        missing_tracking_ids,
+    }))
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct RerankChunksRequest {
+    /// The query to rank the chunks against.
+    pub query: String,
+    /// The ids of the chunks to rerank. At most 100 chunks can be reranked at once.
+    pub chunk_ids: Vec<uuid::Uuid>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RerankChunksResponse {
+    /// The chunks ordered from most to least relevant to the query. Chunk ids which do not exist in the dataset are left out.
+    pub score_chunks: Vec<ScoreChunkDTO>,
+}
+
+/// rerank_chunks
+///
+/// Rerank a set of chunks against a query using the same cross-encoder as hybrid search, without performing any retrieval. This is useful when candidate chunks are generated outside of Trieve. The reranker model can be set with RERANKER_MODEL in the dataset's server configuration and must be cross-encoder/ms-marco-MiniLM-L-4-v2 (the default) or one of the embedding server's ALLOWED_RERANKER_MODELS. Each chunk's score is its relevance score from the reranker, or its reciprocal rank if the reranker does not return scores.
+#[utoipa::path(
+    post,
+    path = "/chunk/rerank",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = RerankChunksRequest, description = "JSON request payload with the query and chunks to rerank", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The chunks reordered by relevance to the query", body = RerankChunksResponse),
+        (status = 400, description = "Service error relating to reranking the chunks", body = DefaultError),
+    ),
+)]
+pub async fn rerank_chunk_ids(
+    data: web::Json<RerankChunksRequest>,
+    pool: web::Data<Pool>,
+    user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data.chunk_ids.len() > 100 {
+        return Err(
+            ServiceError::BadRequest("At most 100 chunks can be reranked at once".into()).into(),
+        );
+    }
+
+    let chunk_ids = data.chunk_ids.clone();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let acl_pool = pool.clone();
+    let chunks = web::block(move || get_metadata_from_ids_query(chunk_ids, dataset_id, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    let chunks = filter_chunks_by_acl(chunks, |chunk| chunk.id, acl_groups, acl_pool).await?;
+
+    let score_chunks = chunks
+        .into_iter()
+        .map(|chunk| ScoreChunkDTO::new(vec![chunk], 0.0))
+        .collect::<Vec<ScoreChunkDTO>>();
+
+    let mut score_chunks = cross_encoder(
+        data.query.clone(),
+        score_chunks,
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration)
+            .RERANKER_MODEL,
+    )
+    .await?;
+
+    if score_chunks.iter().all(|chunk| chunk.score == 0.0) {
+        score_chunks
+            .iter_mut()
+            .enumerate()
+            .for_each(|(rank, chunk)| chunk.score = 1.0 / (rank + 1) as f64);
+    }
+
+    Ok(HttpResponse::Ok().json(RerankChunksResponse { score_chunks }))
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct WeightedChunkExample {
+    /// The id of the chunk to use as an example.
+    pub chunk_id: uuid::Uuid,
+    /// How much the example should count relative to the other examples in the same array.
+    pub weight: f32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RecommendChunksRequest {
+    /// The ids of the chunks to be used as positive examples for the recommendation. The chunks in this array will be used to find similar chunks.
+    #[serde(default)]
+    pub positive_chunk_ids: Vec<uuid::Uuid>,
+    /// Weighted positive examples for the recommendation. If positive or negative is specified, the seed vectors are averaged by weight before searching instead of being weighted equally. Any positive_chunk_ids are included with a weight of 1.
+    pub positive: Option<Vec<WeightedChunkExample>>,
+    /// Weighted negative examples for the recommendation. Results will be pushed away from the weighted average of these chunks.
+    pub negative: Option<Vec<WeightedChunkExample>>,
+}
+
+fn recommend_error_to_service_error(err: DefaultError) -> ServiceError {
+    match qdrant_error_to_service_error(err) {
+        ServiceError::BadRequest(message) => {
+            ServiceError::BadRequest(format!("Could not get recommended chunks: {}", message))
+        }
+        err => err,
+    }
+}
+
+async fn get_recommended_qdrant_point_ids(
+    request: RecommendChunksRequest,
+    dataset_id: uuid::Uuid,
+    embed_size: usize,
+    acl_groups: Option<Vec<String>>,
+    pool: web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, actix_web::Error> {
+    if request.positive.is_none() && request.negative.is_none() {
+        return recommend_qdrant_query(
+            request.positive_chunk_ids,
+            dataset_id,
+            embed_size,
+            acl_groups,
+        )
+        .await
+        .map_err(|err| recommend_error_to_service_error(err).into());
+    }
+
+    let positive = request
+        .positive_chunk_ids
+        .iter()
+        .map(|chunk_id| WeightedChunkExample {
+            chunk_id: *chunk_id,
+            weight: 1.0,
+        })
+        .chain(request.positive.unwrap_or_default())
+        .collect::<Vec<WeightedChunkExample>>();
+    let negative = request.negative.unwrap_or_default();
+
+    let (positive, negative) = web::block(move || {
+        let get_point_ids = |examples: Vec<WeightedChunkExample>| {
+            examples
+                .into_iter()
+                .map(|example| {
+                    get_metadata_from_id_query(example.chunk_id, dataset_id, pool.clone())?;
+                    let point_id =
+                        get_qdrant_id_from_chunk_id_query(example.chunk_id, pool.clone())?;
+                    Ok((point_id, example.weight))
+                })
+                .collect::<Result<Vec<(uuid::Uuid, f32)>, DefaultError>>()
+        };
+
+        Ok::<_, DefaultError>((get_point_ids(positive)?, get_point_ids(negative)?))
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
 
-    Ok(HttpResponse::Ok().json(chunk))
-}
-
-#[derive(Serialize, Deserialize, ToSchema)]
-pub struct RecommendChunksRequest {
-    /// The ids of the chunks to be used as positive examples for the recommendation. The chunks in this array will be used to find similar chunks.
-    pub positive_chunk_ids: Vec<uuid::Uuid>,
+    recommend_weighted_qdrant_query(positive, negative, dataset_id, embed_size, acl_groups)
+        .await
+        .map_err(|err| recommend_error_to_service_error(err).into())
 }
 
 /// get_recommended_chunks
@@ -1021,24 +3193,23 @@ pub struct RecommendChunksRequest {
 pub async fn get_recommended_chunks(
     data: web::Json<RecommendChunksRequest>,
     pool: web::Data<Pool>,
-    _user: LoggedUser,
+    user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let positive_chunk_ids = data.positive_chunk_ids.clone();
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
     let embed_size =
         ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration)
             .EMBEDDING_SIZE
             .unwrap_or(1536);
 
-    let recommended_qdrant_point_ids = recommend_qdrant_query(
-        positive_chunk_ids,
+    let recommended_qdrant_point_ids = get_recommended_qdrant_point_ids(
+        data.into_inner(),
         dataset_org_plan_sub.dataset.id,
         embed_size,
+        acl_groups,
+        pool.clone(),
     )
-    .await
-    .map_err(|err| {
-        ServiceError::BadRequest(format!("Could not get recommended chunks: {}", err))
-    })?;
+    .await?;
 
     let recommended_chunk_metadatas =
         web::block(move || get_metadata_from_point_ids(recommended_qdrant_point_ids, pool))
@@ -1053,14 +3224,126 @@ pub async fn get_recommended_chunks(
     Ok(HttpResponse::Ok().json(recommended_chunk_metadatas))
 }
 
+/// The most seed sets a batch recommend request can have.
+pub const MAX_BATCH_RECOMMEND_KEYS: usize = 50;
+/// How many of a batch recommend request's seed sets are recommended for at once.
+const BATCH_RECOMMEND_CONCURRENCY: usize = 8;
+
+/// get_batch_recommended_chunks
+///
+/// Get recommendations for several named sets of seed chunks in a single request. The request body is a map of keys to recommendation requests and the response maps each key to the chunks recommended for it. The recommend calls are issued to qdrant concurrently, up to 8 at a time, which makes this a good fit for pages that show recommendations for each of a user's lists. At most 50 keys can be requested at once.
+#[utoipa::path(
+    post,
+    path = "/chunk/recommend/batch",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = HashMap<String, RecommendChunksRequest>, description = "JSON request payload mapping keys to the seed chunks to get recommendations for", content_type = "application/json"),
+    responses(
+        (status = 200, description = "JSON response payload mapping each key in the request to the chunks recommended for it", body = HashMap<String, Vec<ChunkMetadataWithFileData>>),
+        (status = 400, description = "Service error relating to to getting similar chunks or too many keys", body = DefaultError),
+    )
+)]
+pub async fn get_batch_recommended_chunks(
+    data: web::Json<HashMap<String, RecommendChunksRequest>>,
+    pool: web::Data<Pool>,
+    user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data.len() > MAX_BATCH_RECOMMEND_KEYS {
+        return Err(ServiceError::BadRequest(format!(
+            "A batch recommend request can have at most {} keys",
+            MAX_BATCH_RECOMMEND_KEYS
+        ))
+        .into());
+    }
+
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let embed_size =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration)
+            .EMBEDDING_SIZE
+            .unwrap_or(1536);
+
+    let recommend_futures = data.into_inner().into_iter().map(|(key, request)| {
+        let pool = pool.clone();
+        let acl_groups = acl_groups.clone();
+        async move {
+            get_recommended_qdrant_point_ids(request, dataset_id, embed_size, acl_groups, pool)
+                .await
+                .map(|point_ids| (key, point_ids))
+        }
+    });
+
+    let recommended_point_ids_by_key: Vec<(String, Vec<uuid::Uuid>)> =
+        futures::TryStreamExt::try_collect(futures::StreamExt::buffer_unordered(
+            futures::stream::iter(recommend_futures),
+            BATCH_RECOMMEND_CONCURRENCY,
+        ))
+        .await?;
+
+    let all_point_ids = recommended_point_ids_by_key
+        .iter()
+        .flat_map(|(_, point_ids)| point_ids.clone())
+        .unique()
+        .collect::<Vec<uuid::Uuid>>();
+
+    let recommended_chunk_metadatas =
+        web::block(move || get_metadata_from_point_ids(all_point_ids, pool))
+            .await?
+            .map_err(|err| {
+                ServiceError::BadRequest(format!(
+                    "Could not get recommended chunk_metadas from qdrant_point_ids: {}",
+                    err
+                ))
+            })?;
+
+    let recommended_chunks_by_key: HashMap<String, Vec<ChunkMetadataWithFileData>> =
+        recommended_point_ids_by_key
+            .into_iter()
+            .map(|(key, point_ids)| {
+                let chunks = point_ids
+                    .iter()
+                    .filter_map(|point_id| {
+                        recommended_chunk_metadatas
+                            .iter()
+                            .find(|chunk| chunk.qdrant_point_id == *point_id)
+                            .cloned()
+                    })
+                    .collect();
+                (key, chunks)
+            })
+            .collect();
+
+    Ok(HttpResponse::Ok().json(recommended_chunks_by_key))
+}
+
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct GenerateChunksRequest {
     /// The model to use for the chat. This can be any model from the model list. If no model is provided, the gryphe/mythomax-l2-13b will be used.
     pub model: Option<String>,
-    /// The previous messages to be placed into the chat history. The last message in this array will be the prompt for the model to inference on.
+    /// The previous messages to be placed into the chat history. The last message in this array will be the prompt for the model to inference on. Must contain at least one message.
     pub prev_messages: Vec<ChatMessageProxy>,
     /// The ids of the chunks to be retrieved and injected into the context window for RAG.
     pub chunk_ids: Vec<uuid::Uuid>,
+    /// If include_links is set to true, the stream will begin with a JSON array of the links of the chunks followed by `||`. The array is ordered by doc number, so the link at index 0 belongs to `Doc 1` in the completion's citations. Chunks without a link are represented by null. Defaults to false.
+    pub include_links: Option<bool>,
+    /// Models to try, in order, if the primary model's stream fails to start. When fallback_models is provided, the stream will begin with a JSON object of the form `{"model": "..."}` followed by `||` naming the model which served the response. This frame comes before the links frame if include_links is also set.
+    pub fallback_models: Option<Vec<String>>,
+    /// If dedupe_collisions is set to true, chunks which are collisions (duplicates) of another chunk in chunk_ids are left out of the context window so it is not spent on redundant text. The root chunk of each collision group is kept if it is in chunk_ids, otherwise the first chunk of the group in chunk_ids is kept. Set to false to include every chunk. Defaults to true.
+    pub dedupe_collisions: Option<bool>,
+}
+
+/// The last of the prev_messages is the prompt, so there must be at least one.
+fn validate_generate_chunks_request(
+    prev_messages: &[ChatMessageProxy],
+) -> Result<(), ServiceError> {
+    if prev_messages.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "prev_messages must contain at least one message to use as the prompt".into(),
+        ));
+    }
+
+    Ok(())
 }
 
 /// generate_off_chunks
@@ -1075,27 +3358,374 @@ pub struct GenerateChunksRequest {
     responses(
         (status = 200, description = "This will be a HTTP stream of a string, check the chat or search UI for an example how to process this",),
         (status = 400, description = "Service error relating to to updating chunk, likely due to conflicting tracking_id", body = DefaultError),
+        (status = 429, description = "This instance is already generating MAX_CONCURRENT_GENERATIONS responses. Retry the request later", body = DefaultError),
     ),
 )]
 pub async fn generate_off_chunks(
     data: web::Json<GenerateChunksRequest>,
     pool: web::Data<Pool>,
-    _user: LoggedUser,
+    user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
+    validate_generate_chunks_request(&data.prev_messages)?;
+
     let prev_messages = data.prev_messages.clone();
     let chunk_ids = data.chunk_ids.clone();
-    let mut chunks = web::block(move || {
-        get_metadata_from_ids_query(chunk_ids, dataset_org_plan_sub.dataset.id, pool)
-    })
-    .await?
-    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    let collisions_pool = pool.clone();
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let acl_pool = pool.clone();
+    let chunks = web::block(move || get_metadata_from_ids_query(chunk_ids, dataset_id, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    let mut chunks = filter_chunks_by_acl(chunks, |chunk| chunk.id, acl_groups, acl_pool).await?;
+
+    chunks.sort_by(|a, b| {
+        data.chunk_ids
+            .iter()
+            .position(|&id| id == a.id)
+            .unwrap()
+            .cmp(&data.chunk_ids.iter().position(|&id| id == b.id).unwrap())
+    });
+
+    if data.dedupe_collisions.unwrap_or(true) {
+        let chunk_ids = data.chunk_ids.clone();
+        let collision_chunk_ids =
+            web::block(move || get_collision_chunk_ids_query(chunk_ids, collisions_pool))
+                .await?
+                .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+        // Chunks in the same collision group share a qdrant_point_id. The root chunk of a group is
+        // kept over its collisions, otherwise the first requested member of the group is kept
+        let mut kept_chunk_by_point_id: HashMap<uuid::Uuid, uuid::Uuid> = HashMap::new();
+        for chunk in chunks.iter() {
+            let is_root = !collision_chunk_ids.contains(&chunk.id);
+            if is_root || !kept_chunk_by_point_id.contains_key(&chunk.qdrant_point_id) {
+                kept_chunk_by_point_id.insert(chunk.qdrant_point_id, chunk.id);
+            }
+        }
+
+        chunks
+            .retain(|chunk| kept_chunk_by_point_id.get(&chunk.qdrant_point_id) == Some(&chunk.id));
+    }
+
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+
+    stream_chunks_completion(
+        chunks,
+        prev_messages,
+        data.model.clone(),
+        data.fallback_models.clone(),
+        data.include_links.unwrap_or(false),
+        dataset_config,
+        vec![],
+    )
+    .await
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SparseEncodeData {
+    /// The text to encode.
+    pub text: String,
+    /// Either "query" or "doc". Use "query" for text which will be searched with and "doc" for text which will be searched over, the same way the full-text search encodes queries and chunks. Defaults to "query".
+    pub encode_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SparseEncodeResponse {
+    /// The SPLADE sparse vector of the text as a map of the index of each token in the model's vocabulary to its weight. Tokens with a weight of 0 are left out.
+    pub vector: HashMap<u32, f32>,
+}
+
+pub const MAX_ESTIMATE_CONTENTS: usize = 1000;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct EstimateCostData {
+    /// The content of the chunks to estimate the cost of, as the plaintext which would be embedded. At most 1000 can be estimated at once.
+    pub contents: Vec<String>,
+    /// Metadata to estimate the embedding text with if the dataset's EMBEDDING_TEXT_TEMPLATE refers to metadata keys.
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ContentCostEstimate {
+    /// The number of tokens which would be embedded for the content.
+    pub embedding_tokens: usize,
+    /// The number of tokens the content adds to the prompt when it is used as a doc for RAG.
+    pub rag_tokens: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct EstimateCostResponse {
+    /// The estimate for each content, in the order they were given.
+    pub estimates: Vec<ContentCostEstimate>,
+    /// The number of tokens which would be embedded across all of the contents.
+    pub total_embedding_tokens: usize,
+    /// The approximate cost in USD of embedding all of the contents. Null if the price of the dataset's embedding model is not known and EMBEDDING_PRICE_PER_MILLION_TOKENS is not set.
+    pub estimated_embedding_cost: Option<f64>,
+    /// The number of prompt tokens the contents add when all of them are used as docs for RAG.
+    pub total_rag_tokens: usize,
+    /// The approximate cost in USD of the prompt tokens the contents add to RAG. Null unless the dataset's LLM_PRICE_PER_MILLION_TOKENS is set.
+    pub estimated_rag_cost: Option<f64>,
+}
+
+/// estimate_cost
+///
+/// Estimate the number of tokens and the approximate cost of embedding content and of using it for RAG before it is ingested. Tokens are counted locally with the cl100k_base tokenizer used by OpenAI's models, so no provider is called, and counts for other providers' models are an approximation. Embedding costs use the list price of OpenAI's embedding models unless the dataset's EMBEDDING_PRICE_PER_MILLION_TOKENS is set, and RAG costs use the dataset's LLM_PRICE_PER_MILLION_TOKENS.
+#[utoipa::path(
+    post,
+    path = "/chunk/estimate",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = EstimateCostData, description = "JSON request payload to estimate the cost of content", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The token counts and approximate costs of the content", body = EstimateCostResponse),
+        (status = 400, description = "Service error relating to estimating the cost", body = DefaultError),
+    ),
+)]
+pub async fn estimate_cost(
+    data: web::Json<EstimateCostData>,
+    _user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    if data.contents.len() > MAX_ESTIMATE_CONTENTS {
+        return Err(ServiceError::BadRequest(format!(
+            "At most {} contents can be estimated at once",
+            MAX_ESTIMATE_CONTENTS
+        ))
+        .into());
+    }
+
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+
+    let estimates = data
+        .contents
+        .iter()
+        .enumerate()
+        .map(|(idx, content)| {
+            let embedding_text = get_embedding_text(content, &data.metadata, &dataset_config);
+            ContentCostEstimate {
+                embedding_tokens: count_tokens(&normalize_content(
+                    &embedding_text,
+                    &dataset_config,
+                )),
+                rag_tokens: count_tokens(&get_rag_doc_text(idx + 1, content)),
+            }
+        })
+        .collect::<Vec<ContentCostEstimate>>();
+
+    let total_embedding_tokens = estimates
+        .iter()
+        .map(|estimate| estimate.embedding_tokens)
+        .sum::<usize>();
+    let total_rag_tokens = estimates
+        .iter()
+        .map(|estimate| estimate.rag_tokens)
+        .sum::<usize>();
+    let cost = |tokens: usize, price_per_million_tokens: f64| {
+        tokens as f64 / 1_000_000.0 * price_per_million_tokens
+    };
+
+    Ok(HttpResponse::Ok().json(EstimateCostResponse {
+        estimates,
+        total_embedding_tokens,
+        estimated_embedding_cost: get_embedding_price_per_million_tokens(&dataset_config)
+            .map(|price| cost(total_embedding_tokens, price)),
+        total_rag_tokens,
+        estimated_rag_cost: dataset_config
+            .LLM_PRICE_PER_MILLION_TOKENS
+            .map(|price| cost(total_rag_tokens, price)),
+    }))
+}
 
-    let openai_api_key = get_env!("OPENROUTER_API_KEY", "OPENROUTER_API_KEY should be set").into();
+/// sparse_encode
+///
+/// Get the SPLADE sparse vector which the full-text search computes for a piece of text. This is useful for scoring against the dataset's full-text vectors in an external hybrid pipeline. If the dataset's FULLTEXT_STEMMING_LANGUAGE is set, the text is stemmed before it is encoded just like it is for search, with quoted phrases left as they are for queries.
+#[utoipa::path(
+    post,
+    path = "/chunk/sparse_encode",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = SparseEncodeData, description = "JSON request payload of the text to encode", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The SPLADE sparse vector of the text", body = SparseEncodeResponse),
+        (status = 400, description = "Service error relating to encoding the text", body = DefaultError),
+    ),
+)]
+pub async fn sparse_encode(
+    data: web::Json<SparseEncodeData>,
+    _user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
     let dataset_config =
         ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    let stemming_language = dataset_config.FULLTEXT_STEMMING_LANGUAGE;
+    let splade_model = dataset_config.SPLADE_MODEL;
+    let text = if dataset_config.FULLTEXT_UNACCENT.unwrap_or(false) {
+        unaccent_text(&data.text)
+    } else {
+        data.text.clone()
+    };
+
+    let embeddings = match data.encode_type.as_deref().unwrap_or("query") {
+        "query" => {
+            let text = match stemming_language {
+                Some(language) => stem_query(&text, &language)?,
+                None => text,
+            };
+            get_splade_query_embedding(&text, splade_model).await?
+        }
+        "doc" => {
+            let text = match stemming_language {
+                Some(language) => stem_text(&text, &language)?,
+                None => text,
+            };
+            get_splade_doc_embedding(&text, splade_model).await?
+        }
+        _ => {
+            return Err(ServiceError::BadRequest(
+                "encode_type must be either \"query\" or \"doc\"".into(),
+            )
+            .into())
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(SparseEncodeResponse {
+        vector: embeddings.into_iter().collect(),
+    }))
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SearchAndGenerateRequest {
+    /// The search to run before generating. Its top results are injected into the context window for RAG, up to the dataset's N_RETRIEVALS_TO_INCLUDE (3 by default).
+    pub search: SearchChunkData,
+    /// The previous messages to be placed into the chat history. The last message in this array will be the prompt for the model to inference on. Must contain at least one message.
+    pub prev_messages: Vec<ChatMessageProxy>,
+    /// The model to use for the chat. This can be any model from the model list. If no model is provided, the gryphe/mythomax-l2-13b will be used.
+    pub model: Option<String>,
+    /// Models to try, in order, if the primary model's stream fails to start. Behaves the same as fallback_models on the generate route.
+    pub fallback_models: Option<Vec<String>>,
+    /// If include_links is set to true, a JSON array of the links of the retrieved chunks followed by `||` is sent after the chunk ids frame. Behaves the same as include_links on the generate route.
+    pub include_links: Option<bool>,
+}
+
+/// search_and_generate
+///
+/// Run a search and then generate a response off of its top results in a single call. This is the "search before generate" flow without having to call the search and generate routes separately. The stream will begin with a JSON object of the form `{"chunk_ids": [...]}` followed by `||` listing the retrieved chunks in the order they are numbered as docs in the completion's citations.
+#[utoipa::path(
+    post,
+    path = "/chunk/search_and_generate",
+    context_path = "/api",
+    tag = "chunk",
+    request_body(content = SearchAndGenerateRequest, description = "JSON request payload to search for chunks and perform RAG on the results", content_type = "application/json"),
+    responses(
+        (status = 200, description = "This will be a HTTP stream of a string, check the chat or search UI for an example how to process this",),
+        (status = 400, description = "Service error relating to searching or generating", body = DefaultError),
+        (status = 403, description = "The search's page is past the deepest page the organization's plan allows searches to fetch", body = SearchPageLimitExceeded),
+        (status = 429, description = "This instance is already generating MAX_CONCURRENT_GENERATIONS responses. Retry the request later", body = DefaultError),
+    ),
+)]
+pub async fn search_and_generate(
+    data: web::Json<SearchAndGenerateRequest>,
+    pool: web::Data<Pool>,
+    user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    validate_generate_chunks_request(&data.prev_messages)?;
+
+    let data = data.into_inner();
+    check_search_page(data.search.page.unwrap_or(1), &dataset_org_plan_sub)?;
+    let dataset_config = ServerDatasetConfiguration::from_json(
+        dataset_org_plan_sub.dataset.server_configuration.clone(),
+    );
+
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let search_results = search_dataset_chunks(
+        web::Json(data.search),
+        pool,
+        dataset_org_plan_sub.dataset,
+        acl_groups,
+    )
+    .await?;
+
+    let chunks = search_results
+        .score_chunks
+        .into_iter()
+        .filter_map(|score_chunk| score_chunk.metadata.into_iter().next())
+        .filter(|chunk| !chunk.id.is_nil())
+        .take(dataset_config.N_RETRIEVALS_TO_INCLUDE.unwrap_or(3))
+        .collect::<Vec<ChunkMetadataWithFileData>>();
+
+    let chunk_ids_frame = format!(
+        "{}||",
+        json!({ "chunk_ids": chunks.iter().map(|chunk| chunk.id).collect::<Vec<uuid::Uuid>>() })
+    );
+
+    stream_chunks_completion(
+        chunks,
+        data.prev_messages,
+        data.model,
+        data.fallback_models,
+        data.include_links.unwrap_or(false),
+        dataset_config,
+        vec![chunk_ids_frame],
+    )
+    .await
+}
+
+/// Limits how many model responses this instance streams at once. Set by the
+/// MAX_CONCURRENT_GENERATIONS env var and unlimited when it is unset.
+static GENERATION_SEMAPHORE: Lazy<Option<Arc<tokio::sync::Semaphore>>> = Lazy::new(|| {
+    std::env::var("MAX_CONCURRENT_GENERATIONS")
+        .ok()
+        .and_then(|max_generations| max_generations.parse::<usize>().ok())
+        .map(|max_generations| Arc::new(tokio::sync::Semaphore::new(max_generations)))
+});
+
+/// The text a chunk is given to the LLM as when it is used as a doc for RAG. Only its first 240
+/// words are included.
+pub fn get_rag_doc_text(doc_number: usize, content: &str) -> String {
+    let first_240_words = content
+        .split_whitespace()
+        .take(240)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!("Doc {}: {}", doc_number, first_240_words)
+}
+
+/// Builds the RAG prompt from the chunks, which are numbered as docs in the order given, and
+/// streams the model's answer. `leading_frames` are sent before the model and links frames.
+pub async fn stream_chunks_completion(
+    chunks: Vec<ChunkMetadataWithFileData>,
+    prev_messages: Vec<ChatMessageProxy>,
+    model: Option<String>,
+    fallback_models: Option<Vec<String>>,
+    include_links: bool,
+    dataset_config: ServerDatasetConfiguration,
+    leading_frames: Vec<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The permit is moved into the response stream so it is held until the stream is dropped
+    let generation_permit = match GENERATION_SEMAPHORE.as_ref() {
+        Some(semaphore) => match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                return Err(ServiceError::TooManyRequests(
+                    "Too many responses are being generated right now. Please try again later"
+                        .into(),
+                )
+                .into());
+            }
+        },
+        None => None,
+    };
+
+    let openai_api_key = get_llm_api_key(&dataset_config)?;
     let base_url = dataset_config
         .LLM_BASE_URL
+        .clone()
         .unwrap_or("https://openrouter.ai/v1".into());
 
     let client = Client {
@@ -1126,24 +3756,10 @@ pub async fn generate_off_chunks(
         name: None,
         tool_call_id: None,
     });
-    chunks.sort_by(|a, b| {
-        data.chunk_ids
-            .iter()
-            .position(|&id| id == a.id)
-            .unwrap()
-            .cmp(&data.chunk_ids.iter().position(|&id| id == b.id).unwrap())
-    });
     chunks.iter().enumerate().for_each(|(idx, bookmark)| {
-        let first_240_words = bookmark
-            .content
-            .split_whitespace()
-            .take(240)
-            .collect::<Vec<_>>()
-            .join(" ");
-
         messages.push(ChatMessage {
             role: Role::User,
-            content: ChatMessageContent::Text(format!("Doc {}: {}", idx + 1, first_240_words)),
+            content: ChatMessageContent::Text(get_rag_doc_text(idx + 1, &bookmark.content)),
             tool_calls: None,
             name: None,
             tool_call_id: None,
@@ -1168,11 +3784,10 @@ pub async fn generate_off_chunks(
             tool_call_id: None,
     });
 
-    let parameters = ChatCompletionParameters {
-        model: data
-            .model
-            .clone()
-            .unwrap_or("gryphe/mythomax-l2-13b".to_string()),
+    let primary_model = model.unwrap_or("gryphe/mythomax-l2-13b".to_string());
+
+    let mut parameters = ChatCompletionParameters {
+        model: primary_model.clone(),
         messages,
         temperature: None,
         top_p: None,
@@ -1191,18 +3806,152 @@ pub async fn generate_off_chunks(
         seed: None,
     };
 
-    let stream = client.chat().create_stream(parameters).await.unwrap();
+    let models_to_try = std::iter::once(primary_model)
+        .chain(fallback_models.clone().unwrap_or_default())
+        .collect::<Vec<String>>();
 
-    Ok(HttpResponse::Ok().streaming(stream.map(
-        move |response| -> Result<Bytes, actix_web::Error> {
-            if let Ok(response) = response {
-                let chat_content = response.choices[0].delta.content.clone();
-                return Ok(Bytes::from(chat_content.unwrap_or("".to_string())));
+    let deadline = tokio::time::Instant::now()
+        + std::time::Duration::from_secs(dataset_config.LLM_STREAM_TIMEOUT_SECS.unwrap_or(60));
+    let idle_timeout =
+        std::time::Duration::from_secs(dataset_config.LLM_STREAM_IDLE_TIMEOUT_SECS.unwrap_or(20));
+
+    let mut served_stream = None;
+    for model in models_to_try {
+        parameters.model = model.clone();
+        let start_deadline = std::cmp::min(deadline, tokio::time::Instant::now() + idle_timeout);
+        match tokio::time::timeout_at(
+            start_deadline,
+            client.chat().create_stream(parameters.clone()),
+        )
+        .await
+        {
+            Ok(Ok(stream)) => {
+                served_stream = Some((model, stream));
+                break;
             }
-            Err(ServiceError::InternalServerError(
-                "Model Response Error. Please try again later".into(),
-            )
-            .into())
-        },
+            Ok(Err(err)) => {
+                log::error!("Failed to start stream with model {}: {:?}", model, err);
+            }
+            Err(_) => {
+                log::error!("Timed out starting stream with model {}", model);
+            }
+        }
+    }
+
+    let (served_model, stream) = served_stream.ok_or(ServiceError::BadRequest(
+        "Failed to get a response from any model. Please try again later".into(),
+    ))?;
+
+    let mut model_frame = "".to_string();
+    if fallback_models.is_some() {
+        model_frame = format!("{}||", json!({ "model": served_model }));
+    }
+
+    let mut links_frame = "".to_string();
+    if include_links {
+        let links = chunks
+            .iter()
+            .map(|chunk| chunk.link.clone())
+            .collect::<Vec<Option<String>>>();
+        links_frame = format!(
+            "{}||",
+            serde_json::to_string(&links).map_err(|_| ServiceError::InternalServerError(
+                "Failed to serialize chunk links".into()
+            ))?
+        );
+    }
+
+    let stream = stream.map(move |response| -> Result<Bytes, actix_web::Error> {
+        let _generation_permit = &generation_permit;
+        if let Ok(response) = response {
+            let chat_content = response
+                .choices
+                .first()
+                .ok_or(ServiceError::InternalServerError(
+                    "Model Response Error. The model returned no choices".into(),
+                ))?
+                .delta
+                .content
+                .clone();
+            return Ok(Bytes::from(chat_content.unwrap_or("".to_string())));
+        }
+        Err(ServiceError::InternalServerError(
+            "Model Response Error. Please try again later".into(),
+        )
+        .into())
+    });
+
+    let links_stream = futures::stream::iter(
+        leading_frames
+            .into_iter()
+            .chain([model_frame, links_frame])
+            .map(|frame| Ok(Bytes::from(frame)))
+            .collect::<Vec<Result<Bytes, actix_web::Error>>>(),
+    );
+
+    Ok(HttpResponse::Ok().streaming(stream_with_timeouts(
+        links_stream.chain(stream),
+        deadline,
+        idle_timeout,
     )))
 }
+
+/// Wraps a streamed model response so that it ends once `deadline` has passed or no frame has
+/// arrived for `idle_timeout`. The deadline is set before the stream is requested from the provider,
+/// so time spent connecting counts towards it. When either limit is hit a final error frame is sent
+/// and the stream is closed so stalled providers cannot hold the connection.
+pub fn stream_with_timeouts<S>(
+    stream: S,
+    deadline: tokio::time::Instant,
+    idle_timeout: std::time::Duration,
+) -> impl futures::Stream<Item = Result<Bytes, actix_web::Error>>
+where
+    S: futures::Stream<Item = Result<Bytes, actix_web::Error>> + Unpin,
+{
+    futures::stream::unfold((stream, false), move |(mut stream, finished)| async move {
+        if finished {
+            return None;
+        }
+
+        let frame_deadline = std::cmp::min(deadline, tokio::time::Instant::now() + idle_timeout);
+
+        match tokio::time::timeout_at(frame_deadline, stream.next()).await {
+            Ok(Some(Ok(frame))) => Some((Ok(frame), (stream, false))),
+            Ok(Some(Err(err))) => Some((Err(err), (stream, true))),
+            Ok(None) => None,
+            Err(_) => {
+                log::error!("Model response stream timed out");
+                Some((
+                    Ok(Bytes::from(
+                        "[ERROR] Model response timed out. Please try again later.",
+                    )),
+                    (stream, true),
+                ))
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_chunks_request_rejects_empty_prev_messages() {
+        let result = validate_generate_chunks_request(&[]);
+
+        assert!(matches!(result, Err(ServiceError::BadRequest(_))));
+    }
+
+    #[test]
+    fn generate_chunks_request_accepts_a_prompt() {
+        let prev_messages = vec![ChatMessageProxy {
+            role: "user".to_string(),
+            content: "What is the return policy?".to_string(),
+        }];
+
+        let result = validate_generate_chunks_request(&prev_messages);
+
+        assert!(result.is_ok());
+    }
+}
@@ -1,16 +1,26 @@
 use super::auth_handler::{AdminOnly, LoggedUser};
+use super::chunk_handler::{
+    check_search_page, filter_chunks_by_acl, get_acl_groups, get_max_search_page,
+    search_dataset_chunks, ScoreChunkDTO, SearchChunkData,
+};
 use crate::{
     data::models::{
         ChunkCollection, ChunkCollectionAndFile, ChunkCollectionBookmark,
-        ChunkMetadataWithFileData, DatasetAndOrgWithSubAndPlan, Pool,
+        ChunkCollectionWithBookmarkCount, ChunkMetadataWithFileData, DatasetAndOrgWithSubAndPlan,
+        Pool, ServerDatasetConfiguration,
     },
     errors::ServiceError,
-    operators::{chunk_operator::get_collided_chunks_query, collection_operator::*},
+    operators::{
+        chunk_operator::{get_collided_chunks_query, get_metadata_from_point_ids},
+        collection_operator::*,
+        qdrant_operator::{qdrant_error_to_service_error, recommend_scored_qdrant_query},
+    },
 };
 use actix_web::{web, HttpResponse};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 pub async fn user_owns_collection(
     user_id: uuid::Uuid,
@@ -145,6 +155,60 @@ pub async fn get_specific_user_chunk_collections(
     }))
 }
 
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct ListCollectionsQuery {
+    /// The page of collections to fetch. Each page contains 10 collections.
+    pub page: Option<u64>,
+    /// Sort_by can be either "count" or "name". "count" returns the collections with the most bookmarks first and "name" sorts the collections alphabetically. If not specified, the most recently updated collections are returned first.
+    pub sort_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CollectionsWithCountsData {
+    pub collections: Vec<ChunkCollectionWithBookmarkCount>,
+    pub total_pages: i64,
+}
+
+/// list_chunk_collections
+///
+/// List the collections in the dataset along with the number of bookmarks in each collection and the last time the collection or its bookmarks were updated. This is useful for building a collections browser without having to search each collection to estimate its size.
+#[utoipa::path(
+    get,
+    path = "/chunk_collection",
+    context_path = "/api",
+    tag = "chunk_collection",
+    responses(
+        (status = 200, description = "The page of collections in the dataset with their bookmark counts", body = CollectionsWithCountsData),
+        (status = 400, description = "Service error relating to getting the collections for the dataset", body = DefaultError),
+    ),
+    params(ListCollectionsQuery),
+)]
+pub async fn list_chunk_collections(
+    query: web::Query<ListCollectionsQuery>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _required_user: LoggedUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = query.into_inner();
+
+    let (collections, total_collections) = web::block(move || {
+        get_collections_with_bookmark_counts_query(
+            query.page.unwrap_or(1),
+            query.sort_by,
+            dataset_org_plan_sub.dataset.id,
+            pool,
+        )
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(CollectionsWithCountsData {
+        collections,
+        total_pages: (total_collections as f64 / 10.0).ceil() as i64,
+    }))
+}
+
 /// get_current_user_collections
 ///
 /// Fetch the collections which belong to the currently logged in user. We are soon going to refactor collections to relate to only datasets instead of datasets and users.
@@ -204,24 +268,42 @@ pub struct DeleteCollectionData {
     pub collection_id: uuid::Uuid,
 }
 
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct DeleteCollectionQuery {
+    /// If delete_chunks is set to true, the collection is permanently deleted along with every chunk which is not bookmarked in any other collection, including deleted collections which have not been purged. The chunks' qdrant points are deleted as well. Chunks shared with other collections are kept. Defaults to false.
+    pub delete_chunks: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
+pub struct DeleteCollectionResult {
+    /// The number of bookmarks which were removed from the collection.
+    pub deleted_bookmarks: usize,
+    /// The number of chunks which were deleted because they were only in the collection.
+    pub deleted_chunks: usize,
+}
+
 /// delete_chunk_collection
 ///
-/// This will delete a chunk_collection. This will not delete the chunks that are in the collection. We will soon support deleting a chunk_collection along with its member chunks.
+/// This will soft delete a chunk_collection. The collection and its bookmarks are hidden but retained until the collection is purged, so it can be brought back with restore_chunk_collection. This will not delete the chunks that are in the collection unless delete_chunks is set, in which case the collection cannot be restored.
 #[utoipa::path(
     delete,
     path = "/chunk_collection/{collection_id}",
     context_path = "/api",
     tag = "chunk_collection",
     responses(
+        (status = 200, description = "The number of bookmarks and chunks deleted when delete_chunks is set", body = DeleteCollectionResult),
         (status = 204, description = "Confirmation that the chunkCollection was deleted"),
         (status = 400, description = "Service error relating to deleting the chunkCollection", body = DefaultError),
     ),
     params(
         ("collection_id" = uuid, description = "Id of the chunk_collection to delete"),
+        DeleteCollectionQuery,
     ),
 )]
 pub async fn delete_chunk_collection(
     collection_id: web::Path<uuid::Uuid>,
+    query: web::Query<DeleteCollectionQuery>,
     pool: web::Data<Pool>,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     user: AdminOnly,
@@ -237,6 +319,21 @@ pub async fn delete_chunk_collection(
     )
     .await?;
 
+    if query.delete_chunks.unwrap_or(false) {
+        let (deleted_bookmarks, deleted_chunks) = delete_collection_and_chunks_query(
+            collection_id,
+            dataset_org_plan_sub.dataset,
+            delete_collection_pool,
+        )
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+        return Ok(HttpResponse::Ok().json(DeleteCollectionResult {
+            deleted_bookmarks,
+            deleted_chunks,
+        }));
+    }
+
     web::block(move || {
         delete_collection_by_id_query(
             collection_id,
@@ -250,6 +347,82 @@ pub async fn delete_chunk_collection(
     Ok(HttpResponse::NoContent().finish())
 }
 
+/// restore_chunk_collection
+///
+/// Restore a chunk_collection which was deleted but has not yet been purged. Its bookmarks become visible again. Only the author of the collection can restore it.
+#[utoipa::path(
+    put,
+    path = "/chunk_collection/{collection_id}/restore",
+    context_path = "/api",
+    tag = "chunk_collection",
+    responses(
+        (status = 200, description = "The restored chunkCollection", body = ChunkCollection),
+        (status = 400, description = "Service error relating to restoring the chunkCollection", body = DefaultError),
+    ),
+    params(
+        ("collection_id" = uuid, description = "Id of the deleted chunk_collection to restore"),
+    ),
+)]
+pub async fn restore_chunk_collection(
+    collection_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let collection_id = collection_id.into_inner();
+
+    let collection = web::block(move || {
+        restore_collection_by_id_query(
+            collection_id,
+            user.0.id,
+            dataset_org_plan_sub.dataset.id,
+            pool,
+        )
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(collection))
+}
+
+/// purge_chunk_collection
+///
+/// Permanently remove a deleted chunk_collection along with its bookmarks. The collection must have been deleted first and only its author can purge it. This will not delete the chunks that were in the collection.
+#[utoipa::path(
+    delete,
+    path = "/chunk_collection/{collection_id}/purge",
+    context_path = "/api",
+    tag = "chunk_collection",
+    responses(
+        (status = 204, description = "Confirmation that the chunkCollection was purged"),
+        (status = 400, description = "Service error relating to purging the chunkCollection", body = DefaultError),
+    ),
+    params(
+        ("collection_id" = uuid, description = "Id of the deleted chunk_collection to purge"),
+    ),
+)]
+pub async fn purge_chunk_collection(
+    collection_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let collection_id = collection_id.into_inner();
+
+    web::block(move || {
+        purge_collection_by_id_query(
+            collection_id,
+            user.0.id,
+            dataset_org_plan_sub.dataset.id,
+            pool,
+        )
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
 pub struct UpdateChunkCollectionData {
     /// Id of the chunk_collection to update.
@@ -357,6 +530,99 @@ pub async fn add_bookmark(
 
     Ok(HttpResponse::NoContent().finish())
 }
+pub const MAX_BOOKMARKS_FROM_SEARCH: usize = 100;
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct AddFromSearchResponse {
+    /// The number of chunks which were added to the collection. Chunks which were already in the collection are not counted.
+    pub added: usize,
+    /// Whether the search matched more chunks than could be added in one request, either because of the 100 chunk cap or because the search reached the max_search_page of the organization's plan. Run the search again with a narrower query or filters to add the rest.
+    pub cap_reached: bool,
+}
+
+/// add_bookmarks_from_search
+///
+/// Run a search and add every chunk it matches to the collection, up to 100 chunks. Pages past the max_search_page of the organization's plan are not searched, and a search starting past it is rejected with a 403. This is useful for saving a search as a collection. All of the bookmarks are created in a single transaction.
+#[utoipa::path(
+    post,
+    path = "/chunk_collection/{collection_id}/add_from_search",
+    context_path = "/api",
+    tag = "chunk_collection",
+    request_body(content = SearchChunkData, description = "JSON request payload of the search whose results should be added to the collection", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The number of chunks added to the collection and whether the cap was reached", body = AddFromSearchResponse),
+        (status = 400, description = "Service error relating to searching or adding the chunks to the collection", body = DefaultError),
+        (status = 403, description = "The search's page is past the deepest page the organization's plan allows searches to fetch", body = SearchPageLimitExceeded),
+    ),
+    params(
+        ("collection_id" = uuid, description = "Id of the collection to add the search results to"),
+    ),
+)]
+pub async fn add_bookmarks_from_search(
+    body: web::Json<SearchChunkData>,
+    collection_id: web::Path<uuid::Uuid>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let collection_id = collection_id.into_inner();
+    let search = body.into_inner();
+    check_search_page(search.page.unwrap_or(1), &dataset_org_plan_sub)?;
+    let max_search_page = get_max_search_page(&dataset_org_plan_sub);
+    let dataset = dataset_org_plan_sub.dataset;
+
+    user_owns_collection(user.0.id, collection_id, dataset.id, pool.clone()).await?;
+
+    let acl_groups = get_acl_groups(&user.0, &dataset);
+    let mut page = search.page.unwrap_or(1);
+    let mut chunk_ids: Vec<uuid::Uuid> = vec![];
+    let mut cap_reached = false;
+
+    loop {
+        let results = search_dataset_chunks(
+            web::Json(SearchChunkData {
+                page: Some(page),
+                ..search.clone()
+            }),
+            pool.clone(),
+            dataset.clone(),
+            acl_groups.clone(),
+        )
+        .await?;
+
+        chunk_ids.extend(
+            results
+                .score_chunks
+                .iter()
+                .filter_map(|score_chunk| score_chunk.metadata.first())
+                .map(|chunk| chunk.id)
+                .filter(|chunk_id| !chunk_id.is_nil()),
+        );
+        chunk_ids = chunk_ids.into_iter().unique().collect();
+
+        if chunk_ids.len() > MAX_BOOKMARKS_FROM_SEARCH {
+            chunk_ids.truncate(MAX_BOOKMARKS_FROM_SEARCH);
+            cap_reached = true;
+            break;
+        }
+
+        if results.score_chunks.is_empty() || page as i64 >= results.total_chunk_pages {
+            break;
+        }
+        if chunk_ids.len() == MAX_BOOKMARKS_FROM_SEARCH || page >= max_search_page {
+            cap_reached = true;
+            break;
+        }
+        page += 1;
+    }
+
+    let added = web::block(move || create_chunk_bookmarks_query(collection_id, chunk_ids, pool))
+        .await?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(AddFromSearchResponse { added, cap_reached }))
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
 pub struct BookmarkData {
     pub bookmarks: Vec<BookmarkChunks>,
@@ -377,7 +643,7 @@ pub struct BookmarkChunks {
 
 /// get_all_bookmarks
 ///
-/// Route to get all bookmarks for a collection. Think of a bookmark as a chunk which is a member of a collection. The response is paginated, with each page containing 10 chunks (bookmarks). Support for custom page size is coming soon.
+/// Route to get all bookmarks for a collection. Think of a bookmark as a chunk which is a member of a collection. The response is paginated, with each page containing 10 chunks (bookmarks) in the order they were added to the collection. Use GET /chunk_collection/{collection_id}/chunks for a custom page size.
 #[utoipa::path(
     get,
     path = "/chunk_collection/{collection_id}/{page}",
@@ -395,7 +661,7 @@ pub struct BookmarkChunks {
 pub async fn get_all_bookmarks(
     path_data: web::Path<GetAllBookmarksData>,
     pool: web::Data<Pool>,
-    _user: LoggedUser,
+    user: LoggedUser,
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
 ) -> Result<HttpResponse, actix_web::Error> {
     let collection_id = path_data.collection_id;
@@ -403,14 +669,23 @@ pub async fn get_all_bookmarks(
     let pool1 = pool.clone();
     let pool2 = pool.clone();
     let dataset_id = dataset_org_plan_sub.dataset.id;
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
 
-    let bookmarks = {
+    let mut bookmarks = {
         web::block(move || {
             get_bookmarks_for_collection_query(collection_id, page, None, dataset_id, pool2)
         })
         .await?
         .map_err(<ServiceError as std::convert::Into<actix_web::Error>>::into)?
     };
+    // The collided chunks of a bookmark share its qdrant point and so its acl
+    bookmarks.metadata = filter_chunks_by_acl(
+        bookmarks.metadata,
+        |chunk| chunk.id,
+        acl_groups,
+        pool.clone(),
+    )
+    .await?;
 
     let point_ids = bookmarks
         .metadata
@@ -475,6 +750,74 @@ pub async fn get_all_bookmarks(
     }))
 }
 
+#[derive(Deserialize, Serialize, ToSchema, IntoParams)]
+#[into_params(style = Form, parameter_in = Query)]
+pub struct CollectionChunksQuery {
+    /// The page of chunks to fetch. Defaults to 1.
+    pub page: Option<u64>,
+    /// The number of chunks to return per page. Defaults to 10 and can be at most 100.
+    pub page_size: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct CollectionChunksResponse {
+    pub chunks: Vec<ChunkMetadataWithFileData>,
+    pub total_pages: i64,
+}
+
+/// get_collection_chunks
+///
+/// Get the chunks bookmarked in a collection with their content and metadata, in the order they were added to the collection. This is useful for rendering a collection in a single call instead of fetching each of its chunks. Unlike get_all_bookmarks, the chunks each bookmark collided with are not included.
+#[utoipa::path(
+    get,
+    path = "/chunk_collection/{collection_id}/chunks",
+    context_path = "/api",
+    tag = "chunk_collection",
+    responses(
+        (status = 200, description = "The page of chunks bookmarked in the collection", body = CollectionChunksResponse),
+        (status = 400, description = "Service error relating to getting the chunks of the collection", body = DefaultError),
+    ),
+    params(
+        ("collection_id" = uuid::Uuid, Path, description = "The id of the collection to get the chunks from"),
+        CollectionChunksQuery,
+    ),
+)]
+pub async fn get_collection_chunks(
+    collection_id: web::Path<uuid::Uuid>,
+    query: web::Query<CollectionChunksQuery>,
+    pool: web::Data<Pool>,
+    user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let acl_pool = pool.clone();
+    let query = query.into_inner();
+    let page_size = query.page_size.unwrap_or(10);
+    if !(1..=100).contains(&page_size) {
+        return Err(ServiceError::BadRequest("page_size must be between 1 and 100".into()).into());
+    }
+    let collection_id = collection_id.into_inner();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+
+    let bookmarks = web::block(move || {
+        get_bookmarks_for_collection_query(
+            collection_id,
+            query.page.unwrap_or(1),
+            Some(page_size),
+            dataset_id,
+            pool,
+        )
+    })
+    .await?
+    .map_err(<ServiceError as std::convert::Into<actix_web::Error>>::into)?;
+
+    Ok(HttpResponse::Ok().json(CollectionChunksResponse {
+        chunks: filter_chunks_by_acl(bookmarks.metadata, |chunk| chunk.id, acl_groups, acl_pool)
+            .await?,
+        total_pages: bookmarks.total_pages,
+    }))
+}
+
 #[derive(Deserialize, Serialize, ToSchema)]
 pub struct GetCollectionsForChunksData {
     pub chunk_ids: Vec<uuid::Uuid>,
@@ -556,6 +899,151 @@ pub async fn delete_bookmark(
     Ok(HttpResponse::NoContent().finish())
 }
 
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SetChunkCollectionsData {
+    /// The full set of collection ids that the chunk should be a member of. The chunk will be added to any of these collections it is not yet in and removed from any other collection owned by the auth'ed user.
+    pub collection_ids: Vec<uuid::Uuid>,
+}
+
+/// set_chunk_collections
+///
+/// Set exactly which collections a chunk is bookmarked in. Missing bookmarks are added and extra bookmarks are removed in a single transaction, so the chunk's membership will either fully match the request or be left unchanged. Only collections owned by the auth'ed user are added to or removed from. Returns the collections the chunk is a member of after the update.
+#[utoipa::path(
+    put,
+    path = "/chunk/{chunk_id}/collections",
+    context_path = "/api",
+    tag = "chunk_collection",
+    request_body(content = SetChunkCollectionsData, description = "JSON request payload with the full set of collections the chunk should be in", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The collections that the chunk is a member of after the update", body = Vec<ChunkCollection>),
+        (status = 400, description = "Service error relating to updating the collections that the chunk is in", body = DefaultError),
+    ),
+    params(
+        ("chunk_id" = uuid::Uuid, description = "Id of the chunk to set the collections for"),
+    ),
+)]
+pub async fn set_chunk_collections(
+    body: web::Json<SetChunkCollectionsData>,
+    chunk_id: web::Path<uuid::Uuid>,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let chunk_id = chunk_id.into_inner();
+    let collection_ids = body.collection_ids.clone();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+
+    let collections = web::block(move || {
+        set_chunk_collections_query(chunk_id, collection_ids, user.0.id, dataset_id, pool)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(collections))
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct RecommendFromCollectionData {
+    /// If within_collection is set to true, only chunks which are bookmarked in the collection will be recommended. This is useful for ranking a collection by how representative each of its chunks is. Seeds are never recommended, so only the chunks past max_seeds can be returned. Defaults to false.
+    pub within_collection: Option<bool>,
+    /// The maximum number of the collection's chunks to use as positive seeds. The most recently bookmarked chunks are used first. Defaults to 50 and can be at most 100.
+    pub max_seeds: Option<usize>,
+}
+
+/// recommend_from_collection
+///
+/// Get chunks similar to the chunks bookmarked in a collection. The collection's bookmarks are used as the positive seeds for the recommendation, so this is a good fit for "more like this list" features. Results are returned in order of their score.
+#[utoipa::path(
+    post,
+    path = "/chunk_collection/{collection_id}/recommend",
+    context_path = "/api",
+    tag = "chunk_collection",
+    request_body(content = RecommendFromCollectionData, description = "JSON request payload to get recommendations seeded by a collection", content_type = "application/json"),
+    responses(
+        (status = 200, description = "The chunks recommended from the collection's bookmarks along with their scores", body = Vec<ScoreChunkDTO>),
+        (status = 400, description = "Service error relating to getting recommendations for the collection", body = DefaultError),
+    ),
+    params(
+        ("collection_id" = uuid, description = "Id of the collection to use as the seeds for the recommendation"),
+    ),
+)]
+pub async fn recommend_from_collection(
+    data: web::Json<RecommendFromCollectionData>,
+    collection_id: web::Path<uuid::Uuid>,
+    pool: web::Data<Pool>,
+    user: LoggedUser,
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+) -> Result<HttpResponse, actix_web::Error> {
+    let max_seeds = data.max_seeds.unwrap_or(50);
+    if !(1..=100).contains(&max_seeds) {
+        return Err(ServiceError::BadRequest("max_seeds must be between 1 and 100".into()).into());
+    }
+
+    let collection_id = collection_id.into_inner();
+    let dataset_id = dataset_org_plan_sub.dataset.id;
+    let acl_groups = get_acl_groups(&user, &dataset_org_plan_sub.dataset);
+    let embed_size =
+        ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration)
+            .EMBEDDING_SIZE
+            .unwrap_or(1536);
+
+    let pool1 = pool.clone();
+    let collection_point_ids = web::block(move || {
+        get_collection_by_id_query(collection_id, dataset_id, pool1.clone())?;
+        get_collection_point_ids_query(collection_id, dataset_id, pool1)
+    })
+    .await?
+    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    if collection_point_ids.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Collection does not have any chunks to recommend from".into(),
+        )
+        .into());
+    }
+
+    let positive_ids = collection_point_ids
+        .iter()
+        .take(max_seeds)
+        .cloned()
+        .collect::<Vec<uuid::Uuid>>();
+    let candidate_point_ids = if data.within_collection.unwrap_or(false) {
+        Some(collection_point_ids)
+    } else {
+        None
+    };
+
+    let recommendations = recommend_scored_qdrant_query(
+        positive_ids,
+        dataset_id,
+        embed_size,
+        acl_groups,
+        candidate_point_ids,
+    )
+    .await
+    .map_err(qdrant_error_to_service_error)?;
+
+    let point_ids = recommendations
+        .iter()
+        .map(|recommendation| recommendation.point_id)
+        .collect::<Vec<uuid::Uuid>>();
+    let chunks = web::block(move || get_metadata_from_point_ids(point_ids, pool))
+        .await?
+        .map_err(|err| ServiceError::InternalServerError(err.message.into()))?;
+
+    let score_chunks = recommendations
+        .into_iter()
+        .filter_map(|recommendation| {
+            chunks
+                .iter()
+                .find(|chunk| chunk.qdrant_point_id == recommendation.point_id)
+                .map(|chunk| ScoreChunkDTO::new(vec![chunk.clone()], recommendation.score as f64))
+        })
+        .collect::<Vec<ScoreChunkDTO>>();
+
+    Ok(HttpResponse::Ok().json(score_chunks))
+}
+
 #[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct GenerateOffCollectionData {
     pub collection_id: uuid::Uuid,
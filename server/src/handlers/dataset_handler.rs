@@ -2,21 +2,33 @@ use super::auth_handler::{AdminOnly, LoggedUser, OwnerOnly};
 use crate::{
     data::models::{
         ClientDatasetConfiguration, Dataset, DatasetAndOrgWithSubAndPlan, Pool,
-        ServerDatasetConfiguration, StripePlan,
+        PublicDatasetConfiguration, ServerDatasetConfiguration, StripePlan,
     },
     errors::ServiceError,
     operators::{
+        chunk_operator::{
+            get_chunk_qdrant_point_ids_by_dataset_query, get_point_chunk_count_query,
+        },
         dataset_operator::{
-            create_dataset_query, delete_dataset_by_id_query, get_dataset_by_id_query,
-            get_datasets_by_organization_id, update_dataset_query,
+            acquire_reindex_fulltext_lease_query, create_dataset_query, delete_dataset_by_id_query,
+            encrypt_server_configuration, get_dataset_by_id_query, get_datasets_by_organization_id,
+            get_reindex_fulltext_progress_query, redact_dataset, reindex_fulltext_query,
+            release_reindex_fulltext_lease_query, set_reindex_fulltext_progress_query,
+            update_dataset_query, ReindexFulltextProgress,
         },
+        model_operator::validate_splade_model,
         organization_operator::{get_org_dataset_count, get_organization_by_key_query},
+        qdrant_operator::{
+            delete_qdrant_point_ids_query, scroll_dataset_qdrant_point_ids_query,
+            validate_embedding_normalization, validate_image_embedding,
+        },
         stripe_operator::refresh_redis_org_plan_sub,
     },
 };
 use actix_web::{web, FromRequest, HttpMessage, HttpResponse};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashSet;
 use std::future::{ready, Ready};
 use utoipa::ToSchema;
 
@@ -45,7 +57,7 @@ pub struct CreateDatasetRequest {
     pub dataset_name: String,
     /// Organization ID that the dataset will belong to.
     pub organization_id: uuid::Uuid,
-    /// Server configuration for the dataset, can be arbitrary JSON. We recommend setting to `{}` to start. See docs.trieve.ai for more information or adjust with the admin dashboard.
+    /// Server configuration for the dataset, can be arbitrary JSON. We recommend setting to `{}` to start. See docs.trieve.ai for more information or adjust with the admin dashboard. LLM_API_KEY and EMBEDDING_API_KEY can be set to use the dataset's own provider keys instead of the server's. They are encrypted before they are stored and are never returned.
     pub server_configuration: serde_json::Value,
     /// Client configuration for the dataset, can be arbitrary JSON. We recommend setting to `{}` to start. See docs.trieve.ai for more information or adjust with the admin dashboard.
     pub client_configuration: serde_json::Value,
@@ -94,15 +106,20 @@ pub async fn create_dataset(
             .json(json!({"message": "Your plan must be upgraded to create additional datasets"})));
     }
 
+    let dataset_config = ServerDatasetConfiguration::from_json(data.server_configuration.clone());
+    validate_embedding_normalization(&dataset_config).await?;
+    validate_image_embedding(&dataset_config).await?;
+    validate_splade_model(&dataset_config)?;
+
     let dataset = Dataset::from_details(
         data.dataset_name.clone(),
         data.organization_id,
-        data.server_configuration.clone(),
+        encrypt_server_configuration(data.server_configuration.clone(), None)?,
         data.client_configuration.clone(),
     );
 
     let d = create_dataset_query(dataset, pool).await?;
-    Ok(HttpResponse::Ok().json(d))
+    Ok(HttpResponse::Ok().json(redact_dataset(d)))
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
@@ -111,7 +128,7 @@ pub struct UpdateDatasetRequest {
     pub dataset_id: uuid::Uuid,
     /// The new name of the dataset. Must be unique within the organization. If not provided, the name will not be updated.
     pub dataset_name: Option<String>,
-    /// The new server configuration of the dataset, can be arbitrary JSON. See docs.trieve.ai for more information. If not provided, the server configuration will not be updated.
+    /// The new server configuration of the dataset, can be arbitrary JSON. See docs.trieve.ai for more information. If not provided, the server configuration will not be updated. LLM_API_KEY and EMBEDDING_API_KEY are kept if they are left out and removed if they are set to null.
     pub server_configuration: Option<serde_json::Value>,
     /// The new client configuration of the dataset, can be arbitrary JSON. See docs.trieve.ai for more information. If not provided, the client configuration will not be updated.
     pub client_configuration: Option<serde_json::Value>,
@@ -119,7 +136,7 @@ pub struct UpdateDatasetRequest {
 
 /// update_dataset
 ///
-/// Update a dataset. The auth'ed user must be an owner of the organization to update a dataset.
+/// Update a dataset. The auth'ed user must be an owner of the organization to update a dataset. Changing the server configuration's SPLADE_MODEL starts a full-text reindex of the dataset so its chunks are encoded with the new model, and is rejected while a reindex is already running.
 #[utoipa::path(
     put,
     path = "/dataset",
@@ -137,18 +154,53 @@ pub async fn update_dataset(
     _user: OwnerOnly,
 ) -> Result<HttpResponse, ServiceError> {
     let curr_dataset = get_dataset_by_id_query(data.dataset_id, pool.clone()).await?;
-    let d = update_dataset_query(
+    let mut reindex_lease_token = None;
+    if let Some(server_configuration) = data.server_configuration.clone() {
+        let dataset_config = ServerDatasetConfiguration::from_json(server_configuration);
+        validate_embedding_normalization(&dataset_config).await?;
+        validate_image_embedding(&dataset_config).await?;
+        validate_splade_model(&dataset_config)?;
+
+        let splade_model_changed = dataset_config.SPLADE_MODEL
+            != ServerDatasetConfiguration::from_json(curr_dataset.server_configuration.clone())
+                .SPLADE_MODEL;
+        if splade_model_changed {
+            // Take the reindex lease before saving so the model cannot change under a running reindex
+            reindex_lease_token = Some(
+                acquire_reindex_fulltext_lease_query(data.dataset_id)
+                    .await?
+                    .ok_or(ServiceError::BadRequest(
+                        "SPLADE_MODEL cannot be changed while a full-text reindex is running for this dataset".into(),
+                    ))?,
+            );
+        }
+    }
+
+    let updated_dataset = update_dataset_query(
         data.dataset_id,
         data.dataset_name.clone().unwrap_or(curr_dataset.name),
-        data.server_configuration
-            .clone()
-            .unwrap_or(curr_dataset.server_configuration),
+        match data.server_configuration.clone() {
+            Some(server_configuration) => encrypt_server_configuration(
+                server_configuration,
+                Some(&curr_dataset.server_configuration),
+            )?,
+            None => curr_dataset.server_configuration,
+        },
         data.client_configuration
             .clone()
             .unwrap_or(curr_dataset.client_configuration),
         pool.clone(),
     )
-    .await?;
+    .await;
+    let d = match updated_dataset {
+        Ok(d) => d,
+        Err(err) => {
+            if let Some(lease_token) = reindex_lease_token {
+                let _ = release_reindex_fulltext_lease_query(data.dataset_id, lease_token).await;
+            }
+            return Err(err);
+        }
+    };
     let _ = refresh_redis_org_plan_sub(d.organization_id, pool.clone())
         .await
         .map_err(|err| {
@@ -157,7 +209,13 @@ pub async fn update_dataset(
                 err
             ))
         });
-    Ok(HttpResponse::Ok().json(d))
+
+    if let Some(lease_token) = reindex_lease_token {
+        // Chunks encoded with the previous model would not match queries encoded with the new one
+        run_reindex_fulltext(d.clone(), lease_token, pool).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(redact_dataset(d)))
 }
 
 #[derive(Serialize, Deserialize, Debug, ToSchema, Clone)]
@@ -279,3 +337,303 @@ pub async fn get_client_dataset_config(
         )),
     )
 }
+
+/// get_dataset_config
+///
+/// Get the search-relevant server configuration for a dataset, such as the embedding size, the embedding model and the duplicate distance threshold. Base urls and prompts are omitted. This is useful for validating the length of a chunk_vector before creating chunks.
+#[utoipa::path(
+    get,
+    path = "/dataset/config",
+    context_path = "/api",
+    tag = "dataset",
+    responses(
+        (status = 200, description = "Search-relevant server configuration for the dataset", body = PublicDatasetConfiguration),
+        (status = 400, description = "Service error relating to retrieving the dataset. Typically this only happens when your auth credentials are invalid.", body = DefaultError),
+    ),
+)]
+pub async fn get_dataset_config(
+    dataset: DatasetAndOrgWithSubAndPlan,
+    _logged_user: LoggedUser,
+) -> Result<HttpResponse, ServiceError> {
+    Ok(HttpResponse::Ok().json(PublicDatasetConfiguration::from(
+        ServerDatasetConfiguration::from_json(dataset.dataset.server_configuration),
+    )))
+}
+
+/// Qdrant points updated within this many seconds are never deleted by a repair, as their chunk may still be being written.
+pub const INTEGRITY_REPAIR_GRACE_PERIOD_SECS: i64 = 600;
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct DatasetIntegrityReport {
+    /// Ids of chunks whose qdrant point is missing from qdrant. These chunks will not show up in semantic or full-text search.
+    pub chunks_missing_points: Vec<uuid::Uuid>,
+    /// Ids of qdrant points in the dataset which have no backing chunk.
+    pub orphaned_point_ids: Vec<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct DatasetIntegrityRepair {
+    /// Ids of the orphaned qdrant points which were deleted.
+    pub deleted_point_ids: Vec<uuid::Uuid>,
+    /// Ids of the orphaned qdrant points which were left in place because they were updated within the grace period. Their chunk may still be being written.
+    pub skipped_point_ids: Vec<uuid::Uuid>,
+}
+
+/// Returns the ids of the chunks missing their qdrant point and the orphaned qdrant points along with the unix timestamp they were last updated at, if their payload has one.
+async fn check_dataset_integrity(
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(Vec<uuid::Uuid>, Vec<(uuid::Uuid, Option<i64>)>), ServiceError> {
+    let chunk_point_ids =
+        web::block(move || get_chunk_qdrant_point_ids_by_dataset_query(dataset_id, pool))
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let qdrant_points = scroll_dataset_qdrant_point_ids_query(dataset_id)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let qdrant_point_ids: HashSet<uuid::Uuid> = qdrant_points
+        .iter()
+        .map(|(point_id, _)| *point_id)
+        .collect();
+
+    let expected_point_ids: HashSet<uuid::Uuid> = chunk_point_ids
+        .iter()
+        .filter_map(|(_, point_id)| *point_id)
+        .collect();
+
+    let chunks_missing_points = chunk_point_ids
+        .iter()
+        .filter(|(_, point_id)| match point_id {
+            Some(point_id) => !qdrant_point_ids.contains(point_id),
+            None => true,
+        })
+        .map(|(chunk_id, _)| *chunk_id)
+        .collect::<Vec<uuid::Uuid>>();
+
+    let orphaned_points = qdrant_points
+        .into_iter()
+        .filter(|(point_id, _)| !expected_point_ids.contains(point_id))
+        .collect::<Vec<(uuid::Uuid, Option<i64>)>>();
+
+    Ok((chunks_missing_points, orphaned_points))
+}
+
+/// get_dataset_integrity
+///
+/// Compare every chunk in the dataset against the points stored in qdrant and report any drift between the two, such as that left behind by a partially failed chunk write. Nothing is modified; use POST /dataset/integrity to delete orphaned points. The auth'ed user must be an admin or owner of the organization.
+#[utoipa::path(
+    get,
+    path = "/dataset/integrity",
+    context_path = "/api",
+    tag = "dataset",
+    responses(
+        (status = 200, description = "Report of the inconsistencies between the dataset's chunks and qdrant points", body = DatasetIntegrityReport),
+        (status = 400, description = "Service error relating to checking the integrity of the dataset", body = DefaultError),
+    ),
+)]
+pub async fn get_dataset_integrity(
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (chunks_missing_points, orphaned_points) =
+        check_dataset_integrity(dataset_org_plan_sub.dataset.id, pool).await?;
+
+    Ok(HttpResponse::Ok().json(DatasetIntegrityReport {
+        chunks_missing_points,
+        orphaned_point_ids: orphaned_points
+            .into_iter()
+            .map(|(point_id, _)| point_id)
+            .collect(),
+    }))
+}
+
+/// repair_dataset_integrity
+///
+/// Delete the qdrant points in the dataset which have no backing chunk. Points updated within the last 10 minutes are skipped, as a chunk write may be in progress between qdrant and the database. Chunks missing their qdrant point are not repaired. The auth'ed user must be an admin or owner of the organization.
+#[utoipa::path(
+    post,
+    path = "/dataset/integrity",
+    context_path = "/api",
+    tag = "dataset",
+    responses(
+        (status = 200, description = "The orphaned qdrant points which were deleted and skipped", body = DatasetIntegrityRepair),
+        (status = 400, description = "Service error relating to repairing the dataset", body = DefaultError),
+    ),
+)]
+pub async fn repair_dataset_integrity(
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (_, orphaned_points) =
+        check_dataset_integrity(dataset_org_plan_sub.dataset.id, pool).await?;
+
+    let grace_cutoff =
+        chrono::Utc::now().naive_local().timestamp() - INTEGRITY_REPAIR_GRACE_PERIOD_SECS;
+
+    let (deleted_points, skipped_points): (Vec<_>, Vec<_>) =
+        orphaned_points.into_iter().partition(|(_, updated_at)| {
+            updated_at.map_or(true, |updated_at| updated_at < grace_cutoff)
+        });
+
+    let deleted_point_ids = deleted_points
+        .into_iter()
+        .map(|(point_id, _)| point_id)
+        .collect::<Vec<uuid::Uuid>>();
+
+    delete_qdrant_point_ids_query(deleted_point_ids.clone())
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    Ok(HttpResponse::Ok().json(DatasetIntegrityRepair {
+        deleted_point_ids,
+        skipped_point_ids: skipped_points
+            .into_iter()
+            .map(|(point_id, _)| point_id)
+            .collect(),
+    }))
+}
+
+/// reindex_fulltext
+///
+/// Start rebuilding the full-text index of every chunk in the dataset in the background. Use this after changing the dataset's FULLTEXT_STEMMING_LANGUAGE or FULLTEXT_UNACCENT, which otherwise only apply to chunks created or updated afterwards. A reindex is started automatically when the dataset's SPLADE_MODEL is changed. Poll GET /dataset/reindex_fulltext for the number of chunks reindexed so far. Only one reindex can run for a dataset at a time. A reindex which stops recording progress for 5 minutes, e.g. because the server restarted, is reported as failed and a new one can be started. The auth'ed user must be an admin or owner of the organization.
+#[utoipa::path(
+    post,
+    path = "/dataset/reindex_fulltext",
+    context_path = "/api",
+    tag = "dataset",
+    responses(
+        (status = 202, description = "The reindex was started, with its initial progress", body = ReindexFulltextProgress),
+        (status = 400, description = "Service error relating to starting the reindex, such as one already running", body = DefaultError),
+    ),
+)]
+pub async fn reindex_fulltext(
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    pool: web::Data<Pool>,
+    _user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let progress = start_reindex_fulltext(dataset_org_plan_sub.dataset, pool).await?;
+
+    Ok(HttpResponse::Accepted().json(progress))
+}
+
+/// Takes the dataset's full-text reindex lease and starts the reindex.
+async fn start_reindex_fulltext(
+    dataset: Dataset,
+    pool: web::Data<Pool>,
+) -> Result<ReindexFulltextProgress, ServiceError> {
+    let lease_token = acquire_reindex_fulltext_lease_query(dataset.id)
+        .await?
+        .ok_or(ServiceError::BadRequest(
+            "A full-text reindex is already running for this dataset".into(),
+        ))?;
+
+    run_reindex_fulltext(dataset, lease_token, pool).await
+}
+
+/// Records the initial progress of a full-text reindex of the dataset and runs it in the
+/// background, releasing the reindex lease held with `lease_token` once it finishes.
+async fn run_reindex_fulltext(
+    dataset: Dataset,
+    lease_token: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<ReindexFulltextProgress, ServiceError> {
+    let dataset_id = dataset.id;
+
+    let count_pool = pool.clone();
+    let total = match web::block(move || get_point_chunk_count_query(dataset_id, count_pool))
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))
+        .and_then(|total| total.map_err(|err| ServiceError::BadRequest(err.message.into())))
+    {
+        Ok(total) => total,
+        Err(err) => {
+            let _ = release_reindex_fulltext_lease_query(dataset_id, lease_token).await;
+            return Err(err);
+        }
+    };
+
+    let started_at = chrono::Utc::now().naive_local();
+    let progress = ReindexFulltextProgress {
+        status: "running".to_string(),
+        reindexed: 0,
+        total,
+        started_at,
+        heartbeat_at: started_at,
+        finished_at: None,
+        error: None,
+    };
+    if let Err(err) = set_reindex_fulltext_progress_query(dataset_id, &progress).await {
+        let _ = release_reindex_fulltext_lease_query(dataset_id, lease_token).await;
+        return Err(err);
+    }
+
+    let job_progress = progress.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(err) =
+            reindex_fulltext_query(dataset, job_progress.clone(), lease_token, pool).await
+        {
+            log::error!(
+                "Failed to reindex full-text for dataset {}: {}",
+                dataset_id,
+                err
+            );
+
+            let stored_progress = get_reindex_fulltext_progress_query(dataset_id)
+                .await
+                .ok()
+                .flatten();
+            // A reindex which lost its lease must not overwrite the progress of the one which took it
+            let superseded = stored_progress
+                .as_ref()
+                .is_some_and(|progress| progress.started_at != job_progress.started_at);
+            if !superseded {
+                let reindexed =
+                    stored_progress.map_or(job_progress.reindexed, |progress| progress.reindexed);
+                let _ = set_reindex_fulltext_progress_query(
+                    dataset_id,
+                    &ReindexFulltextProgress {
+                        status: "failed".to_string(),
+                        reindexed,
+                        finished_at: Some(chrono::Utc::now().naive_local()),
+                        error: Some(err.to_string()),
+                        ..job_progress
+                    },
+                )
+                .await;
+            }
+        }
+
+        let _ = release_reindex_fulltext_lease_query(dataset_id, lease_token).await;
+    });
+
+    Ok(progress)
+}
+
+/// get_reindex_fulltext_progress
+///
+/// Get the progress of the dataset's most recent full-text reindex, including the number of chunks reindexed. Progress is kept for a week after the reindex last made progress. The auth'ed user must be an admin or owner of the organization.
+#[utoipa::path(
+    get,
+    path = "/dataset/reindex_fulltext",
+    context_path = "/api",
+    tag = "dataset",
+    responses(
+        (status = 200, description = "The progress of the dataset's most recent full-text reindex", body = ReindexFulltextProgress),
+        (status = 404, description = "No full-text reindex has been run for the dataset recently"),
+    ),
+)]
+pub async fn get_reindex_fulltext_progress(
+    dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
+    _user: AdminOnly,
+) -> Result<HttpResponse, actix_web::Error> {
+    let progress = get_reindex_fulltext_progress_query(dataset_org_plan_sub.dataset.id)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+
+    Ok(HttpResponse::Ok().json(progress))
+}
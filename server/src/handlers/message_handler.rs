@@ -1,9 +1,11 @@
-use super::{auth_handler::LoggedUser, chunk_handler::ParsedQuery};
+use super::{
+    auth_handler::LoggedUser,
+    chunk_handler::{get_acl_groups, ParsedQuery},
+};
 use crate::{
     data::models::{self, DatasetAndOrgWithSubAndPlan, ServerDatasetConfiguration},
     data::models::{ChunkMetadataWithFileData, Dataset, Pool, StripePlan},
     errors::{DefaultError, ServiceError},
-    get_env,
     operators::{
         chunk_operator::{
             find_relevant_sentence, get_metadata_and_collided_chunks_from_point_ids_query,
@@ -13,8 +15,9 @@ use crate::{
             get_message_by_sort_for_topic_query, get_messages_for_topic_query, get_topic_messages,
             user_owns_topic_query,
         },
-        model_operator::create_embedding,
+        model_operator::{create_query_embedding, get_llm_api_key},
         organization_operator::get_message_org_count,
+        qdrant_operator::qdrant_error_to_service_error,
         search_operator::retrieve_qdrant_points_query,
     },
 };
@@ -151,6 +154,7 @@ pub async fn create_message_completion_handler(
         user.id,
         topic_id,
         create_message_data.model,
+        get_acl_groups(&user, &dataset_org_plan_sub.dataset),
         dataset_org_plan_sub.dataset,
         pool4,
     )
@@ -343,6 +347,7 @@ pub async fn regenerate_message_handler(
             user.id,
             topic_id,
             data.model.clone(),
+            get_acl_groups(&user, &dataset_org_plan_sub.dataset),
             dataset_org_plan_sub.dataset,
             pool3,
         )
@@ -401,6 +406,7 @@ pub async fn regenerate_message_handler(
         user.id,
         topic_id,
         data.model.clone(),
+        get_acl_groups(&user, &dataset_org_plan_sub.dataset),
         dataset_org_plan_sub.dataset,
         pool3,
     )
@@ -439,9 +445,11 @@ pub async fn get_topic_string(prompt: String, dataset: &Dataset) -> Result<Strin
         seed: None,
     };
 
-    let openai_api_key = get_env!("OPENROUTER_API_KEY", "OPENROUTER_API_KEY should be set").into();
     let dataset_config =
         ServerDatasetConfiguration::from_json(dataset.server_configuration.clone());
+    let openai_api_key = get_llm_api_key(&dataset_config).map_err(|_| DefaultError {
+        message: "Failed to decrypt the dataset's LLM API key",
+    })?;
     let base_url = dataset_config
         .LLM_BASE_URL
         .unwrap_or("https://openrouter.ai/v1".into());
@@ -477,6 +485,7 @@ pub async fn stream_response(
     user_id: uuid::Uuid,
     topic_id: uuid::Uuid,
     model: Option<String>,
+    acl_groups: Option<Vec<String>>,
     dataset: Dataset,
     pool: web::Data<Pool>,
 ) -> Result<HttpResponse, actix_web::Error> {
@@ -489,7 +498,7 @@ pub async fn stream_response(
         .map(|message| ChatMessage::from(message.clone()))
         .collect();
 
-    let openai_api_key = get_env!("OPENROUTER_API_KEY", "OPENROUTER_API_KEY should be set").into();
+    let openai_api_key = get_llm_api_key(&dataset_config)?;
     let base_url = dataset_config
         .LLM_BASE_URL
         .clone()
@@ -576,25 +585,37 @@ pub async fn stream_response(
             ChatMessageContent::Text(query) => query.clone(),
             _ => "".to_string(),
         };
-        let embedding_vector = create_embedding(query.as_str(), dataset_config.clone()).await?;
+        let embedding_vector =
+            create_query_embedding(query.as_str(), dataset_config.clone()).await?;
 
         let search_chunk_query_results = retrieve_qdrant_points_query(
             Some(embedding_vector),
+            None,
             1,
             None,
             None,
             None,
             None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            acl_groups,
             ParsedQuery {
                 query: query.to_string(),
                 quote_words: None,
                 negated_words: None,
+                unaccented: false,
+                prefix: None,
+                boosts: vec![],
+                splade_model: None,
             },
             dataset.id,
             pool.clone(),
         )
         .await
-        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+        .map_err(qdrant_error_to_service_error)?;
         let n_retrievals_to_include = dataset_config.N_RETRIEVALS_TO_INCLUDE.unwrap_or(3);
 
         let retrieval_chunk_ids = search_chunk_query_results
@@ -615,7 +636,8 @@ pub async fn stream_response(
         let highlighted_citation_chunks = citation_chunks
             .iter()
             .map(|chunk| {
-                find_relevant_sentence(chunk.clone(), query.to_string()).unwrap_or(chunk.clone())
+                find_relevant_sentence(chunk.clone(), query.to_string(), None, None)
+                    .unwrap_or(chunk.clone())
             })
             .collect::<Vec<ChunkMetadataWithFileData>>();
 
@@ -754,9 +776,9 @@ pub async fn create_suggested_queries_handler(
     dataset_org_plan_sub: DatasetAndOrgWithSubAndPlan,
     _required_user: LoggedUser,
 ) -> Result<HttpResponse, ServiceError> {
-    let openai_api_key = get_env!("OPENROUTER_API_KEY", "OPENROUTER_API_KEY should be set").into();
     let dataset_config =
         ServerDatasetConfiguration::from_json(dataset_org_plan_sub.dataset.server_configuration);
+    let openai_api_key = get_llm_api_key(&dataset_config)?;
     let base_url = dataset_config
         .LLM_BASE_URL
         .unwrap_or("https://openrouter.ai/v1".into());
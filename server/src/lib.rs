@@ -4,7 +4,8 @@ extern crate diesel;
 use crate::{
     handlers::auth_handler::build_oidc_client,
     operators::{
-        qdrant_operator::create_new_qdrant_collection_query, user_operator::create_default_user},
+        qdrant_operator::{create_new_qdrant_collection_query, migrate_qdrant_collection_query},
+        user_operator::create_default_user},
     
 };
 use actix_cors::Cors;
@@ -12,6 +13,7 @@ use actix_identity::IdentityMiddleware;
 use actix_session::{config::PersistentSession, storage::RedisSessionStore, SessionMiddleware};
 use actix_web::{
     cookie::{Key, SameSite},
+    error::JsonPayloadError,
     middleware,
     web::{self, PayloadConfig},
     App, HttpServer,
@@ -33,6 +35,72 @@ pub const SECONDS_IN_MINUTE: u64 = 60;
 pub const SECONDS_IN_HOUR: u64 = 60 * SECONDS_IN_MINUTE;
 pub const SECONDS_IN_DAY: u64 = 24 * SECONDS_IN_HOUR;
 
+/// Default limit on request bodies in bytes, from PAYLOAD_LIMIT_BYTES. Applies to every route
+/// without a limit of its own and defaults to 134200000 (about 128 MiB).
+const DEFAULT_PAYLOAD_LIMIT_BYTES: usize = 134200000;
+/// Default limit for routes importing content, such as creating chunks and uploading files, from
+/// IMPORT_PAYLOAD_LIMIT_BYTES. Defaults to 268435456 (256 MiB) to leave room for base64 encoded
+/// files and large chunk_html.
+const DEFAULT_IMPORT_PAYLOAD_LIMIT_BYTES: usize = 268435456;
+/// Default limit for search, recommendation and other query routes, from SEARCH_PAYLOAD_LIMIT_BYTES.
+/// Their bodies are small, so this defaults to 1048576 (1 MiB) to keep them from being used to tie
+/// up the server.
+const DEFAULT_SEARCH_PAYLOAD_LIMIT_BYTES: usize = 1048576;
+
+fn payload_limit_from_env(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|limit| limit.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+/// JSON extractor config limiting bodies to `limit` bytes. Bodies over the limit are rejected with a
+/// 413 naming the limit instead of actix's bare overflow error.
+fn json_config_with_limit(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|err, _req| match err {
+            JsonPayloadError::OverflowKnownLength { length, limit } => {
+                errors::ServiceError::PayloadTooLarge(format!(
+                    "The request body of {} bytes is larger than this route's limit of {} bytes",
+                    length, limit
+                ))
+                .into()
+            }
+            JsonPayloadError::Overflow { limit } => errors::ServiceError::PayloadTooLarge(format!(
+                "The request body is larger than this route's limit of {} bytes",
+                limit
+            ))
+            .into(),
+            err => err.into(),
+        })
+}
+
+/// The JSON body limit of a route under /api/chunk. Routes importing content get the import limit,
+/// routes whose bodies only hold queries, ids and options get the search limit and the rest keep
+/// the default.
+fn chunk_route_payload_limit(
+    route: &str,
+    payload_limit: usize,
+    import_payload_limit: usize,
+    search_payload_limit: usize,
+) -> usize {
+    match route {
+        "" | "/update" | "/tracking_id/update" => import_payload_limit,
+        "/recommend"
+        | "/recommend/batch"
+        | "/warm_queries"
+        | "/rerank"
+        | "/search"
+        | "/generate"
+        | "/sparse_encode"
+        | "/estimate"
+        | "/search_and_generate"
+        | "/tracking_ids/exists" => search_payload_limit,
+        _ => payload_limit,
+    }
+}
+
 fn run_migrations(conn: &mut impl MigrationHarness<diesel::pg::Pg>) {
     conn.run_pending_migrations(MIGRATIONS).unwrap();
 }
@@ -81,13 +149,27 @@ pub async fn main() -> std::io::Result<()> {
             handlers::chunk_handler::update_chunk,
             handlers::chunk_handler::delete_chunk,
             handlers::chunk_handler::get_recommended_chunks,
+            handlers::chunk_handler::get_batch_recommended_chunks,
+            handlers::chunk_handler::warm_queries,
             handlers::message_handler::create_suggested_queries_handler,
             handlers::chunk_handler::update_chunk_by_tracking_id,
             handlers::chunk_handler::search_chunk,
             handlers::chunk_handler::generate_off_chunks,
+            handlers::chunk_handler::search_and_generate,
+            handlers::chunk_handler::sparse_encode,
+            handlers::chunk_handler::estimate_cost,
             handlers::chunk_handler::get_chunk_by_tracking_id,
+            handlers::chunk_handler::get_chunks_for_file,
+            handlers::chunk_handler::check_tracking_ids_exist,
+            handlers::chunk_handler::rerank_chunk_ids,
             handlers::chunk_handler::delete_chunk_by_tracking_id,
+            handlers::chunk_handler::delete_chunks_by_tracking_ids,
             handlers::chunk_handler::get_chunk_by_id,
+            handlers::chunk_handler::get_collision_count,
+            handlers::chunk_handler::get_chunk_collisions,
+            handlers::chunk_handler::get_chunk_history,
+            handlers::chunk_handler::get_chunk_diff,
+            handlers::chunk_handler::get_chunk_keywords,
             handlers::user_handler::update_user,
             handlers::user_handler::set_user_api_key,
             handlers::user_handler::delete_user_api_key,
@@ -96,12 +178,19 @@ pub async fn main() -> std::io::Result<()> {
             handlers::collection_handler::get_specific_user_chunk_collections,
             handlers::collection_handler::create_chunk_collection,
             handlers::collection_handler::delete_chunk_collection,
+            handlers::collection_handler::restore_chunk_collection,
+            handlers::collection_handler::purge_chunk_collection,
             handlers::collection_handler::update_chunk_collection,
             handlers::collection_handler::add_bookmark,
+            handlers::collection_handler::add_bookmarks_from_search,
             handlers::collection_handler::delete_bookmark,
             handlers::collection_handler::get_logged_in_user_chunk_collections,
+            handlers::collection_handler::list_chunk_collections,
             handlers::collection_handler::get_all_bookmarks,
+            handlers::collection_handler::get_collection_chunks,
             handlers::collection_handler::get_collections_chunk_is_in,
+            handlers::collection_handler::set_chunk_collections,
+            handlers::collection_handler::recommend_from_collection,
             handlers::chunk_handler::search_collections,
             handlers::file_handler::upload_file_handler,
             handlers::file_handler::get_file_handler,
@@ -122,6 +211,14 @@ pub async fn main() -> std::io::Result<()> {
             handlers::dataset_handler::get_dataset,
             handlers::dataset_handler::get_datasets_from_organization,
             handlers::dataset_handler::get_client_dataset_config,
+            handlers::dataset_handler::get_dataset_config,
+            handlers::dataset_handler::get_dataset_integrity,
+            handlers::dataset_handler::repair_dataset_integrity,
+            handlers::dataset_handler::reindex_fulltext,
+            handlers::dataset_handler::get_reindex_fulltext_progress,
+            handlers::analytics_handler::get_top_queries,
+            handlers::analytics_handler::record_search_click,
+            handlers::analytics_handler::record_search_feedback,
             handlers::stripe_handler::direct_to_payment_link,
             handlers::stripe_handler::cancel_subscription,
             handlers::stripe_handler::update_subscription_plan,
@@ -141,13 +238,49 @@ pub async fn main() -> std::io::Result<()> {
                 handlers::message_handler::SuggestedQueriesResponse,
                 handlers::chunk_handler::CreateChunkData,
                 handlers::chunk_handler::ReturnCreatedChunk,
+                handlers::chunk_handler::ChunkCollisionDetails,
+                handlers::chunk_handler::ChunkQuotaExceeded,
+                handlers::chunk_handler::SearchPageLimitExceeded,
+                handlers::chunk_handler::WarmQueriesData,
+                handlers::chunk_handler::WarmQueriesResponse,
+                handlers::chunk_handler::AggSpec,
+                handlers::chunk_handler::AggregationResult,
+                handlers::chunk_handler::ChunkExplanation,
+                handlers::chunk_handler::CollisionCountResponse,
+                handlers::chunk_handler::CollisionGroupResponse,
+                handlers::chunk_handler::DeleteTrackingIdsData,
+                handlers::chunk_handler::DeleteTrackingIdsResponse,
+                handlers::chunk_handler::ChunkDiffQuery,
+                handlers::chunk_handler::ChunkDiffChange,
+                handlers::chunk_handler::ChunkDiffResponse,
+                handlers::chunk_handler::ChunkKeywordsQuery,
+                handlers::chunk_handler::ChunkKeyword,
+                handlers::chunk_handler::ChunkKeywordsResponse,
                 handlers::chunk_handler::UpdateChunkData,
                 handlers::chunk_handler::RecommendChunksRequest,
+                handlers::chunk_handler::WeightedChunkExample,
+                handlers::chunk_handler::ChunksForFileQuery,
+                handlers::chunk_handler::ChunksForFileResponse,
+                handlers::chunk_handler::TrackingIdsExistData,
+                handlers::chunk_handler::TrackingIdsExistResponse,
+                handlers::chunk_handler::RerankChunksRequest,
+                handlers::chunk_handler::RerankChunksResponse,
                 handlers::chunk_handler::UpdateChunkByTrackingIdData,
                 handlers::chunk_handler::SearchChunkQueryResponseBody,
+                handlers::chunk_handler::ScoreChunkGroup,
+                handlers::chunk_handler::GroupedSearchChunkQueryResponseBody,
+                handlers::chunk_handler::SearchChunkResponseTypes,
                 handlers::chunk_handler::GenerateChunksRequest,
+                handlers::chunk_handler::SearchAndGenerateRequest,
+                handlers::chunk_handler::SparseEncodeData,
+                handlers::chunk_handler::SparseEncodeResponse,
+                handlers::chunk_handler::EstimateCostData,
+                handlers::chunk_handler::ContentCostEstimate,
+                handlers::chunk_handler::EstimateCostResponse,
                 handlers::chunk_handler::SearchChunkData,
+                handlers::chunk_handler::SearchQuery,
                 handlers::chunk_handler::ScoreChunkDTO,
+                handlers::chunk_handler::ChunkNeighbors,
                 handlers::chunk_handler::SearchCollectionsData,
                 handlers::chunk_handler::SearchCollectionsResult,
                 handlers::user_handler::UpdateUserData,
@@ -156,17 +289,25 @@ pub async fn main() -> std::io::Result<()> {
                 handlers::user_handler::SetUserApiKeyResponse,
                 handlers::user_handler::DeleteUserApiKeyRequest,
                 handlers::collection_handler::CollectionData,
+                handlers::collection_handler::ListCollectionsQuery,
+                handlers::collection_handler::CollectionsWithCountsData,
                 handlers::collection_handler::UserCollectionQuery,
                 handlers::collection_handler::CreateChunkCollectionData,
                 handlers::collection_handler::DeleteCollectionData,
+                handlers::collection_handler::DeleteCollectionQuery,
+                handlers::collection_handler::DeleteCollectionResult,
                 handlers::collection_handler::UpdateChunkCollectionData,
                 handlers::collection_handler::AddChunkToCollectionData,
                 handlers::collection_handler::GetCollectionsForChunksData,
+                handlers::collection_handler::SetChunkCollectionsData,
                 handlers::collection_handler::DeleteBookmarkPathData,
                 handlers::collection_handler::GenerateOffCollectionData,
                 handlers::collection_handler::GetAllBookmarksData,
                 handlers::collection_handler::BookmarkChunks,
                 handlers::collection_handler::BookmarkData,
+                handlers::collection_handler::CollectionChunksResponse,
+                handlers::collection_handler::AddFromSearchResponse,
+                handlers::collection_handler::RecommendFromCollectionData,
                 operators::collection_operator::BookmarkCollectionResult,
                 handlers::file_handler::UploadFileData,
                 handlers::file_handler::UploadFileResult,
@@ -179,6 +320,14 @@ pub async fn main() -> std::io::Result<()> {
                 handlers::dataset_handler::CreateDatasetRequest,
                 handlers::dataset_handler::UpdateDatasetRequest,
                 handlers::dataset_handler::DeleteDatasetRequest,
+                handlers::dataset_handler::DatasetIntegrityReport,
+                handlers::dataset_handler::DatasetIntegrityRepair,
+                operators::dataset_operator::ReindexFulltextProgress,
+                handlers::analytics_handler::TopQueriesQuery,
+                handlers::analytics_handler::SearchClickData,
+                handlers::analytics_handler::SearchFeedbackData,
+                operators::analytics_operator::QueryCount,
+                operators::analytics_operator::TopQueriesResult,
                 handlers::stripe_handler::GetDirectPaymentLinkData,
                 handlers::stripe_handler::UpdateSubscriptionData,
                 data::models::ApiKeyDTO,
@@ -188,12 +337,14 @@ pub async fn main() -> std::io::Result<()> {
                 data::models::Topic,
                 data::models::Message,
                 data::models::ChunkMetadata,
+                data::models::ChunkVersion,
                 data::models::ChunkMetadataWithFileData,
                 data::models::ChatMessageProxy,
                 data::models::SlimCollection,
                 data::models::UserDTOWithChunks,
                 data::models::File,
                 data::models::ChunkCollection,
+                data::models::ChunkCollectionWithBookmarkCount,
                 data::models::ChunkCollectionAndFile,
                 data::models::FileDTO,
                 data::models::FileUploadCompletedNotificationWithName,
@@ -207,6 +358,7 @@ pub async fn main() -> std::io::Result<()> {
                 data::models::UserRole,
                 data::models::DatasetAndOrgWithSubAndPlan,
                 data::models::ClientDatasetConfiguration,
+                data::models::PublicDatasetConfiguration,
                 data::models::StripePlan,
                 data::models::StripeSubscription,
                 errors::DefaultError,
@@ -226,6 +378,7 @@ pub async fn main() -> std::io::Result<()> {
             (name = "message", description = "Message chat endpoint. Messages are units belonging to a topic in the context of a chat with a LLM. There are system, user, and assistant messages."),
             (name = "stripe", description = "Stripe endpoint. Used for the managed SaaS version of this app. Eventually this will become a micro-service. Reach out to the team using contact info found at `docs.trieve.ai` for more information."),
             (name = "health", description = "Health check endpoint. Used to check if the server is up and running."),
+            (name = "analytics", description = "Analytics endpoint. Used to record how users interact with search results for datasets which have search analytics enabled."),
         )
     )]
     struct ApiDoc;
@@ -253,16 +406,101 @@ pub async fn main() -> std::io::Result<()> {
         log::error!("Failed to create qdrant collection: {:?}", err);
     });
 
+    // Collections created before image embedding was supported have no clip_vectors and qdrant
+    // cannot add them in place, so their points are copied into a new collection instead
+    if let Ok(target_collection) = std::env::var("QDRANT_MIGRATION_TARGET_COLLECTION") {
+        match migrate_qdrant_collection_query(target_collection.clone()).await {
+            Ok(copied_points) => log::info!(
+                "Copied {} points into qdrant collection {}. Set QDRANT_COLLECTION to it and unset QDRANT_MIGRATION_TARGET_COLLECTION",
+                copied_points,
+                target_collection
+            ),
+            Err(err) => log::error!("Failed to migrate qdrant collection: {:?}", err),
+        }
+    }
+
     if std::env::var("ADMIN_API_KEY").is_ok() {
         let _ = create_default_user(&std::env::var("ADMIN_API_KEY").expect("ADMIN_API_KEY should be set"), web::Data::new(pool.clone())).map_err(|err| {
             log::error!("Failed to create default user: {:?}", err);
         });
     }
 
+    // Periodically deletes chunks whose expires_at has passed. Search already leaves them out, so
+    // this only reclaims their storage.
+    let expired_chunks_pool = web::Data::new(pool.clone());
+    let expired_chunks_interval = std::time::Duration::from_secs(
+        std::env::var("EXPIRED_CHUNKS_REAPER_INTERVAL_SECS")
+            .ok()
+            .and_then(|interval| interval.parse::<u64>().ok())
+            .unwrap_or(60),
+    );
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(expired_chunks_interval);
+        loop {
+            interval.tick().await;
+            match operators::chunk_operator::delete_expired_chunks_query(expired_chunks_pool.clone()).await {
+                Ok(0) => {}
+                Ok(deleted_count) => log::info!("Deleted {} expired chunks", deleted_count),
+                Err(err) => log::error!("Failed to delete expired chunks: {}", err.message),
+            }
+        }
+    });
+
+    // Periodically adjusts chunk weights by their search feedback for datasets with a
+    // FEEDBACK_WEIGHT_RATE, and restores them for datasets which no longer have one. Only the
+    // instance holding the redis lease runs each interval.
+    let feedback_weights_pool = web::Data::new(pool.clone());
+    let feedback_weights_interval = std::time::Duration::from_secs(
+        std::env::var("FEEDBACK_WEIGHTS_INTERVAL_SECS")
+            .ok()
+            .and_then(|interval| interval.parse::<u64>().ok())
+            .unwrap_or(3600),
+    );
+    // Expires just before the holder's next tick so it can keep the lease
+    let feedback_weights_lease_secs = feedback_weights_interval.as_secs().saturating_sub(1).max(1);
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(feedback_weights_interval);
+        loop {
+            interval.tick().await;
+            match operators::analytics_operator::acquire_feedback_weights_lease_query(feedback_weights_lease_secs).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    log::error!("Failed to acquire the feedback weights lease: {}", err.message);
+                    continue;
+                }
+            }
+            let pool = feedback_weights_pool.clone();
+            match web::block(move || operators::analytics_operator::apply_feedback_weights_query(pool)).await {
+                Ok(Ok(0)) => {}
+                Ok(Ok(changed_count)) => log::info!("Adjusted {} chunk weights for feedback", changed_count),
+                Ok(Err(err)) => log::error!("Failed to adjust chunk weights for feedback: {}", err.message),
+                Err(err) => log::error!("Failed to adjust chunk weights for feedback: {}", err),
+            }
+        }
+    });
+
+    let payload_limit = payload_limit_from_env("PAYLOAD_LIMIT_BYTES", DEFAULT_PAYLOAD_LIMIT_BYTES);
+    let import_payload_limit =
+        payload_limit_from_env("IMPORT_PAYLOAD_LIMIT_BYTES", DEFAULT_IMPORT_PAYLOAD_LIMIT_BYTES);
+    let search_payload_limit =
+        payload_limit_from_env("SEARCH_PAYLOAD_LIMIT_BYTES", DEFAULT_SEARCH_PAYLOAD_LIMIT_BYTES);
+
     HttpServer::new(move || {
+        let import_json_config = json_config_with_limit(import_payload_limit);
+        let search_json_config = json_config_with_limit(search_payload_limit);
+        let chunk_resource = |route: &str| {
+            web::resource(route).app_data(json_config_with_limit(chunk_route_payload_limit(
+                route,
+                payload_limit,
+                import_payload_limit,
+                search_payload_limit,
+            )))
+        };
+
         App::new()
-            .app_data(PayloadConfig::new(134200000))
-            .app_data( web::JsonConfig::default().limit(134200000))
+            .app_data(PayloadConfig::new(payload_limit))
+            .app_data(json_config_with_limit(payload_limit))
             .app_data(web::Data::new(pool.clone()))
             .app_data(web::Data::new(oidc_client.clone()))
             .wrap(af_middleware::auth_middleware::AuthMiddlewareFactory)
@@ -310,6 +548,20 @@ pub async fn main() -> std::io::Result<()> {
                                     .route(web::get().to(handlers::dataset_handler::get_datasets_from_organization)),
                             ).service(
                                 web::resource("/envs").route(web::get().to(handlers::dataset_handler::get_client_dataset_config))
+                            ).service(
+                                web::resource("/config").route(web::get().to(handlers::dataset_handler::get_dataset_config))
+                            ).service(
+                                web::resource("/integrity")
+                                    .route(web::get().to(handlers::dataset_handler::get_dataset_integrity))
+                                    .route(web::post().to(handlers::dataset_handler::repair_dataset_integrity)),
+                            )
+                            .service(
+                                web::resource("/reindex_fulltext")
+                                    .route(web::post().to(handlers::dataset_handler::reindex_fulltext))
+                                    .route(web::get().to(handlers::dataset_handler::get_reindex_fulltext_progress)),
+                            )
+                            .service(
+                                web::resource("/analytics/top_queries").route(web::get().to(handlers::analytics_handler::get_top_queries))
                             ).service(
                                 web::resource("/{dataset_id}")
                                     .route(web::get().to(handlers::dataset_handler::get_dataset)),
@@ -357,23 +609,48 @@ pub async fn main() -> std::io::Result<()> {
                             web::get().to(handlers::message_handler::get_all_topic_messages),
                         ),
                     )
+                    .service(
+                        web::scope("/analytics")
+                            .service(
+                                web::resource("/click")
+                                    .route(web::post().to(handlers::analytics_handler::record_search_click)),
+                            )
+                            .service(
+                                web::resource("/feedback")
+                                    .route(web::post().to(handlers::analytics_handler::record_search_feedback)),
+                            ),
+                    )
                     .service(
                         web::scope("/chunk")
                             .service(
-                                web::resource("")
+                                chunk_resource("")
                                     .route(web::post().to(handlers::chunk_handler::create_chunk)),
                             )
                             .service(
-                                web::resource("/recommend").route(
-                                    web::post().to(handlers::chunk_handler::get_recommended_chunks),
-                                ),
+                                chunk_resource("/recommend")
+                                    .route(web::post().to(handlers::chunk_handler::get_recommended_chunks)),
+                            )
+                            .service(
+                                chunk_resource("/recommend/batch")
+                                    .route(
+                                        web::post()
+                                            .to(handlers::chunk_handler::get_batch_recommended_chunks),
+                                    ),
+                            )
+                            .service(
+                                chunk_resource("/warm_queries")
+                                    .route(web::post().to(handlers::chunk_handler::warm_queries)),
                             )
                             .service(
-                                web::resource("/update")
+                                chunk_resource("/update")
                                     .route(web::put().to(handlers::chunk_handler::update_chunk)),
                             )
                             .service(
-                                web::resource("/search")
+                                chunk_resource("/rerank")
+                                    .route(web::post().to(handlers::chunk_handler::rerank_chunk_ids)),
+                            )
+                            .service(
+                                chunk_resource("/search")
                                     .route(web::post().to(handlers::chunk_handler::search_chunk)),
                             )
                             .service(
@@ -381,18 +658,66 @@ pub async fn main() -> std::io::Result<()> {
                                     .route(web::post().to(handlers::message_handler::create_suggested_queries_handler)),
                             )
                             .service(
-                                web::resource("/generate")
+                                chunk_resource("/generate")
                                 .route(web::post().to(handlers::chunk_handler::generate_off_chunks)),
                             )
                             .service(
-                                web::resource("/tracking_id/update")
+                                chunk_resource("/sparse_encode")
+                                .route(web::post().to(handlers::chunk_handler::sparse_encode)),
+                            )
+                            .service(
+                                chunk_resource("/estimate")
+                                .route(web::post().to(handlers::chunk_handler::estimate_cost)),
+                            )
+                            .service(
+                                chunk_resource("/search_and_generate")
+                                .route(web::post().to(handlers::chunk_handler::search_and_generate)),
+                            )
+                            .service(
+                                chunk_resource("/tracking_id/update")
                                     .route(web::put().to(handlers::chunk_handler::update_chunk_by_tracking_id)),
                             )
+                            .service(
+                                chunk_resource("/tracking_ids/exists")
+                                    .route(web::post().to(handlers::chunk_handler::check_tracking_ids_exist)),
+                            )
+                            .service(
+                                web::resource("/delete/tracking_ids")
+                                    .route(web::post().to(handlers::chunk_handler::delete_chunks_by_tracking_ids)),
+                            )
                             .service(
                                 web::resource("/tracking_id/{tracking_id}")
                                     .route(web::get().to(handlers::chunk_handler::get_chunk_by_tracking_id))
                                     .route(web::delete().to(handlers::chunk_handler::delete_chunk_by_tracking_id))
                             )
+                            .service(
+                                web::resource("/file/{file_id}")
+                                    .route(web::get().to(handlers::chunk_handler::get_chunks_for_file))
+                            )
+                            .service(
+                                web::resource("/{chunk_id}/collision_count")
+                                    .route(web::get().to(handlers::chunk_handler::get_collision_count)),
+                            )
+                            .service(
+                                web::resource("/{chunk_id}/collisions")
+                                    .route(web::get().to(handlers::chunk_handler::get_chunk_collisions)),
+                            )
+                            .service(
+                                web::resource("/{chunk_id}/history")
+                                    .route(web::get().to(handlers::chunk_handler::get_chunk_history)),
+                            )
+                            .service(
+                                web::resource("/{chunk_id}/diff")
+                                    .route(web::get().to(handlers::chunk_handler::get_chunk_diff)),
+                            )
+                            .service(
+                                web::resource("/{chunk_id}/keywords")
+                                    .route(web::get().to(handlers::chunk_handler::get_chunk_keywords)),
+                            )
+                            .service(
+                                web::resource("/{chunk_id}/collections")
+                                    .route(web::put().to(handlers::collection_handler::set_chunk_collections)),
+                            )
                             .service(
                                 web::resource("/{chunk_id}")
                                     .route(web::get().to(handlers::chunk_handler::get_chunk_by_id))
@@ -432,6 +757,11 @@ pub async fn main() -> std::io::Result<()> {
                         web::scope("/chunk_collection")
                             .service(
                                 web::resource("")
+                                    .route(
+                                        web::get().to(
+                                            handlers::collection_handler::list_chunk_collections,
+                                        ),
+                                    )
                                     .route(
                                         web::post().to(
                                             handlers::collection_handler::create_chunk_collection,
@@ -470,11 +800,47 @@ pub async fn main() -> std::io::Result<()> {
                                             .to(handlers::collection_handler::get_logged_in_user_chunk_collections)),
                             )
                             .service(
-                                web::resource("/search")                                    
+                                web::resource("/search")
+                                .app_data(search_json_config.clone())
                                 .route(
                                     web::post().to(handlers::chunk_handler::search_collections),
                                 ),
                             )
+                            .service(
+                                web::resource("/{collection_id}/restore").route(
+                                    web::put().to(
+                                        handlers::collection_handler::restore_chunk_collection,
+                                    ),
+                                ),
+                            )
+                            .service(
+                                web::resource("/{collection_id}/purge").route(
+                                    web::delete().to(
+                                        handlers::collection_handler::purge_chunk_collection,
+                                    ),
+                                ),
+                            )
+                            .service(
+                                web::resource("/{collection_id}/add_from_search").route(
+                                    web::post().to(
+                                        handlers::collection_handler::add_bookmarks_from_search,
+                                    ),
+                                ),
+                            )
+                            .service(
+                                web::resource("/{collection_id}/recommend").route(
+                                    web::post().to(
+                                        handlers::collection_handler::recommend_from_collection,
+                                    ),
+                                ),
+                            )
+                            .service(
+                                web::resource("/{collection_id}/chunks").route(
+                                    web::get().to(
+                                        handlers::collection_handler::get_collection_chunks,
+                                    ),
+                                ),
+                            )
                             .service(web::resource("/{collection_id}/{page}").route(
                                 web::get().to(handlers::collection_handler::get_all_bookmarks),
                             )),
@@ -483,6 +849,7 @@ pub async fn main() -> std::io::Result<()> {
                         web::scope("/file")
                             .service(
                                 web::resource("")
+                                    .app_data(import_json_config.clone())
                                     .route(web::post().to(handlers::file_handler::upload_file_handler)),
                             )
                             .service(
@@ -574,3 +941,42 @@ pub async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_routes_get_their_payload_limits() {
+        let (payload_limit, import_payload_limit, search_payload_limit) = (100, 1000, 10);
+        let limit_of = |route: &str| {
+            chunk_route_payload_limit(
+                route,
+                payload_limit,
+                import_payload_limit,
+                search_payload_limit,
+            )
+        };
+
+        for route in ["", "/update", "/tracking_id/update"] {
+            assert_eq!(limit_of(route), import_payload_limit, "{}", route);
+        }
+        for route in [
+            "/recommend",
+            "/recommend/batch",
+            "/warm_queries",
+            "/rerank",
+            "/search",
+            "/generate",
+            "/sparse_encode",
+            "/estimate",
+            "/search_and_generate",
+            "/tracking_ids/exists",
+        ] {
+            assert_eq!(limit_of(route), search_payload_limit, "{}", route);
+        }
+        for route in ["/gen_suggestions", "/delete/tracking_ids"] {
+            assert_eq!(limit_of(route), payload_limit, "{}", route);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use crate::{
+    data::models::{
+        ChunkFeedbackWeight, Pool, SearchClickEvent, SearchEvent, SearchFeedbackEvent,
+        ServerDatasetConfiguration,
+    },
+    errors::DefaultError,
+    operators::user_operator::SECRET_KEY,
+};
+use actix_web::web;
+use diesel::{
+    dsl::count_star,
+    sql_types::{Float8, Int8, Nullable},
+    Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+/// Hashes the user id with HMAC-SHA256 keyed by the server's SECRET_KEY so that search events can
+/// be grouped by user without storing who the user is. Without the key the hashes cannot be matched
+/// to user ids by hashing every known id, and unlike std's hasher the output is the same across
+/// builds.
+pub fn anonymize_user_id(user_id: uuid::Uuid) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET_KEY.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(user_id.as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn create_search_event_query(
+    search_event: SearchEvent,
+    pool: web::Data<Pool>,
+) -> Result<(), DefaultError> {
+    use crate::data::schema::search_events::dsl as search_events_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    diesel::insert_into(search_events_columns::search_events)
+        .values(&search_event)
+        .execute(&mut conn)
+        .map_err(|err| {
+            log::error!("Error creating search event {:}", err);
+            DefaultError {
+                message: "Error creating search event",
+            }
+        })?;
+
+    Ok(())
+}
+
+pub fn create_search_click_event_query(
+    search_click_event: SearchClickEvent,
+    pool: web::Data<Pool>,
+) -> Result<(), DefaultError> {
+    use crate::data::schema::search_click_events::dsl as search_click_events_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    diesel::insert_into(search_click_events_columns::search_click_events)
+        .values(&search_click_event)
+        .execute(&mut conn)
+        .map_err(|err| {
+            log::error!("Error creating search click event {:}", err);
+            DefaultError {
+                message: "Error creating search click event",
+            }
+        })?;
+
+    Ok(())
+}
+
+pub fn create_search_feedback_event_query(
+    search_feedback_event: SearchFeedbackEvent,
+    pool: web::Data<Pool>,
+) -> Result<(), DefaultError> {
+    use crate::data::schema::search_feedback_events::dsl as search_feedback_events_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    diesel::insert_into(search_feedback_events_columns::search_feedback_events)
+        .values(&search_feedback_event)
+        .execute(&mut conn)
+        .map_err(|err| {
+            log::error!("Error creating search feedback event {:}", err);
+            DefaultError {
+                message: "Error creating search feedback event",
+            }
+        })?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, ToSchema)]
+pub struct QueryCount {
+    pub query: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TopQueriesResult {
+    pub top_queries: Vec<QueryCount>,
+    pub zero_result_queries: Vec<QueryCount>,
+}
+
+pub fn get_top_queries_query(
+    dataset_uuid: uuid::Uuid,
+    since: chrono::NaiveDateTime,
+    limit: i64,
+    pool: web::Data<Pool>,
+) -> Result<TopQueriesResult, DefaultError> {
+    use crate::data::schema::search_events::dsl as search_events_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let top_queries = search_events_columns::search_events
+        .filter(search_events_columns::dataset_id.eq(dataset_uuid))
+        .filter(search_events_columns::created_at.ge(since))
+        .group_by(search_events_columns::query)
+        .select((search_events_columns::query, count_star()))
+        .order(count_star().desc())
+        .limit(limit)
+        .load::<QueryCount>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Error getting top queries",
+        })?;
+
+    let zero_result_queries = search_events_columns::search_events
+        .filter(search_events_columns::dataset_id.eq(dataset_uuid))
+        .filter(search_events_columns::created_at.ge(since))
+        .filter(search_events_columns::result_count.eq(0))
+        .group_by(search_events_columns::query)
+        .select((search_events_columns::query, count_star()))
+        .order(count_star().desc())
+        .limit(limit)
+        .load::<QueryCount>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Error getting zero result queries",
+        })?;
+
+    Ok(TopQueriesResult {
+        top_queries,
+        zero_result_queries,
+    })
+}
+
+/// The multiplier a chunk's weight is adjusted by for its feedback, `1 + rate` raised to the
+/// number of positive feedback events minus the number of negative ones. It is bounded by
+/// max_multiplier and its reciprocal so feedback alone can never hide or dominate a chunk.
+pub fn get_feedback_multiplier(net_feedback: i64, rate: f64, max_multiplier: f64) -> f64 {
+    let max_multiplier = max_multiplier.max(1.0);
+
+    (1.0 + rate)
+        .powf(net_feedback as f64)
+        .clamp(1.0 / max_multiplier, max_multiplier)
+}
+
+#[derive(diesel::QueryableByName)]
+struct ChunkFeedback {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    chunk_id: uuid::Uuid,
+    #[diesel(sql_type = Int8)]
+    net_feedback: i64,
+    #[diesel(sql_type = Float8)]
+    weight: f64,
+    #[diesel(sql_type = Nullable<Float8>)]
+    base_weight: Option<f64>,
+    #[diesel(sql_type = Nullable<Float8>)]
+    multiplier: Option<f64>,
+}
+
+/// Sets the weight of every chunk of the dataset with feedback to its base weight times its
+/// feedback multiplier, and returns how many weights were changed. A chunk's base weight is its
+/// weight before it was first adjusted, or its weight after it was last updated if that happened
+/// since.
+fn adjust_dataset_feedback_weights(
+    dataset_id: uuid::Uuid,
+    rate: f64,
+    max_multiplier: f64,
+    conn: &mut PgConnection,
+) -> Result<usize, DefaultError> {
+    use crate::data::schema::chunk_feedback_weights::dsl as chunk_feedback_weights_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let chunk_feedback = diesel::sql_query(
+        "SELECT chunk_metadata.id AS chunk_id, feedback.net_feedback, chunk_metadata.weight, chunk_feedback_weights.base_weight, chunk_feedback_weights.multiplier
+        FROM (
+            SELECT chunk_id, SUM(CASE WHEN positive THEN 1 ELSE -1 END) AS net_feedback
+            FROM search_feedback_events
+            WHERE dataset_id = $1
+            GROUP BY chunk_id
+        ) AS feedback
+        INNER JOIN chunk_metadata ON chunk_metadata.id = feedback.chunk_id AND chunk_metadata.dataset_id = $1
+        LEFT JOIN chunk_feedback_weights ON chunk_feedback_weights.chunk_id = chunk_metadata.id",
+    )
+    .bind::<diesel::sql_types::Uuid, _>(dataset_id)
+    .load::<ChunkFeedback>(conn)
+    .map_err(|_err| DefaultError {
+        message: "Failed to load the feedback of the dataset's chunks",
+    })?;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let mut changed_count = 0;
+        for chunk in chunk_feedback {
+            let multiplier = get_feedback_multiplier(chunk.net_feedback, rate, max_multiplier);
+            let base_weight = match (chunk.base_weight, chunk.multiplier) {
+                (Some(base_weight), Some(previous_multiplier))
+                    if base_weight * previous_multiplier == chunk.weight =>
+                {
+                    base_weight
+                }
+                _ => chunk.weight,
+            };
+            let weight = base_weight * multiplier;
+            if weight == chunk.weight
+                && (chunk.multiplier == Some(multiplier)
+                    || (chunk.multiplier.is_none() && multiplier == 1.0))
+            {
+                continue;
+            }
+
+            diesel::insert_into(chunk_feedback_weights_columns::chunk_feedback_weights)
+                .values(ChunkFeedbackWeight {
+                    chunk_id: chunk.chunk_id,
+                    dataset_id,
+                    base_weight,
+                    multiplier,
+                    updated_at: chrono::Utc::now().naive_local(),
+                })
+                .on_conflict(chunk_feedback_weights_columns::chunk_id)
+                .do_update()
+                .set((
+                    chunk_feedback_weights_columns::base_weight.eq(base_weight),
+                    chunk_feedback_weights_columns::multiplier.eq(multiplier),
+                    chunk_feedback_weights_columns::updated_at.eq(chrono::Utc::now().naive_local()),
+                ))
+                .execute(conn)?;
+
+            diesel::update(
+                chunk_metadata_columns::chunk_metadata
+                    .filter(chunk_metadata_columns::id.eq(chunk.chunk_id)),
+            )
+            .set(chunk_metadata_columns::weight.eq(weight))
+            .execute(conn)?;
+
+            changed_count += 1;
+        }
+
+        Ok(changed_count)
+    })
+    .map_err(|_err| DefaultError {
+        message: "Failed to adjust the weights of the dataset's chunks",
+    })
+}
+
+/// Restores the weights of the dataset's chunks from before they were adjusted for feedback and
+/// forgets the adjustments. Chunks whose weight was changed by an update since they were last
+/// adjusted keep their new weight. Returns how many weights were restored.
+fn reset_dataset_feedback_weights(
+    dataset_id: uuid::Uuid,
+    conn: &mut PgConnection,
+) -> Result<usize, DefaultError> {
+    use crate::data::schema::chunk_feedback_weights::dsl as chunk_feedback_weights_columns;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let restored_count = diesel::sql_query(
+            "UPDATE chunk_metadata SET weight = chunk_feedback_weights.base_weight
+            FROM chunk_feedback_weights
+            WHERE chunk_metadata.id = chunk_feedback_weights.chunk_id
+            AND chunk_feedback_weights.dataset_id = $1
+            AND chunk_metadata.weight = chunk_feedback_weights.base_weight * chunk_feedback_weights.multiplier",
+        )
+        .bind::<diesel::sql_types::Uuid, _>(dataset_id)
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_feedback_weights_columns::chunk_feedback_weights
+                .filter(chunk_feedback_weights_columns::dataset_id.eq(dataset_id)),
+        )
+        .execute(conn)?;
+
+        Ok(restored_count)
+    })
+    .map_err(|_err| DefaultError {
+        message: "Failed to restore the weights of the dataset's chunks",
+    })
+}
+
+/// Takes the lease on adjusting feedback weights for `lease_secs`, returning whether it was taken.
+/// Every server instance runs the feedback weights job, so the lease is left to expire instead of
+/// being released to let only one of them adjust the weights each interval.
+pub async fn acquire_feedback_weights_lease_query(lease_secs: u64) -> Result<bool, DefaultError> {
+    let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
+    let client = redis::Client::open(redis_url).map_err(|_| DefaultError {
+        message: "Could not create redis client",
+    })?;
+    let mut redis_conn = client
+        .get_async_connection()
+        .await
+        .map_err(|_| DefaultError {
+            message: "Could not connect to redis",
+        })?;
+
+    let acquired: Option<String> = redis::cmd("SET")
+        .arg("feedback_weights_lease")
+        .arg(uuid::Uuid::new_v4().to_string())
+        .arg("NX")
+        .arg("EX")
+        .arg(lease_secs)
+        .query_async(&mut redis_conn)
+        .await
+        .map_err(|_| DefaultError {
+            message: "Could not acquire feedback weights lease in redis",
+        })?;
+
+    Ok(acquired.is_some())
+}
+
+/// Adjusts the chunk weights of every dataset with a FEEDBACK_WEIGHT_RATE by the feedback their
+/// chunks have received, and restores the weights of datasets whose FEEDBACK_WEIGHT_RATE has been
+/// removed or set to 0. Returns how many chunk weights were changed.
+pub fn apply_feedback_weights_query(pool: web::Data<Pool>) -> Result<usize, DefaultError> {
+    use crate::data::schema::chunk_feedback_weights::dsl as chunk_feedback_weights_columns;
+    use crate::data::schema::datasets::dsl as datasets_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let datasets: Vec<(uuid::Uuid, serde_json::Value)> = datasets_columns::datasets
+        .select((datasets_columns::id, datasets_columns::server_configuration))
+        .load(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load datasets",
+        })?;
+
+    let adjusted_dataset_ids = chunk_feedback_weights_columns::chunk_feedback_weights
+        .select(chunk_feedback_weights_columns::dataset_id)
+        .distinct()
+        .load::<uuid::Uuid>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load the datasets with feedback weights",
+        })?
+        .into_iter()
+        .collect::<HashSet<uuid::Uuid>>();
+
+    let mut changed_count = 0;
+    for (dataset_id, server_configuration) in datasets {
+        let dataset_config = ServerDatasetConfiguration::from_json(server_configuration);
+
+        match dataset_config
+            .FEEDBACK_WEIGHT_RATE
+            .filter(|rate| *rate > 0.0)
+        {
+            Some(rate) => {
+                changed_count += adjust_dataset_feedback_weights(
+                    dataset_id,
+                    rate,
+                    dataset_config.FEEDBACK_WEIGHT_MAX_MULTIPLIER.unwrap_or(2.0),
+                    &mut conn,
+                )?
+            }
+            None if adjusted_dataset_ids.contains(&dataset_id) => {
+                changed_count += reset_dataset_feedback_weights(dataset_id, &mut conn)?
+            }
+            None => {}
+        }
+    }
+
+    Ok(changed_count)
+}
@@ -1,23 +1,32 @@
 use crate::data::models::{
-    ChunkCollisions, ChunkFile, ChunkMetadataWithFileData, Dataset, FullTextSearchResult,
-    ServerDatasetConfiguration,
+    ChunkCollisions, ChunkFile, ChunkMetadataWithFileData, ChunkVersion, Dataset,
+    FullTextSearchResult, ServerDatasetConfiguration,
 };
 use crate::diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
-use crate::operators::model_operator::create_embedding;
-use crate::operators::qdrant_operator::get_qdrant_connection;
+use crate::handlers::chunk_handler::{ChunkDiffChange, ChunkKeyword, ChunkNeighbors};
+use crate::operators::dataset_operator::get_dataset_by_id_query;
+use crate::operators::model_operator::{create_embedding, get_splade_doc_token_weights};
+use crate::operators::qdrant_operator::{delete_qdrant_point_ids_query, get_qdrant_connection};
 use crate::operators::search_operator::get_metadata_query;
 use crate::{
     data::models::{ChunkMetadata, Pool},
-    errors::DefaultError,
+    errors::{DefaultError, ServiceError},
 };
 use actix_web::web;
 use diesel::{
+    dsl::sql,
+    sql_types::{Int8, Text},
     BoolExpressionMethods, Connection, JoinOnDsl, NullableExpressionMethods, SelectableHelper,
 };
 use itertools::Itertools;
+use jsonschema::JSONSchema;
+use once_cell::sync::Lazy;
 use qdrant_client::qdrant::{PointId, PointVectors};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
 use simsearch::SimSearch;
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize)]
 pub struct ScoredchunkDTO {
@@ -250,6 +259,92 @@ pub fn get_metadata_from_tracking_id_query(
         })
 }
 
+pub fn get_chunks_for_file_query(
+    file_uuid: uuid::Uuid,
+    page: u64,
+    page_size: i64,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(Vec<ChunkMetadata>, i64), DefaultError> {
+    use crate::data::schema::chunk_files::dsl as chunk_files_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let page = if page == 0 { 1 } else { page };
+    let mut conn = pool.get().unwrap();
+
+    let chunks = chunk_metadata_columns::chunk_metadata
+        .inner_join(
+            chunk_files_columns::chunk_files
+                .on(chunk_files_columns::chunk_id.eq(chunk_metadata_columns::id)),
+        )
+        .filter(chunk_files_columns::file_id.eq(file_uuid))
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+        .select((
+            ChunkMetadata::as_select(),
+            sql::<Int8>("count(*) OVER() AS full_count"),
+        ))
+        .order((
+            chunk_files_columns::chunk_index.asc(),
+            chunk_metadata_columns::created_at.asc(),
+            chunk_metadata_columns::id.asc(),
+        ))
+        .limit(page_size)
+        .offset(((page - 1) * page_size as u64).try_into().unwrap_or(0))
+        .load::<(ChunkMetadata, i64)>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Failed to load chunks for file",
+        })?;
+
+    let total_chunks = chunks
+        .first()
+        .map(|(_, full_count)| *full_count)
+        .unwrap_or(0);
+
+    Ok((
+        chunks.into_iter().map(|(chunk, _)| chunk).collect(),
+        total_chunks,
+    ))
+}
+
+pub fn get_existing_tracking_ids_query(
+    tracking_ids: Vec<String>,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<String>, DefaultError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_metadata_columns::chunk_metadata
+        .filter(chunk_metadata_columns::tracking_id.eq_any(tracking_ids))
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+        .select(chunk_metadata_columns::tracking_id.assume_not_null())
+        .distinct()
+        .load::<String>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Failed to load tracking_ids",
+        })
+}
+
+pub fn get_metadata_from_tracking_ids_query(
+    tracking_ids: Vec<String>,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<ChunkMetadata>, DefaultError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_metadata_columns::chunk_metadata
+        .filter(chunk_metadata_columns::tracking_id.eq_any(tracking_ids))
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+        .select(ChunkMetadata::as_select())
+        .load::<ChunkMetadata>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Failed to load chunks by tracking_ids",
+        })
+}
+
 pub fn get_metadata_from_ids_query(
     chunk_ids: Vec<uuid::Uuid>,
     dataset_uuid: uuid::Uuid,
@@ -278,6 +373,7 @@ pub fn get_metadata_from_ids_query(
 pub async fn insert_chunk_metadata_query(
     chunk_data: ChunkMetadata,
     file_uuid: Option<uuid::Uuid>,
+    chunk_index: Option<i32>,
     pool: web::Data<Pool>,
 ) -> Result<ChunkMetadata, DefaultError> {
     use crate::data::schema::chunk_files::dsl as chunk_files_columns;
@@ -295,6 +391,7 @@ pub async fn insert_chunk_metadata_query(
                 .values(&ChunkFile::from_details(
                     chunk_data.id,
                     file_uuid.expect("file_uuid should be Some"),
+                    chunk_index,
                 ))
                 .execute(conn)?;
         }
@@ -331,6 +428,7 @@ pub fn insert_duplicate_chunk_metadata_query(
     chunk_data: ChunkMetadata,
     duplicate_chunk: uuid::Uuid,
     file_uuid: Option<uuid::Uuid>,
+    chunk_index: Option<i32>,
     pool: web::Data<Pool>,
 ) -> Result<ChunkMetadata, DefaultError> {
     use crate::data::schema::chunk_collisions::dsl::*;
@@ -357,6 +455,7 @@ pub fn insert_duplicate_chunk_metadata_query(
                 .values(&ChunkFile::from_details(
                     chunk_data.id,
                     file_uuid.expect("file_uuid should be some"),
+                    chunk_index,
                 ))
                 .execute(conn)?;
         }
@@ -375,6 +474,10 @@ pub fn insert_duplicate_chunk_metadata_query(
     Ok(chunk_data)
 }
 
+/// The number of previous versions kept for each chunk. The oldest versions are deleted once a
+/// chunk has been updated more times than this.
+pub const MAX_CHUNK_VERSIONS: i32 = 20;
+
 pub async fn update_chunk_metadata_query(
     chunk_data: ChunkMetadata,
     file_uuid: Option<uuid::Uuid>,
@@ -383,10 +486,50 @@ pub async fn update_chunk_metadata_query(
 ) -> Result<(), DefaultError> {
     use crate::data::schema::chunk_files::dsl as chunk_files_columns;
     use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+    use crate::data::schema::chunk_versions::dsl as chunk_versions_columns;
 
     let mut conn = pool.get().unwrap();
 
     let transaction_result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Locking the chunk's row makes concurrent updates of the chunk wait for this one, so each
+        // records the version the other left and they never number their versions the same
+        let previous_chunk = chunk_metadata_columns::chunk_metadata
+            .filter(chunk_metadata_columns::id.eq(chunk_data.id))
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+            .select(ChunkMetadata::as_select())
+            .for_update()
+            .first::<ChunkMetadata>(conn)?;
+
+        let content_changed = previous_chunk.content != chunk_data.content
+            || previous_chunk.chunk_html != chunk_data.chunk_html
+            || previous_chunk.link != chunk_data.link
+            || previous_chunk.tag_set != chunk_data.tag_set
+            || previous_chunk.metadata != chunk_data.metadata;
+
+        if content_changed {
+            let latest_version = chunk_versions_columns::chunk_versions
+                .filter(chunk_versions_columns::chunk_id.eq(chunk_data.id))
+                .select(diesel::dsl::max(chunk_versions_columns::version))
+                .first::<Option<i32>>(conn)?
+                .unwrap_or(0);
+
+            diesel::insert_into(chunk_versions_columns::chunk_versions)
+                .values(ChunkVersion::from_chunk(
+                    &previous_chunk,
+                    latest_version + 1,
+                ))
+                .execute(conn)?;
+
+            diesel::delete(
+                chunk_versions_columns::chunk_versions
+                    .filter(chunk_versions_columns::chunk_id.eq(chunk_data.id))
+                    .filter(
+                        chunk_versions_columns::version.le(latest_version + 1 - MAX_CHUNK_VERSIONS),
+                    ),
+            )
+            .execute(conn)?;
+        }
+
         diesel::update(
             chunk_metadata_columns::chunk_metadata
                 .filter(chunk_metadata_columns::id.eq(chunk_data.id))
@@ -399,16 +542,24 @@ pub async fn update_chunk_metadata_query(
             chunk_metadata_columns::metadata.eq(chunk_data.metadata),
             chunk_metadata_columns::tag_set.eq(chunk_data.tag_set),
             chunk_metadata_columns::weight.eq(chunk_data.weight),
+            chunk_metadata_columns::updated_at.eq(chunk_data.updated_at),
         ))
         .execute(conn)?;
 
-        if file_uuid.is_some() {
-            diesel::insert_into(chunk_files_columns::chunk_files)
-                .values(ChunkFile::from_details(
-                    chunk_data.id,
-                    file_uuid.expect("file_uuid should be some"),
-                ))
-                .execute(conn)?;
+        if let Some(file_uuid) = file_uuid {
+            // A chunk already in the file keeps its row, and with it its position in the file
+            let already_in_file = diesel::select(diesel::dsl::exists(
+                chunk_files_columns::chunk_files
+                    .filter(chunk_files_columns::chunk_id.eq(chunk_data.id))
+                    .filter(chunk_files_columns::file_id.eq(file_uuid)),
+            ))
+            .get_result::<bool>(conn)?;
+
+            if !already_in_file {
+                diesel::insert_into(chunk_files_columns::chunk_files)
+                    .values(ChunkFile::from_details(chunk_data.id, file_uuid, None))
+                    .execute(conn)?;
+            }
         }
         Ok(())
     });
@@ -425,11 +576,189 @@ pub async fn update_chunk_metadata_query(
     Ok(())
 }
 
+pub fn get_chunk_versions_query(
+    chunk_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<ChunkVersion>, DefaultError> {
+    use crate::data::schema::chunk_versions::dsl as chunk_versions_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_versions_columns::chunk_versions
+        .filter(chunk_versions_columns::chunk_id.eq(chunk_id))
+        .filter(chunk_versions_columns::dataset_id.eq(dataset_id))
+        .order(chunk_versions_columns::version.desc())
+        .select(ChunkVersion::as_select())
+        .load::<ChunkVersion>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Failed to load chunk versions",
+        })
+}
+
+pub fn get_chunk_version_query(
+    chunk_id: uuid::Uuid,
+    version: i32,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<ChunkVersion, DefaultError> {
+    use crate::data::schema::chunk_versions::dsl as chunk_versions_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_versions_columns::chunk_versions
+        .filter(chunk_versions_columns::chunk_id.eq(chunk_id))
+        .filter(chunk_versions_columns::version.eq(version))
+        .filter(chunk_versions_columns::dataset_id.eq(dataset_id))
+        .select(ChunkVersion::as_select())
+        .first::<ChunkVersion>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Chunk version not found, it may have been pruned from the chunk's history",
+        })
+}
+
+/// Word level diff between two versions of a chunk's content.
+pub fn diff_chunk_content(from: &str, to: &str) -> Vec<ChunkDiffChange> {
+    TextDiff::from_words(from, to)
+        .iter_all_changes()
+        .map(|change| ChunkDiffChange {
+            tag: match change.tag() {
+                ChangeTag::Equal => "equal",
+                ChangeTag::Insert => "insert",
+                ChangeTag::Delete => "delete",
+            }
+            .to_string(),
+            value: change.value().to_string(),
+        })
+        .coalesce(|prev, next| {
+            if prev.tag == next.tag {
+                Ok(ChunkDiffChange {
+                    tag: prev.tag,
+                    value: prev.value + &next.value,
+                })
+            } else {
+                Err((prev, next))
+            }
+        })
+        .collect()
+}
+
 enum TransactionResult {
     ChunkCollisionDetected(ChunkMetadata),
     ChunkCollisionNotDetected,
 }
 
+/// Checks chunk metadata against the dataset's METADATA_SCHEMA, a JSON schema. Datasets without a
+/// schema accept any metadata.
+pub fn validate_chunk_metadata(
+    metadata: &serde_json::Value,
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let schema = match dataset_config.METADATA_SCHEMA.as_ref() {
+        Some(schema) => schema,
+        None => return Ok(()),
+    };
+
+    let compiled_schema = JSONSchema::compile(schema).map_err(|err| {
+        ServiceError::BadRequest(format!(
+            "The dataset's METADATA_SCHEMA is not a valid JSON schema: {}",
+            err
+        ))
+    })?;
+
+    if let Err(errors) = compiled_schema.validate(metadata) {
+        let violations = errors
+            .map(|err| format!("{} at \"{}\"", err, err.instance_path))
+            .collect::<Vec<String>>();
+
+        return Err(ServiceError::BadRequest(format!(
+            "Metadata does not match the dataset's METADATA_SCHEMA: {}",
+            violations.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+static EMBEDDING_TEMPLATE_PLACEHOLDER_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"\{\{\s*(?:content|metadata\.([^}\s]+))\s*\}\}|\{\s*(?:content|(?:metadata\.)?([^{}\s]+))\s*\}",
+    )
+    .expect("Invalid regex")
+});
+
+fn get_embedding_template_key<'a>(captures: &regex::Captures<'a>) -> Option<&'a str> {
+    captures.get(1).or(captures.get(2)).map(|key| key.as_str())
+}
+
+/// Builds the text which is embedded for a chunk from the dataset's EMBEDDING_TEXT_TEMPLATE.
+/// `{{content}}` is replaced with the innerText of the chunk_html and `{{metadata.<key>}}` with the
+/// value of that key in the chunk's metadata, or an empty string if the key is missing. The
+/// shorthands `{content}` and `{<key>}` are accepted as well, so `"{title}\n\n{content}"` embeds the
+/// chunk's title metadata above its content.
+pub fn get_embedding_text(
+    content: &str,
+    metadata: &Option<serde_json::Value>,
+    dataset_config: &ServerDatasetConfiguration,
+) -> String {
+    let template = dataset_config
+        .EMBEDDING_TEXT_TEMPLATE
+        .clone()
+        .unwrap_or("{{content}}".to_string());
+
+    EMBEDDING_TEMPLATE_PLACEHOLDER_REGEX
+        .replace_all(
+            &template,
+            |captures: &regex::Captures| match get_embedding_template_key(captures) {
+                Some(key) => metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.get(key.as_str()))
+                    .map(|value| match value {
+                        serde_json::Value::String(value) => value.clone(),
+                        serde_json::Value::Null => "".to_string(),
+                        value => value.to_string(),
+                    })
+                    .unwrap_or_default(),
+                None => content.to_string(),
+            },
+        )
+        .trim()
+        .to_string()
+}
+
+/// Checks that chunk metadata has every key which the dataset's EMBEDDING_TEXT_TEMPLATE refers to,
+/// so a typo in a key fails the write instead of silently embedding an empty string.
+pub fn validate_embedding_text_metadata(
+    metadata: &Option<serde_json::Value>,
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let template = match dataset_config.EMBEDDING_TEXT_TEMPLATE.as_ref() {
+        Some(template) => template,
+        None => return Ok(()),
+    };
+
+    let missing_keys = EMBEDDING_TEMPLATE_PLACEHOLDER_REGEX
+        .captures_iter(template)
+        .filter_map(|captures| get_embedding_template_key(&captures))
+        .filter(|key| {
+            metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(key))
+                .is_none()
+        })
+        .unique()
+        .collect::<Vec<&str>>();
+
+    if !missing_keys.is_empty() {
+        return Err(ServiceError::BadRequest(format!(
+            "Metadata is missing the keys used by the dataset's EMBEDDING_TEXT_TEMPLATE: {}",
+            missing_keys.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
 pub async fn delete_chunk_metadata_query(
     chunk_uuid: uuid::Uuid,
     qdrant_point_id: Option<uuid::Uuid>,
@@ -609,14 +938,20 @@ pub async fn delete_chunk_metadata_query(
                     .clone()
                     .unwrap_or(latest_collision_metadata.content.clone());
 
-                let new_embedding_vector = create_embedding(
+                let dataset_config =
+                    ServerDatasetConfiguration::from_json(dataset.server_configuration.clone());
+                let embedding_text = get_embedding_text(
                     collision_content.as_str(),
-                    ServerDatasetConfiguration::from_json(dataset.server_configuration.clone()),
-                )
-                .await
-                .map_err(|_e| DefaultError {
-                    message: "Failed to create embedding for chunk",
-                })?;
+                    &latest_collision_metadata.metadata,
+                    &dataset_config,
+                );
+
+                let new_embedding_vector =
+                    create_embedding(embedding_text.as_str(), dataset_config)
+                        .await
+                        .map_err(|_e| DefaultError {
+                            message: "Failed to create embedding for chunk",
+                        })?;
 
                 let _ = qdrant
                     .update_vectors_blocking(
@@ -649,6 +984,210 @@ pub async fn delete_chunk_metadata_query(
     Ok(())
 }
 
+/// Deletes a batch of the dataset's chunks with one delete of their rows and one delete of their
+/// qdrant points. A root chunk whose point is shared by collisions outside the batch hands its
+/// point to the most recent of them, so those are deleted one at a time with
+/// delete_chunk_metadata_query once the rest of the batch is gone.
+pub async fn delete_chunk_metadata_batch_query(
+    chunks: Vec<ChunkMetadata>,
+    dataset: Dataset,
+    pool: web::Data<Pool>,
+) -> Result<(), DefaultError> {
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_files::dsl as chunk_files_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    if chunks.iter().any(|chunk| chunk.dataset_id != dataset.id) {
+        return Err(DefaultError {
+            message: "chunk does not belong to dataset",
+        });
+    }
+
+    let chunk_ids = chunks
+        .iter()
+        .map(|chunk| chunk.id)
+        .collect::<Vec<uuid::Uuid>>();
+    let point_ids = chunks
+        .iter()
+        .filter_map(|chunk| chunk.qdrant_point_id)
+        .collect::<Vec<uuid::Uuid>>();
+
+    let mut conn = pool.get().unwrap();
+
+    let (deleted_point_ids, shared_point_chunks) = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let shared_point_ids: Vec<Option<uuid::Uuid>> =
+                chunk_collisions_columns::chunk_collisions
+                    .filter(chunk_collisions_columns::collision_qdrant_id.eq_any(&point_ids))
+                    .filter(chunk_collisions_columns::chunk_id.ne_all(&chunk_ids))
+                    .select(chunk_collisions_columns::collision_qdrant_id)
+                    .distinct()
+                    .load(conn)?;
+
+            let (shared_point_chunks, batch_chunks): (Vec<ChunkMetadata>, Vec<ChunkMetadata>) =
+                chunks.into_iter().partition(|chunk| {
+                    chunk.qdrant_point_id.is_some()
+                        && shared_point_ids.contains(&chunk.qdrant_point_id)
+                });
+            let batch_chunk_ids = batch_chunks
+                .iter()
+                .map(|chunk| chunk.id)
+                .collect::<Vec<uuid::Uuid>>();
+
+            diesel::delete(
+                chunk_files_columns::chunk_files
+                    .filter(chunk_files_columns::chunk_id.eq_any(&batch_chunk_ids)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                chunk_collection_bookmarks_columns::chunk_collection_bookmarks.filter(
+                    chunk_collection_bookmarks_columns::chunk_metadata_id.eq_any(&batch_chunk_ids),
+                ),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                chunk_collisions_columns::chunk_collisions
+                    .filter(chunk_collisions_columns::chunk_id.eq_any(&batch_chunk_ids)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(
+                chunk_metadata_columns::chunk_metadata
+                    .filter(chunk_metadata_columns::id.eq_any(&batch_chunk_ids))
+                    .filter(chunk_metadata_columns::dataset_id.eq(dataset.id)),
+            )
+            .execute(conn)?;
+
+            let deleted_point_ids = batch_chunks
+                .iter()
+                .filter_map(|chunk| chunk.qdrant_point_id)
+                .collect::<Vec<uuid::Uuid>>();
+
+            Ok((deleted_point_ids, shared_point_chunks))
+        })
+        .map_err(|_| DefaultError {
+            message: "Failed to delete chunk data",
+        })?;
+
+    delete_qdrant_point_ids_query(deleted_point_ids).await?;
+
+    for chunk in shared_point_chunks {
+        delete_chunk_metadata_query(
+            chunk.id,
+            chunk.qdrant_point_id,
+            dataset.clone(),
+            pool.clone(),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// How many expired chunks the reaper loads from postgres at a time.
+pub const EXPIRED_CHUNKS_BATCH_SIZE: i64 = 500;
+
+/// Deletes chunks whose expires_at has passed along with their qdrant points, oldest expiry first,
+/// and returns how many were deleted. Each chunk is deleted as it would be through the delete
+/// route, so an expired root chunk hands its point to its most recent collision. Chunks which
+/// fail to delete are logged and skipped so they do not hold back the rest until the next run.
+pub async fn delete_expired_chunks_query(pool: web::Data<Pool>) -> Result<usize, DefaultError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut last_seen: Option<(chrono::NaiveDateTime, uuid::Uuid)> = None;
+    let mut datasets: HashMap<uuid::Uuid, Option<Dataset>> = HashMap::new();
+    let mut deleted_count = 0;
+
+    loop {
+        let expired_chunks: Vec<(
+            uuid::Uuid,
+            Option<uuid::Uuid>,
+            uuid::Uuid,
+            Option<chrono::NaiveDateTime>,
+        )> = {
+            let mut conn = pool.get().unwrap();
+
+            let mut query = chunk_metadata_columns::chunk_metadata
+                .filter(chunk_metadata_columns::expires_at.le(now))
+                .select((
+                    chunk_metadata_columns::id,
+                    chunk_metadata_columns::qdrant_point_id,
+                    chunk_metadata_columns::dataset_id,
+                    chunk_metadata_columns::expires_at,
+                ))
+                .order((
+                    chunk_metadata_columns::expires_at.asc(),
+                    chunk_metadata_columns::id.asc(),
+                ))
+                .limit(EXPIRED_CHUNKS_BATCH_SIZE)
+                .into_boxed();
+
+            if let Some((last_expires_at, last_id)) = last_seen {
+                query = query.filter(
+                    chunk_metadata_columns::expires_at.gt(last_expires_at).or(
+                        chunk_metadata_columns::expires_at
+                            .eq(last_expires_at)
+                            .and(chunk_metadata_columns::id.gt(last_id)),
+                    ),
+                );
+            }
+
+            query.load(&mut conn).map_err(|_err| DefaultError {
+                message: "Failed to load expired chunks",
+            })?
+        };
+
+        let is_last_batch = (expired_chunks.len() as i64) < EXPIRED_CHUNKS_BATCH_SIZE;
+
+        for (chunk_id, qdrant_point_id, dataset_id, expires_at) in expired_chunks {
+            last_seen = expires_at.map(|expires_at| (expires_at, chunk_id));
+
+            let dataset = match datasets.get(&dataset_id) {
+                Some(dataset) => dataset.clone(),
+                None => {
+                    let dataset = match get_dataset_by_id_query(dataset_id, pool.clone()).await {
+                        Ok(dataset) => Some(dataset),
+                        Err(err) => {
+                            log::error!(
+                                "Failed to get dataset {} of expired chunks: {}",
+                                dataset_id,
+                                err
+                            );
+                            None
+                        }
+                    };
+                    datasets.insert(dataset_id, dataset.clone());
+                    dataset
+                }
+            };
+            let Some(dataset) = dataset else {
+                continue;
+            };
+
+            match delete_chunk_metadata_query(chunk_id, qdrant_point_id, dataset, pool.clone())
+                .await
+            {
+                Ok(()) => deleted_count += 1,
+                Err(err) => log::error!(
+                    "Failed to delete expired chunk {}: {}",
+                    chunk_id,
+                    err.message
+                ),
+            }
+        }
+
+        if is_last_batch || last_seen.is_none() {
+            break;
+        }
+    }
+
+    Ok(deleted_count)
+}
+
 pub fn get_qdrant_id_from_chunk_id_query(
     chunk_id: uuid::Uuid,
     pool: web::Data<Pool>,
@@ -690,9 +1229,46 @@ pub fn get_qdrant_id_from_chunk_id_query(
     }
 }
 
+pub fn get_qdrant_ids_from_chunk_ids_query(
+    chunk_ids: Vec<uuid::Uuid>,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let qdrant_point_ids: Vec<(Option<uuid::Uuid>, Option<uuid::Uuid>)> =
+        chunk_metadata_columns::chunk_metadata
+            .left_outer_join(
+                chunk_collisions_columns::chunk_collisions
+                    .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+            )
+            .select((
+                chunk_metadata_columns::qdrant_point_id,
+                chunk_collisions_columns::collision_qdrant_id.nullable(),
+            ))
+            .filter(chunk_metadata_columns::id.eq_any(chunk_ids))
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+            .load(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Failed to get qdrant_point_ids and collision_qdrant_ids",
+            })?;
+
+    Ok(qdrant_point_ids
+        .into_iter()
+        .filter_map(|(qdrant_point_id, collision_qdrant_id)| {
+            qdrant_point_id.or(collision_qdrant_id)
+        })
+        .collect())
+}
+
 pub fn find_relevant_sentence(
     input: ChunkMetadataWithFileData,
     query: String,
+    highlight_max_num: Option<usize>,
+    highlight_window: Option<usize>,
 ) -> Result<ChunkMetadataWithFileData, DefaultError> {
     let content = &input.chunk_html.clone().unwrap_or(input.content.clone());
     let mut engine: SimSearch<String> = SimSearch::new();
@@ -717,7 +1293,7 @@ pub fn find_relevant_sentence(
 
     //search for the query
     let results = engine.search(&query);
-    let amount = if split_content.len() < 5 { 2 } else { 3 };
+    let amount = highlight_max_num.unwrap_or(if split_content.len() < 5 { 2 } else { 3 });
     for x in results.iter().take(amount) {
         let split_x: Vec<&str> = x.split(',').collect();
         if split_x.len() < 3 {
@@ -728,16 +1304,145 @@ pub fn find_relevant_sentence(
         let highlighted_sentence = format!("{}{}{}", "<b>", split_x[2], "</b>");
         split_content[sentence_index][phrase_index] = highlighted_sentence;
     }
-    new_output.chunk_html = Some(
-        split_content
-            .iter()
-            .map(|x| x.join(", "))
-            .collect::<Vec<String>>()
-            .join(". "),
-    );
+    let highlighted_content = split_content
+        .iter()
+        .map(|x| x.join(", "))
+        .collect::<Vec<String>>()
+        .join(". ");
+
+    new_output.chunk_html = Some(match highlight_window {
+        Some(window) => {
+            get_highlight_snippets(&highlighted_content, window).unwrap_or(highlighted_content)
+        }
+        None => highlighted_content,
+    });
     Ok(new_output)
 }
 
+const MAX_SEMANTIC_HIGHLIGHT_SENTENCES: usize = 20;
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a * norm_b)
+}
+
+/// Splits the chunk's plain text content into sentences. Returns the sentences along with the
+/// indices of the first MAX_SEMANTIC_HIGHLIGHT_SENTENCES non-empty ones, which are the only ones
+/// embedded for semantic highlighting.
+pub fn get_semantic_highlight_sentences(
+    input: &ChunkMetadataWithFileData,
+) -> (Vec<String>, Vec<usize>) {
+    let sentences = input
+        .content
+        .split(". ")
+        .map(|sentence| sentence.to_string())
+        .collect::<Vec<String>>();
+
+    let candidate_indices = sentences
+        .iter()
+        .enumerate()
+        .filter(|(_, sentence)| !sentence.trim().is_empty())
+        .map(|(idx, _)| idx)
+        .take(MAX_SEMANTIC_HIGHLIGHT_SENTENCES)
+        .collect::<Vec<usize>>();
+
+    (sentences, candidate_indices)
+}
+
+/// Highlights the sentences whose embeddings are closest to the query vector. Unlike
+/// find_relevant_sentence this highlights whole sentences and does not need the sentences to share
+/// any words with the query. `candidate_vectors` pairs the indices from
+/// get_semantic_highlight_sentences with their embeddings.
+pub fn find_semantically_relevant_sentences(
+    input: ChunkMetadataWithFileData,
+    mut sentences: Vec<String>,
+    candidate_vectors: Vec<(usize, Vec<f32>)>,
+    query_vector: &[f32],
+    highlight_max_num: Option<usize>,
+    highlight_window: Option<usize>,
+) -> ChunkMetadataWithFileData {
+    let amount = highlight_max_num.unwrap_or(if sentences.len() < 5 { 2 } else { 3 });
+    let closest_indices = candidate_vectors
+        .into_iter()
+        .map(|(idx, sentence_vector)| (idx, cosine_similarity(query_vector, &sentence_vector)))
+        .sorted_by(|a, b| b.1.total_cmp(&a.1))
+        .take(amount)
+        .map(|(idx, _)| idx)
+        .collect::<Vec<usize>>();
+
+    for idx in closest_indices {
+        sentences[idx] = format!("<b>{}</b>", sentences[idx]);
+    }
+    let highlighted_content = sentences.join(". ");
+
+    let mut new_output = input;
+    new_output.chunk_html = Some(match highlight_window {
+        Some(window) => {
+            get_highlight_snippets(&highlighted_content, window).unwrap_or(highlighted_content)
+        }
+        None => highlighted_content,
+    });
+    new_output
+}
+
+/// Keeps only the highlighted spans of `highlighted_content` along with `window` words of context
+/// on either side of them, joining non-adjacent snippets with an ellipsis. Returns None if nothing
+/// was highlighted.
+fn get_highlight_snippets(highlighted_content: &str, window: usize) -> Option<String> {
+    let words = highlighted_content
+        .split_whitespace()
+        .collect::<Vec<&str>>();
+
+    let mut in_highlight = false;
+    let highlighted_word_indices = words
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, word)| {
+            if word.contains("<b>") {
+                in_highlight = true;
+            }
+            let is_highlighted = in_highlight;
+            if word.contains("</b>") {
+                in_highlight = false;
+            }
+            is_highlighted.then_some(idx)
+        })
+        .collect::<Vec<usize>>();
+
+    if highlighted_word_indices.is_empty() {
+        return None;
+    }
+
+    let mut keep_word = vec![false; words.len()];
+    for idx in highlighted_word_indices {
+        let start = idx.saturating_sub(window);
+        let end = (idx + window + 1).min(words.len());
+        keep_word[start..end].fill(true);
+    }
+
+    let mut snippets: Vec<String> = Vec::new();
+    let mut current_snippet: Vec<&str> = Vec::new();
+    for (word, keep) in words.iter().zip(keep_word) {
+        if keep {
+            current_snippet.push(word);
+        } else if !current_snippet.is_empty() {
+            snippets.push(current_snippet.join(" "));
+            current_snippet.clear();
+        }
+    }
+    if !current_snippet.is_empty() {
+        snippets.push(current_snippet.join(" "));
+    }
+
+    Some(snippets.join(" ... "))
+}
+
 pub fn get_row_count_for_dataset_id_query(
     dataset_id: uuid::Uuid,
     pool: web::Data<Pool>,
@@ -756,3 +1461,548 @@ pub fn get_row_count_for_dataset_id_query(
 
     Ok(chunk_metadata_count)
 }
+
+pub fn get_point_chunk_count_query(
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<i64, DefaultError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_metadata_columns::chunk_metadata
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+        .filter(chunk_metadata_columns::qdrant_point_id.is_not_null())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Failed to count chunks for dataset",
+        })
+}
+
+/// A page of the dataset's chunks which own a qdrant point, ordered by id, for jobs which walk
+/// every point of a dataset. Pass the id of the last chunk of the previous page to get the next.
+pub fn get_point_chunks_page_query(
+    dataset_id: uuid::Uuid,
+    after_chunk_id: Option<uuid::Uuid>,
+    limit: i64,
+    pool: web::Data<Pool>,
+) -> Result<Vec<ChunkMetadata>, DefaultError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let mut query = chunk_metadata_columns::chunk_metadata
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+        .filter(chunk_metadata_columns::qdrant_point_id.is_not_null())
+        .into_boxed();
+    if let Some(after_chunk_id) = after_chunk_id {
+        query = query.filter(chunk_metadata_columns::id.gt(after_chunk_id));
+    }
+
+    query
+        .order(chunk_metadata_columns::id.asc())
+        .limit(limit)
+        .select(ChunkMetadata::as_select())
+        .load::<ChunkMetadata>(&mut conn)
+        .map_err(|_| DefaultError {
+            message: "Failed to load chunks for dataset",
+        })
+}
+
+/// Returns every chunk in the dataset alongside the qdrant point it is expected to be stored under.
+/// Collided chunks resolve to the point of the chunk they collided with.
+pub fn get_chunk_qdrant_point_ids_by_dataset_query(
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<(uuid::Uuid, Option<uuid::Uuid>)>, DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let chunk_point_ids: Vec<(uuid::Uuid, Option<uuid::Uuid>, Option<uuid::Uuid>)> =
+        chunk_metadata_columns::chunk_metadata
+            .left_outer_join(
+                chunk_collisions_columns::chunk_collisions
+                    .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+            )
+            .select((
+                chunk_metadata_columns::id,
+                chunk_metadata_columns::qdrant_point_id,
+                chunk_collisions_columns::collision_qdrant_id.nullable(),
+            ))
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+            .load(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Failed to get qdrant_point_ids for dataset",
+            })?;
+
+    Ok(chunk_point_ids
+        .into_iter()
+        .map(|(chunk_id, qdrant_point_id, collision_qdrant_id)| {
+            (chunk_id, qdrant_point_id.or(collision_qdrant_id))
+        })
+        .collect())
+}
+
+/// Returns each of the given chunks alongside the qdrant point it is stored under. Collided chunks
+/// resolve to the point of the chunk they collided with.
+pub fn get_qdrant_point_ids_by_chunk_ids_query(
+    chunk_ids: Vec<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<Vec<(uuid::Uuid, Option<uuid::Uuid>)>, DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let chunk_point_ids: Vec<(uuid::Uuid, Option<uuid::Uuid>, Option<uuid::Uuid>)> =
+        chunk_metadata_columns::chunk_metadata
+            .left_outer_join(
+                chunk_collisions_columns::chunk_collisions
+                    .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+            )
+            .select((
+                chunk_metadata_columns::id,
+                chunk_metadata_columns::qdrant_point_id,
+                chunk_collisions_columns::collision_qdrant_id.nullable(),
+            ))
+            .filter(chunk_metadata_columns::id.eq_any(chunk_ids))
+            .load(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Failed to get qdrant_point_ids for chunks",
+            })?;
+
+    Ok(chunk_point_ids
+        .into_iter()
+        .map(|(chunk_id, qdrant_point_id, collision_qdrant_id)| {
+            (chunk_id, qdrant_point_id.or(collision_qdrant_id))
+        })
+        .collect())
+}
+
+/// Returns which of the given chunks are collisions of another chunk rather than root chunks.
+pub fn get_collision_chunk_ids_query(
+    chunk_ids: Vec<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_collisions_columns::chunk_collisions
+        .select(chunk_collisions_columns::chunk_id)
+        .filter(chunk_collisions_columns::chunk_id.eq_any(chunk_ids))
+        .load::<uuid::Uuid>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load chunk collisions",
+        })
+}
+
+/// Gets up to `n` chunks before and after each of the given chunks in the file they were created
+/// from, using the same order as get_chunks_for_file_query. Chunks which did not come from a file
+/// have no neighbors. The qdrant point of every neighbor, its own or the one it collided with, is
+/// returned as well so the caller can check the neighbors against ACLs.
+pub fn get_neighbor_chunks_query(
+    chunk_ids: Vec<uuid::Uuid>,
+    n: usize,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<
+    (
+        HashMap<uuid::Uuid, ChunkNeighbors>,
+        HashMap<uuid::Uuid, uuid::Uuid>,
+    ),
+    DefaultError,
+> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_files::dsl as chunk_files_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let chunk_file_ids: Vec<(uuid::Uuid, uuid::Uuid)> = chunk_files_columns::chunk_files
+        .select((chunk_files_columns::chunk_id, chunk_files_columns::file_id))
+        .filter(chunk_files_columns::chunk_id.eq_any(&chunk_ids))
+        .load(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load files for chunks",
+        })?;
+
+    let file_chunks: Vec<(ChunkMetadata, uuid::Uuid, Option<uuid::Uuid>)> =
+        chunk_metadata_columns::chunk_metadata
+            .inner_join(
+                chunk_files_columns::chunk_files
+                    .on(chunk_files_columns::chunk_id.eq(chunk_metadata_columns::id)),
+            )
+            .left_outer_join(
+                chunk_collisions_columns::chunk_collisions
+                    .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+            )
+            .filter(
+                chunk_files_columns::file_id.eq_any(
+                    chunk_file_ids
+                        .iter()
+                        .map(|(_, file_id)| *file_id)
+                        .unique()
+                        .collect::<Vec<uuid::Uuid>>(),
+                ),
+            )
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+            .select((
+                ChunkMetadata::as_select(),
+                chunk_files_columns::file_id,
+                chunk_collisions_columns::collision_qdrant_id.nullable(),
+            ))
+            .order((
+                chunk_files_columns::file_id.asc(),
+                chunk_files_columns::chunk_index.asc(),
+                chunk_metadata_columns::created_at.asc(),
+                chunk_metadata_columns::id.asc(),
+            ))
+            .load(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Failed to load neighbor chunks",
+            })?;
+
+    let neighbor_point_ids = file_chunks
+        .iter()
+        .filter_map(|(chunk, _, collision_qdrant_id)| {
+            chunk
+                .qdrant_point_id
+                .or(*collision_qdrant_id)
+                .map(|point_id| (chunk.id, point_id))
+        })
+        .collect::<HashMap<uuid::Uuid, uuid::Uuid>>();
+
+    let chunks_by_file = file_chunks
+        .into_iter()
+        .map(|(chunk, file_id, _)| (file_id, chunk))
+        .into_group_map();
+
+    let neighbors = chunk_file_ids
+        .into_iter()
+        .filter_map(|(chunk_id, file_id)| {
+            let file_chunks = chunks_by_file.get(&file_id)?;
+            let position = file_chunks.iter().position(|chunk| chunk.id == chunk_id)?;
+
+            Some((
+                chunk_id,
+                ChunkNeighbors {
+                    previous: file_chunks[position.saturating_sub(n)..position].to_vec(),
+                    next: file_chunks[position + 1..(position + 1 + n).min(file_chunks.len())]
+                        .to_vec(),
+                },
+            ))
+        })
+        .collect::<HashMap<uuid::Uuid, ChunkNeighbors>>();
+
+    Ok((neighbors, neighbor_point_ids))
+}
+
+/// Returns the qdrant point ids of the chunks created from a file. Chunks which collided with
+/// another chunk are represented by the point of the chunk they collided with.
+pub fn get_point_ids_for_file_query(
+    file_uuid: uuid::Uuid,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_files::dsl as chunk_files_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let point_ids: Vec<(Option<uuid::Uuid>, Option<uuid::Uuid>)> =
+        chunk_metadata_columns::chunk_metadata
+            .inner_join(
+                chunk_files_columns::chunk_files
+                    .on(chunk_files_columns::chunk_id.eq(chunk_metadata_columns::id)),
+            )
+            .left_outer_join(
+                chunk_collisions_columns::chunk_collisions
+                    .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+            )
+            .filter(chunk_files_columns::file_id.eq(file_uuid))
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+            .select((
+                chunk_metadata_columns::qdrant_point_id,
+                chunk_collisions_columns::collision_qdrant_id.nullable(),
+            ))
+            .load(&mut conn)
+            .map_err(|_| DefaultError {
+                message: "Failed to load point ids for file",
+            })?;
+
+    Ok(point_ids
+        .into_iter()
+        .filter_map(|(qdrant_point_id, collision_qdrant_id)| {
+            qdrant_point_id.or(collision_qdrant_id)
+        })
+        .unique()
+        .collect())
+}
+
+/// Counts the collisions (duplicates) attached to the root chunk owning the given qdrant point.
+pub fn get_collision_count_query(
+    qdrant_point_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<i64, DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_collisions_columns::chunk_collisions
+        .filter(chunk_collisions_columns::collision_qdrant_id.eq(qdrant_point_id))
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to count chunk collisions",
+        })
+}
+
+/// Gets the root chunk owning the given qdrant point and the collisions (duplicates) linked to it,
+/// oldest first.
+pub fn get_collision_group_query(
+    qdrant_point_id: uuid::Uuid,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(ChunkMetadataWithFileData, Vec<ChunkMetadataWithFileData>), DefaultError> {
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let root_chunk: ChunkMetadata = chunk_metadata_columns::chunk_metadata
+        .filter(chunk_metadata_columns::qdrant_point_id.eq(qdrant_point_id))
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+        .select(ChunkMetadata::as_select())
+        .first::<ChunkMetadata>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load the root chunk of the collision group",
+        })?;
+
+    let collision_chunks: Vec<ChunkMetadata> = chunk_collisions_columns::chunk_collisions
+        .inner_join(
+            chunk_metadata_columns::chunk_metadata
+                .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+        )
+        .filter(chunk_collisions_columns::collision_qdrant_id.eq(qdrant_point_id))
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+        .select(ChunkMetadata::as_select())
+        .load::<ChunkMetadata>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load the collisions of the chunk",
+        })?;
+
+    let root_chunk_id = root_chunk.id;
+    let mut chunks = get_metadata_query(
+        std::iter::once(root_chunk)
+            .chain(collision_chunks)
+            .map(|chunk| chunk.into())
+            .collect::<Vec<FullTextSearchResult>>(),
+        conn,
+    )?;
+    chunks.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+
+    let root_index = chunks
+        .iter()
+        .position(|chunk| chunk.id == root_chunk_id)
+        .ok_or(DefaultError {
+            message: "Failed to load the root chunk of the collision group",
+        })?;
+    let root_chunk = chunks.remove(root_index);
+
+    Ok((root_chunk, chunks))
+}
+
+/// Deletes the `n` oldest collisions of the root chunk owning the given qdrant point. Collisions
+/// do not have qdrant points of their own, so only their rows in postgres need to be removed.
+pub fn delete_oldest_collisions_query(
+    qdrant_point_id: uuid::Uuid,
+    n: i64,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(), DefaultError> {
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_files::dsl as chunk_files_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let evicted_chunk_ids: Vec<uuid::Uuid> = chunk_collisions_columns::chunk_collisions
+            .select(chunk_collisions_columns::chunk_id)
+            .filter(chunk_collisions_columns::collision_qdrant_id.eq(qdrant_point_id))
+            .order_by(chunk_collisions_columns::created_at.asc())
+            .limit(n)
+            .load::<uuid::Uuid>(conn)?;
+
+        diesel::delete(
+            chunk_files_columns::chunk_files
+                .filter(chunk_files_columns::chunk_id.eq_any(&evicted_chunk_ids)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_collection_bookmarks_columns::chunk_collection_bookmarks.filter(
+                chunk_collection_bookmarks_columns::chunk_metadata_id.eq_any(&evicted_chunk_ids),
+            ),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_collisions_columns::chunk_collisions
+                .filter(chunk_collisions_columns::chunk_id.eq_any(&evicted_chunk_ids)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_metadata_columns::chunk_metadata
+                .filter(chunk_metadata_columns::id.eq_any(&evicted_chunk_ids))
+                .filter(chunk_metadata_columns::dataset_id.eq(dataset_id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    })
+    .map_err(|_err| DefaultError {
+        message: "Failed to evict the oldest chunk collisions",
+    })
+}
+
+const MAX_DID_YOU_MEAN_SUGGESTIONS: usize = 3;
+
+#[derive(diesel::QueryableByName)]
+struct SimilarTerm {
+    #[diesel(sql_type = Text)]
+    term: String,
+}
+
+/// Builds alternative queries by replacing each word of the query which does not appear in the
+/// dataset with the closest words that do by trigram similarity. Words are looked up in the
+/// dataset_words table, which a trigger keeps in step with the content of the dataset's chunks.
+pub fn get_did_you_mean_query(
+    query: String,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<String>, DefaultError> {
+    let mut conn = pool.get().unwrap();
+
+    let words = query
+        .to_lowercase()
+        .split_whitespace()
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphanumeric())
+                .to_string()
+        })
+        .filter(|word| !word.is_empty())
+        .take(10)
+        .collect::<Vec<String>>();
+
+    let mut candidates_per_word: Vec<Vec<String>> = vec![];
+    let mut has_correction = false;
+    for word in words {
+        let similar_terms = diesel::sql_query(
+            "SELECT word AS term FROM dataset_words
+            WHERE dataset_id = $1 AND word % $2
+            ORDER BY similarity(word, $2) DESC, word
+            LIMIT $3",
+        )
+        .bind::<diesel::sql_types::Uuid, _>(dataset_uuid)
+        .bind::<Text, _>(&word)
+        .bind::<Int8, _>(MAX_DID_YOU_MEAN_SUGGESTIONS as i64)
+        .load::<SimilarTerm>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to load similar terms for suggestions",
+        })?;
+
+        if similar_terms.is_empty() || similar_terms.iter().any(|t| t.term == word) {
+            candidates_per_word.push(vec![word]);
+        } else {
+            has_correction = true;
+            candidates_per_word.push(similar_terms.into_iter().map(|t| t.term).collect());
+        }
+    }
+
+    if !has_correction {
+        return Ok(vec![]);
+    }
+
+    Ok((0..MAX_DID_YOU_MEAN_SUGGESTIONS)
+        .map(|rank| {
+            candidates_per_word
+                .iter()
+                .map(|candidates| candidates[rank.min(candidates.len() - 1)].clone())
+                .join(" ")
+        })
+        .unique()
+        .collect())
+}
+
+/// Finds the metadata keys which no chunk in the dataset has, so that filters on misspelled keys
+/// can be told apart from filters which simply match nothing.
+pub fn get_missing_metadata_keys_query(
+    keys: Vec<String>,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<String>, DefaultError> {
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let mut missing_keys = vec![];
+    for key in keys {
+        let key_exists: bool = diesel::select(diesel::dsl::exists(
+            chunk_metadata_columns::chunk_metadata
+                .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+                .filter(
+                    sql::<diesel::sql_types::Bool>("chunk_metadata.metadata ? ")
+                        .bind::<Text, _>(key.clone()),
+                ),
+        ))
+        .get_result(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to check for metadata keys",
+        })?;
+
+        if !key_exists {
+            missing_keys.push(key);
+        }
+    }
+
+    Ok(missing_keys)
+}
+
+/// Ranks the tokens of the SPLADE doc vector of a chunk's content by their weight, so the terms the
+/// full-text model most strongly associates with the chunk come first. These can include closely
+/// related terms which do not appear in the content. Word piece continuations, special tokens,
+/// tokens shorter than 3 characters and numbers are left out.
+pub async fn get_chunk_keywords_query(
+    content: String,
+    splade_model: Option<String>,
+    limit: usize,
+) -> Result<Vec<ChunkKeyword>, ServiceError> {
+    let token_weights = get_splade_doc_token_weights(&content, splade_model).await?;
+
+    Ok(token_weights
+        .into_iter()
+        .filter(|(token, _)| token.chars().count() >= 3)
+        .filter(|(token, _)| token.chars().all(|c| c.is_alphanumeric()))
+        .filter(|(token, _)| !token.chars().all(|c| c.is_numeric()))
+        .map(|(token, weight)| ChunkKeyword {
+            term: token.to_lowercase(),
+            score: weight as f64,
+        })
+        .sorted_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.term.cmp(&b.term))
+        })
+        .unique_by(|keyword| keyword.term.clone())
+        .take(limit)
+        .collect())
+}
@@ -1,11 +1,15 @@
 use crate::{
-    data::models::{ChunkCollection, Pool},
+    data::models::{ChunkCollection, Dataset, Pool},
     errors::DefaultError,
+    operators::{
+        chunk_operator::delete_chunk_metadata_query, qdrant_operator::delete_qdrant_point_ids_query,
+    },
 };
 use crate::{
     data::models::{
-        ChunkCollectionAndFileWithCount, ChunkCollectionBookmark, ChunkMetadataWithCount,
-        ChunkMetadataWithFileData, FileCollection, FullTextSearchResult, SlimCollection,
+        ChunkCollectionAndFileWithCount, ChunkCollectionBookmark, ChunkCollectionWithBookmarkCount,
+        ChunkMetadataWithCount, ChunkMetadataWithFileData, FileCollection, FullTextSearchResult,
+        SlimCollection,
     },
     diesel::{Connection, ExpressionMethods, QueryDsl, RunQueryDsl},
     errors::ServiceError,
@@ -14,8 +18,11 @@ use crate::{
 use actix_web::web;
 use diesel::{
     dsl::sql, sql_types::Int8, BoolExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    SelectableHelper,
 };
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use utoipa::ToSchema;
 
 pub fn create_collection_query(
@@ -134,6 +141,7 @@ pub fn get_collections_for_specific_user_query(
         .order_by(updated_at.desc())
         .filter(author_id.eq(user_id))
         .filter(dataset_id.eq(dataset_uuid))
+        .filter(deleted_at.is_null())
         .into_boxed();
 
     let collections = collections
@@ -182,6 +190,7 @@ pub fn get_collections_for_logged_in_user_query(
         ))
         .filter(author_id.eq(current_user_id))
         .filter(dataset_id.eq(dataset_uuid))
+        .filter(deleted_at.is_null())
         .order(updated_at.desc())
         .limit(5)
         .offset(((page - 1) * 5).try_into().unwrap_or(0))
@@ -193,6 +202,86 @@ pub fn get_collections_for_logged_in_user_query(
     Ok(collections)
 }
 
+pub fn get_collections_with_bookmark_counts_query(
+    page: u64,
+    sort_by: Option<String>,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<(Vec<ChunkCollectionWithBookmarkCount>, i64), DefaultError> {
+    use crate::data::schema::chunk_collection::dsl as chunk_collection_columns;
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+
+    let page = if page == 0 { 1 } else { page };
+    let mut conn = pool.get().unwrap();
+
+    let bookmark_count = diesel::dsl::count(chunk_collection_bookmarks_columns::id.nullable());
+
+    let query = chunk_collection_columns::chunk_collection
+        .left_join(
+            chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+                .on(chunk_collection_columns::id
+                    .eq(chunk_collection_bookmarks_columns::collection_id)),
+        )
+        .filter(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+        .filter(chunk_collection_columns::deleted_at.is_null())
+        .group_by(chunk_collection_columns::id)
+        .select((
+            (
+                chunk_collection_columns::id,
+                chunk_collection_columns::author_id,
+                chunk_collection_columns::name,
+                chunk_collection_columns::description,
+                chunk_collection_columns::created_at,
+                chunk_collection_columns::updated_at,
+                chunk_collection_columns::dataset_id,
+                chunk_collection_columns::deleted_at,
+            ),
+            bookmark_count,
+            diesel::dsl::max(chunk_collection_bookmarks_columns::created_at.nullable()),
+            sql::<Int8>("count(*) OVER() AS full_count"),
+        ))
+        .into_boxed();
+
+    let query = match sort_by.as_deref() {
+        Some("count") => query.order((
+            bookmark_count.desc(),
+            chunk_collection_columns::updated_at.desc(),
+        )),
+        Some("name") => query.order((
+            chunk_collection_columns::name.asc(),
+            chunk_collection_columns::id.asc(),
+        )),
+        _ => query.order(chunk_collection_columns::updated_at.desc()),
+    };
+
+    let collections = query
+        .limit(10)
+        .offset(((page - 1) * 10).try_into().unwrap_or(0))
+        .load::<(ChunkCollection, i64, Option<chrono::NaiveDateTime>, i64)>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Error getting collections",
+        })?;
+
+    let total_collections = collections
+        .first()
+        .map(|(_, _, _, full_count)| *full_count)
+        .unwrap_or(0);
+
+    Ok((
+        collections
+            .into_iter()
+            .map(|(collection, bookmark_count, last_bookmarked_at, _)| {
+                ChunkCollectionWithBookmarkCount::from_details(
+                    collection,
+                    bookmark_count,
+                    last_bookmarked_at,
+                )
+            })
+            .collect(),
+        total_collections,
+    ))
+}
+
 pub fn get_collection_by_id_query(
     collection_id: uuid::Uuid,
     dataset_uuid: uuid::Uuid,
@@ -205,6 +294,7 @@ pub fn get_collection_by_id_query(
     let collection = chunk_collection
         .filter(dataset_id.eq(dataset_uuid))
         .filter(id.eq(collection_id))
+        .filter(deleted_at.is_null())
         .first::<ChunkCollection>(&mut conn)
         .map_err(|_err| DefaultError {
             message: "Collection not found",
@@ -217,6 +307,55 @@ pub fn delete_collection_by_id_query(
     collection_id: uuid::Uuid,
     dataset_uuid: uuid::Uuid,
     pool: web::Data<Pool>,
+) -> Result<(), DefaultError> {
+    use crate::data::schema::chunk_collection::dsl::*;
+
+    let mut conn = pool.get().unwrap();
+
+    diesel::update(
+        chunk_collection
+            .filter(id.eq(collection_id))
+            .filter(dataset_id.eq(dataset_uuid))
+            .filter(deleted_at.is_null()),
+    )
+    .set(deleted_at.eq(chrono::Utc::now().naive_local()))
+    .execute(&mut conn)
+    .map_err(|_err| DefaultError {
+        message: "Error deleting collection",
+    })?;
+
+    Ok(())
+}
+
+pub fn restore_collection_by_id_query(
+    collection_id: uuid::Uuid,
+    author_uuid: uuid::Uuid,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<ChunkCollection, DefaultError> {
+    use crate::data::schema::chunk_collection::dsl::*;
+
+    let mut conn = pool.get().unwrap();
+
+    diesel::update(
+        chunk_collection
+            .filter(id.eq(collection_id))
+            .filter(dataset_id.eq(dataset_uuid))
+            .filter(author_id.eq(author_uuid))
+            .filter(deleted_at.is_not_null()),
+    )
+    .set(deleted_at.eq(None::<chrono::NaiveDateTime>))
+    .get_result::<ChunkCollection>(&mut conn)
+    .map_err(|_err| DefaultError {
+        message: "Deleted collection not found or not owned by the current user",
+    })
+}
+
+pub fn purge_collection_by_id_query(
+    collection_id: uuid::Uuid,
+    author_uuid: uuid::Uuid,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
 ) -> Result<(), DefaultError> {
     use crate::data::schema::chunk_collection::dsl as chunk_collection_columns;
     use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
@@ -225,6 +364,17 @@ pub fn delete_collection_by_id_query(
 
     let mut conn = pool.get().unwrap();
 
+    chunk_collection_columns::chunk_collection
+        .filter(chunk_collection_columns::id.eq(collection_id))
+        .filter(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+        .filter(chunk_collection_columns::author_id.eq(author_uuid))
+        .filter(chunk_collection_columns::deleted_at.is_not_null())
+        .select(chunk_collection_columns::id)
+        .first::<uuid::Uuid>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Deleted collection not found or not owned by the current user",
+        })?;
+
     let transaction_result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
         diesel::delete(
             file_upload_completed_notifications_columns::file_upload_completed_notifications
@@ -259,11 +409,183 @@ pub fn delete_collection_by_id_query(
     match transaction_result {
         Ok(_) => Ok(()),
         Err(_) => Err(DefaultError {
-            message: "Error deleting collection",
+            message: "Error purging collection",
         }),
     }
 }
 
+/// Permanently deletes a collection along with the chunks which are bookmarked in it and in no
+/// other collection, returning the number of bookmarks and chunks deleted. Chunks which are the
+/// root of a collision group with members outside of the collection are deleted afterwards through
+/// `delete_chunk_metadata_query` so that one of those members is promoted in their place.
+pub async fn delete_collection_and_chunks_query(
+    collection_id: uuid::Uuid,
+    dataset: Dataset,
+    pool: web::Data<Pool>,
+) -> Result<(usize, usize), DefaultError> {
+    use crate::data::schema::chunk_collection::dsl as chunk_collection_columns;
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_files::dsl as chunk_files_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+    use crate::data::schema::collections_from_files::dsl as collections_from_files_columns;
+    use crate::data::schema::file_upload_completed_notifications::dsl as file_upload_completed_notifications_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let collection_chunk_ids: Vec<uuid::Uuid> =
+        chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+            .filter(chunk_collection_bookmarks_columns::collection_id.eq(collection_id))
+            .select(chunk_collection_bookmarks_columns::chunk_metadata_id)
+            .load::<uuid::Uuid>(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Error getting bookmarks for collection",
+            })?;
+
+    // Chunks bookmarked in any other collection, including deleted ones which may be restored, are kept
+    let shared_chunk_ids: HashSet<uuid::Uuid> =
+        chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+            .filter(
+                chunk_collection_bookmarks_columns::chunk_metadata_id.eq_any(&collection_chunk_ids),
+            )
+            .filter(chunk_collection_bookmarks_columns::collection_id.ne(collection_id))
+            .select(chunk_collection_bookmarks_columns::chunk_metadata_id)
+            .load::<uuid::Uuid>(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Error getting bookmarks for collection",
+            })?
+            .into_iter()
+            .collect();
+
+    let exclusive_chunks: Vec<(uuid::Uuid, Option<uuid::Uuid>)> =
+        chunk_metadata_columns::chunk_metadata
+            .filter(
+                chunk_metadata_columns::id.eq_any(
+                    collection_chunk_ids
+                        .iter()
+                        .filter(|chunk_id| !shared_chunk_ids.contains(chunk_id))
+                        .collect::<Vec<&uuid::Uuid>>(),
+                ),
+            )
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset.id))
+            .select((
+                chunk_metadata_columns::id,
+                chunk_metadata_columns::qdrant_point_id,
+            ))
+            .load::<(uuid::Uuid, Option<uuid::Uuid>)>(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Error getting chunks for collection",
+            })?;
+    let exclusive_chunk_ids: Vec<uuid::Uuid> = exclusive_chunks
+        .iter()
+        .map(|(chunk_id, _)| *chunk_id)
+        .collect();
+    let root_point_ids: Vec<uuid::Uuid> = exclusive_chunks
+        .iter()
+        .filter_map(|(_, qdrant_point_id)| *qdrant_point_id)
+        .collect();
+
+    let surviving_collision_point_ids: HashSet<uuid::Uuid> =
+        chunk_collisions_columns::chunk_collisions
+            .filter(chunk_collisions_columns::collision_qdrant_id.eq_any(&root_point_ids))
+            .filter(chunk_collisions_columns::chunk_id.ne_all(&exclusive_chunk_ids))
+            .select(chunk_collisions_columns::collision_qdrant_id)
+            .load::<Option<uuid::Uuid>>(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Error getting collisions for collection",
+            })?
+            .into_iter()
+            .flatten()
+            .collect();
+
+    let (promoted_roots, removable_chunks): (Vec<_>, Vec<_>) = exclusive_chunks
+        .into_iter()
+        .partition(|(_, qdrant_point_id)| {
+            qdrant_point_id
+                .map(|point_id| surviving_collision_point_ids.contains(&point_id))
+                .unwrap_or(false)
+        });
+    let removable_chunk_ids: Vec<uuid::Uuid> = removable_chunks
+        .iter()
+        .map(|(chunk_id, _)| *chunk_id)
+        .collect();
+
+    let transaction_result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(
+            file_upload_completed_notifications_columns::file_upload_completed_notifications
+                .filter(
+                    file_upload_completed_notifications_columns::collection_uuid.eq(collection_id),
+                ),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            collections_from_files_columns::collections_from_files
+                .filter(collections_from_files_columns::collection_id.eq(collection_id)),
+        )
+        .execute(conn)?;
+
+        let deleted_bookmarks = diesel::delete(
+            chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+                .filter(chunk_collection_bookmarks_columns::collection_id.eq(collection_id)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_collection_columns::chunk_collection
+                .filter(chunk_collection_columns::id.eq(collection_id))
+                .filter(chunk_collection_columns::dataset_id.eq(dataset.id)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_files_columns::chunk_files
+                .filter(chunk_files_columns::chunk_id.eq_any(&removable_chunk_ids)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_collisions_columns::chunk_collisions
+                .filter(chunk_collisions_columns::chunk_id.eq_any(&removable_chunk_ids)),
+        )
+        .execute(conn)?;
+
+        diesel::delete(
+            chunk_metadata_columns::chunk_metadata
+                .filter(chunk_metadata_columns::id.eq_any(&removable_chunk_ids))
+                .filter(chunk_metadata_columns::dataset_id.eq(dataset.id)),
+        )
+        .execute(conn)?;
+
+        Ok(deleted_bookmarks)
+    });
+
+    let deleted_bookmarks = transaction_result.map_err(|err| {
+        log::error!("Error deleting collection and chunks {:}", err);
+        DefaultError {
+            message: "Error deleting collection and chunks",
+        }
+    })?;
+
+    delete_qdrant_point_ids_query(
+        removable_chunks
+            .iter()
+            .filter_map(|(_, qdrant_point_id)| *qdrant_point_id)
+            .collect(),
+    )
+    .await?;
+
+    for (chunk_id, qdrant_point_id) in promoted_roots.iter() {
+        delete_chunk_metadata_query(*chunk_id, *qdrant_point_id, dataset.clone(), pool.clone())
+            .await?;
+    }
+
+    Ok((
+        deleted_bookmarks,
+        removable_chunks.len() + promoted_roots.len(),
+    ))
+}
+
 pub fn update_chunk_collection_query(
     collection: ChunkCollection,
     new_name: Option<String>,
@@ -278,7 +600,8 @@ pub fn update_chunk_collection_query(
     diesel::update(
         chunk_collection
             .filter(id.eq(collection.id))
-            .filter(dataset_id.eq(dataset_uuid)),
+            .filter(dataset_id.eq(dataset_uuid))
+            .filter(deleted_at.is_null()),
     )
     .set((
         name.eq(new_name.unwrap_or(collection.name)),
@@ -312,6 +635,46 @@ pub fn create_chunk_bookmark_query(
 
     Ok(())
 }
+/// Bookmarks the given chunks into a collection in a single transaction. Chunks which are
+/// already in the collection are skipped. Returns the number of bookmarks which were created.
+pub fn create_chunk_bookmarks_query(
+    collection_uuid: uuid::Uuid,
+    chunk_ids: Vec<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<usize, DefaultError> {
+    use crate::data::schema::chunk_collection_bookmarks::dsl::*;
+
+    let mut conn = pool.get().unwrap();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let existing_chunk_ids: HashSet<uuid::Uuid> = chunk_collection_bookmarks
+            .filter(collection_id.eq(collection_uuid))
+            .filter(chunk_metadata_id.eq_any(&chunk_ids))
+            .select(chunk_metadata_id)
+            .load::<uuid::Uuid>(conn)?
+            .into_iter()
+            .collect();
+
+        diesel::insert_into(chunk_collection_bookmarks)
+            .values(
+                chunk_ids
+                    .iter()
+                    .filter(|chunk_id| !existing_chunk_ids.contains(chunk_id))
+                    .map(|chunk_id| {
+                        ChunkCollectionBookmark::from_details(collection_uuid, *chunk_id)
+                    })
+                    .collect::<Vec<ChunkCollectionBookmark>>(),
+            )
+            .execute(conn)
+    })
+    .map_err(|_err| {
+        log::error!("Error creating bookmarks {:}", _err);
+        DefaultError {
+            message: "Error creating bookmarks",
+        }
+    })
+}
+
 pub struct CollectionsBookmarkQueryResult {
     pub metadata: Vec<ChunkMetadataWithFileData>,
     pub collection: ChunkCollection,
@@ -351,6 +714,7 @@ pub fn get_bookmarks_for_collection_query(
                 chunk_collection_bookmarks_columns::collection_id
                     .eq(collection)
                     .and(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+                    .and(chunk_collection_columns::deleted_at.is_null())
                     .and(chunk_metadata_columns::dataset_id.eq(dataset_uuid)),
             )
             .select((
@@ -379,8 +743,13 @@ pub fn get_bookmarks_for_collection_query(
                     chunk_collection_columns::created_at.assume_not_null(),
                     chunk_collection_columns::updated_at.assume_not_null(),
                     chunk_collection_columns::dataset_id.assume_not_null(),
+                    chunk_collection_columns::deleted_at,
                 ),
             ))
+            .order((
+                chunk_collection_bookmarks_columns::created_at.asc(),
+                chunk_collection_bookmarks_columns::id.asc(),
+            ))
             .limit(limit)
             .offset(((page - 1) * limit as u64).try_into().unwrap_or(0))
             .load::<(ChunkMetadataWithCount, Option<uuid::Uuid>, ChunkCollection)>(&mut conn)
@@ -392,6 +761,7 @@ pub fn get_bookmarks_for_collection_query(
         chunk_collection_columns::chunk_collection
             .filter(chunk_collection_columns::id.eq(collection))
             .filter(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+            .filter(chunk_collection_columns::deleted_at.is_null())
             .first::<ChunkCollection>(&mut conn)
             .map_err(|_err| ServiceError::BadRequest("Error getting collection".to_string()))?
     };
@@ -414,7 +784,7 @@ pub fn get_bookmarks_for_collection_query(
         .map_err(|_| ServiceError::BadRequest("Failed to load metadata".to_string()))?;
 
     let total_pages = match bookmark_metadata.first() {
-        Some(metadata) => (metadata.0.count as f64 / 10.0).ceil() as i64,
+        Some(metadata) => (metadata.0.count as f64 / limit as f64).ceil() as i64,
         None => 0,
     };
 
@@ -448,6 +818,7 @@ pub fn get_collections_for_bookmark_query(
                     .eq(chunk_collection_bookmarks_columns::collection_id)),
         )
         .filter(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+        .filter(chunk_collection_columns::deleted_at.is_null())
         .filter(chunk_collection_bookmarks_columns::chunk_metadata_id.eq_any(chunk_ids))
         .select((
             chunk_collection_columns::id,
@@ -505,6 +876,74 @@ pub fn get_collections_for_bookmark_query(
 
     Ok(bookmark_collections)
 }
+
+/// Gets the qdrant point ids of the chunks bookmarked in a collection, most recently bookmarked
+/// first. Collisions are given the point of the chunk they collided with.
+pub fn get_collection_point_ids_query(
+    collection_id: uuid::Uuid,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+    use crate::data::schema::chunk_collisions::dsl as chunk_collisions_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    let point_ids: Vec<(Option<uuid::Uuid>, Option<uuid::Uuid>)> =
+        chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+            .inner_join(
+                chunk_metadata_columns::chunk_metadata.on(chunk_metadata_columns::id
+                    .eq(chunk_collection_bookmarks_columns::chunk_metadata_id)),
+            )
+            .left_outer_join(
+                chunk_collisions_columns::chunk_collisions
+                    .on(chunk_metadata_columns::id.eq(chunk_collisions_columns::chunk_id)),
+            )
+            .filter(chunk_collection_bookmarks_columns::collection_id.eq(collection_id))
+            .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+            .order(chunk_collection_bookmarks_columns::created_at.desc())
+            .select((
+                chunk_metadata_columns::qdrant_point_id,
+                chunk_collisions_columns::collision_qdrant_id.nullable(),
+            ))
+            .load(&mut conn)
+            .map_err(|_err| DefaultError {
+                message: "Failed to load the chunks of the collection",
+            })?;
+
+    Ok(point_ids
+        .into_iter()
+        .filter_map(|(qdrant_point_id, collision_qdrant_id)| {
+            qdrant_point_id.or(collision_qdrant_id)
+        })
+        .unique()
+        .collect())
+}
+
+/// Finds which of the given collections each of the given chunks is bookmarked in, as pairs of chunk id and collection id.
+pub fn get_bookmark_collection_ids_query(
+    chunk_ids: Vec<uuid::Uuid>,
+    collection_ids: Vec<uuid::Uuid>,
+    pool: web::Data<Pool>,
+) -> Result<Vec<(uuid::Uuid, uuid::Uuid)>, DefaultError> {
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+        .filter(chunk_collection_bookmarks_columns::chunk_metadata_id.eq_any(chunk_ids))
+        .filter(chunk_collection_bookmarks_columns::collection_id.eq_any(collection_ids))
+        .select((
+            chunk_collection_bookmarks_columns::chunk_metadata_id,
+            chunk_collection_bookmarks_columns::collection_id,
+        ))
+        .load::<(uuid::Uuid, uuid::Uuid)>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Error getting bookmarks",
+        })
+}
+
 pub fn delete_bookmark_query(
     bookmark_id: uuid::Uuid,
     collection_id: uuid::Uuid,
@@ -519,6 +958,7 @@ pub fn delete_bookmark_query(
     chunk_collection_columns::chunk_collection
         .filter(chunk_collection_columns::id.eq(collection_id))
         .filter(chunk_collection_columns::dataset_id.eq(dataset_id))
+        .filter(chunk_collection_columns::deleted_at.is_null())
         .first::<ChunkCollection>(&mut conn)
         .map_err(|_err| DefaultError {
             message: "Collection not found, likely incorrect dataset_id",
@@ -539,3 +979,107 @@ pub fn delete_bookmark_query(
 
     Ok(())
 }
+
+pub fn set_chunk_collections_query(
+    chunk_id: uuid::Uuid,
+    collection_ids: Vec<uuid::Uuid>,
+    author_uuid: uuid::Uuid,
+    dataset_uuid: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<ChunkCollection>, DefaultError> {
+    use crate::data::schema::chunk_collection::dsl as chunk_collection_columns;
+    use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
+    use crate::data::schema::chunk_metadata::dsl as chunk_metadata_columns;
+
+    let mut conn = pool.get().unwrap();
+
+    chunk_metadata_columns::chunk_metadata
+        .filter(chunk_metadata_columns::id.eq(chunk_id))
+        .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+        .select(chunk_metadata_columns::id)
+        .first::<uuid::Uuid>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Chunk not found, likely incorrect dataset_id",
+        })?;
+
+    let collection_ids: Vec<uuid::Uuid> = collection_ids
+        .into_iter()
+        .collect::<HashSet<uuid::Uuid>>()
+        .into_iter()
+        .collect();
+
+    let owned_collection_ids: Vec<uuid::Uuid> = chunk_collection_columns::chunk_collection
+        .filter(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+        .filter(chunk_collection_columns::author_id.eq(author_uuid))
+        .filter(chunk_collection_columns::deleted_at.is_null())
+        .select(chunk_collection_columns::id)
+        .load::<uuid::Uuid>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Error getting collections",
+        })?;
+
+    if collection_ids
+        .iter()
+        .any(|collection_id| !owned_collection_ids.contains(collection_id))
+    {
+        return Err(DefaultError {
+            message: "Collection not found or not owned by the current user",
+        });
+    }
+
+    let transaction_result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let removed_collection_ids = owned_collection_ids
+            .iter()
+            .filter(|collection_id| !collection_ids.contains(collection_id))
+            .cloned()
+            .collect::<Vec<uuid::Uuid>>();
+
+        diesel::delete(
+            chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+                .filter(chunk_collection_bookmarks_columns::chunk_metadata_id.eq(chunk_id))
+                .filter(
+                    chunk_collection_bookmarks_columns::collection_id
+                        .eq_any(removed_collection_ids),
+                ),
+        )
+        .execute(conn)?;
+
+        let existing_collection_ids: Vec<uuid::Uuid> =
+            chunk_collection_bookmarks_columns::chunk_collection_bookmarks
+                .filter(chunk_collection_bookmarks_columns::chunk_metadata_id.eq(chunk_id))
+                .filter(
+                    chunk_collection_bookmarks_columns::collection_id
+                        .eq_any(collection_ids.clone()),
+                )
+                .select(chunk_collection_bookmarks_columns::collection_id)
+                .load::<uuid::Uuid>(conn)?;
+
+        let new_bookmarks = collection_ids
+            .iter()
+            .filter(|collection_id| !existing_collection_ids.contains(collection_id))
+            .map(|collection_id| ChunkCollectionBookmark::from_details(*collection_id, chunk_id))
+            .collect::<Vec<ChunkCollectionBookmark>>();
+
+        if !new_bookmarks.is_empty() {
+            diesel::insert_into(chunk_collection_bookmarks_columns::chunk_collection_bookmarks)
+                .values(&new_bookmarks)
+                .execute(conn)?;
+        }
+
+        chunk_collection_columns::chunk_collection
+            .inner_join(chunk_collection_bookmarks_columns::chunk_collection_bookmarks)
+            .filter(chunk_collection_bookmarks_columns::chunk_metadata_id.eq(chunk_id))
+            .filter(chunk_collection_columns::dataset_id.eq(dataset_uuid))
+            .filter(chunk_collection_columns::deleted_at.is_null())
+            .select(ChunkCollection::as_select())
+            .order(chunk_collection_columns::name.asc())
+            .load::<ChunkCollection>(conn)
+    });
+
+    transaction_result.map_err(|err| {
+        log::error!("Error setting chunk collections {:}", err);
+        DefaultError {
+            message: "Error setting chunk collections",
+        }
+    })
+}
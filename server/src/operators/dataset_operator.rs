@@ -1,11 +1,130 @@
-use crate::data::models::{DatasetAndUsage, DatasetUsageCount};
+use crate::data::models::{DatasetAndUsage, DatasetUsageCount, ServerDatasetConfiguration};
 use crate::diesel::RunQueryDsl;
+use crate::operators::chunk_operator::get_point_chunks_page_query;
+use crate::operators::qdrant_operator::{get_fulltext_sparse_vector, update_sparse_vectors_query};
+use crate::operators::user_operator::{SALT, SECRET_KEY};
 use crate::{
     data::models::{Dataset, Pool},
     errors::ServiceError,
 };
 use actix_web::web;
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::NaiveDateTime;
 use diesel::{ExpressionMethods, QueryDsl, SelectableHelper};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Server configuration keys which hold provider API keys. They are encrypted before they are
+/// stored and removed from every dataset returned by the API.
+pub const ENCRYPTED_CONFIGURATION_KEYS: [&str; 2] = ["LLM_API_KEY", "EMBEDDING_API_KEY"];
+
+const ENCRYPTED_VALUE_PREFIX: &str = "enc:";
+
+static API_KEY_ENCRYPTION_KEY: Lazy<Vec<u8>> = Lazy::new(|| {
+    let config = argon2::Config {
+        hash_length: 32,
+        ..argon2::Config::original()
+    };
+    argon2::hash_raw(SECRET_KEY.as_bytes(), SALT.as_bytes(), &config)
+        .expect("Deriving the API key encryption key should not fail")
+});
+
+pub fn encrypt_api_key(api_key: &str) -> Result<String, ServiceError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&API_KEY_ENCRYPTION_KEY));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, api_key.as_bytes())
+        .map_err(|_| ServiceError::InternalServerError("Failed to encrypt API key".to_string()))?;
+
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_VALUE_PREFIX,
+        general_purpose::STANDARD.encode([nonce.as_slice(), &ciphertext].concat())
+    ))
+}
+
+pub fn decrypt_api_key(encrypted_api_key: &str) -> Result<String, ServiceError> {
+    let decrypt_error =
+        || ServiceError::InternalServerError("Failed to decrypt the dataset's API key".to_string());
+
+    let bytes = general_purpose::STANDARD
+        .decode(
+            encrypted_api_key
+                .strip_prefix(ENCRYPTED_VALUE_PREFIX)
+                .ok_or_else(decrypt_error)?,
+        )
+        .map_err(|_| decrypt_error())?;
+    if bytes.len() < 12 {
+        return Err(decrypt_error());
+    }
+    let (nonce, ciphertext) = bytes.split_at(12);
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&API_KEY_ENCRYPTION_KEY));
+    let api_key = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| decrypt_error())?;
+
+    String::from_utf8(api_key).map_err(|_| decrypt_error())
+}
+
+/// Encrypts any plaintext API keys in a server configuration before it is stored. Keys which are
+/// left out of the new configuration are carried over from the current one, since they are never
+/// returned to be sent back, and keys set to null are removed.
+pub fn encrypt_server_configuration(
+    mut server_configuration: serde_json::Value,
+    current_server_configuration: Option<&serde_json::Value>,
+) -> Result<serde_json::Value, ServiceError> {
+    let Some(configuration) = server_configuration.as_object_mut() else {
+        return Ok(server_configuration);
+    };
+
+    for key in ENCRYPTED_CONFIGURATION_KEYS {
+        match configuration.get(key).cloned() {
+            None => {
+                if let Some(current_value) =
+                    current_server_configuration.and_then(|current| current.get(key))
+                {
+                    configuration.insert(key.to_string(), current_value.clone());
+                }
+            }
+            Some(serde_json::Value::Null) => {
+                configuration.remove(key);
+            }
+            Some(serde_json::Value::String(api_key)) => {
+                if !api_key.starts_with(ENCRYPTED_VALUE_PREFIX) {
+                    configuration.insert(
+                        key.to_string(),
+                        serde_json::Value::String(encrypt_api_key(&api_key)?),
+                    );
+                }
+            }
+            Some(_) => {
+                return Err(ServiceError::BadRequest(format!(
+                    "{} must be a string",
+                    key
+                )));
+            }
+        }
+    }
+
+    Ok(server_configuration)
+}
+
+/// Removes the encrypted API keys from a dataset before it is returned by the API.
+pub fn redact_dataset(mut dataset: Dataset) -> Dataset {
+    if let Some(configuration) = dataset.server_configuration.as_object_mut() {
+        for key in ENCRYPTED_CONFIGURATION_KEYS {
+            configuration.remove(key);
+        }
+    }
+
+    dataset
+}
 
 pub async fn create_dataset_query(
     new_dataset: Dataset,
@@ -189,3 +308,265 @@ pub fn get_datasets_by_organization_id(
 
     Ok(dataset_and_usages)
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ReindexFulltextProgress {
+    /// One of "running", "completed" or "failed". A running reindex which has not recorded progress
+    /// within the reindex lease, e.g. because the server restarted, is reported as failed.
+    pub status: String,
+    /// The number of chunks whose full-text vectors have been rebuilt so far.
+    pub reindexed: i64,
+    /// The number of chunks in the dataset when the reindex was started.
+    pub total: i64,
+    pub started_at: NaiveDateTime,
+    /// When the reindex last recorded progress.
+    pub heartbeat_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+    /// Why the reindex failed, set when status is "failed".
+    pub error: Option<String>,
+}
+
+/// How long the progress of a full-text reindex is kept in redis after it was last updated.
+const REINDEX_FULLTEXT_PROGRESS_TTL_SECS: u64 = 60 * 60 * 24 * 7;
+/// How long a full-text reindex holds its dataset's lease without recording progress before it is
+/// considered to have died and another reindex may start.
+pub const REINDEX_FULLTEXT_LEASE_SECS: i64 = 300;
+const REINDEX_FULLTEXT_PAGE_SIZE: i64 = 100;
+const REINDEX_FULLTEXT_BATCH_SIZE: usize = 10;
+
+async fn get_redis_connection() -> Result<redis::aio::Connection, ServiceError> {
+    let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
+    let client = redis::Client::open(redis_url).map_err(|err| {
+        ServiceError::BadRequest(format!("Could not create redis client: {}", err))
+    })?;
+
+    client
+        .get_async_connection()
+        .await
+        .map_err(|err| ServiceError::BadRequest(format!("Could not connect to redis: {}", err)))
+}
+
+pub async fn get_reindex_fulltext_progress_query(
+    dataset_id: uuid::Uuid,
+) -> Result<Option<ReindexFulltextProgress>, ServiceError> {
+    let mut redis_conn = get_redis_connection().await?;
+
+    let progress: Option<String> = redis::cmd("GET")
+        .arg(format!("reindex_fulltext:{}", dataset_id))
+        .query_async(&mut redis_conn)
+        .await
+        .map_err(|err| {
+            ServiceError::BadRequest(format!(
+                "Could not get reindex progress from redis: {}",
+                err
+            ))
+        })?;
+
+    progress
+        .map(|progress| {
+            serde_json::from_str::<ReindexFulltextProgress>(&progress)
+                .map(fail_stale_reindex_fulltext_progress)
+                .map_err(|_| {
+                    ServiceError::BadRequest(
+                        "Could not parse reindex progress from redis".to_string(),
+                    )
+                })
+        })
+        .transpose()
+}
+
+/// Reports a running reindex as failed once it has gone longer than its lease without recording
+/// progress, since the job which was running it is gone.
+fn fail_stale_reindex_fulltext_progress(
+    progress: ReindexFulltextProgress,
+) -> ReindexFulltextProgress {
+    let lease_expired_at =
+        progress.heartbeat_at + chrono::Duration::seconds(REINDEX_FULLTEXT_LEASE_SECS);
+    if progress.status != "running" || lease_expired_at > chrono::Utc::now().naive_local() {
+        return progress;
+    }
+
+    ReindexFulltextProgress {
+        status: "failed".to_string(),
+        finished_at: Some(progress.heartbeat_at),
+        error: Some(
+            "The reindex stopped recording progress, likely because the server restarted"
+                .to_string(),
+        ),
+        ..progress
+    }
+}
+
+/// Takes the dataset's full-text reindex lease, returning the token to renew and release it with,
+/// or None if another reindex holds it.
+pub async fn acquire_reindex_fulltext_lease_query(
+    dataset_id: uuid::Uuid,
+) -> Result<Option<uuid::Uuid>, ServiceError> {
+    let mut redis_conn = get_redis_connection().await?;
+    let lease_token = uuid::Uuid::new_v4();
+
+    let acquired: Option<String> = redis::cmd("SET")
+        .arg(format!("reindex_fulltext_lease:{}", dataset_id))
+        .arg(lease_token.to_string())
+        .arg("NX")
+        .arg("EX")
+        .arg(REINDEX_FULLTEXT_LEASE_SECS)
+        .query_async(&mut redis_conn)
+        .await
+        .map_err(|err| {
+            ServiceError::BadRequest(format!("Could not acquire reindex lease in redis: {}", err))
+        })?;
+
+    Ok(acquired.map(|_| lease_token))
+}
+
+/// Extends the dataset's full-text reindex lease, failing if it expired and may have been taken by
+/// another reindex.
+pub async fn renew_reindex_fulltext_lease_query(
+    dataset_id: uuid::Uuid,
+    lease_token: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = get_redis_connection().await?;
+
+    let renewed: i64 = redis::Script::new(
+        r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('EXPIRE', KEYS[1], ARGV[2])
+        end
+        return 0",
+    )
+    .key(format!("reindex_fulltext_lease:{}", dataset_id))
+    .arg(lease_token.to_string())
+    .arg(REINDEX_FULLTEXT_LEASE_SECS)
+    .invoke_async(&mut redis_conn)
+    .await
+    .map_err(|err| {
+        ServiceError::BadRequest(format!("Could not renew reindex lease in redis: {}", err))
+    })?;
+
+    if renewed == 0 {
+        return Err(ServiceError::BadRequest(
+            "The reindex lease expired before the reindex recorded progress".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Releases the dataset's full-text reindex lease if it is still held with `lease_token`.
+pub async fn release_reindex_fulltext_lease_query(
+    dataset_id: uuid::Uuid,
+    lease_token: uuid::Uuid,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = get_redis_connection().await?;
+
+    redis::Script::new(
+        r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0",
+    )
+    .key(format!("reindex_fulltext_lease:{}", dataset_id))
+    .arg(lease_token.to_string())
+    .invoke_async::<_, i64>(&mut redis_conn)
+    .await
+    .map_err(|err| {
+        ServiceError::BadRequest(format!("Could not release reindex lease in redis: {}", err))
+    })?;
+
+    Ok(())
+}
+
+pub async fn set_reindex_fulltext_progress_query(
+    dataset_id: uuid::Uuid,
+    progress: &ReindexFulltextProgress,
+) -> Result<(), ServiceError> {
+    let mut redis_conn = get_redis_connection().await?;
+
+    redis::cmd("SET")
+        .arg(format!("reindex_fulltext:{}", dataset_id))
+        .arg(serde_json::to_string(progress).map_err(|err| {
+            ServiceError::BadRequest(format!("Could not stringify reindex progress: {}", err))
+        })?)
+        .arg("EX")
+        .arg(REINDEX_FULLTEXT_PROGRESS_TTL_SECS)
+        .query_async(&mut redis_conn)
+        .await
+        .map_err(|err| {
+            ServiceError::BadRequest(format!("Could not set reindex progress in redis: {}", err))
+        })?;
+
+    Ok(())
+}
+
+/// Rebuilds the full-text sparse vector of every chunk in the dataset with its current
+/// FULLTEXT_STEMMING_LANGUAGE, FULLTEXT_UNACCENT and SPLADE_MODEL, recording the progress in redis and
+/// renewing the reindex lease held with `lease_token` after each batch. Collisions share the point
+/// of the chunk they collided with, so only chunks which own a point are reindexed.
+pub async fn reindex_fulltext_query(
+    dataset: Dataset,
+    mut progress: ReindexFulltextProgress,
+    lease_token: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<ReindexFulltextProgress, ServiceError> {
+    let dataset_config = ServerDatasetConfiguration::from_json(dataset.server_configuration);
+    let stemming_language = dataset_config.FULLTEXT_STEMMING_LANGUAGE;
+    let unaccent = dataset_config.FULLTEXT_UNACCENT.unwrap_or(false);
+    let splade_model = dataset_config.SPLADE_MODEL;
+
+    let mut after_chunk_id = None;
+    loop {
+        let page_pool = pool.clone();
+        let chunks = web::block(move || {
+            get_point_chunks_page_query(
+                dataset.id,
+                after_chunk_id,
+                REINDEX_FULLTEXT_PAGE_SIZE,
+                page_pool,
+            )
+        })
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+        let Some(last_chunk) = chunks.last() else {
+            break;
+        };
+        after_chunk_id = Some(last_chunk.id);
+
+        for batch in chunks.chunks(REINDEX_FULLTEXT_BATCH_SIZE) {
+            let sparse_vectors = futures::future::try_join_all(batch.iter().map(|chunk| {
+                let stemming_language = stemming_language.clone();
+                let splade_model = splade_model.clone();
+                async move {
+                    let sparse_vector = get_fulltext_sparse_vector(
+                        chunk.chunk_html.clone().unwrap_or(chunk.content.clone()),
+                        stemming_language,
+                        unaccent,
+                        splade_model,
+                    )
+                    .await?;
+
+                    Ok::<(uuid::Uuid, Vec<(u32, f32)>), ServiceError>((
+                        chunk.qdrant_point_id.unwrap_or_default(),
+                        sparse_vector,
+                    ))
+                }
+            }))
+            .await?;
+
+            update_sparse_vectors_query(sparse_vectors).await?;
+
+            renew_reindex_fulltext_lease_query(dataset.id, lease_token).await?;
+            progress.reindexed += batch.len() as i64;
+            progress.heartbeat_at = chrono::Utc::now().naive_local();
+            set_reindex_fulltext_progress_query(dataset.id, &progress).await?;
+        }
+    }
+
+    progress.status = "completed".to_string();
+    progress.heartbeat_at = chrono::Utc::now().naive_local();
+    progress.finished_at = Some(progress.heartbeat_at);
+    set_reindex_fulltext_progress_query(dataset.id, &progress).await?;
+
+    Ok(progress)
+}
@@ -330,7 +330,7 @@ pub async fn create_chunks_with_handler(
 
     let pool1 = pool.clone();
 
-    for chunk_html in chunk_htmls {
+    for (chunk_index, chunk_html) in chunk_htmls.into_iter().enumerate() {
         let create_chunk_data = CreateChunkData {
             chunk_html: Some(chunk_html.clone()),
             link: link.clone(),
@@ -342,6 +342,10 @@ pub async fn create_chunks_with_handler(
             time_stamp: time_stamp.clone(),
             chunk_vector: None,
             weight: None,
+            image_urls: None,
+            acl: None,
+            expires_at: None,
+            chunk_index: Some(chunk_index as i32),
         };
         let web_json_create_chunk_data = web::Json(create_chunk_data);
 
@@ -369,7 +373,7 @@ pub async fn create_chunks_with_handler(
             }
         }
     }
-    let converted_description = convert_html(&description.unwrap_or("".to_string()))?;
+    let converted_description = convert_html(&description.unwrap_or("".to_string())).await?;
     let collection_id;
     match create_collection_and_add_bookmarks_query(
         ChunkCollection::from_details(
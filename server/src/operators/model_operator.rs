@@ -1,40 +1,814 @@
 use crate::{
     data::models::ServerDatasetConfiguration, errors::ServiceError, get_env,
-    handlers::chunk_handler::ScoreChunkDTO,
+    handlers::chunk_handler::ScoreChunkDTO, operators::dataset_operator::decrypt_api_key,
 };
+use once_cell::sync::Lazy;
 use openai_dive::v1::{api::Client, resources::embedding::EmbeddingParameters};
+use regex::Regex;
+use rust_stemmers::{Algorithm, Stemmer};
 use serde::{Deserialize, Serialize};
+use tiktoken_rs::{cl100k_base, CoreBPE};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
+/// Whether text is embedded to be stored as a chunk or to search with. Some providers, such as
+/// Cohere, embed the two differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingInputType {
+    Document,
+    Query,
+}
+
+/// A source of dense embeddings for chunk content and search queries.
+pub trait EmbeddingProvider {
+    fn embed<'a>(
+        &'a self,
+        message: &'a str,
+        input_type: EmbeddingInputType,
+    ) -> impl std::future::Future<Output = Result<Vec<f32>, ServiceError>> + 'a;
+
+    /// Embeds several texts with one call, returning their vectors in the same order.
+    fn embed_batch<'a>(
+        &'a self,
+        messages: &'a [String],
+        input_type: EmbeddingInputType,
+    ) -> impl std::future::Future<Output = Result<Vec<Vec<f32>>, ServiceError>> + 'a;
+}
+
+/// Any server exposing the OpenAI `/embeddings` API shape. This is the default provider.
+pub struct OpenAiEmbeddingProvider {
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+impl EmbeddingProvider for OpenAiEmbeddingProvider {
+    async fn embed(
+        &self,
+        message: &str,
+        _input_type: EmbeddingInputType,
+    ) -> Result<Vec<f32>, ServiceError> {
+        let client = Client {
+            http_client: reqwest::Client::new(),
+            api_key: self.api_key.clone(),
+            base_url: self.base_url.clone(),
+        };
+
+        let parameters = EmbeddingParameters {
+            model: self.model.clone(),
+            input: message.to_string(),
+            user: None,
+            encoding_format: None,
+        };
+
+        let embeddings = client
+            .embeddings()
+            .create(parameters)
+            .await
+            .map_err(|err| {
+                ServiceError::BadRequest(format!("Failed to create embedding {:?}", err))
+            })?;
+
+        let vector = embeddings
+            .data
+            .first()
+            .ok_or(ServiceError::BadRequest(
+                "No embedding returned from embedding server".to_string(),
+            ))?
+            .embedding
+            .clone();
+        Ok(vector.iter().map(|&x| x as f32).collect())
+    }
+
+    async fn embed_batch(
+        &self,
+        messages: &[String],
+        _input_type: EmbeddingInputType,
+    ) -> Result<Vec<Vec<f32>>, ServiceError> {
+        let resp = reqwest::Client::new()
+            .post(format!(
+                "{}/embeddings",
+                self.base_url.trim_end_matches('/')
+            ))
+            .bearer_auth(&self.api_key)
+            .json(&OpenAiBatchEmbedData {
+                model: self.model.clone(),
+                input: messages.to_vec(),
+            })
+            .send()
+            .await
+            .map_err(|err| {
+                ServiceError::BadRequest(format!("Failed making call to server {:?}", err))
+            })?
+            .json::<OpenAiBatchEmbedResponse>()
+            .await
+            .map_err(|_e| {
+                log::error!("Failed parsing response from embedding server {:?}", _e);
+                ServiceError::BadRequest(
+                    "Failed parsing response from embedding server".to_string(),
+                )
+            })?;
+
+        let mut data = resp.data;
+        data.sort_by_key(|embedding| embedding.index);
+        Ok(data
+            .into_iter()
+            .map(|embedding| embedding.embedding)
+            .collect())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiBatchEmbedData {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiBatchEmbedding {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenAiBatchEmbedResponse {
+    pub data: Vec<OpenAiBatchEmbedding>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalEmbedData {
+    pub inputs: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalBatchEmbedData {
+    pub inputs: Vec<String>,
+}
+
+/// A self-hosted embedding server using the text-embeddings-inference `/embed` API.
+pub struct LocalEmbeddingProvider {
+    pub base_url: String,
+}
+
+impl EmbeddingProvider for LocalEmbeddingProvider {
+    async fn embed(
+        &self,
+        message: &str,
+        _input_type: EmbeddingInputType,
+    ) -> Result<Vec<f32>, ServiceError> {
+        let embeddings = reqwest::Client::new()
+            .post(format!("{}/embed", self.base_url.trim_end_matches('/')))
+            .json(&LocalEmbedData {
+                inputs: message.to_string(),
+            })
+            .send()
+            .await
+            .map_err(|err| {
+                ServiceError::BadRequest(format!("Failed making call to server {:?}", err))
+            })?
+            .json::<Vec<Vec<f32>>>()
+            .await
+            .map_err(|_e| {
+                log::error!(
+                    "Failed parsing response from local embedding server {:?}",
+                    _e
+                );
+                ServiceError::BadRequest(
+                    "Failed parsing response from local embedding server".to_string(),
+                )
+            })?;
+
+        embeddings
+            .into_iter()
+            .next()
+            .ok_or(ServiceError::BadRequest(
+                "No embedding returned from local embedding server".to_string(),
+            ))
+    }
+
+    async fn embed_batch(
+        &self,
+        messages: &[String],
+        _input_type: EmbeddingInputType,
+    ) -> Result<Vec<Vec<f32>>, ServiceError> {
+        reqwest::Client::new()
+            .post(format!("{}/embed", self.base_url.trim_end_matches('/')))
+            .json(&LocalBatchEmbedData {
+                inputs: messages.to_vec(),
+            })
+            .send()
+            .await
+            .map_err(|err| {
+                ServiceError::BadRequest(format!("Failed making call to server {:?}", err))
+            })?
+            .json::<Vec<Vec<f32>>>()
+            .await
+            .map_err(|_e| {
+                log::error!(
+                    "Failed parsing response from local embedding server {:?}",
+                    _e
+                );
+                ServiceError::BadRequest(
+                    "Failed parsing response from local embedding server".to_string(),
+                )
+            })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohereEmbedData {
+    pub texts: Vec<String>,
+    pub model: String,
+    pub input_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohereEmbedResponse {
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// Cohere's hosted `/embed` API. Requires `COHERE_API_KEY` to be set.
+pub struct CohereEmbeddingProvider {
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+}
+
+impl EmbeddingProvider for CohereEmbeddingProvider {
+    async fn embed(
+        &self,
+        message: &str,
+        input_type: EmbeddingInputType,
+    ) -> Result<Vec<f32>, ServiceError> {
+        self.embed_batch(&[message.to_string()], input_type)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(ServiceError::BadRequest(
+                "No embedding returned from Cohere".to_string(),
+            ))
+    }
+
+    async fn embed_batch(
+        &self,
+        messages: &[String],
+        input_type: EmbeddingInputType,
+    ) -> Result<Vec<Vec<f32>>, ServiceError> {
+        let resp = reqwest::Client::new()
+            .post(format!("{}/embed", self.base_url.trim_end_matches('/')))
+            .bearer_auth(&self.api_key)
+            .json(&CohereEmbedData {
+                texts: messages.to_vec(),
+                model: self.model.clone(),
+                input_type: match input_type {
+                    EmbeddingInputType::Document => "search_document".to_string(),
+                    EmbeddingInputType::Query => "search_query".to_string(),
+                },
+            })
+            .send()
+            .await
+            .map_err(|err| {
+                ServiceError::BadRequest(format!("Failed making call to server {:?}", err))
+            })?
+            .json::<CohereEmbedResponse>()
+            .await
+            .map_err(|_e| {
+                log::error!("Failed parsing response from Cohere {:?}", _e);
+                ServiceError::BadRequest("Failed parsing response from Cohere".to_string())
+            })?;
+
+        Ok(resp.embeddings)
+    }
+}
+
+/// The embedding provider selected by a dataset's `EMBEDDING_PROVIDER` configuration.
+pub enum ConfiguredEmbeddingProvider {
+    OpenAi(OpenAiEmbeddingProvider),
+    Local(LocalEmbeddingProvider),
+    Cohere(CohereEmbeddingProvider),
+}
+
+/// The key to call the LLM provider with. This is the dataset's own LLM_API_KEY if it has one and
+/// the server's OPENROUTER_API_KEY otherwise.
+pub fn get_llm_api_key(
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<String, ServiceError> {
+    match &dataset_config.LLM_API_KEY {
+        Some(encrypted_api_key) => decrypt_api_key(encrypted_api_key),
+        None => Ok(get_env!("OPENROUTER_API_KEY", "OPENROUTER_API_KEY should be set").into()),
+    }
+}
+
+impl ConfiguredEmbeddingProvider {
+    pub fn from_config(dataset_config: &ServerDatasetConfiguration) -> Result<Self, ServiceError> {
+        let provider = dataset_config
+            .EMBEDDING_PROVIDER
+            .clone()
+            .unwrap_or("openai".to_string());
+        let model = dataset_config
+            .EMBEDDING_MODEL_NAME
+            .clone()
+            .unwrap_or("text-embedding-ada-002".to_string());
+        let dataset_api_key = dataset_config
+            .EMBEDDING_API_KEY
+            .as_deref()
+            .map(decrypt_api_key)
+            .transpose()?;
+
+        match provider.to_lowercase().as_str() {
+            "openai" => Ok(ConfiguredEmbeddingProvider::OpenAi(
+                OpenAiEmbeddingProvider {
+                    base_url: dataset_config
+                        .EMBEDDING_BASE_URL
+                        .clone()
+                        .unwrap_or("https://api.openai.com/v1".to_string()),
+                    api_key: dataset_api_key.unwrap_or_else(|| {
+                        get_env!("OPENAI_API_KEY", "OPENAI_API_KEY should be set").into()
+                    }),
+                    model,
+                },
+            )),
+            "local" => Ok(ConfiguredEmbeddingProvider::Local(LocalEmbeddingProvider {
+                base_url: dataset_config.EMBEDDING_BASE_URL.clone().ok_or(
+                    ServiceError::BadRequest(
+                        "EMBEDDING_BASE_URL must be set to use a local embedding server"
+                            .to_string(),
+                    ),
+                )?,
+            })),
+            "cohere" => Ok(ConfiguredEmbeddingProvider::Cohere(
+                CohereEmbeddingProvider {
+                    base_url: dataset_config
+                        .EMBEDDING_BASE_URL
+                        .clone()
+                        .unwrap_or("https://api.cohere.ai/v1".to_string()),
+                    api_key: match dataset_api_key {
+                        Some(api_key) => api_key,
+                        None => std::env::var("COHERE_API_KEY").map_err(|_| {
+                            ServiceError::BadRequest(
+                                "COHERE_API_KEY or the dataset's EMBEDDING_API_KEY must be set to use Cohere embeddings".to_string(),
+                            )
+                        })?,
+                    },
+                    model,
+                },
+            )),
+            _ => Err(ServiceError::BadRequest(format!(
+                "Unknown EMBEDDING_PROVIDER {}, expected one of openai, local or cohere",
+                provider
+            ))),
+        }
+    }
+}
+
+impl EmbeddingProvider for ConfiguredEmbeddingProvider {
+    async fn embed(
+        &self,
+        message: &str,
+        input_type: EmbeddingInputType,
+    ) -> Result<Vec<f32>, ServiceError> {
+        match self {
+            ConfiguredEmbeddingProvider::OpenAi(provider) => {
+                provider.embed(message, input_type).await
+            }
+            ConfiguredEmbeddingProvider::Local(provider) => {
+                provider.embed(message, input_type).await
+            }
+            ConfiguredEmbeddingProvider::Cohere(provider) => {
+                provider.embed(message, input_type).await
+            }
+        }
+    }
+
+    async fn embed_batch(
+        &self,
+        messages: &[String],
+        input_type: EmbeddingInputType,
+    ) -> Result<Vec<Vec<f32>>, ServiceError> {
+        match self {
+            ConfiguredEmbeddingProvider::OpenAi(provider) => {
+                provider.embed_batch(messages, input_type).await
+            }
+            ConfiguredEmbeddingProvider::Local(provider) => {
+                provider.embed_batch(messages, input_type).await
+            }
+            ConfiguredEmbeddingProvider::Cohere(provider) => {
+                provider.embed_batch(messages, input_type).await
+            }
+        }
+    }
+}
+
+/// Identifies the model which produces the dataset's embeddings, e.g.
+/// `openai/text-embedding-3-large@1024`. The dimensions are only included when EMBEDDING_DIMENSIONS
+/// is set, as the same model truncated to a different size produces incompatible vectors.
+pub fn get_embedding_model_id(dataset_config: &ServerDatasetConfiguration) -> String {
+    let provider = dataset_config
+        .EMBEDDING_PROVIDER
+        .clone()
+        .unwrap_or("openai".to_string())
+        .to_lowercase();
+    let model = match provider.as_str() {
+        "local" => dataset_config
+            .EMBEDDING_MODEL_NAME
+            .clone()
+            .or(dataset_config.EMBEDDING_BASE_URL.clone())
+            .unwrap_or_default(),
+        _ => dataset_config
+            .EMBEDDING_MODEL_NAME
+            .clone()
+            .unwrap_or("text-embedding-ada-002".to_string()),
+    };
+
+    match dataset_config.EMBEDDING_DIMENSIONS {
+        Some(dimensions) => format!("{}/{}@{}", provider, model, dimensions),
+        None => format!("{}/{}", provider, model),
+    }
+}
+
+/// Normalizes text before it is embedded so that the same content with different unicode forms,
+/// whitespace or casing lands on the same vector. NORMALIZE_CONTENT applies NFKC and collapses
+/// runs of whitespace, and LOWERCASE_CONTENT lowercases the text. Both are off by default.
+pub fn normalize_content(text: &str, dataset_config: &ServerDatasetConfiguration) -> String {
+    let mut text = text.to_string();
+
+    if dataset_config.NORMALIZE_CONTENT.unwrap_or(false) {
+        text = text.nfkc().collect::<String>();
+        text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    }
+
+    if dataset_config.LOWERCASE_CONTENT.unwrap_or(false) {
+        text = text.to_lowercase();
+    }
+
+    text
+}
+
+static TOKENIZER: Lazy<CoreBPE> =
+    Lazy::new(|| cl100k_base().expect("The cl100k_base tokenizer should always load"));
+
+/// Counts the tokens in the text with the cl100k_base tokenizer used by OpenAI's embedding and chat
+/// models. Counts for other providers' models are an approximation.
+pub fn count_tokens(text: &str) -> usize {
+    TOKENIZER.encode_with_special_tokens(text).len()
+}
+
+/// The price of embedding a million tokens with the dataset's model. This is the dataset's
+/// EMBEDDING_PRICE_PER_MILLION_TOKENS if it is set and the list price of OpenAI's models otherwise.
+pub fn get_embedding_price_per_million_tokens(
+    dataset_config: &ServerDatasetConfiguration,
+) -> Option<f64> {
+    if let Some(price) = dataset_config.EMBEDDING_PRICE_PER_MILLION_TOKENS {
+        return Some(price);
+    }
+
+    match dataset_config
+        .EMBEDDING_MODEL_NAME
+        .as_deref()
+        .unwrap_or("text-embedding-ada-002")
+    {
+        "text-embedding-ada-002" => Some(0.10),
+        "text-embedding-3-small" => Some(0.02),
+        "text-embedding-3-large" => Some(0.13),
+        _ => None,
+    }
+}
+
+/// Strips diacritics from the text so that "café" and "cafe" share terms. Used on both sides of the
+/// full-text search when the dataset's FULLTEXT_UNACCENT is set.
+pub fn unaccent_text(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .nfc()
+        .collect()
+}
+
+/// Embeds chunk content with the dataset's embedding provider.
 pub async fn create_embedding(
     message: &str,
     dataset_config: ServerDatasetConfiguration,
 ) -> Result<Vec<f32>, actix_web::Error> {
-    let open_ai_api_key = get_env!("OPENAI_API_KEY", "OPENAI_API_KEY should be set").into();
-    let base_url = dataset_config
-        .EMBEDDING_BASE_URL
-        .unwrap_or("https://api.openai.com/v1".to_string());
-    let client = Client {
-        http_client: reqwest::Client::new(),
-        api_key: open_ai_api_key,
-        base_url,
-    };
+    embed_text(message, EmbeddingInputType::Document, dataset_config).await
+}
+
+/// Embeds several pieces of chunk content with one call to the dataset's embedding provider.
+pub async fn create_embeddings(
+    messages: &[String],
+    dataset_config: ServerDatasetConfiguration,
+) -> Result<Vec<Vec<f32>>, actix_web::Error> {
+    if messages.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let provider = ConfiguredEmbeddingProvider::from_config(&dataset_config)?;
+
+    let messages = messages
+        .iter()
+        .map(|message| normalize_content(message, &dataset_config))
+        .collect::<Vec<String>>();
+    let embeddings = provider
+        .embed_batch(&messages, EmbeddingInputType::Document)
+        .await?;
+    if embeddings.len() != messages.len() {
+        return Err(ServiceError::BadRequest(format!(
+            "The embedding server returned {} embeddings for {} inputs",
+            embeddings.len(),
+            messages.len()
+        ))
+        .into());
+    }
+
+    embeddings
+        .into_iter()
+        .map(|embedding| prepare_embedding(embedding, &dataset_config))
+        .collect()
+}
+
+async fn embed_text(
+    message: &str,
+    input_type: EmbeddingInputType,
+    dataset_config: ServerDatasetConfiguration,
+) -> Result<Vec<f32>, actix_web::Error> {
+    let provider = ConfiguredEmbeddingProvider::from_config(&dataset_config)?;
+
+    let message = normalize_content(message, &dataset_config);
+    let embedding = provider.embed(&message, input_type).await?;
 
-    // Vectorize
-    let parameters = EmbeddingParameters {
-        model: "text-embedding-ada-002".to_string(),
-        input: message.to_string(),
-        user: None,
-        encoding_format: None,
+    prepare_embedding(embedding, &dataset_config)
+}
+
+/// Truncates, checks and normalizes a vector from the embedding provider according to the
+/// dataset's configuration.
+fn prepare_embedding(
+    embedding: Vec<f32>,
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<Vec<f32>, actix_web::Error> {
+    let collection_dimensions = dataset_config.EMBEDDING_SIZE.unwrap_or(1536);
+
+    let embedding = match dataset_config.EMBEDDING_DIMENSIONS {
+        Some(dimensions) => {
+            if dimensions != collection_dimensions {
+                return Err(ServiceError::BadRequest(format!(
+                    "EMBEDDING_DIMENSIONS of {} does not match the dataset's EMBEDDING_SIZE of {}",
+                    dimensions, collection_dimensions
+                ))
+                .into());
+            }
+
+            truncate_embedding(embedding, dimensions)?
+        }
+        None => embedding,
     };
 
-    let embeddings = client
-        .embeddings()
-        .create(parameters)
+    if embedding.len() != collection_dimensions {
+        return Err(ServiceError::BadRequest(format!(
+            "The embedding model returned a vector of size {} but the dataset's EMBEDDING_SIZE is {}, check the dataset's EMBEDDING_PROVIDER and EMBEDDING_MODEL_NAME",
+            embedding.len(),
+            collection_dimensions
+        ))
+        .into());
+    }
+
+    if dataset_config.NORMALIZE_EMBEDDINGS.unwrap_or(false) {
+        return Ok(l2_normalize(embedding));
+    }
+
+    Ok(embedding)
+}
+
+/// How long query embeddings are kept in redis, from QUERY_EMBEDDING_CACHE_TTL_SECS. Defaults to a
+/// day. Setting it to 0 turns the cache off.
+fn get_query_embedding_cache_ttl_secs() -> u64 {
+    std::env::var("QUERY_EMBEDDING_CACHE_TTL_SECS")
+        .ok()
+        .and_then(|ttl_secs| ttl_secs.parse::<u64>().ok())
+        .unwrap_or(86400)
+}
+
+/// The redis key of a query's embedding. It covers everything which changes the vector a query
+/// embeds to, so datasets with different embedding settings never share entries.
+fn get_query_embedding_cache_key(
+    query: &str,
+    dataset_config: &ServerDatasetConfiguration,
+) -> String {
+    let cache_id = uuid::Uuid::new_v5(
+        &uuid::Uuid::NAMESPACE_OID,
+        format!(
+            "{}:{}:{}:query:{}",
+            get_embedding_model_id(dataset_config),
+            dataset_config
+                .EMBEDDING_BASE_URL
+                .clone()
+                .unwrap_or_default(),
+            dataset_config.NORMALIZE_EMBEDDINGS.unwrap_or(false),
+            normalize_content(query, dataset_config)
+        )
+        .as_bytes(),
+    );
+
+    format!("query_embedding:{}", cache_id)
+}
+
+async fn get_redis_connection() -> Result<redis::aio::Connection, ServiceError> {
+    let redis_url = std::env::var("REDIS_URL").expect("REDIS_URL must be set");
+    let redis_client = redis::Client::open(redis_url)
+        .map_err(|_| ServiceError::BadRequest("Could not create redis client".to_string()))?;
+
+    redis_client
+        .get_async_connection()
+        .await
+        .map_err(|_| ServiceError::BadRequest("Could not get redis connection".to_string()))
+}
+
+async fn get_cached_query_embedding(
+    cache_key: &str,
+    redis_conn: &mut redis::aio::Connection,
+) -> Option<Vec<f32>> {
+    let cached_embedding: Option<String> = redis::cmd("GET")
+        .arg(cache_key)
+        .query_async(redis_conn)
+        .await
+        .ok()?;
+
+    serde_json::from_str(&cached_embedding?).ok()
+}
+
+async fn set_cached_query_embedding(
+    cache_key: &str,
+    embedding: &[f32],
+    ttl_secs: u64,
+    redis_conn: &mut redis::aio::Connection,
+) {
+    let result: Result<(), redis::RedisError> = redis::cmd("SET")
+        .arg(cache_key)
+        .arg(serde_json::to_string(embedding).unwrap_or_default())
+        .arg("EX")
+        .arg(ttl_secs)
+        .query_async(redis_conn)
+        .await;
+
+    if let Err(err) = result {
+        log::error!("Failed to cache query embedding: {}", err);
+    }
+}
+
+/// Embeds a search query like `create_embedding`, but as a query for providers which embed queries
+/// differently and reusing the embedding of the same query from the redis cache when there is one.
+/// Cache failures fall back to embedding the query.
+pub async fn create_query_embedding(
+    query: &str,
+    dataset_config: ServerDatasetConfiguration,
+) -> Result<Vec<f32>, actix_web::Error> {
+    let ttl_secs = get_query_embedding_cache_ttl_secs();
+    if ttl_secs == 0 {
+        return embed_text(query, EmbeddingInputType::Query, dataset_config).await;
+    }
+
+    let cache_key = get_query_embedding_cache_key(query, &dataset_config);
+    let mut redis_conn = get_redis_connection().await.ok();
+    if let Some(redis_conn) = redis_conn.as_mut() {
+        if let Some(embedding) = get_cached_query_embedding(&cache_key, redis_conn).await {
+            return Ok(embedding);
+        }
+    }
+
+    let embedding = embed_text(query, EmbeddingInputType::Query, dataset_config).await?;
+    if let Some(redis_conn) = redis_conn.as_mut() {
+        set_cached_query_embedding(&cache_key, &embedding, ttl_secs, redis_conn).await;
+    }
+
+    Ok(embedding)
+}
+
+/// Embeds a search query into the redis cache ahead of time. Returns false without calling the
+/// embedding model if the query is already cached, in which case its expiry is left as is.
+pub async fn warm_query_embedding(
+    query: &str,
+    dataset_config: ServerDatasetConfiguration,
+) -> Result<bool, actix_web::Error> {
+    let ttl_secs = get_query_embedding_cache_ttl_secs();
+    if ttl_secs == 0 {
+        return Err(ServiceError::BadRequest(
+            "The query embedding cache is disabled because QUERY_EMBEDDING_CACHE_TTL_SECS is 0"
+                .to_string(),
+        )
+        .into());
+    }
+
+    let cache_key = get_query_embedding_cache_key(query, &dataset_config);
+    let mut redis_conn = get_redis_connection().await?;
+    if get_cached_query_embedding(&cache_key, &mut redis_conn)
         .await
-        .map_err(actix_web::error::ErrorBadRequest)?;
+        .is_some()
+    {
+        return Ok(false);
+    }
+
+    let embedding = embed_text(query, EmbeddingInputType::Query, dataset_config).await?;
+    set_cached_query_embedding(&cache_key, &embedding, ttl_secs, &mut redis_conn).await;
+
+    Ok(true)
+}
+
+/// Scales an embedding to unit length. A zero vector is returned unchanged.
+pub fn l2_normalize(mut embedding: Vec<f32>) -> Vec<f32> {
+    let norm = embedding
+        .iter()
+        .map(|value| value * value)
+        .sum::<f32>()
+        .sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|value| *value /= norm);
+    }
 
-    let vector = embeddings.data.first().unwrap().embedding.clone();
-    Ok(vector.iter().map(|&x| x as f32).collect())
+    embedding
+}
+
+/// Shortens an embedding from a Matryoshka model such as text-embedding-3-large to its first
+/// `dimensions` values and rescales it back to unit length so cosine scores stay comparable.
+pub fn truncate_embedding(
+    mut embedding: Vec<f32>,
+    dimensions: usize,
+) -> Result<Vec<f32>, ServiceError> {
+    if ![384, 768, 1024, 1536].contains(&dimensions) {
+        return Err(ServiceError::BadRequest(format!(
+            "EMBEDDING_DIMENSIONS must be one of 384, 768, 1024 or 1536, got {}",
+            dimensions
+        )));
+    }
+
+    if embedding.len() < dimensions {
+        return Err(ServiceError::BadRequest(format!(
+            "Cannot truncate an embedding of size {} to {} dimensions",
+            embedding.len(),
+            dimensions
+        )));
+    }
+
+    embedding.truncate(dimensions);
+
+    Ok(l2_normalize(embedding))
+}
+
+fn get_stemmer(language: &str) -> Result<Stemmer, ServiceError> {
+    let algorithm = match language.to_lowercase().as_str() {
+        "arabic" => Algorithm::Arabic,
+        "danish" => Algorithm::Danish,
+        "dutch" => Algorithm::Dutch,
+        "english" => Algorithm::English,
+        "finnish" => Algorithm::Finnish,
+        "french" => Algorithm::French,
+        "german" => Algorithm::German,
+        "greek" => Algorithm::Greek,
+        "hungarian" => Algorithm::Hungarian,
+        "italian" => Algorithm::Italian,
+        "norwegian" => Algorithm::Norwegian,
+        "portuguese" => Algorithm::Portuguese,
+        "romanian" => Algorithm::Romanian,
+        "russian" => Algorithm::Russian,
+        "spanish" => Algorithm::Spanish,
+        "swedish" => Algorithm::Swedish,
+        "tamil" => Algorithm::Tamil,
+        "turkish" => Algorithm::Turkish,
+        _ => {
+            return Err(ServiceError::BadRequest(format!(
+                "FULLTEXT_STEMMING_LANGUAGE {} is not supported",
+                language
+            )))
+        }
+    };
+
+    Ok(Stemmer::create(algorithm))
+}
+
+static STEM_WORD_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[\p{L}\p{N}]+").expect("Invalid stem word regex"));
+static STEM_QUOTE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#""(.*?)""#).expect("Invalid stem quote regex"));
+
+/// Replaces every word in the text with its lowercased Snowball stem, leaving everything between
+/// the words untouched. Used on the text given to SPLADE so that morphological variants share terms.
+pub fn stem_text(text: &str, language: &str) -> Result<String, ServiceError> {
+    let stemmer = get_stemmer(language)?;
+
+    Ok(STEM_WORD_REGEX
+        .replace_all(text, |caps: &regex::Captures| {
+            stemmer.stem(&caps[0].to_lowercase()).to_string()
+        })
+        .to_string())
+}
+
+/// Stems the query like `stem_text` but leaves quoted phrases as they were written.
+pub fn stem_query(query: &str, language: &str) -> Result<String, ServiceError> {
+    let mut stemmed_query = String::new();
+    let mut last_end = 0;
+    for quote in STEM_QUOTE_REGEX.find_iter(query) {
+        stemmed_query.push_str(&stem_text(&query[last_end..quote.start()], language)?);
+        stemmed_query.push_str(quote.as_str());
+        last_end = quote.end();
+    }
+    stemmed_query.push_str(&stem_text(&query[last_end..], language)?);
+
+    Ok(stemmed_query)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,9 +820,59 @@ pub struct SpladeEmbedding {
 pub struct CustomSparseEmbedData {
     pub input: String,
     pub encode_type: String,
+    /// The SPLADE model to encode with, the embedding server's default model if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Whether to also return the vocabulary token of each index of the vector.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub return_tokens: bool,
 }
 
-pub async fn get_splade_doc_embedding(message: &str) -> Result<Vec<(u32, f32)>, ServiceError> {
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpladeEmbeddingWithTokens {
+    pub embeddings: Vec<(u32, f32)>,
+    pub tokens: Vec<String>,
+}
+
+static SPLADE_MODEL_NAME_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[\w.-]+(/[\w.-]+)?$").expect("Invalid SPLADE model name regex"));
+
+/// Checks that the dataset's SPLADE_MODEL is a model name of the form `name` or `owner/name`, and
+/// that it is one of ALLOWED_SPLADE_MODELS if that is set. The embedding server only encodes with
+/// the models in its own ALLOWED_SPLADE_MODELS, which is why the same list is usually set here.
+pub fn validate_splade_model(
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let Some(splade_model) = dataset_config.SPLADE_MODEL.as_ref() else {
+        return Ok(());
+    };
+
+    if !SPLADE_MODEL_NAME_REGEX.is_match(splade_model) {
+        return Err(ServiceError::BadRequest(format!(
+            "SPLADE_MODEL {:?} is not a valid model name, it must be of the form name or owner/name",
+            splade_model
+        )));
+    }
+
+    if let Ok(allowed_splade_models) = std::env::var("ALLOWED_SPLADE_MODELS") {
+        if !allowed_splade_models
+            .split(',')
+            .any(|allowed_model| allowed_model.trim() == splade_model)
+        {
+            return Err(ServiceError::BadRequest(format!(
+                "SPLADE_MODEL must be one of {}",
+                allowed_splade_models
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn get_splade_doc_embedding(
+    message: &str,
+    model: Option<String>,
+) -> Result<Vec<(u32, f32)>, ServiceError> {
     let mut embedding_server_call: String = get_env!(
         "GPU_SERVER_ORIGIN",
         "GPU_SERVER_ORIGIN should be set if this is called"
@@ -62,10 +886,18 @@ pub async fn get_splade_doc_embedding(message: &str) -> Result<Vec<(u32, f32)>,
         .json(&CustomSparseEmbedData {
             input: message.to_string(),
             encode_type: "doc".to_string(),
+            model,
+            return_tokens: false,
         })
         .send()
         .await
         .map_err(|err| ServiceError::BadRequest(format!("Failed making call to server {:?}", err)))?
+        .error_for_status()
+        .map_err(|_| {
+            ServiceError::BadRequest(
+                "The embedding server rejected the request, check that SPLADE_MODEL is allowed by the embedding server".to_string(),
+            )
+        })?
         .json::<SpladeEmbedding>()
         .await
         .map_err(|_e| {
@@ -81,7 +913,10 @@ pub async fn get_splade_doc_embedding(message: &str) -> Result<Vec<(u32, f32)>,
     Ok(resp.embeddings)
 }
 
-pub async fn get_splade_query_embedding(message: &str) -> Result<Vec<(u32, f32)>, ServiceError> {
+pub async fn get_splade_query_embedding(
+    message: &str,
+    model: Option<String>,
+) -> Result<Vec<(u32, f32)>, ServiceError> {
     let mut embedding_server_call: String = get_env!(
         "GPU_SERVER_ORIGIN",
         "GPU_SERVER_ORIGIN should be set if this is called"
@@ -95,10 +930,18 @@ pub async fn get_splade_query_embedding(message: &str) -> Result<Vec<(u32, f32)>
         .json(&CustomSparseEmbedData {
             input: message.to_string(),
             encode_type: "query".to_string(),
+            model,
+            return_tokens: false,
         })
         .send()
         .await
         .map_err(|err| ServiceError::BadRequest(format!("Failed making call to server {:?}", err)))?
+        .error_for_status()
+        .map_err(|_| {
+            ServiceError::BadRequest(
+                "The embedding server rejected the request, check that SPLADE_MODEL is allowed by the embedding server".to_string(),
+            )
+        })?
         .json::<SpladeEmbedding>()
         .await
         .map_err(|_e| {
@@ -114,20 +957,146 @@ pub async fn get_splade_query_embedding(message: &str) -> Result<Vec<(u32, f32)>
     Ok(resp.embeddings)
 }
 
+/// The vocabulary tokens of the message's SPLADE doc vector paired with their weights, which are
+/// how strongly the model associates each token with the message.
+pub async fn get_splade_doc_token_weights(
+    message: &str,
+    model: Option<String>,
+) -> Result<Vec<(String, f32)>, ServiceError> {
+    let mut embedding_server_call: String = get_env!(
+        "GPU_SERVER_ORIGIN",
+        "GPU_SERVER_ORIGIN should be set if this is called"
+    )
+    .to_string();
+    embedding_server_call.push_str("/sparse_encode");
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(embedding_server_call)
+        .json(&CustomSparseEmbedData {
+            input: message.to_string(),
+            encode_type: "doc".to_string(),
+            model,
+            return_tokens: true,
+        })
+        .send()
+        .await
+        .map_err(|err| ServiceError::BadRequest(format!("Failed making call to server {:?}", err)))?
+        .error_for_status()
+        .map_err(|_| {
+            ServiceError::BadRequest(
+                "The embedding server rejected the request, check that SPLADE_MODEL is allowed by the embedding server".to_string(),
+            )
+        })?
+        .json::<SpladeEmbeddingWithTokens>()
+        .await
+        .map_err(|_e| {
+            log::error!(
+                "Failed parsing response from custom embedding server {:?}",
+                _e
+            );
+            ServiceError::BadRequest(
+                "Failed parsing response from custom embedding server".to_string(),
+            )
+        })?;
+
+    if resp.tokens.len() != resp.embeddings.len() {
+        return Err(ServiceError::BadRequest(
+            "The embedding server returned a different number of tokens than weights".to_string(),
+        ));
+    }
+
+    Ok(resp
+        .tokens
+        .into_iter()
+        .zip(resp.embeddings)
+        .map(|(token, (_, weight))| (token, weight))
+        .collect())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClipEmbedData {
+    pub inputs: Vec<String>,
+    pub encode_type: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClipEmbedding {
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// Embeds either image urls (`encode_type` of "image") or text (`encode_type` of "text") into the
+/// shared CLIP vector space so that text queries can be compared against images. When several
+/// inputs are given their embeddings are averaged into one vector.
+pub async fn get_clip_embedding(
+    inputs: Vec<String>,
+    encode_type: &str,
+) -> Result<Vec<f32>, ServiceError> {
+    let mut embedding_server_call: String = get_env!(
+        "GPU_SERVER_ORIGIN",
+        "GPU_SERVER_ORIGIN should be set if this is called"
+    )
+    .to_string();
+    embedding_server_call.push_str("/clip_encode");
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(embedding_server_call)
+        .json(&ClipEmbedData {
+            inputs,
+            encode_type: encode_type.to_string(),
+        })
+        .send()
+        .await
+        .map_err(|err| ServiceError::BadRequest(format!("Failed making call to server {:?}", err)))?
+        .json::<ClipEmbedding>()
+        .await
+        .map_err(|_e| {
+            log::error!(
+                "Failed parsing response from custom embedding server {:?}",
+                _e
+            );
+            ServiceError::BadRequest(
+                "Failed parsing response from custom embedding server".to_string(),
+            )
+        })?;
+
+    let num_embeddings = resp.embeddings.len();
+    let first_embedding = resp.embeddings.first().ok_or(ServiceError::BadRequest(
+        "No embedding returned from CLIP server".to_string(),
+    ))?;
+
+    let mut averaged_embedding = vec![0.0; first_embedding.len()];
+    for embedding in resp.embeddings.iter() {
+        for (averaged, value) in averaged_embedding.iter_mut().zip(embedding.iter()) {
+            *averaged += value / num_embeddings as f32;
+        }
+    }
+
+    Ok(averaged_embedding)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ReRankResponse {
     pub docs: Vec<String>,
+    /// Relevance scores of the reranked docs in the same order as docs, if the reranker returns them.
+    #[serde(default)]
+    pub scores: Option<Vec<f32>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CrossEncoderData {
     pub query: String,
     pub docs: Vec<String>,
+    pub model: Option<String>,
 }
 
+/// Reorders the results by the cross-encoder's relevance to the query. If the reranker returns
+/// scores, each result's score is replaced with its reranker score.
 pub async fn cross_encoder(
     query: String,
     mut results: Vec<ScoreChunkDTO>,
+    model: Option<String>,
 ) -> Result<Vec<ScoreChunkDTO>, actix_web::Error> {
     let mut embedding_server_call: String = get_env!(
         "GPU_SERVER_ORIGIN",
@@ -148,10 +1117,17 @@ pub async fn cross_encoder(
         .json(&CrossEncoderData {
             query: query.to_string(),
             docs: request_docs,
+            model,
         })
         .send()
         .await
         .map_err(|err| ServiceError::BadRequest(format!("Failed making call to server {:?}", err)))?
+        .error_for_status()
+        .map_err(|_| {
+            ServiceError::BadRequest(
+                "The reranker rejected the request, check that RERANKER_MODEL is allowed by the embedding server".to_string(),
+            )
+        })?
         .json::<ReRankResponse>()
         .await
         .map_err(|_e| {
@@ -170,5 +1146,46 @@ pub async fn cross_encoder(
         index_a.cmp(&index_b)
     });
 
+    if let Some(scores) = resp.scores {
+        results.iter_mut().for_each(|result| {
+            if let Some(score) = resp
+                .docs
+                .iter()
+                .position(|s| s == &result.metadata[0].content)
+                .and_then(|index| scores.get(index))
+            {
+                result.score = *score as f64;
+            }
+        });
+    }
+
     Ok(results)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::chunk_operator::cosine_similarity;
+
+    #[test]
+    fn l2_normalize_scales_to_unit_length() {
+        assert_eq!(l2_normalize(vec![3.0, 4.0]), vec![0.6, 0.8]);
+        assert_eq!(l2_normalize(vec![0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn l2_normalized_dot_scores_match_cosine_scores() {
+        let query = l2_normalize(vec![1.0, 1.0]);
+
+        for document in [vec![10.0, 0.0], vec![0.9, 1.0]] {
+            let normalized_document = l2_normalize(document.clone());
+            let dot_score = query
+                .iter()
+                .zip(&normalized_document)
+                .map(|(a, b)| a * b)
+                .sum::<f32>();
+
+            assert!((dot_score - cosine_similarity(&query, &document)).abs() < 1e-6);
+        }
+    }
+}
@@ -1,25 +1,54 @@
 use super::{
-    model_operator::{get_splade_doc_embedding, get_splade_query_embedding},
-    search_operator::SearchResult,
+    model_operator::{
+        get_splade_doc_embedding, get_splade_query_embedding, stem_text, unaccent_text,
+    },
+    search_operator::{page_search_results, SearchResult},
 };
 use crate::{
-    data::models::ChunkMetadata,
+    data::models::{ChunkMetadata, ServerDatasetConfiguration},
     errors::{DefaultError, ServiceError},
     get_env,
 };
 use itertools::Itertools;
+use once_cell::sync::Lazy;
 use qdrant_client::{
-    client::{QdrantClient, QdrantClientConfig},
+    client::{Payload, QdrantClient, QdrantClientConfig},
     qdrant::{
-        payload_index_params::IndexParams, point_id::PointIdOptions,
-        with_payload_selector::SelectorOptions, Condition, CreateCollection, Distance, FieldType,
-        Filter, HnswConfigDiff, PayloadIndexParams, PointId, PointStruct, RecommendPoints,
-        SearchPoints, SparseIndexConfig, SparseVectorConfig, SparseVectorParams, TextIndexParams,
-        TokenizerType, Vector, VectorParams, VectorParamsMap, VectorsConfig, WithPayloadSelector,
+        condition::ConditionOneOf::HasId, payload_index_params::IndexParams,
+        point_id::PointIdOptions, vectors::VectorsOptions, with_payload_selector::SelectorOptions,
+        with_vectors_selector, Condition, CreateCollection, Distance, FieldType, Filter,
+        HasIdCondition, HnswConfigDiff, PayloadIncludeSelector, PayloadIndexParams, PointId,
+        PointStruct, PointVectors, Range, RecommendPoints, ScrollPoints, SearchPoints,
+        SparseIndexConfig, SparseVectorConfig, SparseVectorParams, TextIndexParams, TokenizerType,
+        Vector, VectorParams, VectorParamsMap, VectorsConfig, VectorsSelector, WithPayloadSelector,
+        WithVectorsSelector,
     },
 };
 use serde_json::json;
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Size of the CLIP image embeddings stored under the CLIP_VECTOR_NAME named vector.
+pub const CLIP_VECTOR_SIZE: usize = 512;
+
+/// Name of the named vector which CLIP image embeddings are stored under.
+pub const CLIP_VECTOR_NAME: &str = "clip_vectors";
+
+/// Points fetched past the end of the requested page so that points tied in score with the last
+/// results of the page are ordered by id together with them rather than cut off in qdrant's order.
+///
+/// Qdrant cannot resume a search after a (score, point id) cursor, so every point up to the end of
+/// the page is fetched and the earlier pages are skipped here, which makes deeper pages slower.
+/// The plan's max_search_page bounds how much is refetched. Ties are only paged consistently
+/// while they fit in the overfetch: when more than TIE_BREAK_OVERFETCH points after the end of a
+/// page share the score of its last point, qdrant picks which of them are fetched and that page
+/// can still overlap the next one.
+const TIE_BREAK_OVERFETCH: u64 = 10;
 
 pub async fn get_qdrant_connection() -> Result<QdrantClient, DefaultError> {
     let qdrant_url = get_env!("QDRANT_URL", "QDRANT_URL should be set");
@@ -31,6 +60,163 @@ pub async fn get_qdrant_connection() -> Result<QdrantClient, DefaultError> {
     })
 }
 
+pub const QDRANT_UNAVAILABLE_MESSAGE: &str =
+    "Qdrant is currently unavailable, please try again later";
+
+/// Retry and circuit breaker policy for qdrant calls. Each value can be overridden with the
+/// environment variable of the same name in upper case, e.g. QDRANT_RETRY_ATTEMPTS.
+struct QdrantRetryPolicy {
+    /// Total number of attempts made for a call, including the first one.
+    qdrant_retry_attempts: u64,
+    /// Delay before the first retry, doubled for every retry after it.
+    qdrant_retry_base_delay_ms: u64,
+    /// Number of consecutive failed calls after which the circuit opens.
+    qdrant_circuit_breaker_threshold: u64,
+    /// How long the circuit stays open before calls are let through to qdrant again.
+    qdrant_circuit_breaker_cooldown_secs: u64,
+}
+
+static QDRANT_RETRY_POLICY: Lazy<QdrantRetryPolicy> = Lazy::new(|| {
+    let env_or = |name: &str, default: u64| {
+        std::env::var(name)
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(default)
+    };
+
+    QdrantRetryPolicy {
+        qdrant_retry_attempts: env_or("QDRANT_RETRY_ATTEMPTS", 3).max(1),
+        qdrant_retry_base_delay_ms: env_or("QDRANT_RETRY_BASE_DELAY_MS", 100),
+        qdrant_circuit_breaker_threshold: env_or("QDRANT_CIRCUIT_BREAKER_THRESHOLD", 5).max(1),
+        qdrant_circuit_breaker_cooldown_secs: env_or("QDRANT_CIRCUIT_BREAKER_COOLDOWN_SECS", 30),
+    }
+});
+
+static QDRANT_CONSECUTIVE_FAILURES: AtomicU64 = AtomicU64::new(0);
+static QDRANT_CIRCUIT_OPEN_UNTIL: AtomicU64 = AtomicU64::new(0);
+
+pub enum QdrantCallError<E> {
+    /// The circuit is open, so the call was not attempted.
+    Unavailable,
+    Failed(E),
+}
+
+impl<E: std::fmt::Debug> QdrantCallError<E> {
+    pub fn into_default_error(self, message: &'static str) -> DefaultError {
+        match self {
+            QdrantCallError::Unavailable => DefaultError {
+                message: QDRANT_UNAVAILABLE_MESSAGE,
+            },
+            QdrantCallError::Failed(err) => {
+                log::error!("{} {:?}", message, err);
+                DefaultError { message }
+            }
+        }
+    }
+
+    pub fn into_service_error(self, message: &str) -> ServiceError {
+        match self {
+            QdrantCallError::Unavailable => {
+                ServiceError::ServiceUnavailable(QDRANT_UNAVAILABLE_MESSAGE.into())
+            }
+            QdrantCallError::Failed(err) => {
+                log::error!("{} {:?}", message, err);
+                ServiceError::BadRequest(message.into())
+            }
+        }
+    }
+}
+
+/// Maps an error from a qdrant operator to a 503 if qdrant is unavailable and a 400 otherwise.
+pub fn qdrant_error_to_service_error(err: DefaultError) -> ServiceError {
+    if err.message == QDRANT_UNAVAILABLE_MESSAGE {
+        ServiceError::ServiceUnavailable(err.message.into())
+    } else {
+        ServiceError::BadRequest(err.message.into())
+    }
+}
+
+fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Qdrant errors from connection problems, timeouts or restarts are worth retrying. Anything else,
+/// like an invalid point id, means qdrant is up and the call would fail again.
+fn is_transient_qdrant_error<E: std::fmt::Debug>(err: &E) -> bool {
+    let err = format!("{:?}", err);
+    [
+        "Unavailable",
+        "DeadlineExceeded",
+        "ResourceExhausted",
+        "Cancelled",
+        "transport error",
+        "Connection refused",
+    ]
+    .iter()
+    .any(|transient_error| err.contains(transient_error))
+}
+
+/// Runs a qdrant call, retrying transient failures with exponential backoff. A call which is still
+/// failing after every attempt is reported as unavailable. Once qdrant_circuit_breaker_threshold
+/// calls in a row have been unavailable, the circuit opens and calls fail fast without reaching
+/// qdrant until the cooldown has passed.
+pub async fn with_qdrant_retry<T, E, F, Fut>(operation: F) -> Result<T, QdrantCallError<E>>
+where
+    E: std::fmt::Debug,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let policy = &*QDRANT_RETRY_POLICY;
+
+    if QDRANT_CIRCUIT_OPEN_UNTIL.load(Ordering::Relaxed) > unix_now_secs() {
+        return Err(QdrantCallError::Unavailable);
+    }
+
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Ok(result) => {
+                QDRANT_CONSECUTIVE_FAILURES.store(0, Ordering::Relaxed);
+                return Ok(result);
+            }
+            Err(err) if !is_transient_qdrant_error(&err) => {
+                QDRANT_CONSECUTIVE_FAILURES.store(0, Ordering::Relaxed);
+                return Err(QdrantCallError::Failed(err));
+            }
+            Err(err) if attempt >= policy.qdrant_retry_attempts => {
+                log::error!("Qdrant call failed after {} attempts {:?}", attempt, err);
+                let failures = QDRANT_CONSECUTIVE_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+                if failures >= policy.qdrant_circuit_breaker_threshold {
+                    log::error!(
+                        "Opening qdrant circuit breaker for {}s after {} failed calls",
+                        policy.qdrant_circuit_breaker_cooldown_secs,
+                        failures
+                    );
+                    QDRANT_CIRCUIT_OPEN_UNTIL.store(
+                        unix_now_secs() + policy.qdrant_circuit_breaker_cooldown_secs,
+                        Ordering::Relaxed,
+                    );
+                }
+                return Err(QdrantCallError::Unavailable);
+            }
+            Err(err) => {
+                let delay_ms = policy.qdrant_retry_base_delay_ms * 2_u64.pow(attempt as u32 - 1);
+                log::warn!(
+                    "Qdrant call failed on attempt {}, retrying in {}ms {:?}",
+                    attempt,
+                    delay_ms,
+                    err
+                );
+                actix_web::rt::time::sleep(Duration::from_millis(delay_ms)).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
 /// Create Qdrant collection and indexes needed
 pub async fn create_new_qdrant_collection_query() -> Result<(), ServiceError> {
     let qdrant_collection = get_env!(
@@ -39,6 +225,11 @@ pub async fn create_new_qdrant_collection_query() -> Result<(), ServiceError> {
     )
     .to_string();
 
+    create_qdrant_collection_query(qdrant_collection).await
+}
+
+/// Create a Qdrant collection named `qdrant_collection` with the vectors and indexes needed
+async fn create_qdrant_collection_query(qdrant_collection: String) -> Result<(), ServiceError> {
     let qdrant_client = get_qdrant_connection()
         .await
         .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
@@ -113,6 +304,16 @@ pub async fn create_new_qdrant_collection_query() -> Result<(), ServiceError> {
                                     on_disk: None,
                                 },
                             ),
+                            (
+                                CLIP_VECTOR_NAME.to_string(),
+                                VectorParams {
+                                    size: CLIP_VECTOR_SIZE as u64,
+                                    distance: Distance::Cosine.into(),
+                                    hnsw_config: None,
+                                    quantization_config: None,
+                                    on_disk: None,
+                                },
+                            ),
                         ]),
                     },
                 )),
@@ -168,6 +369,17 @@ pub async fn create_new_qdrant_collection_query() -> Result<(), ServiceError> {
         .await
         .map_err(|_| ServiceError::BadRequest("Failed to create index".into()))?;
 
+    qdrant_client
+        .create_field_index(
+            qdrant_collection.clone(),
+            "acl",
+            FieldType::Keyword,
+            None,
+            None,
+        )
+        .await
+        .map_err(|_| ServiceError::BadRequest("Failed to create index".into()))?;
+
     qdrant_client
         .create_field_index(
             qdrant_collection.clone(),
@@ -189,12 +401,278 @@ pub async fn create_new_qdrant_collection_query() -> Result<(), ServiceError> {
     Ok(())
 }
 
+/// Copies every point of QDRANT_COLLECTION, with its vectors and payload, into a new collection
+/// named `target_collection` which is created with the current vectors and indexes. Qdrant cannot
+/// add a named vector to an existing collection, so this is how a collection created before image
+/// embedding was supported gains its CLIP_VECTOR_NAME vector. Nothing should write to
+/// QDRANT_COLLECTION while the copy runs. Returns the number of points copied.
+pub async fn migrate_qdrant_collection_query(
+    target_collection: String,
+) -> Result<u64, ServiceError> {
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    create_qdrant_collection_query(target_collection.clone()).await?;
+
+    let qdrant_client = get_qdrant_connection()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let mut copied_points: u64 = 0;
+    let mut offset: Option<PointId> = None;
+
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: qdrant_collection.clone(),
+            offset: offset.clone(),
+            limit: Some(256),
+            with_payload: Some(WithPayloadSelector {
+                selector_options: Some(SelectorOptions::Enable(true)),
+            }),
+            with_vectors: Some(WithVectorsSelector {
+                selector_options: Some(with_vectors_selector::SelectorOptions::Enable(true)),
+            }),
+            ..Default::default()
+        };
+
+        let data = with_qdrant_retry(|| qdrant_client.scroll(&scroll_points))
+            .await
+            .map_err(|err| err.into_service_error("Failed to scroll points on Qdrant"))?;
+
+        let points = data
+            .result
+            .into_iter()
+            .map(|point| PointStruct {
+                id: point.id,
+                payload: point.payload,
+                vectors: point.vectors,
+            })
+            .collect::<Vec<PointStruct>>();
+
+        if !points.is_empty() {
+            with_qdrant_retry(|| {
+                qdrant_client.upsert_points_blocking(&target_collection, None, points.clone(), None)
+            })
+            .await
+            .map_err(|err| err.into_service_error("Failed copying points to new collection"))?;
+            copied_points += points.len() as u64;
+        }
+
+        match data.next_page_offset {
+            Some(next_page_offset) => offset = Some(next_page_offset),
+            None => break,
+        }
+    }
+
+    Ok(copied_points)
+}
+
+/// Gets the parameters of the collection's named vector `vector_name`, or None if the collection
+/// has no such vector.
+pub async fn get_collection_vector_params_query(
+    vector_name: &str,
+) -> Result<Option<VectorParams>, ServiceError> {
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let qdrant_client = get_qdrant_connection()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let collection = qdrant_client
+        .collection_info(qdrant_collection)
+        .await
+        .map_err(|_| ServiceError::BadRequest("Failed to get collection info".into()))?;
+
+    Ok(collection
+        .result
+        .and_then(|info| info.config)
+        .and_then(|config| config.params)
+        .and_then(|params| params.vectors_config)
+        .and_then(|vectors_config| vectors_config.config)
+        .and_then(|config| match config {
+            qdrant_client::qdrant::vectors_config::Config::ParamsMap(params_map) => {
+                params_map.map.get(vector_name).cloned()
+            }
+            qdrant_client::qdrant::vectors_config::Config::Params(params) => Some(params),
+        }))
+}
+
+/// Gets the distance metric of the collection's named vector for embeddings of `vector_size`.
+pub async fn get_vector_distance_query(vector_size: usize) -> Result<Distance, ServiceError> {
+    let vector_params = get_collection_vector_params_query(&format!("{}_vectors", vector_size))
+        .await?
+        .ok_or(ServiceError::BadRequest(format!(
+            "The collection has no vectors of size {}",
+            vector_size
+        )))?;
+
+    Distance::from_i32(vector_params.distance).ok_or(ServiceError::BadRequest(
+        "The collection's vectors have an unknown distance metric".into(),
+    ))
+}
+
+/// Checks that the dataset's NORMALIZE_EMBEDDINGS suits the distance metric of the collection's
+/// vectors for its EMBEDDING_SIZE. Dot product scores grow with the length of the vectors, so
+/// without normalization longer vectors would outrank closer ones. Cosine already normalizes, so
+/// the setting makes no difference to it.
+pub async fn validate_embedding_normalization(
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let distance = get_vector_distance_query(dataset_config.EMBEDDING_SIZE.unwrap_or(1536)).await?;
+
+    if distance == Distance::Dot && !dataset_config.NORMALIZE_EMBEDDINGS.unwrap_or(false) {
+        return Err(ServiceError::BadRequest(
+            "The collection scores this dataset's vectors by dot product, so NORMALIZE_EMBEDDINGS must be set to true".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that the collection can store the CLIP image embeddings of a dataset which sets
+/// IMAGE_EMBEDDING_ENABLED. Qdrant cannot add a named vector to an existing collection, so
+/// collections created before image embedding was supported have no CLIP_VECTOR_NAME vector and
+/// must first be migrated by starting the server with QDRANT_MIGRATION_TARGET_COLLECTION set.
+pub async fn validate_image_embedding(
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), ServiceError> {
+    if !dataset_config.IMAGE_EMBEDDING_ENABLED.unwrap_or(false) {
+        return Ok(());
+    }
+
+    if get_collection_vector_params_query(CLIP_VECTOR_NAME)
+        .await?
+        .is_none()
+    {
+        return Err(ServiceError::BadRequest(
+            "The collection has no clip_vectors for image embeddings. It must be migrated before IMAGE_EMBEDDING_ENABLED can be set".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks that a search's requested distance metric, "cosine" or "dot", is the one the collection
+/// scores the dataset's vectors by. Qdrant can only score by the metric the collection was created
+/// with, so this never changes how results are scored and only rejects a mismatch.
+pub async fn validate_distance_metric(
+    distance_metric: &str,
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), ServiceError> {
+    let distance = get_vector_distance_query(dataset_config.EMBEDDING_SIZE.unwrap_or(1536)).await?;
+
+    check_distance_metric(
+        distance_metric,
+        distance,
+        dataset_config.NORMALIZE_EMBEDDINGS.unwrap_or(false),
+    )
+}
+
+/// Decides whether a requested distance metric is compatible with the collection's `distance`.
+/// Cosine is also accepted for a dot product collection of normalized embeddings, as the dot
+/// product of unit vectors is their cosine similarity.
+pub fn check_distance_metric(
+    distance_metric: &str,
+    distance: Distance,
+    normalize_embeddings: bool,
+) -> Result<(), ServiceError> {
+    let requested_distance = match distance_metric {
+        "cosine" => Distance::Cosine,
+        "dot" => Distance::Dot,
+        _ => {
+            return Err(ServiceError::BadRequest(
+                "distance_metric must be either \"cosine\" or \"dot\"".into(),
+            ))
+        }
+    };
+
+    let is_normalized_dot = distance == Distance::Dot && normalize_embeddings;
+    if requested_distance != distance
+        && !(requested_distance == Distance::Cosine && is_normalized_dot)
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "distance_metric is \"{}\" but the collection scores this dataset's vectors by {}. distance_metric only checks the collection's metric and cannot change how results are scored",
+            distance_metric,
+            distance.as_str_name().to_lowercase()
+        )));
+    }
+
+    Ok(())
+}
+
+/// The SPLADE vector a chunk is found by in full-text search. The dataset's FULLTEXT_UNACCENT and
+/// FULLTEXT_STEMMING_LANGUAGE are applied to the text first so it matches queries processed the
+/// same way, and it is encoded with the dataset's SPLADE_MODEL.
+pub async fn get_fulltext_sparse_vector(
+    text: String,
+    stemming_language: Option<String>,
+    unaccent: bool,
+    splade_model: Option<String>,
+) -> Result<Vec<(u32, f32)>, ServiceError> {
+    let splade_input = if unaccent { unaccent_text(&text) } else { text };
+    let splade_input = match stemming_language {
+        Some(language) => stem_text(&splade_input, &language)?,
+        None => splade_input,
+    };
+
+    get_splade_doc_embedding(&splade_input, splade_model).await
+}
+
+/// Replaces the full-text sparse vectors of the points, leaving their dense vectors and payloads
+/// as they are.
+pub async fn update_sparse_vectors_query(
+    sparse_vectors: Vec<(uuid::Uuid, Vec<(u32, f32)>)>,
+) -> Result<(), ServiceError> {
+    if sparse_vectors.is_empty() {
+        return Ok(());
+    }
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let qdrant = get_qdrant_connection()
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let points = sparse_vectors
+        .into_iter()
+        .map(|(point_id, sparse_vector)| PointVectors {
+            id: Some(point_id.to_string().into()),
+            vectors: Some(
+                HashMap::from([("sparse_vectors".to_string(), Vector::from(sparse_vector))]).into(),
+            ),
+        })
+        .collect::<Vec<PointVectors>>();
+
+    with_qdrant_retry(|| qdrant.update_vectors_blocking(&qdrant_collection, None, &points, None))
+        .await
+        .map_err(|err| err.into_service_error("Failed to update sparse vectors in qdrant"))?;
+
+    Ok(())
+}
+
 pub async fn create_new_qdrant_point_query(
     point_id: uuid::Uuid,
     embedding_vector: Vec<f32>,
     chunk_metadata: ChunkMetadata,
     author_id: Option<uuid::Uuid>,
     dataset_id: uuid::Uuid,
+    image_vector: Option<Vec<f32>>,
+    stemming_language: Option<String>,
+    unaccent: bool,
+    splade_model: Option<String>,
+    acl: Option<Vec<String>>,
+    embedding_model: Option<String>,
 ) -> Result<(), actix_web::Error> {
     let qdrant_collection = get_env!(
         "QDRANT_COLLECTION",
@@ -206,15 +684,15 @@ pub async fn create_new_qdrant_point_query(
         .await
         .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
 
-    let splade_vector = get_splade_doc_embedding(
-        chunk_metadata
-            .chunk_html
-            .as_ref()
-            .unwrap_or(&"".to_string()),
+    let splade_vector = get_fulltext_sparse_vector(
+        chunk_metadata.chunk_html.clone().unwrap_or_default(),
+        stemming_language,
+        unaccent,
+        splade_model,
     )
     .await?;
 
-    let payload = json!({"authors": vec![author_id.unwrap_or_default().to_string()], "tag_set": chunk_metadata.tag_set.unwrap_or("".to_string()).split(',').collect_vec(), "link": chunk_metadata.link.unwrap_or("".to_string()).split(',').collect_vec(), "chunk_html": chunk_metadata.chunk_html.unwrap_or("".to_string()), "metadata": chunk_metadata.metadata.unwrap_or_default(), "time_stamp": chunk_metadata.time_stamp.unwrap_or_default().timestamp(), "dataset_id": dataset_id.to_string()})
+    let payload = json!({"authors": vec![author_id.unwrap_or_default().to_string()], "tag_set": chunk_metadata.tag_set.unwrap_or("".to_string()).split(',').collect_vec(), "link": chunk_metadata.link.unwrap_or("".to_string()).split(',').collect_vec(), "chunk_html": chunk_metadata.chunk_html.unwrap_or("".to_string()), "metadata": chunk_metadata.metadata.unwrap_or_default(), "time_stamp": chunk_metadata.time_stamp.unwrap_or_default().timestamp(), "created_at": chunk_metadata.created_at.timestamp(), "updated_at": chunk_metadata.updated_at.timestamp(), "dataset_id": dataset_id.to_string(), "acl": acl.unwrap_or_default(), "embedding_model": embedding_model, "expires_at": chunk_metadata.expires_at.map(|expires_at| expires_at.timestamp())})
                 .try_into()
                 .expect("A json! Value must always be a valid Payload");
 
@@ -226,22 +704,21 @@ pub async fn create_new_qdrant_point_query(
         _ => return Err(ServiceError::BadRequest("Invalid embedding vector size".into()).into()),
     };
 
-    let point = PointStruct::new(
-        point_id.clone().to_string(),
-        HashMap::from([
-            (vector_name.to_string(), Vector::from(embedding_vector)),
-            ("sparse_vectors".to_string(), Vector::from(splade_vector)),
-        ]),
-        payload,
-    );
+    let mut vectors = HashMap::from([
+        (vector_name.to_string(), Vector::from(embedding_vector)),
+        ("sparse_vectors".to_string(), Vector::from(splade_vector)),
+    ]);
+    if let Some(image_vector) = image_vector {
+        vectors.insert(CLIP_VECTOR_NAME.to_string(), Vector::from(image_vector));
+    }
 
-    qdrant
-        .upsert_points_blocking(qdrant_collection, None, vec![point], None)
-        .await
-        .map_err(|err| {
-            log::info!("Failed inserting chunk to qdrant {:?}", err);
-            ServiceError::BadRequest("Failed inserting chunk to qdrant".into())
-        })?;
+    let point = PointStruct::new(point_id.clone().to_string(), vectors, payload);
+
+    with_qdrant_retry(|| {
+        qdrant.upsert_points_blocking(&qdrant_collection, None, vec![point.clone()], None)
+    })
+    .await
+    .map_err(|err| err.into_service_error("Failed inserting chunk to qdrant"))?;
 
     Ok(())
 }
@@ -252,6 +729,10 @@ pub async fn update_qdrant_point_query(
     author_id: Option<uuid::Uuid>,
     updated_vector: Option<Vec<f32>>,
     dataset_id: uuid::Uuid,
+    stemming_language: Option<String>,
+    unaccent: bool,
+    splade_model: Option<String>,
+    embedding_model: Option<String>,
 ) -> Result<(), actix_web::Error> {
     let qdrant_point_id: Vec<PointId> = vec![point_id.to_string().into()];
 
@@ -265,18 +746,19 @@ pub async fn update_qdrant_point_query(
     )
     .to_string();
 
-    let current_point_vec = qdrant
-        .get_points(
-            qdrant_collection.clone(),
+    let current_point_vec = with_qdrant_retry(|| {
+        qdrant.get_points(
+            &qdrant_collection,
             None,
             &qdrant_point_id,
             false.into(),
             true.into(),
             None,
         )
-        .await
-        .map_err(|_err| ServiceError::BadRequest("Failed to search_points from qdrant".into()))?
-        .result;
+    })
+    .await
+    .map_err(|err| err.into_service_error("Failed to search_points from qdrant"))?
+    .result;
 
     let current_point = match current_point_vec.first() {
         Some(point) => point,
@@ -311,15 +793,37 @@ pub async fn update_qdrant_point_query(
         current_author_ids.push(author_id.unwrap().to_string());
     }
 
+    let empty_acl = qdrant_client::qdrant::Value::from(Vec::<String>::new());
+    let current_acl = current_point.payload.get("acl").unwrap_or(&empty_acl);
+    // A new vector is embedded with the dataset's current model, otherwise the point keeps its own
+    let embedding_model = match (
+        &updated_vector,
+        current_point.payload.get("embedding_model"),
+    ) {
+        (Some(_), _) => json!(embedding_model),
+        (None, Some(current_embedding_model)) => json!(current_embedding_model),
+        (None, None) => serde_json::Value::Null,
+    };
+    let current_expires_at = match current_point.payload.get("expires_at") {
+        Some(expires_at) => json!(expires_at),
+        None => serde_json::Value::Null,
+    };
+
     let payload = if let Some(metadata) = metadata.clone() {
-        json!({"authors": current_author_ids, "tag_set": metadata.tag_set.unwrap_or("".to_string()).split(',').collect_vec(), "link": metadata.link.unwrap_or("".to_string()).split(',').collect_vec(), "chunk_html": metadata.chunk_html.unwrap_or("".to_string()), "metadata": metadata.metadata.unwrap_or_default(), "time_stamp": metadata.time_stamp.unwrap_or_default().timestamp(), "dataset_id": dataset_id.to_string()})
+        json!({"authors": current_author_ids, "tag_set": metadata.tag_set.unwrap_or("".to_string()).split(',').collect_vec(), "link": metadata.link.unwrap_or("".to_string()).split(',').collect_vec(), "chunk_html": metadata.chunk_html.unwrap_or("".to_string()), "metadata": metadata.metadata.unwrap_or_default(), "time_stamp": metadata.time_stamp.unwrap_or_default().timestamp(), "created_at": metadata.created_at.timestamp(), "updated_at": metadata.updated_at.timestamp(), "dataset_id": dataset_id.to_string(), "acl": current_acl, "embedding_model": embedding_model, "expires_at": metadata.expires_at.map(|expires_at| expires_at.timestamp())})
     } else {
-        json!({"authors": current_author_ids, "tag_set": current_point.payload.get("tag_set").unwrap_or(&qdrant_client::qdrant::Value::from("")), "link": current_point.payload.get("link").unwrap_or(&qdrant_client::qdrant::Value::from("")), "chunk_html": current_point.payload.get("chunk_html").unwrap_or(&qdrant_client::qdrant::Value::from("")), "metadata": current_point.payload.get("metadata").unwrap_or(&qdrant_client::qdrant::Value::from("")), "time_stamp": current_point.payload.get("time_stamp").unwrap_or(&qdrant_client::qdrant::Value::from("")), "dataset_id": current_point.payload.get("dataset_id").unwrap_or(&qdrant_client::qdrant::Value::from(""))})
+        json!({"authors": current_author_ids, "tag_set": current_point.payload.get("tag_set").unwrap_or(&qdrant_client::qdrant::Value::from("")), "link": current_point.payload.get("link").unwrap_or(&qdrant_client::qdrant::Value::from("")), "chunk_html": current_point.payload.get("chunk_html").unwrap_or(&qdrant_client::qdrant::Value::from("")), "metadata": current_point.payload.get("metadata").unwrap_or(&qdrant_client::qdrant::Value::from("")), "time_stamp": current_point.payload.get("time_stamp").unwrap_or(&qdrant_client::qdrant::Value::from("")), "created_at": current_point.payload.get("created_at").unwrap_or(&qdrant_client::qdrant::Value::from("")), "updated_at": current_point.payload.get("updated_at").unwrap_or(&qdrant_client::qdrant::Value::from("")), "dataset_id": current_point.payload.get("dataset_id").unwrap_or(&qdrant_client::qdrant::Value::from("")), "acl": current_acl, "embedding_model": embedding_model, "expires_at": current_expires_at})
     };
     let points_selector = qdrant_point_id.into();
 
     if let Some(updated_vector) = updated_vector {
-        let splade_vector = get_splade_doc_embedding(&metadata.unwrap().content).await?;
+        let splade_vector = get_fulltext_sparse_vector(
+            metadata.unwrap().content,
+            stemming_language,
+            unaccent,
+            splade_model,
+        )
+        .await?;
         let vector_name = match updated_vector.len() {
             384 => "384_vectors",
             768 => "768_vectors",
@@ -340,36 +844,238 @@ pub async fn update_qdrant_point_query(
                 .expect("A json! value must always be a valid Payload"),
         );
 
-        qdrant
-            .upsert_points(qdrant_collection, None, vec![point], None)
-            .await
-            .map_err(|_err| ServiceError::BadRequest("Failed upserting chunk in qdrant".into()))?;
+        with_qdrant_retry(|| {
+            qdrant.upsert_points(&qdrant_collection, None, vec![point.clone()], None)
+        })
+        .await
+        .map_err(|err| err.into_service_error("Failed upserting chunk in qdrant"))?;
 
         return Ok(());
     }
 
-    qdrant
-        .overwrite_payload(
-            qdrant_collection,
+    let payload: Payload = payload
+        .try_into()
+        .expect("A json! value must always be a valid Payload");
+
+    with_qdrant_retry(|| {
+        qdrant.overwrite_payload(
+            &qdrant_collection,
             None,
             &points_selector,
-            payload
-                .try_into()
-                .expect("A json! value must always be a valid Payload"),
+            payload.clone(),
             None,
         )
-        .await
-        .map_err(|_err| {
-            ServiceError::BadRequest("Failed updating chunk payload in qdrant".into())
-        })?;
+    })
+    .await
+    .map_err(|err| err.into_service_error("Failed updating chunk payload in qdrant"))?;
 
     Ok(())
 }
 
+/// Returns the embedding_model stored on the payload of each of the given points which has one.
+pub async fn get_point_embedding_models_query(
+    point_ids: Vec<uuid::Uuid>,
+) -> Result<HashMap<uuid::Uuid, String>, DefaultError> {
+    if point_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let qdrant = get_qdrant_connection().await?;
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let qdrant_point_ids: Vec<PointId> = point_ids
+        .iter()
+        .map(|point_id| point_id.to_string().into())
+        .collect();
+
+    let points = with_qdrant_retry(|| {
+        qdrant.get_points(
+            &qdrant_collection,
+            None,
+            &qdrant_point_ids,
+            false.into(),
+            Some(WithPayloadSelector {
+                selector_options: Some(SelectorOptions::Include(PayloadIncludeSelector {
+                    fields: vec!["embedding_model".to_string()],
+                })),
+            }),
+            None,
+        )
+    })
+    .await
+    .map_err(|err| err.into_default_error("Failed to get embedding models from qdrant"))?
+    .result;
+
+    Ok(points
+        .into_iter()
+        .filter_map(|point| {
+            let point_id = match point.id?.point_id_options? {
+                PointIdOptions::Uuid(id) => uuid::Uuid::parse_str(&id).ok()?,
+                PointIdOptions::Num(_) => return None,
+            };
+            let embedding_model = point.payload.get("embedding_model")?.as_str()?.to_string();
+            Some((point_id, embedding_model))
+        })
+        .collect())
+}
+
+/// Only lets through points whose acl is empty or shares a group with the searcher.
+pub fn acl_condition(acl_groups: Vec<String>) -> Condition {
+    Filter::should([
+        Condition::is_empty("acl"),
+        Condition::matches("acl", acl_groups),
+    ])
+    .into()
+}
+
+/// Only lets through points which have not expired yet. Points without an expires_at never expire.
+pub fn not_expired_condition() -> Condition {
+    Filter::should([
+        Condition::is_empty("expires_at"),
+        Condition::range(
+            "expires_at",
+            Range {
+                gt: Some(chrono::Utc::now().timestamp() as f64),
+                ..Default::default()
+            },
+        ),
+    ])
+    .into()
+}
+
+/// Only lets through points whose vector was produced by the embedding model, or whose model is not
+/// known because they were created from a client provided vector or before models were recorded.
+pub fn embedding_model_condition(embedding_model: String) -> Condition {
+    Filter::should([
+        Condition::is_empty("embedding_model"),
+        Condition::matches("embedding_model", embedding_model),
+    ])
+    .into()
+}
+
+/// Whether any of the dataset's points has a vector from an embedding model other than the given
+/// one, as it does while the dataset is being migrated to a new model.
+pub async fn has_other_embedding_model_points_query(
+    dataset_id: uuid::Uuid,
+    embedding_model: String,
+) -> Result<bool, DefaultError> {
+    let qdrant = get_qdrant_connection().await?;
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let scroll_points = ScrollPoints {
+        collection_name: qdrant_collection,
+        filter: Some(Filter {
+            must: vec![Condition::matches("dataset_id", dataset_id.to_string())],
+            must_not: vec![
+                Condition::is_empty("embedding_model"),
+                Condition::matches("embedding_model", embedding_model),
+            ],
+            ..Default::default()
+        }),
+        limit: Some(1),
+        with_payload: Some(WithPayloadSelector {
+            selector_options: Some(SelectorOptions::Enable(false)),
+        }),
+        with_vectors: Some(WithVectorsSelector {
+            selector_options: Some(with_vectors_selector::SelectorOptions::Enable(false)),
+        }),
+        ..Default::default()
+    };
+
+    let other_model_points = with_qdrant_retry(|| qdrant.scroll(&scroll_points))
+        .await
+        .map_err(|err| err.into_default_error("Failed to check embedding models on Qdrant"))?
+        .result;
+
+    Ok(!other_model_points.is_empty())
+}
+
+/// Returns which of the given points are visible to a searcher with the given acl groups.
+pub async fn filter_point_ids_by_acl_query(
+    point_ids: Vec<uuid::Uuid>,
+    acl_groups: Vec<String>,
+) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    if point_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let qdrant = get_qdrant_connection().await?;
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let scroll_points = ScrollPoints {
+        collection_name: qdrant_collection,
+        filter: Some(Filter::must([
+            Condition {
+                condition_one_of: Some(HasId(HasIdCondition {
+                    has_id: point_ids
+                        .iter()
+                        .map(|point_id| point_id.to_string().into())
+                        .collect(),
+                })),
+            },
+            acl_condition(acl_groups),
+        ])),
+        limit: Some(point_ids.len() as u32),
+        with_payload: Some(WithPayloadSelector {
+            selector_options: Some(SelectorOptions::Enable(false)),
+        }),
+        with_vectors: Some(WithVectorsSelector {
+            selector_options: Some(with_vectors_selector::SelectorOptions::Enable(false)),
+        }),
+        ..Default::default()
+    };
+
+    let visible_point_ids = with_qdrant_retry(|| qdrant.scroll(&scroll_points))
+        .await
+        .map_err(|err| err.into_default_error("Failed to check chunk acls on Qdrant"))?
+        .result
+        .into_iter()
+        .filter_map(|point| match point.id?.point_id_options? {
+            PointIdOptions::Uuid(id) => uuid::Uuid::parse_str(&id).ok(),
+            PointIdOptions::Num(_) => None,
+        })
+        .collect::<HashSet<uuid::Uuid>>();
+
+    Ok(point_ids
+        .into_iter()
+        .filter(|point_id| visible_point_ids.contains(point_id))
+        .collect())
+}
+
+/// Returns the name of the named vector which dense text embeddings of the given size are stored
+/// under.
+pub fn text_vector_name(embedding_size: usize) -> Result<&'static str, DefaultError> {
+    match embedding_size {
+        384 => Ok("384_vectors"),
+        768 => Ok("768_vectors"),
+        1024 => Ok("1024_vectors"),
+        1536 => Ok("1536_vectors"),
+        _ => Err(DefaultError {
+            message: "Invalid embedding vector size",
+        }),
+    }
+}
+
 pub async fn search_semantic_qdrant_query(
     page: u64,
     mut filter: Filter,
     embedding_vector: Vec<f32>,
+    vector_name: &str,
     dataset_id: uuid::Uuid,
 ) -> Result<Vec<SearchResult>, DefaultError> {
     let qdrant = get_qdrant_connection().await?;
@@ -384,36 +1090,20 @@ pub async fn search_semantic_qdrant_query(
         .must
         .push(Condition::matches("dataset_id", dataset_id.to_string()));
 
-    let vector_name = match embedding_vector.len() {
-        384 => "384_vectors",
-        768 => "768_vectors",
-        1024 => "1024_vectors",
-        1536 => "1536_vectors",
-        _ => {
-            return Err(DefaultError {
-                message: "Invalid embedding vector size",
-            })
-        }
+    let search_points = SearchPoints {
+        collection_name: qdrant_collection.to_string(),
+        vector: embedding_vector,
+        vector_name: Some(vector_name.to_string()),
+        limit: page * 10 + TIE_BREAK_OVERFETCH,
+        offset: None,
+        with_payload: None,
+        filter: Some(filter),
+        ..Default::default()
     };
 
-    let data = qdrant
-        .search_points(&SearchPoints {
-            collection_name: qdrant_collection.to_string(),
-            vector: embedding_vector,
-            vector_name: Some(vector_name.to_string()),
-            limit: 10,
-            offset: Some((page - 1) * 10),
-            with_payload: None,
-            filter: Some(filter),
-            ..Default::default()
-        })
+    let data = with_qdrant_retry(|| qdrant.search_points(&search_points))
         .await
-        .map_err(|e| {
-            log::error!("Failed to search points on Qdrant {:?}", e);
-            DefaultError {
-                message: "Failed to search points on Qdrant",
-            }
-        })?;
+        .map_err(|err| err.into_default_error("Failed to search points on Qdrant"))?;
 
     let point_ids: Vec<SearchResult> = data
         .result
@@ -426,14 +1116,43 @@ pub async fn search_semantic_qdrant_query(
             PointIdOptions::Num(_) => None,
         })
         .collect();
+    let point_ids = page_search_results(point_ids, page);
 
     Ok(point_ids)
 }
 
+/// Multiplies the weights of the tokens of each boosted term in a SPLADE query vector by the term's
+/// weight. Tokens of a boosted term which are not in the query vector are added with the term's own
+/// weight for them scaled the same way.
+async fn apply_query_boosts(
+    mut query_vector: Vec<(u32, f32)>,
+    boosts: Vec<(String, f32)>,
+    splade_model: Option<String>,
+) -> Result<Vec<(u32, f32)>, DefaultError> {
+    for (term, weight) in boosts {
+        let term_vector = get_splade_query_embedding(&term, splade_model.clone())
+            .await
+            .map_err(|_err| DefaultError {
+                message: "Failed to get splade embedding for boosted term",
+            })?;
+
+        for (index, value) in term_vector {
+            match query_vector.iter_mut().find(|(i, _)| *i == index) {
+                Some((_, query_value)) => *query_value *= weight,
+                None => query_vector.push((index, value * weight)),
+            }
+        }
+    }
+
+    Ok(query_vector)
+}
+
 pub async fn search_full_text_qdrant_query(
     page: u64,
     mut filter: Filter,
     query: String,
+    boosts: Vec<(String, f32)>,
+    splade_model: Option<String>,
     dataset_id: uuid::Uuid,
 ) -> Result<Vec<SearchResult>, DefaultError> {
     let qdrant = get_qdrant_connection().await?;
@@ -444,12 +1163,12 @@ pub async fn search_full_text_qdrant_query(
     )
     .to_string();
 
-    let embedding_vector =
-        get_splade_query_embedding(&query)
-            .await
-            .map_err(|_err| DefaultError {
-                message: "Failed to get splade query embedding",
-            })?;
+    let embedding_vector = get_splade_query_embedding(&query, splade_model.clone())
+        .await
+        .map_err(|_err| DefaultError {
+            message: "Failed to get splade query embedding",
+        })?;
+    let embedding_vector = apply_query_boosts(embedding_vector, boosts, splade_model).await?;
 
     filter
         .must
@@ -457,25 +1176,21 @@ pub async fn search_full_text_qdrant_query(
 
     let sparse_vector: Vector = embedding_vector.into();
 
-    let data = qdrant
-        .search_points(&SearchPoints {
-            collection_name: qdrant_collection.to_string(),
-            vector: sparse_vector.data,
-            sparse_indices: sparse_vector.indices,
-            vector_name: Some("sparse_vectors".to_string()),
-            limit: 10,
-            offset: Some((page - 1) * 10),
-            with_payload: None,
-            filter: Some(filter),
-            ..Default::default()
-        })
+    let search_points = SearchPoints {
+        collection_name: qdrant_collection.to_string(),
+        vector: sparse_vector.data,
+        sparse_indices: sparse_vector.indices,
+        vector_name: Some("sparse_vectors".to_string()),
+        limit: page * 10 + TIE_BREAK_OVERFETCH,
+        offset: None,
+        with_payload: None,
+        filter: Some(filter),
+        ..Default::default()
+    };
+
+    let data = with_qdrant_retry(|| qdrant.search_points(&search_points))
         .await
-        .map_err(|e| {
-            log::error!("Failed to search points on Qdrant {:?}", e);
-            DefaultError {
-                message: "Failed to search points on Qdrant",
-            }
-        })?;
+        .map_err(|err| err.into_default_error("Failed to search points on Qdrant"))?;
 
     let point_ids: Vec<SearchResult> = data
         .result
@@ -488,6 +1203,7 @@ pub async fn search_full_text_qdrant_query(
             PointIdOptions::Num(_) => None,
         })
         .collect();
+    let point_ids = page_search_results(point_ids, page);
 
     Ok(point_ids)
 }
@@ -502,12 +1218,9 @@ pub async fn delete_qdrant_point_id_query(
     let points_selector = qdrant_point_id.into();
     let qdrant_collection = dataset_id.to_string();
 
-    qdrant
-        .delete_points(qdrant_collection, None, &points_selector, None)
+    with_qdrant_retry(|| qdrant.delete_points(&qdrant_collection, None, &points_selector, None))
         .await
-        .map_err(|_err| DefaultError {
-            message: "Failed to delete point from qdrant",
-        })?;
+        .map_err(|err| err.into_default_error("Failed to delete point from qdrant"))?;
 
     Ok(())
 }
@@ -516,35 +1229,56 @@ pub async fn recommend_qdrant_query(
     positive_ids: Vec<uuid::Uuid>,
     dataset_id: uuid::Uuid,
     embed_size: usize,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    Ok(
+        recommend_scored_qdrant_query(positive_ids, dataset_id, embed_size, acl_groups, None)
+            .await?
+            .into_iter()
+            .map(|search_result| search_result.point_id)
+            .collect(),
+    )
+}
+
+/// Recommends points like `recommend_qdrant_query` but keeps the score of each recommendation. If
+/// candidate_point_ids is given, only those points can be recommended.
+pub async fn recommend_scored_qdrant_query(
+    positive_ids: Vec<uuid::Uuid>,
+    dataset_id: uuid::Uuid,
+    embed_size: usize,
+    acl_groups: Option<Vec<String>>,
+    candidate_point_ids: Option<Vec<uuid::Uuid>>,
+) -> Result<Vec<SearchResult>, DefaultError> {
     let collection_name = dataset_id.to_string();
 
     let point_ids: Vec<PointId> = positive_ids
         .iter()
         .map(|id| id.to_string().into())
         .collect();
-    let dataset_filter = Some(Filter::must([Condition::matches(
-        "dataset_id",
-        dataset_id.to_string(),
-    )]));
+    let mut dataset_filter =
+        Filter::must([Condition::matches("dataset_id", dataset_id.to_string())]);
+    dataset_filter.must.push(not_expired_condition());
+    if let Some(acl_groups) = acl_groups {
+        dataset_filter.must.push(acl_condition(acl_groups));
+    }
+    if let Some(candidate_point_ids) = candidate_point_ids {
+        dataset_filter.must.push(Condition {
+            condition_one_of: Some(HasId(HasIdCondition {
+                has_id: candidate_point_ids
+                    .iter()
+                    .map(|id| id.to_string().into())
+                    .collect(),
+            })),
+        });
+    }
 
-    let vector_name = match embed_size {
-        384 => "384_vectors",
-        768 => "768_vectors",
-        1024 => "1024_vectors",
-        1536 => "1536_vectors",
-        _ => {
-            return Err(DefaultError {
-                message: "Invalid embedding vector size",
-            })
-        }
-    };
+    let vector_name = text_vector_name(embed_size)?;
 
     let recommend_points = RecommendPoints {
         collection_name,
         positive: point_ids,
         negative: vec![],
-        filter: dataset_filter,
+        filter: Some(dataset_filter),
         limit: 10,
         with_payload: Some(WithPayloadSelector {
             selector_options: Some(SelectorOptions::Enable(true)),
@@ -565,17 +1299,186 @@ pub async fn recommend_qdrant_query(
 
     let qdrant_client = get_qdrant_connection().await?;
 
-    let recommended_point_ids = qdrant_client
-        .recommend(&recommend_points)
+    let recommended_point_ids = with_qdrant_retry(|| qdrant_client.recommend(&recommend_points))
         .await
         .map_err(|err| {
-            log::info!("Failed to recommend points from qdrant: {:?}", err);
-            DefaultError {
-                message: "Failed to recommend points from qdrant. Your are likely providing an invalid point id.",
-            }
+            err.into_default_error(
+                "Failed to recommend points from qdrant. Your are likely providing an invalid point id.",
+            )
         })?
         .result
         .into_iter()
+        .filter_map(|point| match point.id?.point_id_options? {
+            PointIdOptions::Uuid(id) => Some(SearchResult {
+                score: point.score,
+                point_id: uuid::Uuid::from_str(&id).ok()?,
+            }),
+            PointIdOptions::Num(_) => None,
+        })
+        .collect::<Vec<SearchResult>>();
+
+    Ok(recommended_point_ids)
+}
+
+fn weighted_average_vector(vectors: &[(Vec<f32>, f32)]) -> Option<Vec<f32>> {
+    let total_weight: f32 = vectors.iter().map(|(_, weight)| weight).sum();
+    if vectors.is_empty() || total_weight == 0.0 {
+        return None;
+    }
+
+    let mut average = vec![0.0; vectors[0].0.len()];
+    for (vector, weight) in vectors {
+        for (average_value, value) in average.iter_mut().zip(vector) {
+            *average_value += value * weight / total_weight;
+        }
+    }
+
+    Some(average)
+}
+
+/// Fetch the named vector of each of the given points. Points which do not exist or do not have
+/// the vector are left out of the returned map.
+pub async fn get_point_vectors_query(
+    point_ids: Vec<uuid::Uuid>,
+    vector_name: &str,
+) -> Result<HashMap<uuid::Uuid, Vec<f32>>, DefaultError> {
+    if point_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let point_ids: Vec<PointId> = point_ids.iter().map(|id| id.to_string().into()).collect();
+
+    let qdrant_client = get_qdrant_connection().await?;
+
+    let points = with_qdrant_retry(|| {
+        qdrant_client.get_points(
+            &qdrant_collection,
+            None,
+            &point_ids,
+            Some(WithVectorsSelector {
+                selector_options: Some(with_vectors_selector::SelectorOptions::Include(
+                    VectorsSelector {
+                        names: vec![vector_name.to_string()],
+                    },
+                )),
+            }),
+            false.into(),
+            None,
+        )
+    })
+    .await
+    .map_err(|err| err.into_default_error("Failed to get point vectors from qdrant"))?
+    .result;
+
+    Ok(points
+        .into_iter()
+        .filter_map(|point| {
+            let point_id = match point.id?.point_id_options? {
+                PointIdOptions::Uuid(id) => uuid::Uuid::from_str(&id).ok()?,
+                PointIdOptions::Num(_) => return None,
+            };
+            let vector = match point.vectors?.vectors_options? {
+                VectorsOptions::Vectors(named_vectors) => {
+                    named_vectors.vectors.get(vector_name)?.data.clone()
+                }
+                VectorsOptions::Vector(vector) => vector.data,
+            };
+            Some((point_id, vector))
+        })
+        .collect())
+}
+
+/// Recommend points using a weighted average of the seed vectors instead of qdrant's built-in
+/// equal weight strategy. The target vector is `avg_positive + (avg_positive - avg_negative)`.
+pub async fn recommend_weighted_qdrant_query(
+    positive: Vec<(uuid::Uuid, f32)>,
+    negative: Vec<(uuid::Uuid, f32)>,
+    dataset_id: uuid::Uuid,
+    embed_size: usize,
+    acl_groups: Option<Vec<String>>,
+) -> Result<Vec<uuid::Uuid>, DefaultError> {
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let vector_name = text_vector_name(embed_size)?;
+
+    let seed_ids: Vec<uuid::Uuid> = positive
+        .iter()
+        .chain(negative.iter())
+        .map(|(id, _)| *id)
+        .collect();
+    let seed_point_ids: Vec<PointId> = seed_ids.iter().map(|id| id.to_string().into()).collect();
+
+    let seed_vectors = get_point_vectors_query(seed_ids, vector_name).await?;
+
+    let get_weighted_vectors = |examples: &Vec<(uuid::Uuid, f32)>| {
+        examples
+            .iter()
+            .map(|(id, weight)| match seed_vectors.get(id) {
+                Some(vector) => Ok((vector.clone(), *weight)),
+                None => Err(DefaultError {
+                    message: "Failed to find a vector for one of the example chunks",
+                }),
+            })
+            .collect::<Result<Vec<(Vec<f32>, f32)>, DefaultError>>()
+    };
+
+    let average_positive =
+        weighted_average_vector(&get_weighted_vectors(&positive)?).ok_or(DefaultError {
+            message: "At least one positive example with a non-zero weight is required",
+        })?;
+
+    let target_vector = match weighted_average_vector(&get_weighted_vectors(&negative)?) {
+        Some(average_negative) => average_positive
+            .iter()
+            .zip(average_negative)
+            .map(|(positive_value, negative_value)| {
+                positive_value + (positive_value - negative_value)
+            })
+            .collect(),
+        None => average_positive,
+    };
+
+    let qdrant_client = get_qdrant_connection().await?;
+
+    let mut filter = Filter::default();
+    filter
+        .must
+        .push(Condition::matches("dataset_id", dataset_id.to_string()));
+    filter.must_not.push(Condition {
+        condition_one_of: Some(HasId(HasIdCondition {
+            has_id: seed_point_ids,
+        })),
+    });
+    filter.must.push(not_expired_condition());
+    if let Some(acl_groups) = acl_groups {
+        filter.must.push(acl_condition(acl_groups));
+    }
+
+    let search_points = SearchPoints {
+        collection_name: qdrant_collection,
+        vector: target_vector,
+        vector_name: Some(vector_name.to_string()),
+        limit: 10,
+        with_payload: None,
+        filter: Some(filter),
+        ..Default::default()
+    };
+
+    let recommended_point_ids = with_qdrant_retry(|| qdrant_client.search_points(&search_points))
+        .await
+        .map_err(|err| err.into_default_error("Failed to recommend points from qdrant"))?
+        .result
+        .into_iter()
         .filter_map(|point| match point.id?.point_id_options? {
             PointIdOptions::Uuid(id) => uuid::Uuid::from_str(&id).ok(),
             PointIdOptions::Num(_) => None,
@@ -584,3 +1487,114 @@ pub async fn recommend_qdrant_query(
 
     Ok(recommended_point_ids)
 }
+
+/// Scrolls through every point belonging to the dataset and returns their ids along with the updated_at unix timestamp on their payload, if any.
+pub async fn scroll_dataset_qdrant_point_ids_query(
+    dataset_id: uuid::Uuid,
+) -> Result<Vec<(uuid::Uuid, Option<i64>)>, DefaultError> {
+    let qdrant = get_qdrant_connection().await?;
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let mut point_ids: Vec<(uuid::Uuid, Option<i64>)> = vec![];
+    let mut offset: Option<PointId> = None;
+
+    loop {
+        let scroll_points = ScrollPoints {
+            collection_name: qdrant_collection.clone(),
+            filter: Some(Filter::must([Condition::matches(
+                "dataset_id",
+                dataset_id.to_string(),
+            )])),
+            offset: offset.clone(),
+            limit: Some(1000),
+            with_payload: Some(WithPayloadSelector {
+                selector_options: Some(SelectorOptions::Include(PayloadIncludeSelector {
+                    fields: vec!["updated_at".to_string()],
+                })),
+            }),
+            with_vectors: Some(WithVectorsSelector {
+                selector_options: Some(with_vectors_selector::SelectorOptions::Enable(false)),
+            }),
+            ..Default::default()
+        };
+
+        let data = with_qdrant_retry(|| qdrant.scroll(&scroll_points))
+            .await
+            .map_err(|err| err.into_default_error("Failed to scroll points on Qdrant"))?;
+
+        point_ids.extend(data.result.iter().filter_map(|point| {
+            let point_id = match point.clone().id?.point_id_options? {
+                PointIdOptions::Uuid(id) => uuid::Uuid::parse_str(&id).ok()?,
+                PointIdOptions::Num(_) => return None,
+            };
+            let updated_at = point
+                .payload
+                .get("updated_at")
+                .and_then(|updated_at| updated_at.as_integer());
+            Some((point_id, updated_at))
+        }));
+
+        match data.next_page_offset {
+            Some(next_page_offset) => offset = Some(next_page_offset),
+            None => break,
+        }
+    }
+
+    Ok(point_ids)
+}
+
+pub async fn delete_qdrant_point_ids_query(point_ids: Vec<uuid::Uuid>) -> Result<(), DefaultError> {
+    if point_ids.is_empty() {
+        return Ok(());
+    }
+
+    let qdrant = get_qdrant_connection().await?;
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let qdrant_point_ids: Vec<PointId> = point_ids
+        .iter()
+        .map(|point_id| point_id.to_string().into())
+        .collect();
+    let points_selector = qdrant_point_ids.into();
+
+    with_qdrant_retry(|| qdrant.delete_points(&qdrant_collection, None, &points_selector, None))
+        .await
+        .map_err(|err| err.into_default_error("Failed to delete points from qdrant"))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_distance_metric_accepts_the_collection_metric() {
+        assert!(check_distance_metric("cosine", Distance::Cosine, false).is_ok());
+        assert!(check_distance_metric("dot", Distance::Dot, false).is_ok());
+        assert!(check_distance_metric("dot", Distance::Dot, true).is_ok());
+    }
+
+    #[test]
+    fn check_distance_metric_rejects_a_mismatched_metric() {
+        assert!(check_distance_metric("dot", Distance::Cosine, false).is_err());
+        assert!(check_distance_metric("dot", Distance::Cosine, true).is_err());
+        assert!(check_distance_metric("cosine", Distance::Dot, false).is_err());
+        assert!(check_distance_metric("euclid", Distance::Cosine, false).is_err());
+    }
+
+    #[test]
+    fn check_distance_metric_accepts_cosine_for_normalized_dot_collection() {
+        assert!(check_distance_metric("cosine", Distance::Dot, true).is_ok());
+    }
+}
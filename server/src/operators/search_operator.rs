@@ -1,38 +1,51 @@
 use super::chunk_operator::{
-    find_relevant_sentence, get_collided_chunks_query,
-    get_metadata_and_collided_chunks_from_point_ids_query, get_metadata_from_point_ids,
+    cosine_similarity, find_relevant_sentence, find_semantically_relevant_sentences,
+    get_collided_chunks_query, get_metadata_and_collided_chunks_from_point_ids_query,
+    get_metadata_from_id_query, get_metadata_from_point_ids, get_neighbor_chunks_query,
+    get_point_ids_for_file_query, get_qdrant_ids_from_chunk_ids_query,
+};
+use super::model_operator::{
+    create_embeddings, create_query_embedding, cross_encoder, get_clip_embedding,
 };
-use super::model_operator::{create_embedding, cross_encoder};
 use crate::data::models::{
-    ChunkCollection, ChunkFileWithName, ChunkMetadataWithFileData, Dataset, FullTextSearchResult,
-    ServerDatasetConfiguration, User, UserDTO,
+    ChunkCollection, ChunkFileWithName, ChunkMetadata, ChunkMetadataWithFileData, Dataset,
+    FullTextSearchResult, ServerDatasetConfiguration, User, UserDTO,
 };
 use crate::data::schema::{self};
 use crate::diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 use crate::errors::ServiceError;
 use crate::get_env;
 use crate::handlers::chunk_handler::{
-    ParsedQuery, ScoreChunkDTO, SearchChunkData, SearchChunkQueryResponseBody,
+    parse_query_boosts, AggSpec, AggregationResult, ChunkExplanation, ChunkNeighbors, ParsedQuery,
+    ScoreChunkDTO, ScoreChunkGroup, SearchChunkData, SearchChunkQueryResponseBody,
     SearchCollectionsData, SearchCollectionsResult,
 };
 use crate::operators::qdrant_operator::{
-    get_qdrant_connection, search_full_text_qdrant_query, search_semantic_qdrant_query,
+    acl_condition, filter_point_ids_by_acl_query, get_point_embedding_models_query,
+    get_point_vectors_query, get_qdrant_connection, qdrant_error_to_service_error,
+    search_full_text_qdrant_query, search_semantic_qdrant_query, text_vector_name,
+    with_qdrant_retry, CLIP_VECTOR_NAME,
 };
 use crate::{data::models::Pool, errors::DefaultError};
 use actix_web::web;
 use dateparser::DateTimeUtc;
-use diesel::{dsl::sql, sql_types::Text};
+use diesel::{
+    dsl::{sql, AsExprOf},
+    expression::{SqlLiteral, UncheckedBind},
+    sql_types::{Bool, Float8, Int8, Nullable, Text},
+};
 use diesel::{
     BoolExpressionMethods, JoinOnDsl, NullableExpressionMethods, PgTextExpressionMethods,
 };
 use itertools::Itertools;
 
+use chrono::NaiveDateTime;
 use qdrant_client::qdrant::condition::ConditionOneOf::HasId;
 use qdrant_client::qdrant::{
     point_id::PointIdOptions, Condition, Filter, HasIdCondition, PointId, SearchPoints,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::f32::consts::E;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,26 +54,105 @@ pub struct SearchResult {
     pub point_id: uuid::Uuid,
 }
 
+/// Orders search results by score, highest first, breaking ties by point id. Qdrant does not order
+/// points with equal scores consistently between calls, so without the tie-break a point could be
+/// shown on two pages, or on none.
+pub fn cmp_search_results(a: &SearchResult, b: &SearchResult) -> std::cmp::Ordering {
+    b.score
+        .partial_cmp(&a.score)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| a.point_id.cmp(&b.point_id))
+}
+
+/// Cuts the requested page of 10 out of every result qdrant returned up to the end of that page,
+/// ordering them with cmp_search_results first so results tied in score are paged by point id.
+pub fn page_search_results(search_results: Vec<SearchResult>, page: u64) -> Vec<SearchResult> {
+    search_results
+        .into_iter()
+        .sorted_by(cmp_search_results)
+        .skip((page.saturating_sub(1) * 10) as usize)
+        .take(10)
+        .collect()
+}
+
+/// Orders results by score, highest first, breaking ties by chunk id so that results with equal
+/// scores come back in the same order on every call.
+pub fn cmp_score_chunks(a: &ScoreChunkDTO, b: &ScoreChunkDTO) -> std::cmp::Ordering {
+    let chunk_id = |score_chunk: &ScoreChunkDTO| score_chunk.metadata.first().map(|chunk| chunk.id);
+
+    b.score
+        .partial_cmp(&a.score)
+        .unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| chunk_id(a).cmp(&chunk_id(b)))
+}
+
+type MetadataFilter = UncheckedBind<
+    SqlLiteral<Bool, UncheckedBind<SqlLiteral<Bool>, AsExprOf<String, Text>>>,
+    AsExprOf<String, Text>,
+>;
+
+/// Matches chunks whose metadata value for `key` contains `value`, ignoring case unless
+/// `case_sensitive` is set. Both the key and the value come from the request, so they are bound as
+/// parameters instead of being written into the SQL.
+fn metadata_contains_filter(key: &str, value: &str, case_sensitive: bool) -> MetadataFilter {
+    sql::<Bool>("chunk_metadata.metadata->>")
+        .bind::<Text, _>(key.to_string())
+        .sql(if case_sensitive { " LIKE " } else { " ILIKE " })
+        .bind::<Text, _>(format!("%{}%", value))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SearchchunkQueryResult {
     pub search_results: Vec<SearchResult>,
     pub total_chunk_pages: i64,
+    pub total_results: i64,
+}
+
+/// Parses one end of a created_range or updated_range, where "null" leaves that end open.
+fn parse_range_bound(bound: &str) -> Result<Option<NaiveDateTime>, DefaultError> {
+    if bound == "null" {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        bound
+            .parse::<DateTimeUtc>()
+            .map_err(|_| DefaultError {
+                message: "Failed to parse time range",
+            })?
+            .0
+            .with_timezone(&chrono::Local)
+            .naive_local(),
+    ))
 }
 
+/// A chunk matching a search's filters as its qdrant_point_id, the qdrant_point_id of the chunk it
+/// collided with, its time_stamp, its created_at and its id.
+type FilteredChunk = (
+    Option<uuid::Uuid>,
+    Option<uuid::Uuid>,
+    Option<NaiveDateTime>,
+    NaiveDateTime,
+    uuid::Uuid,
+);
+
+/// Loads the chunks matching a search's filters from Postgres, one for each qdrant point. If
+/// chunk_id is given, only that chunk is checked.
 #[allow(clippy::too_many_arguments)]
-pub async fn retrieve_qdrant_points_query(
-    embedding_vector: Option<Vec<f32>>,
-    page: u64,
+fn get_filtered_chunks_query(
     link: Option<Vec<String>>,
     tag_set: Option<Vec<String>>,
     time_range: Option<(String, String)>,
+    created_range: Option<(String, String)>,
+    updated_range: Option<(String, String)>,
+    since: Option<NaiveDateTime>,
     filters: Option<serde_json::Value>,
-    parsed_query: ParsedQuery,
+    case_sensitive_filters: Option<bool>,
+    parsed_query: &ParsedQuery,
+    chunk_id: Option<uuid::Uuid>,
     dataset_id: uuid::Uuid,
     pool: web::Data<Pool>,
-) -> Result<SearchchunkQueryResult, DefaultError> {
-    let page = if page == 0 { 1 } else { page };
-
+) -> Result<Vec<FilteredChunk>, DefaultError> {
     // TODO: Talk to Qdrant team about how to force substring match on a field instead of keyword match
     // TEMPORARY: Using postgres to qdrant_point_id's for chunks that match filter conditions
     // NOTE: Replacement function for native qdrant filters at https://gist.github.com/skeptrunedev/3ede217aa78d6462c5c52c63d0318764
@@ -80,11 +172,20 @@ pub async fn retrieve_qdrant_points_query(
                 .eq(chunk_collisions_columns::collision_qdrant_id)),
         )
         .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
+        // Expired chunks are left out even before the reaper has deleted them
+        .filter(
+            chunk_metadata_columns::expires_at
+                .is_null()
+                .or(chunk_metadata_columns::expires_at.gt(chrono::Utc::now().naive_utc())),
+        )
         .select((
             chunk_metadata_columns::qdrant_point_id,
             second_join
                 .field(schema::chunk_metadata::qdrant_point_id)
                 .nullable(),
+            chunk_metadata_columns::time_stamp,
+            chunk_metadata_columns::created_at,
+            chunk_metadata_columns::id,
         ))
         .distinct_on((
             chunk_metadata_columns::qdrant_point_id,
@@ -94,6 +195,10 @@ pub async fn retrieve_qdrant_points_query(
         ))
         .into_boxed();
 
+    if let Some(chunk_id) = chunk_id {
+        query = query.filter(chunk_metadata_columns::id.eq(chunk_id));
+    }
+
     let tag_set_inner = tag_set.unwrap_or_default();
     let link_inner = link.unwrap_or_default();
     if !tag_set_inner.is_empty() {
@@ -173,48 +278,179 @@ pub async fn retrieve_qdrant_points_query(
         }
     }
 
+    if let Some((start, end)) = created_range {
+        if let Some(start) = parse_range_bound(&start)? {
+            query = query.filter(chunk_metadata_columns::created_at.ge(start));
+        }
+        if let Some(end) = parse_range_bound(&end)? {
+            query = query.filter(chunk_metadata_columns::created_at.le(end));
+        }
+    }
+
+    if let Some((start, end)) = updated_range {
+        if let Some(start) = parse_range_bound(&start)? {
+            query = query.filter(chunk_metadata_columns::updated_at.ge(start));
+        }
+        if let Some(end) = parse_range_bound(&end)? {
+            query = query.filter(chunk_metadata_columns::updated_at.le(end));
+        }
+    }
+
+    if let Some(since) = since {
+        query = query.filter(chunk_metadata_columns::created_at.gt(since));
+    }
+
+    // Metadata filters match case-insensitively unless the caller opts into case-sensitive matching
+    let case_sensitive_filters = case_sensitive_filters.unwrap_or(false);
+    let metadata_filter =
+        |key: &str, value: &str| metadata_contains_filter(key, value, case_sensitive_filters);
+
     if let Some(serde_json::Value::Object(obj)) = &filters {
         for key in obj.keys() {
             let value = obj.get(key).expect("Value should exist");
             match value {
                 serde_json::Value::Array(arr) => {
-                    query = query.filter(
-                        sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                            .ilike(format!("%{}%", arr.first().unwrap().as_str().unwrap_or(""))),
-                    );
+                    query = query.filter(metadata_filter(
+                        key,
+                        arr.first().unwrap().as_str().unwrap_or(""),
+                    ));
                     for item in arr.iter().skip(1) {
-                        query = query.or_filter(
-                            sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                .ilike(format!("%{}%", item.as_str().unwrap_or(""))),
-                        );
+                        query = query.or_filter(metadata_filter(key, item.as_str().unwrap_or("")));
                     }
                 }
                 _ => {
-                    query = query.filter(
-                        sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                            .ilike(format!("%{}%", value.as_str().unwrap_or(""))),
-                    );
+                    query = query.filter(metadata_filter(key, value.as_str().unwrap_or("")));
                 }
             }
         }
     }
 
-    if let Some(quote_words) = parsed_query.quote_words {
+    if let Some(quote_words) = &parsed_query.quote_words {
         for word in quote_words.iter() {
-            query = query.filter(chunk_metadata_columns::content.ilike(format!("%{}%", word)));
+            query = if parsed_query.unaccented {
+                // Requires the unaccent extension, the word is already unaccented
+                query.filter(
+                    sql::<Bool>("unaccent(chunk_metadata.content) ILIKE ")
+                        .bind::<Text, _>(format!("%{}%", word)),
+                )
+            } else {
+                query.filter(chunk_metadata_columns::content.ilike(format!("%{}%", word)))
+            };
         }
     }
 
-    if let Some(negated_words) = parsed_query.negated_words {
+    if let Some(negated_words) = &parsed_query.negated_words {
         for word in negated_words.iter() {
-            query = query.filter(chunk_metadata_columns::content.not_ilike(format!("%{}%", word)));
+            query = if parsed_query.unaccented {
+                query.filter(
+                    sql::<Bool>("unaccent(chunk_metadata.content) NOT ILIKE ")
+                        .bind::<Text, _>(format!("%{}%", word)),
+                )
+            } else {
+                query.filter(chunk_metadata_columns::content.not_ilike(format!("%{}%", word)))
+            };
         }
     }
 
-    let matching_qdrant_point_ids: Vec<(Option<uuid::Uuid>, Option<uuid::Uuid>)> =
-        query.load(&mut conn).map_err(|_| DefaultError {
-            message: "Failed to load full-text searched chunks",
-        })?;
+    if let Some(prefix) = &parsed_query.prefix {
+        let content = if parsed_query.unaccented {
+            "unaccent(chunk_metadata.content)"
+        } else {
+            "chunk_metadata.content"
+        };
+        query = query.filter(
+            sql::<Bool>(&format!(
+                "to_tsvector('simple', {}) @@ to_tsquery('simple', ",
+                content
+            ))
+            .bind::<Text, _>(format!("{}:*", prefix))
+            .sql(")"),
+        );
+    }
+
+    query.load(&mut conn).map_err(|_| DefaultError {
+        message: "Failed to load full-text searched chunks",
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn retrieve_qdrant_points_query(
+    embedding_vector: Option<Vec<f32>>,
+    vector_name: Option<&str>,
+    page: u64,
+    link: Option<Vec<String>>,
+    tag_set: Option<Vec<String>>,
+    time_range: Option<(String, String)>,
+    created_range: Option<(String, String)>,
+    updated_range: Option<(String, String)>,
+    since: Option<NaiveDateTime>,
+    filters: Option<serde_json::Value>,
+    case_sensitive_filters: Option<bool>,
+    exclude_chunk_ids: Option<Vec<uuid::Uuid>>,
+    acl_groups: Option<Vec<String>>,
+    parsed_query: ParsedQuery,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<SearchchunkQueryResult, DefaultError> {
+    let page = if page == 0 { 1 } else { page };
+
+    let matching_qdrant_point_ids = get_filtered_chunks_query(
+        link,
+        tag_set,
+        time_range,
+        created_range,
+        updated_range,
+        since,
+        filters,
+        case_sensitive_filters,
+        &parsed_query,
+        None,
+        dataset_id,
+        pool.clone(),
+    )?;
+
+    let exclude_chunk_ids = exclude_chunk_ids.unwrap_or_default();
+    let excluded_point_ids = if exclude_chunk_ids.is_empty() {
+        vec![]
+    } else {
+        get_qdrant_ids_from_chunk_ids_query(exclude_chunk_ids, dataset_id, pool)?
+    };
+
+    // Without a query there is nothing to rank by, so the filtered chunks are browsed newest first
+    if embedding_vector.is_none() && parsed_query.query.trim().is_empty() {
+        let browsed_point_ids = matching_qdrant_point_ids
+            .iter()
+            .sorted_by(|a, b| {
+                b.2.cmp(&a.2)
+                    .then_with(|| b.3.cmp(&a.3))
+                    .then_with(|| a.0.cmp(&b.0))
+                    .then_with(|| a.1.cmp(&b.1))
+            })
+            .map(|uuid| uuid.0.unwrap_or(uuid.1.unwrap_or(uuid::Uuid::nil())))
+            .filter(|point_id| !excluded_point_ids.contains(point_id))
+            .unique()
+            .collect::<Vec<uuid::Uuid>>();
+        let browsed_point_ids = match acl_groups {
+            Some(acl_groups) => {
+                filter_point_ids_by_acl_query(browsed_point_ids, acl_groups).await?
+            }
+            None => browsed_point_ids,
+        };
+
+        return Ok(SearchchunkQueryResult {
+            total_chunk_pages: (browsed_point_ids.len() as f64 / 10.0).ceil() as i64,
+            total_results: browsed_point_ids.len() as i64,
+            search_results: browsed_point_ids
+                .into_iter()
+                .skip(((page - 1) * 10) as usize)
+                .take(10)
+                .map(|point_id| SearchResult {
+                    score: 0.0,
+                    point_id,
+                })
+                .collect(),
+        });
+    }
 
     let matching_point_ids: Vec<PointId> = matching_qdrant_point_ids
         .iter()
@@ -235,21 +471,459 @@ pub async fn retrieve_qdrant_points_query(
         })),
     });
 
+    if !excluded_point_ids.is_empty() {
+        filter.must_not.push(Condition {
+            condition_one_of: Some(HasId(HasIdCondition {
+                has_id: excluded_point_ids
+                    .iter()
+                    .map(|uuid| uuid.to_string().into())
+                    .collect(),
+            })),
+        });
+    }
+
+    if let Some(acl_groups) = acl_groups {
+        filter.must.push(acl_condition(acl_groups));
+    }
+
     let point_ids = if let Some(embedding_vector) = embedding_vector {
-        search_semantic_qdrant_query(page, filter, embedding_vector, dataset_id).await
+        // Text embeddings are stored under the named vector for their size
+        let vector_name = match vector_name {
+            Some(vector_name) => vector_name,
+            None => text_vector_name(embedding_vector.len())?,
+        };
+        search_semantic_qdrant_query(page, filter, embedding_vector, vector_name, dataset_id).await
     } else {
-        search_full_text_qdrant_query(page, filter, parsed_query.query, dataset_id).await
+        search_full_text_qdrant_query(
+            page,
+            filter,
+            parsed_query.query,
+            parsed_query.boosts,
+            parsed_query.splade_model,
+            dataset_id,
+        )
+        .await
     };
 
     Ok(SearchchunkQueryResult {
         search_results: point_ids?,
         total_chunk_pages: (matching_qdrant_point_ids.len() as f64 / 10.0).ceil() as i64,
+        total_results: matching_qdrant_point_ids.len() as i64,
     })
 }
 
+pub const METADATA_AGGREGATION_OPS: [&str; 4] = ["min", "max", "avg", "sum"];
+
+#[derive(diesel::QueryableByName)]
+struct MetadataValueStats {
+    #[diesel(sql_type = Nullable<Float8>)]
+    min: Option<f64>,
+    #[diesel(sql_type = Nullable<Float8>)]
+    max: Option<f64>,
+    #[diesel(sql_type = Nullable<Float8>)]
+    avg: Option<f64>,
+    #[diesel(sql_type = Nullable<Float8>)]
+    sum: Option<f64>,
+    #[diesel(sql_type = Int8)]
+    count: i64,
+}
+
+/// Computes aggregations of numeric metadata values over the chunks matching a search's filters,
+/// one chunk for each qdrant point like the search itself. Values which are neither numbers nor
+/// strings of a number are skipped rather than failing the aggregation.
+pub async fn get_metadata_aggregations_query(
+    aggregations: Vec<AggSpec>,
+    data: &SearchChunkData,
+    parsed_query: &ParsedQuery,
+    acl_groups: Option<Vec<String>>,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<AggregationResult>, DefaultError> {
+    let filtered_chunks = get_filtered_chunks_query(
+        data.link.clone(),
+        data.tag_set.clone(),
+        data.time_range.clone(),
+        data.created_range.clone(),
+        data.updated_range.clone(),
+        data.since,
+        data.filters.clone(),
+        data.case_sensitive_filters,
+        parsed_query,
+        None,
+        dataset_id,
+        pool.clone(),
+    )?;
+
+    let filtered_chunks = match acl_groups {
+        Some(acl_groups) => {
+            let allowed_point_ids = filter_point_ids_by_acl_query(
+                filtered_chunks
+                    .iter()
+                    .map(|chunk| chunk.0.unwrap_or(chunk.1.unwrap_or(uuid::Uuid::nil())))
+                    .unique()
+                    .collect(),
+                acl_groups,
+            )
+            .await?
+            .into_iter()
+            .collect::<HashSet<uuid::Uuid>>();
+
+            filtered_chunks
+                .into_iter()
+                .filter(|chunk| {
+                    allowed_point_ids
+                        .contains(&chunk.0.unwrap_or(chunk.1.unwrap_or(uuid::Uuid::nil())))
+                })
+                .collect()
+        }
+        None => filtered_chunks,
+    };
+    let chunk_ids = filtered_chunks
+        .iter()
+        .map(|chunk| chunk.4)
+        .collect::<Vec<uuid::Uuid>>();
+
+    let mut conn = pool.get().unwrap();
+    let mut stats_by_key: HashMap<String, MetadataValueStats> = HashMap::new();
+    for key in aggregations
+        .iter()
+        .map(|aggregation| &aggregation.key)
+        .unique()
+    {
+        let stats = diesel::sql_query(
+            "SELECT MIN(value) AS min, MAX(value) AS max, AVG(value) AS avg, SUM(value) AS sum, COUNT(value) AS count FROM (
+                SELECT (metadata->>$1)::float8 AS value
+                FROM chunk_metadata
+                WHERE id = ANY($2) AND metadata->>$1 ~ '^\\s*-?[0-9]+(\\.[0-9]+)?([eE][-+]?[0-9]+)?\\s*$'
+            ) AS numeric_values",
+        )
+        .bind::<Text, _>(key)
+        .bind::<diesel::sql_types::Array<diesel::sql_types::Uuid>, _>(&chunk_ids)
+        .get_result::<MetadataValueStats>(&mut conn)
+        .map_err(|_err| DefaultError {
+            message: "Failed to aggregate metadata values",
+        })?;
+
+        stats_by_key.insert(key.clone(), stats);
+    }
+
+    Ok(aggregations
+        .into_iter()
+        .map(|aggregation| {
+            let stats = stats_by_key.get(&aggregation.key);
+            let value = stats.and_then(|stats| match aggregation.op.as_str() {
+                "min" => stats.min,
+                "max" => stats.max,
+                "avg" => stats.avg,
+                _ => stats.sum,
+            });
+
+            AggregationResult {
+                count: stats.map(|stats| stats.count).unwrap_or(0),
+                key: aggregation.key,
+                op: aggregation.op,
+                value,
+            }
+        })
+        .collect())
+}
+
+/// Checks why a chunk could be missing from a search's results before anything is ranked: whether
+/// it exists, has expired, was excluded, fails any of the filters or is hidden by the ACL. Returns
+/// None if the chunk passes all of them and could be ranked.
+pub async fn explain_chunk_filters_query(
+    chunk_id: uuid::Uuid,
+    data: &SearchChunkData,
+    parsed_query: &ParsedQuery,
+    acl_groups: Option<Vec<String>>,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Option<ChunkExplanation>, DefaultError> {
+    let explanation = |status: &str, reason: &str, failed_filters: Vec<String>| {
+        Some(ChunkExplanation {
+            chunk_id,
+            status: status.to_string(),
+            reason: reason.to_string(),
+            failed_filters,
+            rank: None,
+            score: None,
+        })
+    };
+
+    let chunk = match get_metadata_from_id_query(chunk_id, dataset_id, pool.clone()) {
+        Ok(chunk) => chunk,
+        Err(_) => {
+            return Ok(explanation(
+                "not_found",
+                "The chunk does not exist in this dataset",
+                vec![],
+            ))
+        }
+    };
+
+    if chunk
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now().naive_utc())
+    {
+        return Ok(explanation(
+            "expired",
+            "The chunk has expired and is left out of every search",
+            vec![],
+        ));
+    }
+
+    if data
+        .exclude_chunk_ids
+        .as_ref()
+        .is_some_and(|exclude_chunk_ids| exclude_chunk_ids.contains(&chunk_id))
+    {
+        return Ok(explanation(
+            "excluded",
+            "The chunk is in the request's exclude_chunk_ids",
+            vec![],
+        ));
+    }
+
+    let matches_filters = |link: Option<Vec<String>>,
+                           tag_set: Option<Vec<String>>,
+                           time_range: Option<(String, String)>,
+                           created_range: Option<(String, String)>,
+                           updated_range: Option<(String, String)>,
+                           since: Option<NaiveDateTime>,
+                           filters: Option<serde_json::Value>,
+                           parsed_query: &ParsedQuery|
+     -> Result<bool, DefaultError> {
+        Ok(get_filtered_chunks_query(
+            link,
+            tag_set,
+            time_range,
+            created_range,
+            updated_range,
+            since,
+            filters,
+            data.case_sensitive_filters,
+            parsed_query,
+            Some(chunk_id),
+            dataset_id,
+            pool.clone(),
+        )?
+        .iter()
+        .any(|filtered_chunk| filtered_chunk.4 == chunk_id))
+    };
+
+    if !matches_filters(
+        data.link.clone(),
+        data.tag_set.clone(),
+        data.time_range.clone(),
+        data.created_range.clone(),
+        data.updated_range.clone(),
+        data.since,
+        data.filters.clone(),
+        parsed_query,
+    )? {
+        // Each filter is checked on its own to find the ones the chunk fails
+        let unfiltered_query = ParsedQuery {
+            query: parsed_query.query.clone(),
+            quote_words: None,
+            negated_words: None,
+            unaccented: parsed_query.unaccented,
+            prefix: None,
+            boosts: vec![],
+            splade_model: parsed_query.splade_model.clone(),
+        };
+        let mut failed_filters = vec![];
+        let filter_checks = [
+            ("link", data.link.is_some()),
+            ("tag_set", data.tag_set.is_some()),
+            ("time_range", data.time_range.is_some()),
+            ("created_range", data.created_range.is_some()),
+            ("updated_range", data.updated_range.is_some()),
+            ("since", data.since.is_some()),
+            ("filters", data.filters.is_some()),
+            ("quoted_words", parsed_query.quote_words.is_some()),
+            ("negated_words", parsed_query.negated_words.is_some()),
+            ("prefix", parsed_query.prefix.is_some()),
+        ];
+        for (filter_name, _) in filter_checks.iter().filter(|(_, is_set)| *is_set) {
+            let filter_query = ParsedQuery {
+                quote_words: parsed_query
+                    .quote_words
+                    .clone()
+                    .filter(|_| *filter_name == "quoted_words"),
+                negated_words: parsed_query
+                    .negated_words
+                    .clone()
+                    .filter(|_| *filter_name == "negated_words"),
+                prefix: parsed_query
+                    .prefix
+                    .clone()
+                    .filter(|_| *filter_name == "prefix"),
+                ..unfiltered_query.clone()
+            };
+            let matches_filter = matches_filters(
+                data.link.clone().filter(|_| *filter_name == "link"),
+                data.tag_set.clone().filter(|_| *filter_name == "tag_set"),
+                data.time_range
+                    .clone()
+                    .filter(|_| *filter_name == "time_range"),
+                data.created_range
+                    .clone()
+                    .filter(|_| *filter_name == "created_range"),
+                data.updated_range
+                    .clone()
+                    .filter(|_| *filter_name == "updated_range"),
+                data.since.filter(|_| *filter_name == "since"),
+                data.filters.clone().filter(|_| *filter_name == "filters"),
+                &filter_query,
+            )?;
+            if !matches_filter {
+                failed_filters.push(filter_name.to_string());
+            }
+        }
+
+        return Ok(explanation(
+            "filtered",
+            "The chunk does not match the request's filters, see failed_filters",
+            failed_filters,
+        ));
+    }
+
+    if let Some(acl_groups) = acl_groups {
+        let point_ids = get_qdrant_ids_from_chunk_ids_query(vec![chunk_id], dataset_id, pool)?;
+        if filter_point_ids_by_acl_query(point_ids, acl_groups)
+            .await?
+            .is_empty()
+        {
+            return Ok(explanation(
+                "acl",
+                "The chunk is not visible to the requesting user's groups",
+                vec![],
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Explains where a chunk which passed the filters of a search ended up: on the returned page, either
+/// as a result or as a collision shown with the result it collided with, or ranked outside of it.
+pub fn explain_chunk_ranking(
+    chunk_id: uuid::Uuid,
+    score_chunks: &[ScoreChunkDTO],
+) -> ChunkExplanation {
+    let result_position = score_chunks.iter().position(|score_chunk| {
+        score_chunk
+            .metadata
+            .iter()
+            .any(|chunk| chunk.id == chunk_id)
+    });
+
+    match result_position {
+        Some(position) => {
+            let is_collision = score_chunks[position]
+                .metadata
+                .first()
+                .is_some_and(|chunk| chunk.id != chunk_id);
+
+            ChunkExplanation {
+                chunk_id,
+                status: "in_results".to_string(),
+                reason: if is_collision {
+                    "The chunk is on the returned page as a collision of the result's chunk"
+                        .to_string()
+                } else {
+                    "The chunk is on the returned page".to_string()
+                },
+                failed_filters: vec![],
+                rank: Some(position + 1),
+                score: Some(score_chunks[position].score),
+            }
+        }
+        None => ChunkExplanation {
+            chunk_id,
+            status: "not_in_page".to_string(),
+            reason: "The chunk matches the filters but did not rank high enough for the returned page, it may be on a later page".to_string(),
+            failed_filters: vec![],
+            rank: None,
+            score: None,
+        },
+    }
+}
+
+/// Builds the conditions which limit a collision check to the new chunk's COLLISION_SCOPE. The
+/// scope is "global", "tag_set", "file" or "metadata.<key>". A chunk without a value for its
+/// dataset's scope, e.g. one without a file under the "file" scope, is checked globally.
+pub async fn get_collision_scope_conditions(
+    collision_scope: &str,
+    tag_set: Option<String>,
+    metadata: Option<serde_json::Value>,
+    file_uuid: Option<uuid::Uuid>,
+    dataset_id: uuid::Uuid,
+    pool: web::Data<Pool>,
+) -> Result<Vec<Condition>, ServiceError> {
+    let conditions = match collision_scope {
+        "global" => vec![],
+        "tag_set" => tag_set
+            .unwrap_or_default()
+            .split(',')
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| Condition::matches("tag_set", tag.to_string()))
+            .collect(),
+        "file" => match file_uuid {
+            Some(file_uuid) => {
+                let point_ids =
+                    web::block(move || get_point_ids_for_file_query(file_uuid, dataset_id, pool))
+                        .await
+                        .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+                        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+                vec![Condition {
+                    condition_one_of: Some(HasId(HasIdCondition {
+                        has_id: point_ids
+                            .iter()
+                            .map(|point_id| point_id.to_string().into())
+                            .collect(),
+                    })),
+                }]
+            }
+            None => vec![],
+        },
+        scope => {
+            let Some(key) = scope
+                .strip_prefix("metadata.")
+                .filter(|key| !key.is_empty())
+            else {
+                return Err(ServiceError::BadRequest(format!(
+                    "Invalid COLLISION_SCOPE: {}. Must be global, tag_set, file or metadata.<key>",
+                    scope
+                )));
+            };
+            let field = format!("metadata.{}", key);
+
+            match metadata.as_ref().and_then(|metadata| metadata.get(key)) {
+                Some(serde_json::Value::String(value)) => {
+                    vec![Condition::matches(field, value.clone())]
+                }
+                Some(serde_json::Value::Bool(value)) => vec![Condition::matches(field, *value)],
+                Some(serde_json::Value::Number(value)) if value.is_i64() => {
+                    vec![Condition::matches(
+                        field,
+                        value.as_i64().unwrap_or_default(),
+                    )]
+                }
+                _ => vec![],
+            }
+        }
+    };
+
+    Ok(conditions)
+}
+
 pub async fn global_unfiltered_top_match_query(
     embedding_vector: Vec<f32>,
     dataset_id: uuid::Uuid,
+    scope_conditions: Vec<Condition>,
 ) -> Result<SearchResult, DefaultError> {
     let qdrant = get_qdrant_connection().await?;
 
@@ -263,36 +937,23 @@ pub async fn global_unfiltered_top_match_query(
     dataset_filter
         .must
         .push(Condition::matches("dataset_id", dataset_id.to_string()));
-
-    let vector_name = match embedding_vector.len() {
-        384 => "384_vectors",
-        768 => "768_vectors",
-        1024 => "1024_vectors",
-        1536 => "1536_vectors",
-        _ => {
-            return Err(DefaultError {
-                message: "Invalid embedding vector size",
-            })
-        }
+    dataset_filter.must.extend(scope_conditions);
+
+    let vector_name = text_vector_name(embedding_vector.len())?;
+
+    let search_points = SearchPoints {
+        collection_name: qdrant_collection,
+        vector: embedding_vector,
+        vector_name: Some(vector_name.to_string()),
+        limit: 1,
+        with_payload: None,
+        filter: Some(dataset_filter),
+        ..Default::default()
     };
 
-    let data = qdrant
-        .search_points(&SearchPoints {
-            collection_name: qdrant_collection,
-            vector: embedding_vector,
-            vector_name: Some(vector_name.to_string()),
-            limit: 1,
-            with_payload: None,
-            filter: Some(dataset_filter),
-            ..Default::default()
-        })
+    let data = with_qdrant_retry(|| qdrant.search_points(&search_points))
         .await
-        .map_err(|e| {
-            log::error!("Failed to search points on Qdrant {:?}", e);
-            DefaultError {
-                message: "Failed to search points on Qdrant",
-            }
-        })?;
+        .map_err(|err| err.into_default_error("Failed to search points on Qdrant"))?;
 
     let top_search_result: SearchResult = match data.result.first() {
         Some(point) => match point.clone().id {
@@ -330,6 +991,88 @@ pub async fn global_unfiltered_top_match_query(
     Ok(top_search_result)
 }
 
+const RECENT_TOP_MATCH_CANDIDATES: u64 = 10;
+
+/// Like global_unfiltered_top_match_query, but only matches chunks whose root was created or
+/// updated within the window. The nearest candidates scoring at least score_threshold are checked
+/// in order, so a stale near-duplicate does not hide a more recent one right behind it. Returns a
+/// nil point with a score of 0 if no candidate is recent enough.
+pub async fn recent_top_match_query(
+    embedding_vector: Vec<f32>,
+    dataset_id: uuid::Uuid,
+    score_threshold: f32,
+    window: chrono::Duration,
+    scope_conditions: Vec<Condition>,
+    pool: web::Data<Pool>,
+) -> Result<SearchResult, DefaultError> {
+    let qdrant = get_qdrant_connection().await?;
+
+    let qdrant_collection = get_env!(
+        "QDRANT_COLLECTION",
+        "QDRANT_COLLECTION should be set if this is called"
+    )
+    .to_string();
+
+    let mut dataset_filter = Filter::default();
+    dataset_filter
+        .must
+        .push(Condition::matches("dataset_id", dataset_id.to_string()));
+    dataset_filter.must.extend(scope_conditions);
+
+    let vector_name = text_vector_name(embedding_vector.len())?;
+
+    let search_points = SearchPoints {
+        collection_name: qdrant_collection,
+        vector: embedding_vector,
+        vector_name: Some(vector_name.to_string()),
+        limit: RECENT_TOP_MATCH_CANDIDATES,
+        with_payload: None,
+        filter: Some(dataset_filter),
+        score_threshold: Some(score_threshold),
+        ..Default::default()
+    };
+
+    let data = with_qdrant_retry(|| qdrant.search_points(&search_points))
+        .await
+        .map_err(|err| err.into_default_error("Failed to search points on Qdrant"))?;
+
+    let candidates = data
+        .result
+        .iter()
+        .filter_map(|point| match point.id.clone()?.point_id_options? {
+            PointIdOptions::Uuid(id) => Some(SearchResult {
+                score: point.score,
+                point_id: uuid::Uuid::parse_str(&id).ok()?,
+            }),
+            PointIdOptions::Num(_) => None,
+        })
+        .collect::<Vec<SearchResult>>();
+
+    let point_ids = candidates
+        .iter()
+        .map(|candidate| candidate.point_id)
+        .collect::<Vec<uuid::Uuid>>();
+    let candidate_chunks = web::block(move || get_metadata_from_point_ids(point_ids, pool))
+        .await
+        .map_err(|_| DefaultError {
+            message: "Failed to load metadata",
+        })??;
+
+    let oldest_allowed = chrono::Utc::now().naive_local() - window;
+
+    Ok(candidates
+        .into_iter()
+        .find(|candidate| {
+            candidate_chunks.iter().any(|chunk| {
+                chunk.qdrant_point_id == candidate.point_id && chunk.updated_at >= oldest_allowed
+            })
+        })
+        .unwrap_or(SearchResult {
+            score: 0.0,
+            point_id: uuid::Uuid::nil(),
+        }))
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn search_chunk_collections_query(
     embedding_vector: Vec<f32>,
@@ -338,9 +1081,11 @@ pub async fn search_chunk_collections_query(
     link: Option<Vec<String>>,
     tag_set: Option<Vec<String>>,
     filters: Option<serde_json::Value>,
-    collection_id: uuid::Uuid,
+    case_sensitive_filters: Option<bool>,
+    collection_ids: Vec<uuid::Uuid>,
     dataset_id: uuid::Uuid,
     parsed_query: ParsedQuery,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<SearchchunkQueryResult, DefaultError> {
     let page = if page == 0 { 1 } else { page };
     use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
@@ -358,7 +1103,7 @@ pub async fn search_chunk_collections_query(
             chunk_collection_bookmarks_columns::chunk_collection_bookmarks.on(
                 chunk_metadata_columns::id
                     .eq(chunk_collection_bookmarks_columns::chunk_metadata_id)
-                    .and(chunk_collection_bookmarks_columns::collection_id.eq(collection_id)),
+                    .and(chunk_collection_bookmarks_columns::collection_id.eq_any(&collection_ids)),
             ),
         )
         .select((
@@ -366,7 +1111,12 @@ pub async fn search_chunk_collections_query(
             chunk_collisions_columns::collision_qdrant_id.nullable(),
         ))
         .filter(chunk_metadata_columns::dataset_id.eq(dataset_id))
-        .filter(chunk_collection_bookmarks_columns::collection_id.eq(collection_id))
+        .filter(
+            chunk_metadata_columns::expires_at
+                .is_null()
+                .or(chunk_metadata_columns::expires_at.gt(chrono::Utc::now().naive_utc())),
+        )
+        .filter(chunk_collection_bookmarks_columns::collection_id.eq_any(&collection_ids))
         .distinct()
         .into_boxed();
     let tag_set_inner = tag_set.unwrap_or_default();
@@ -393,27 +1143,30 @@ pub async fn search_chunk_collections_query(
                     serde_json::Value::Array(arr) => {
                         if let Some(first_val) = arr.first() {
                             if let Some(string_val) = first_val.as_str() {
-                                query = query.filter(
-                                    sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                        .ilike(format!("%{}%", string_val)),
-                                );
+                                query = query.filter(metadata_contains_filter(
+                                    key,
+                                    string_val,
+                                    case_sensitive_filters.unwrap_or(false),
+                                ));
                             }
                         }
 
                         for item in arr.iter().skip(1) {
                             if let Some(string_val) = item.as_str() {
-                                query = query.or_filter(
-                                    sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                        .ilike(format!("%{}%", string_val)),
-                                );
+                                query = query.or_filter(metadata_contains_filter(
+                                    key,
+                                    string_val,
+                                    case_sensitive_filters.unwrap_or(false),
+                                ));
                             }
                         }
                     }
                     serde_json::Value::String(string_val) => {
-                        query = query.filter(
-                            sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                .ilike(format!("%{}%", string_val)),
-                        );
+                        query = query.filter(metadata_contains_filter(
+                            key,
+                            string_val,
+                            case_sensitive_filters.unwrap_or(false),
+                        ));
                     }
                     _ => (),
                 }
@@ -423,13 +1176,27 @@ pub async fn search_chunk_collections_query(
 
     if let Some(quote_words) = parsed_query.quote_words {
         for word in quote_words.iter() {
-            query = query.filter(chunk_metadata_columns::content.ilike(format!("%{}%", word)));
+            query = if parsed_query.unaccented {
+                query.filter(
+                    sql::<Bool>("unaccent(chunk_metadata.content) ILIKE ")
+                        .bind::<Text, _>(format!("%{}%", word)),
+                )
+            } else {
+                query.filter(chunk_metadata_columns::content.ilike(format!("%{}%", word)))
+            };
         }
     }
 
     if let Some(negated_words) = parsed_query.negated_words {
         for word in negated_words.iter() {
-            query = query.filter(chunk_metadata_columns::content.not_ilike(format!("%{}%", word)));
+            query = if parsed_query.unaccented {
+                query.filter(
+                    sql::<Bool>("unaccent(chunk_metadata.content) NOT ILIKE ")
+                        .bind::<Text, _>(format!("%{}%", word)),
+                )
+            } else {
+                query.filter(chunk_metadata_columns::content.not_ilike(format!("%{}%", word)))
+            };
         }
     }
 
@@ -457,13 +1224,19 @@ pub async fn search_chunk_collections_query(
             has_id: (filtered_point_ids).to_vec(),
         })),
     });
+    if let Some(acl_groups) = acl_groups {
+        filter.must.push(acl_condition(acl_groups));
+    }
 
+    let vector_name = text_vector_name(embedding_vector.len())?;
     let point_ids: Vec<SearchResult> =
-        search_semantic_qdrant_query(page, filter, embedding_vector, dataset_id).await?;
+        search_semantic_qdrant_query(page, filter, embedding_vector, vector_name, dataset_id)
+            .await?;
 
     Ok(SearchchunkQueryResult {
         search_results: point_ids,
         total_chunk_pages: (filtered_option_ids.len() as f64 / 10.0).ceil() as i64,
+        total_results: filtered_option_ids.len() as i64,
     })
 }
 
@@ -611,11 +1384,13 @@ pub async fn search_full_text_collection_query(
     page: u64,
     pool: web::Data<Pool>,
     filters: Option<serde_json::Value>,
+    case_sensitive_filters: Option<bool>,
     link: Option<Vec<String>>,
     tag_set: Option<Vec<String>>,
-    collection_id: uuid::Uuid,
+    collection_ids: Vec<uuid::Uuid>,
     parsed_query: ParsedQuery,
     dataset_uuid: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<SearchchunkQueryResult, DefaultError> {
     let page = if page == 0 { 1 } else { page };
     use crate::data::schema::chunk_collection_bookmarks::dsl as chunk_collection_bookmarks_columns;
@@ -653,16 +1428,23 @@ pub async fn search_full_text_collection_query(
             chunk_collection_bookmarks_columns::chunk_collection_bookmarks.on(
                 chunk_metadata_columns::id
                     .eq(chunk_collection_bookmarks_columns::chunk_metadata_id)
-                    .and(chunk_collection_bookmarks_columns::collection_id.eq(collection_id)),
+                    .and(chunk_collection_bookmarks_columns::collection_id.eq_any(&collection_ids)),
             ),
         )
-        .filter(chunk_collection_bookmarks_columns::collection_id.eq(collection_id))
+        .filter(chunk_collection_bookmarks_columns::collection_id.eq_any(&collection_ids))
         .filter(chunk_metadata_columns::dataset_id.eq(dataset_uuid))
+        .filter(
+            chunk_metadata_columns::expires_at
+                .is_null()
+                .or(chunk_metadata_columns::expires_at.gt(chrono::Utc::now().naive_utc())),
+        )
         .select((
             chunk_metadata_columns::qdrant_point_id,
             second_join
                 .field(schema::chunk_metadata::qdrant_point_id)
                 .nullable(),
+            chunk_metadata_columns::time_stamp,
+            chunk_metadata_columns::created_at,
         ))
         .distinct_on((
             chunk_metadata_columns::qdrant_point_id,
@@ -696,27 +1478,30 @@ pub async fn search_full_text_collection_query(
                     serde_json::Value::Array(arr) => {
                         if let Some(first_val) = arr.first() {
                             if let Some(string_val) = first_val.as_str() {
-                                query = query.filter(
-                                    sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                        .ilike(format!("%{}%", string_val)),
-                                );
+                                query = query.filter(metadata_contains_filter(
+                                    key,
+                                    string_val,
+                                    case_sensitive_filters.unwrap_or(false),
+                                ));
                             }
                         }
 
                         for item in arr.iter().skip(1) {
                             if let Some(string_val) = item.as_str() {
-                                query = query.or_filter(
-                                    sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                        .ilike(format!("%{}%", string_val)),
-                                );
+                                query = query.or_filter(metadata_contains_filter(
+                                    key,
+                                    string_val,
+                                    case_sensitive_filters.unwrap_or(false),
+                                ));
                             }
                         }
                     }
                     serde_json::Value::String(string_val) => {
-                        query = query.filter(
-                            sql::<Text>(&format!("chunk_metadata.metadata->>'{}'", key))
-                                .ilike(format!("%{}%", string_val)),
-                        );
+                        query = query.filter(metadata_contains_filter(
+                            key,
+                            string_val,
+                            case_sensitive_filters.unwrap_or(false),
+                        ));
                     }
                     _ => (),
                 }
@@ -726,13 +1511,27 @@ pub async fn search_full_text_collection_query(
 
     if let Some(quote_words) = parsed_query.quote_words {
         for word in quote_words.iter() {
-            query = query.filter(chunk_metadata_columns::content.ilike(format!("%{}%", word)));
+            query = if parsed_query.unaccented {
+                query.filter(
+                    sql::<Bool>("unaccent(chunk_metadata.content) ILIKE ")
+                        .bind::<Text, _>(format!("%{}%", word)),
+                )
+            } else {
+                query.filter(chunk_metadata_columns::content.ilike(format!("%{}%", word)))
+            };
         }
     }
 
     if let Some(negated_words) = parsed_query.negated_words {
         for word in negated_words.iter() {
-            query = query.filter(chunk_metadata_columns::content.not_ilike(format!("%{}%", word)));
+            query = if parsed_query.unaccented {
+                query.filter(
+                    sql::<Bool>("unaccent(chunk_metadata.content) NOT ILIKE ")
+                        .bind::<Text, _>(format!("%{}%", word)),
+                )
+            } else {
+                query.filter(chunk_metadata_columns::content.not_ilike(format!("%{}%", word)))
+            };
         }
     }
 
@@ -764,12 +1563,24 @@ pub async fn search_full_text_collection_query(
             has_id: (matching_point_ids).to_vec(),
         })),
     });
+    if let Some(acl_groups) = acl_groups {
+        filter.must.push(acl_condition(acl_groups));
+    }
 
-    let point_ids = search_full_text_qdrant_query(page, filter, user_query, dataset_uuid).await;
+    let point_ids = search_full_text_qdrant_query(
+        page,
+        filter,
+        user_query,
+        parsed_query.boosts,
+        parsed_query.splade_model,
+        dataset_uuid,
+    )
+    .await;
 
     Ok(SearchchunkQueryResult {
         search_results: point_ids?,
         total_chunk_pages: (matching_qdrant_point_ids.len() as f64 / 10.0).ceil() as i64,
+        total_results: matching_qdrant_point_ids.len() as i64,
     })
 }
 
@@ -798,26 +1609,19 @@ pub async fn retrieve_chunks_from_point_ids(
                 .find(|metadata_chunk| metadata_chunk.qdrant_point_id == search_result.point_id)
             {
                 Some(metadata_chunk) => metadata_chunk.clone(),
-                None => ChunkMetadataWithFileData {
-                    id: uuid::Uuid::default(),
-                    author: None,
-                    qdrant_point_id: uuid::Uuid::default(),
-                    created_at: chrono::Utc::now().naive_local(),
-                    updated_at: chrono::Utc::now().naive_local(),
-                    file_id: None,
-                    file_name: None,
-                    content: "".to_string(),
-                    chunk_html: Some("".to_string()),
-                    link: Some("".to_string()),
-                    tag_set: Some("".to_string()),
-                    metadata: None,
-                    tracking_id: None,
-                    time_stamp: None,
-                    weight: 1.0,
-                },
+                None => ChunkMetadataWithFileData::placeholder(search_result.point_id),
             };
 
-            chunk = find_relevant_sentence(chunk.clone(), data.query.clone()).unwrap_or(chunk);
+            // Semantic highlights are added once the page of results is known
+            if data.highlight_strategy.as_deref() != Some("semantic") {
+                chunk = find_relevant_sentence(
+                    chunk.clone(),
+                    parse_query_boosts(&data.query.primary()).0,
+                    data.highlight_max_num,
+                    data.highlight_window,
+                )
+                .unwrap_or(chunk);
+            }
             let mut collided_chunks: Vec<ChunkMetadataWithFileData> = collided_chunks
                 .iter()
                 .filter(|chunk| chunk.qdrant_id == search_result.point_id)
@@ -826,16 +1630,16 @@ pub async fn retrieve_chunks_from_point_ids(
 
             collided_chunks.insert(0, chunk);
 
-            ScoreChunkDTO {
-                metadata: collided_chunks,
-                score: search_result.score.into(),
-            }
+            ScoreChunkDTO::new(collided_chunks, search_result.score.into())
         })
         .collect();
-    Ok(SearchChunkQueryResponseBody {
+    let page = data.page.unwrap_or(1).max(1);
+    Ok(SearchChunkQueryResponseBody::new(
         score_chunks,
-        total_chunk_pages: search_chunk_query_results.total_chunk_pages,
-    })
+        search_chunk_query_results.total_chunk_pages,
+        search_chunk_query_results.total_results,
+        page,
+    ))
 }
 
 pub fn rerank_chunks(chunks: Vec<ScoreChunkDTO>, date_bias: Option<bool>) -> Vec<ScoreChunkDTO> {
@@ -857,56 +1661,383 @@ pub fn rerank_chunks(chunks: Vec<ScoreChunkDTO>, date_bias: Option<bool>) -> Vec
         });
     }
 
-    reranked_chunks.sort_by(|a, b| {
-        b.score
-            .partial_cmp(&a.score)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
+    reranked_chunks.sort_by(cmp_score_chunks);
 
     reranked_chunks
 }
 
-pub async fn search_semantic_chunks(
-    data: web::Json<SearchChunkData>,
-    parsed_query: ParsedQuery,
-    page: u64,
-    pool: web::Data<Pool>,
-    dataset: Dataset,
-) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
-    let embedding_vector = create_embedding(
-        &data.query,
-        ServerDatasetConfiguration::from_json(dataset.server_configuration.clone()),
+/// Multiplies each result's score by a boost from a numeric metadata field such as view counts. Values
+/// are log-scaled against the largest value on the page, so the boost ranges from 1 for a value of 0
+/// to 2 for the largest value. Results without a numeric value for the field keep their score.
+pub fn boost_by_metadata_field(
+    score_chunks: Vec<ScoreChunkDTO>,
+    boost_field: &str,
+) -> Vec<ScoreChunkDTO> {
+    let boost_values = score_chunks
+        .iter()
+        .map(|score_chunk| {
+            score_chunk
+                .metadata
+                .first()
+                .and_then(|chunk| chunk.metadata.as_ref())
+                .and_then(|metadata| metadata.get(boost_field))
+                .and_then(|value| match value {
+                    serde_json::Value::Number(value) => value.as_f64(),
+                    serde_json::Value::String(value) => value.trim().parse::<f64>().ok(),
+                    _ => None,
+                })
+                .filter(|value| value.is_finite())
+                .map(|value| value.max(0.0).ln_1p())
+        })
+        .collect::<Vec<Option<f64>>>();
+    let max_boost_value = boost_values
+        .iter()
+        .flatten()
+        .fold(0.0_f64, |max, value| max.max(*value));
+
+    let mut boosted_chunks = score_chunks
+        .into_iter()
+        .zip(boost_values)
+        .map(|(mut score_chunk, boost_value)| {
+            if let Some(boost_value) = boost_value {
+                if max_boost_value > 0.0 {
+                    score_chunk.score *= 1.0 + boost_value / max_boost_value;
+                }
+            }
+            score_chunk
+        })
+        .collect::<Vec<ScoreChunkDTO>>();
+
+    boosted_chunks.sort_by(cmp_score_chunks);
+
+    boosted_chunks
+}
+
+/// Re-orders the results with maximal marginal relevance so that near duplicates do not crowd the
+/// page. Results are picked greedily by `(1 - diversity) * relevance - diversity * similarity`, where
+/// relevance is the score min-max normalized over the page and similarity is the largest cosine
+/// similarity to an already picked result. Scores are left as they were. Results whose point has no
+/// vector are treated as dissimilar to everything.
+pub async fn diversify_score_chunks(
+    score_chunks: Vec<ScoreChunkDTO>,
+    diversity: f32,
+    vector_name: &str,
+) -> Result<Vec<ScoreChunkDTO>, ServiceError> {
+    if diversity <= 0.0 || score_chunks.len() < 2 {
+        return Ok(score_chunks);
+    }
+
+    let point_ids = score_chunks
+        .iter()
+        .filter_map(|score_chunk| score_chunk.metadata.first())
+        .map(|chunk| chunk.qdrant_point_id)
+        .unique()
+        .collect::<Vec<uuid::Uuid>>();
+    let vectors = get_point_vectors_query(point_ids, vector_name)
+        .await
+        .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    let (min_score, max_score) = score_chunks.iter().fold(
+        (f64::INFINITY, f64::NEG_INFINITY),
+        |(min, max), score_chunk| (min.min(score_chunk.score), max.max(score_chunk.score)),
+    );
+    let score_range = max_score - min_score;
+    let diversity = diversity as f64;
+
+    let mut candidates = score_chunks
+        .into_iter()
+        .map(|score_chunk| {
+            let relevance = if score_range > 0.0 {
+                (score_chunk.score - min_score) / score_range
+            } else {
+                1.0
+            };
+            let vector = score_chunk
+                .metadata
+                .first()
+                .and_then(|chunk| vectors.get(&chunk.qdrant_point_id));
+            (score_chunk, relevance, vector)
+        })
+        .collect::<Vec<(ScoreChunkDTO, f64, Option<&Vec<f32>>)>>();
+
+    let mut selected_vectors: Vec<&Vec<f32>> = vec![];
+    let mut diversified_chunks = Vec::with_capacity(candidates.len());
+    while !candidates.is_empty() {
+        let mmr_scores = candidates.iter().map(|(_, relevance, vector)| {
+            let max_similarity = vector
+                .map(|vector| {
+                    selected_vectors
+                        .iter()
+                        .map(|selected| cosine_similarity(vector, selected) as f64)
+                        .fold(0.0_f64, f64::max)
+                })
+                .unwrap_or(0.0);
+            (1.0 - diversity) * relevance - diversity * max_similarity
+        });
+        let (best_index, _) =
+            mmr_scores
+                .enumerate()
+                .fold((0, f64::NEG_INFINITY), |best, (index, mmr_score)| {
+                    if mmr_score > best.1 {
+                        (index, mmr_score)
+                    } else {
+                        best
+                    }
+                });
+
+        let (score_chunk, _, vector) = candidates.remove(best_index);
+        if let Some(vector) = vector {
+            selected_vectors.push(vector);
+        }
+        diversified_chunks.push(score_chunk);
+    }
+
+    Ok(diversified_chunks)
+}
+
+/// Embeds each of the request's queries and runs a qdrant search per vector. When there are several
+/// queries the results are merged by keeping each point's best score across the searches.
+async fn retrieve_semantic_qdrant_points(
+    data: &web::Json<SearchChunkData>,
+    parsed_query: ParsedQuery,
+    page: u64,
+    pool: web::Data<Pool>,
+    dataset: &Dataset,
+    acl_groups: Option<Vec<String>>,
+) -> Result<(SearchchunkQueryResult, Vec<f32>), actix_web::Error> {
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset.server_configuration.clone());
+    let queries = data.query.all();
+
+    let embedding_vectors =
+        futures::future::try_join_all(queries.iter().map(|query| {
+            create_query_embedding(&parse_query_boosts(query).0, dataset_config.clone())
+        }))
+        .await?;
+    let query_vector = embedding_vectors.first().cloned().unwrap_or_default();
+
+    let mut search_chunk_query_results =
+        futures::future::try_join_all(embedding_vectors.into_iter().map(|embedding_vector| {
+            retrieve_qdrant_points_query(
+                Some(embedding_vector),
+                None,
+                page,
+                data.link.clone(),
+                data.tag_set.clone(),
+                data.time_range.clone(),
+                data.created_range.clone(),
+                data.updated_range.clone(),
+                data.since,
+                data.filters.clone(),
+                data.case_sensitive_filters,
+                data.exclude_chunk_ids.clone(),
+                acl_groups.clone(),
+                parsed_query.clone(),
+                dataset.id,
+                pool.clone(),
+            )
+        }))
+        .await
+        .map_err(qdrant_error_to_service_error)?;
+
+    if search_chunk_query_results.len() == 1 {
+        return Ok((search_chunk_query_results.remove(0), query_vector));
+    }
+
+    let total_chunk_pages = search_chunk_query_results
+        .iter()
+        .map(|results| results.total_chunk_pages)
+        .max()
+        .unwrap_or(0);
+    let total_results = search_chunk_query_results
+        .iter()
+        .map(|results| results.total_results)
+        .max()
+        .unwrap_or(0);
+
+    let mut best_score_by_point_id: HashMap<uuid::Uuid, f32> = HashMap::new();
+    for search_result in search_chunk_query_results
+        .into_iter()
+        .flat_map(|results| results.search_results)
+    {
+        let best_score = best_score_by_point_id
+            .entry(search_result.point_id)
+            .or_insert(search_result.score);
+        if search_result.score > *best_score {
+            *best_score = search_result.score;
+        }
+    }
+
+    let mut search_results = best_score_by_point_id
+        .into_iter()
+        .map(|(point_id, score)| SearchResult { score, point_id })
+        .collect::<Vec<SearchResult>>();
+    search_results.sort_by(cmp_search_results);
+    search_results.truncate(10);
+
+    Ok((
+        SearchchunkQueryResult {
+            search_results,
+            total_chunk_pages,
+            total_results,
+        },
+        query_vector,
+    ))
+}
+
+pub async fn search_semantic_chunks(
+    data: web::Json<SearchChunkData>,
+    parsed_query: ParsedQuery,
+    page: u64,
+    pool: web::Data<Pool>,
+    dataset: Dataset,
+    acl_groups: Option<Vec<String>>,
+) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
+    let (search_chunk_query_results, query_vector) = retrieve_semantic_qdrant_points(
+        &data,
+        parsed_query,
+        page,
+        pool.clone(),
+        &dataset,
+        acl_groups,
     )
     .await?;
 
+    let mut result_chunks =
+        retrieve_chunks_from_point_ids(search_chunk_query_results, &data, pool.clone()).await?;
+
+    result_chunks.score_chunks = rerank_chunks(result_chunks.score_chunks, data.date_bias);
+    if data.include_query_vector.unwrap_or(false)
+        || data.highlight_strategy.as_deref() == Some("semantic")
+    {
+        result_chunks.query_vector = Some(query_vector);
+    }
+
+    Ok(result_chunks)
+}
+
+/// Returns the chunks matching the request's filters ordered by time_stamp, newest first, for
+/// requests without a query. Each result is given a score of 0 as there is no relevance to rank by.
+pub async fn browse_chunks(
+    data: web::Json<SearchChunkData>,
+    parsed_query: ParsedQuery,
+    page: u64,
+    pool: web::Data<Pool>,
+    dataset_id: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
+) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
     let search_chunk_query_results = retrieve_qdrant_points_query(
-        Some(embedding_vector),
+        None,
+        None,
+        page,
+        data.link.clone(),
+        data.tag_set.clone(),
+        data.time_range.clone(),
+        data.created_range.clone(),
+        data.updated_range.clone(),
+        data.since,
+        data.filters.clone(),
+        data.case_sensitive_filters,
+        data.exclude_chunk_ids.clone(),
+        acl_groups.clone(),
+        parsed_query,
+        dataset_id,
+        pool.clone(),
+    )
+    .await
+    .map_err(qdrant_error_to_service_error)?;
+
+    retrieve_chunks_from_point_ids(search_chunk_query_results, &data, pool).await
+}
+
+/// Searches the CLIP image embeddings of the dataset's chunks with a CLIP text embedding of the
+/// query. Only chunks created with image_urls while IMAGE_EMBEDDING_ENABLED was set can be found.
+pub async fn search_image_chunks(
+    data: web::Json<SearchChunkData>,
+    parsed_query: ParsedQuery,
+    page: u64,
+    pool: web::Data<Pool>,
+    dataset: Dataset,
+    acl_groups: Option<Vec<String>>,
+) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
+    if !ServerDatasetConfiguration::from_json(dataset.server_configuration.clone())
+        .IMAGE_EMBEDDING_ENABLED
+        .unwrap_or(false)
+    {
+        return Err(ServiceError::BadRequest(
+            "Image search requires IMAGE_EMBEDDING_ENABLED to be set for the dataset".into(),
+        )
+        .into());
+    }
+
+    let clip_vector =
+        get_clip_embedding(vec![parse_query_boosts(&data.query.primary()).0], "text").await?;
+
+    let query_vector = clip_vector.clone();
+    let search_chunk_query_results = retrieve_qdrant_points_query(
+        Some(clip_vector),
+        Some(CLIP_VECTOR_NAME),
         page,
         data.link.clone(),
         data.tag_set.clone(),
         data.time_range.clone(),
+        data.created_range.clone(),
+        data.updated_range.clone(),
+        data.since,
         data.filters.clone(),
+        data.case_sensitive_filters,
+        data.exclude_chunk_ids.clone(),
+        acl_groups.clone(),
         parsed_query,
         dataset.id,
         pool.clone(),
     )
     .await
-    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    .map_err(qdrant_error_to_service_error)?;
 
     let mut result_chunks =
         retrieve_chunks_from_point_ids(search_chunk_query_results, &data, pool.clone()).await?;
 
     result_chunks.score_chunks = rerank_chunks(result_chunks.score_chunks, data.date_bias);
+    if data.include_query_vector.unwrap_or(false) {
+        result_chunks.query_vector = Some(query_vector);
+    }
 
     Ok(result_chunks)
 }
 
 pub async fn search_full_text_chunks(
+    data: web::Json<SearchChunkData>,
+    parsed_query: ParsedQuery,
+    page: u64,
+    pool: web::Data<Pool>,
+    dataset_id: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
+) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
+    let mut result_chunks = retrieve_full_text_chunks(
+        web::Json(data.clone()),
+        parsed_query,
+        page,
+        pool,
+        dataset_id,
+        acl_groups,
+    )
+    .await?;
+
+    result_chunks.score_chunks = rerank_chunks(result_chunks.score_chunks, data.date_bias);
+
+    Ok(result_chunks)
+}
+
+/// Runs the full-text retrieval without applying chunk weights or date bias so that callers
+/// which fuse several result sets only apply `rerank_chunks` once.
+async fn retrieve_full_text_chunks(
     data: web::Json<SearchChunkData>,
     mut parsed_query: ParsedQuery,
     page: u64,
     pool: web::Data<Pool>,
     dataset_id: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
     parsed_query.query = parsed_query
         .query
@@ -915,34 +2046,38 @@ pub async fn search_full_text_chunks(
         .replace('\"', "");
 
     let search_chunk_query_results = retrieve_qdrant_points_query(
+        None,
         None,
         page,
         data.link.clone(),
         data.tag_set.clone(),
         data.time_range.clone(),
+        data.created_range.clone(),
+        data.updated_range.clone(),
+        data.since,
         data.filters.clone(),
+        data.case_sensitive_filters,
+        data.exclude_chunk_ids.clone(),
+        acl_groups.clone(),
         parsed_query,
         dataset_id,
         pool.clone(),
     )
     .await
-    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    .map_err(qdrant_error_to_service_error)?;
 
-    let mut result_chunks =
-        retrieve_chunks_from_point_ids(search_chunk_query_results, &data, pool).await?;
-
-    result_chunks.score_chunks = rerank_chunks(result_chunks.score_chunks, data.date_bias);
-
-    Ok(result_chunks)
+    retrieve_chunks_from_point_ids(search_chunk_query_results, &data, pool).await
 }
 
 fn reciprocal_rank_fusion(
     semantic_results: Vec<ScoreChunkDTO>,
     full_text_results: Vec<ScoreChunkDTO>,
     weights: Option<(f64, f64)>,
+    rrf_k: Option<f64>,
 ) -> Vec<ScoreChunkDTO> {
     let mut fused_ranking: Vec<ScoreChunkDTO> = Vec::new();
     let weights = weights.unwrap_or((1.0, 1.0));
+    let rrf_k = rrf_k.unwrap_or(60.0);
     // Iterate through the union of the two result sets
     for mut document in full_text_results
         .clone()
@@ -958,9 +2093,11 @@ fn reciprocal_rank_fusion(
             .iter()
             .position(|doc| doc.metadata[0].id == document.metadata[0].id);
 
-        // Combine Reciprocal Ranks using average or another strategy
-        let combined_rank = weights.0 * (rank_semantic.unwrap_or(0) as f64)
-            + weights.1 * (rank_full_text.unwrap_or(0) as f64);
+        // Sum the weighted reciprocal ranks, documents missing from a result set contribute nothing
+        let reciprocal_rank =
+            |rank: Option<usize>| rank.map_or(0.0, |rank| 1.0 / (rrf_k + (rank + 1) as f64));
+        let combined_rank = weights.0 * reciprocal_rank(rank_semantic)
+            + weights.1 * reciprocal_rank(rank_full_text);
         document.score = combined_rank;
 
         // Add the document ID and combined rank to the fused ranking
@@ -968,17 +2105,104 @@ fn reciprocal_rank_fusion(
     }
 
     // Sort the fused ranking by combined rank in descending order
-    fused_ranking.sort_by(|a, b| {
-        b.score
-            .partial_cmp(&a.score)
-            .unwrap_or(std::cmp::Ordering::Equal)
+    fused_ranking.sort_by(cmp_score_chunks);
+
+    fused_ranking.truncate(10);
+
+    fused_ranking
+}
+
+fn min_max_normalize(score_chunks: &mut [ScoreChunkDTO]) {
+    let max_score = score_chunks
+        .iter()
+        .map(|chunk| chunk.score)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_score = score_chunks
+        .iter()
+        .map(|chunk| chunk.score)
+        .fold(f64::INFINITY, f64::min);
+
+    score_chunks.iter_mut().for_each(|chunk| {
+        chunk.score = if max_score > min_score {
+            (chunk.score - min_score) / (max_score - min_score)
+        } else {
+            1.0
+        };
     });
+}
+
+fn weighted_normalized_fusion(
+    mut semantic_results: Vec<ScoreChunkDTO>,
+    mut full_text_results: Vec<ScoreChunkDTO>,
+    weights: Option<(f64, f64)>,
+) -> Vec<ScoreChunkDTO> {
+    let weights = weights.unwrap_or((0.5, 0.5));
+    min_max_normalize(&mut semantic_results);
+    min_max_normalize(&mut full_text_results);
+
+    // Convex combination of the normalized scores, documents missing from a result set contribute nothing
+    let mut fused_ranking: Vec<ScoreChunkDTO> = full_text_results
+        .iter()
+        .chain(semantic_results.iter())
+        .unique_by(|chunk| chunk.metadata[0].id)
+        .map(|document| {
+            let normalized_score = |results: &[ScoreChunkDTO]| {
+                results
+                    .iter()
+                    .find(|doc| doc.metadata[0].id == document.metadata[0].id)
+                    .map_or(0.0, |doc| doc.score)
+            };
+            let mut document = document.clone();
+            document.score = weights.0 * normalized_score(&semantic_results)
+                + weights.1 * normalized_score(&full_text_results);
+            document
+        })
+        .collect();
+
+    fused_ranking.sort_by(cmp_score_chunks);
 
     fused_ranking.truncate(10);
 
     fused_ranking
 }
 
+/// Keeps only the highest scoring result for each chunk so that a chunk which came back from both
+/// the semantic and full-text searches is never shown twice on a page. Placeholder results for
+/// points without metadata all share the nil id and are left alone.
+pub fn dedupe_score_chunks(mut score_chunks: Vec<ScoreChunkDTO>) -> Vec<ScoreChunkDTO> {
+    score_chunks.sort_by(cmp_score_chunks);
+
+    let mut seen_chunk_ids = HashSet::new();
+    score_chunks.retain(|score_chunk| {
+        let chunk_id = score_chunk
+            .metadata
+            .first()
+            .map(|chunk| chunk.id)
+            .unwrap_or_default();
+        chunk_id.is_nil() || seen_chunk_ids.insert(chunk_id)
+    });
+
+    score_chunks
+}
+
+fn fuse_hybrid_results(
+    fusion: Option<&str>,
+    semantic_results: Vec<ScoreChunkDTO>,
+    full_text_results: Vec<ScoreChunkDTO>,
+    weights: Option<(f64, f64)>,
+    rrf_k: Option<f64>,
+) -> Vec<ScoreChunkDTO> {
+    match fusion {
+        Some("weighted_norm") => {
+            weighted_normalized_fusion(semantic_results, full_text_results, weights)
+        }
+        _ => reciprocal_rank_fusion(semantic_results, full_text_results, weights, rrf_k),
+    }
+}
+
+/// How long a hybrid search waits past its soft deadline for either branch to finish before failing.
+const HYBRID_SEARCH_FALLBACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 #[allow(clippy::too_many_arguments)]
 pub async fn search_hybrid_chunks(
     data: web::Json<SearchChunkData>,
@@ -986,42 +2210,92 @@ pub async fn search_hybrid_chunks(
     page: u64,
     pool: web::Data<Pool>,
     dataset: Dataset,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<SearchChunkQueryResponseBody, actix_web::Error> {
-    let embedding_vector = create_embedding(
-        &data.query,
-        ServerDatasetConfiguration::from_json(dataset.server_configuration.clone()),
-    )
-    .await?;
+    if !matches!(
+        data.fusion.as_deref(),
+        None | Some("rrf") | Some("weighted_norm")
+    ) {
+        return Err(ServiceError::BadRequest(
+            "Invalid fusion, must be either \"rrf\" or \"weighted_norm\"".into(),
+        )
+        .into());
+    }
+
     let pool1 = pool.clone();
 
-    let search_chunk_query_results = retrieve_qdrant_points_query(
-        Some(embedding_vector),
-        page,
-        data.link.clone(),
-        data.tag_set.clone(),
-        data.time_range.clone(),
-        data.filters.clone(),
+    let search_chunk_query_results = futures::future::maybe_done(retrieve_semantic_qdrant_points(
+        &data,
         parsed_query.clone(),
-        dataset.id,
+        page,
         pool.clone(),
-    );
+        &dataset,
+        acl_groups.clone(),
+    ));
 
-    let full_text_handler_results = search_full_text_chunks(
+    let full_text_handler_results = futures::future::maybe_done(retrieve_full_text_chunks(
         web::Json(data.clone()),
         parsed_query,
         page,
         pool,
         dataset.id,
-    );
+        acl_groups,
+    ));
 
-    let (search_chunk_query_results, full_text_handler_results) =
-        futures::join!(search_chunk_query_results, full_text_handler_results);
+    futures::pin_mut!(search_chunk_query_results, full_text_handler_results);
 
-    let search_chunk_query_results =
-        search_chunk_query_results.map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    let deadline = data.soft_deadline_ms.map(|soft_deadline_ms| {
+        tokio::time::Instant::now() + std::time::Duration::from_millis(soft_deadline_ms)
+    });
+    let both_branches = futures::future::join(
+        search_chunk_query_results.as_mut(),
+        full_text_handler_results.as_mut(),
+    );
+    match deadline {
+        Some(deadline) => {
+            if tokio::time::timeout_at(deadline, both_branches)
+                .await
+                .is_err()
+            {
+                // Past the deadline the results of whichever branch finishes first are used
+                let _ = tokio::time::timeout(
+                    HYBRID_SEARCH_FALLBACK_TIMEOUT,
+                    futures::future::select(
+                        search_chunk_query_results.as_mut(),
+                        full_text_handler_results.as_mut(),
+                    ),
+                )
+                .await;
+            }
+        }
+        None => {
+            both_branches.await;
+        }
+    }
 
-    let full_text_handler_results =
-        full_text_handler_results.map_err(|err| ServiceError::BadRequest(err.to_string()))?;
+    let full_text_handler_results = full_text_handler_results
+        .as_mut()
+        .take_output()
+        .transpose()?;
+
+    let (search_chunk_query_results, query_vector) = match search_chunk_query_results
+        .as_mut()
+        .take_output()
+        .transpose()?
+    {
+        Some(search_chunk_query_results) => search_chunk_query_results,
+        None => {
+            // The semantic branch missed the deadline, so the full-text results are returned alone
+            let mut result_chunks =
+                full_text_handler_results.ok_or(ServiceError::ServiceUnavailable(
+                    "Neither branch of the hybrid search finished after its soft deadline".into(),
+                ))?;
+            result_chunks.score_chunks = dedupe_score_chunks(result_chunks.score_chunks);
+            result_chunks.score_chunks = rerank_chunks(result_chunks.score_chunks, data.date_bias);
+            result_chunks.partial = Some(true);
+            return Ok(result_chunks);
+        }
+    };
 
     let point_ids = search_chunk_query_results
         .search_results
@@ -1042,26 +2316,19 @@ pub async fn search_hybrid_chunks(
                 .find(|metadata_chunk| metadata_chunk.qdrant_point_id == search_result.point_id)
             {
                 Some(metadata_chunk) => metadata_chunk.clone(),
-                None => ChunkMetadataWithFileData {
-                    id: uuid::Uuid::default(),
-                    author: None,
-                    qdrant_point_id: uuid::Uuid::default(),
-                    created_at: chrono::Utc::now().naive_local(),
-                    updated_at: chrono::Utc::now().naive_local(),
-                    file_id: None,
-                    file_name: None,
-                    content: "".to_string(),
-                    chunk_html: Some("".to_string()),
-                    link: Some("".to_string()),
-                    tag_set: Some("".to_string()),
-                    metadata: None,
-                    tracking_id: None,
-                    time_stamp: None,
-                    weight: 1.0,
-                },
+                None => ChunkMetadataWithFileData::placeholder(search_result.point_id),
             };
 
-            chunk = find_relevant_sentence(chunk.clone(), data.query.clone()).unwrap_or(chunk);
+            // Semantic highlights are added once the page of results is known
+            if data.highlight_strategy.as_deref() != Some("semantic") {
+                chunk = find_relevant_sentence(
+                    chunk.clone(),
+                    parse_query_boosts(&data.query.primary()).0,
+                    data.highlight_max_num,
+                    data.highlight_window,
+                )
+                .unwrap_or(chunk);
+            }
             let mut collided_chunks: Vec<ChunkMetadataWithFileData> = collided_chunks
                 .iter()
                 .filter(|chunk| chunk.qdrant_id == search_result.point_id)
@@ -1070,55 +2337,131 @@ pub async fn search_hybrid_chunks(
 
             collided_chunks.insert(0, chunk);
 
-            ScoreChunkDTO {
-                metadata: collided_chunks,
-                score: search_result.score as f64 * 0.5,
-            }
+            ScoreChunkDTO::new(collided_chunks, search_result.score as f64 * 0.5)
         })
         .collect();
 
-    let mut result_chunks = if data.cross_encoder.unwrap_or(false) {
+    let page = page.max(1);
+    let mut partial = false;
+
+    let full_text_handler_results = match full_text_handler_results {
+        Some(full_text_handler_results) => full_text_handler_results,
+        None => {
+            // The full-text branch missed the deadline, so the semantic results are returned alone
+            partial = true;
+            SearchChunkQueryResponseBody::new(
+                vec![],
+                search_chunk_query_results.total_chunk_pages,
+                search_chunk_query_results.total_results,
+                page,
+            )
+        }
+    };
+
+    let dataset_config =
+        ServerDatasetConfiguration::from_json(dataset.server_configuration.clone());
+    let candidate_count = semantic_score_chunks
+        .iter()
+        .chain(full_text_handler_results.score_chunks.iter())
+        .unique_by(|score_chunk| score_chunk.metadata[0].id)
+        .count();
+    // Reranking only a few candidates costs a model call for little change, so they are fused instead
+    let use_cross_encoder = data.cross_encoder.unwrap_or(false)
+        && candidate_count >= dataset_config.MIN_RESULTS_TO_RERANK.unwrap_or(0);
+
+    let mut result_chunks = if partial {
+        SearchChunkQueryResponseBody {
+            score_chunks: semantic_score_chunks,
+            ..full_text_handler_results
+        }
+    } else if use_cross_encoder {
         let combined_results = semantic_score_chunks
-            .into_iter()
-            .chain(full_text_handler_results.score_chunks.into_iter())
+            .iter()
+            .chain(full_text_handler_results.score_chunks.iter())
             .unique_by(|score_chunk| score_chunk.metadata[0].id)
+            .cloned()
             .collect::<Vec<ScoreChunkDTO>>();
-        SearchChunkQueryResponseBody {
-            score_chunks: cross_encoder(data.query.clone(), combined_results).await?,
-            total_chunk_pages: search_chunk_query_results.total_chunk_pages,
-        }
+        let reranked_results = cross_encoder(
+            parse_query_boosts(&data.query.primary()).0,
+            combined_results,
+            dataset_config.RERANKER_MODEL,
+        );
+        let score_chunks = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, reranked_results).await {
+                Ok(reranked_results) => reranked_results?,
+                Err(_) => {
+                    // The cross encoder missed the deadline, so the retrieved results are fused instead
+                    partial = true;
+                    fuse_hybrid_results(
+                        data.fusion.as_deref(),
+                        semantic_score_chunks,
+                        full_text_handler_results.score_chunks,
+                        data.weights,
+                        data.rrf_k,
+                    )
+                }
+            },
+            None => reranked_results.await?,
+        };
+        SearchChunkQueryResponseBody::new(
+            score_chunks,
+            search_chunk_query_results.total_chunk_pages,
+            search_chunk_query_results.total_results,
+            page,
+        )
     } else if let Some(weights) = data.weights {
         if weights.0 == 1.0 {
-            SearchChunkQueryResponseBody {
-                score_chunks: semantic_score_chunks,
-                total_chunk_pages: search_chunk_query_results.total_chunk_pages,
-            }
+            SearchChunkQueryResponseBody::new(
+                semantic_score_chunks,
+                search_chunk_query_results.total_chunk_pages,
+                search_chunk_query_results.total_results,
+                page,
+            )
         } else if weights.1 == 1.0 {
-            SearchChunkQueryResponseBody {
-                score_chunks: full_text_handler_results.score_chunks,
-                total_chunk_pages: full_text_handler_results.total_chunk_pages,
-            }
+            SearchChunkQueryResponseBody::new(
+                full_text_handler_results.score_chunks,
+                full_text_handler_results.total_chunk_pages,
+                full_text_handler_results.total_results,
+                page,
+            )
         } else {
-            SearchChunkQueryResponseBody {
-                score_chunks: reciprocal_rank_fusion(
+            SearchChunkQueryResponseBody::new(
+                fuse_hybrid_results(
+                    data.fusion.as_deref(),
                     semantic_score_chunks,
                     full_text_handler_results.score_chunks,
                     data.weights,
+                    data.rrf_k,
                 ),
-                total_chunk_pages: search_chunk_query_results.total_chunk_pages,
-            }
+                search_chunk_query_results.total_chunk_pages,
+                search_chunk_query_results.total_results,
+                page,
+            )
         }
     } else {
-        SearchChunkQueryResponseBody {
-            score_chunks: reciprocal_rank_fusion(
+        SearchChunkQueryResponseBody::new(
+            fuse_hybrid_results(
+                data.fusion.as_deref(),
                 semantic_score_chunks,
                 full_text_handler_results.score_chunks,
                 data.weights,
+                data.rrf_k,
             ),
-            total_chunk_pages: search_chunk_query_results.total_chunk_pages,
-        }
+            search_chunk_query_results.total_chunk_pages,
+            search_chunk_query_results.total_results,
+            page,
+        )
     };
+    result_chunks.score_chunks = dedupe_score_chunks(result_chunks.score_chunks);
     result_chunks.score_chunks = rerank_chunks(result_chunks.score_chunks, data.date_bias);
+    if data.include_query_vector.unwrap_or(false)
+        || data.highlight_strategy.as_deref() == Some("semantic")
+    {
+        result_chunks.query_vector = Some(query_vector);
+    }
+    if deadline.is_some() {
+        result_chunks.partial = Some(partial);
+    }
     Ok(result_chunks)
 }
 
@@ -1126,13 +2469,14 @@ pub async fn search_hybrid_chunks(
 pub async fn search_semantic_collections(
     data: web::Json<SearchCollectionsData>,
     parsed_query: ParsedQuery,
-    collection: ChunkCollection,
+    collections: Vec<ChunkCollection>,
     page: u64,
     pool: web::Data<Pool>,
     dataset: Dataset,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<SearchCollectionsResult, actix_web::Error> {
-    let embedding_vector: Vec<f32> = create_embedding(
-        &data.query,
+    let embedding_vector: Vec<f32> = create_query_embedding(
+        &parse_query_boosts(&data.query).0,
         ServerDatasetConfiguration::from_json(dataset.server_configuration.clone()),
     )
     .await?;
@@ -1147,12 +2491,14 @@ pub async fn search_semantic_collections(
         data.link.clone(),
         data.tag_set.clone(),
         data.filters.clone(),
-        data.collection_id,
+        data.case_sensitive_filters,
+        collections.iter().map(|collection| collection.id).collect(),
         dataset.id,
         parsed_query,
+        acl_groups,
     )
     .await
-    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    .map_err(qdrant_error_to_service_error)?;
 
     let point_ids = search_chunk_query_results
         .search_results
@@ -1177,25 +2523,10 @@ pub async fn search_semantic_collections(
                 .find(|metadata_chunk| metadata_chunk.qdrant_point_id == search_result.point_id)
             {
                 Some(metadata_chunk) => metadata_chunk.clone(),
-                None => ChunkMetadataWithFileData {
-                    id: uuid::Uuid::default(),
-                    author: None,
-                    qdrant_point_id: uuid::Uuid::default(),
-                    created_at: chrono::Utc::now().naive_local(),
-                    updated_at: chrono::Utc::now().naive_local(),
-                    file_id: None,
-                    file_name: None,
-                    content: "".to_string(),
-                    chunk_html: Some("".to_string()),
-                    link: Some("".to_string()),
-                    tag_set: Some("".to_string()),
-                    metadata: None,
-                    tracking_id: None,
-                    time_stamp: None,
-                    weight: 1.0,
-                },
+                None => ChunkMetadataWithFileData::placeholder(search_result.point_id),
             };
-            chunk = find_relevant_sentence(chunk.clone(), data.query.clone()).unwrap_or(chunk);
+            chunk = find_relevant_sentence(chunk.clone(), data.query.clone(), None, None)
+                .unwrap_or(chunk);
 
             let mut collided_chunks: Vec<ChunkMetadataWithFileData> = collided_chunks
                 .iter()
@@ -1216,17 +2547,15 @@ pub async fn search_semantic_collections(
                 }
             }
 
-            ScoreChunkDTO {
-                metadata: collided_chunks,
-                score: search_result.score.into(),
-            }
+            ScoreChunkDTO::new(collided_chunks, search_result.score.into())
         })
         .collect();
 
     score_chunks = rerank_chunks(score_chunks, data.date_bias);
     Ok(SearchCollectionsResult {
         bookmarks: score_chunks,
-        collection,
+        collection: collections[0].clone(),
+        collections,
         total_pages: search_chunk_query_results.total_chunk_pages,
     })
 }
@@ -1235,27 +2564,30 @@ pub async fn search_semantic_collections(
 pub async fn search_full_text_collections(
     data: web::Json<SearchCollectionsData>,
     parsed_query: ParsedQuery,
-    collection: ChunkCollection,
+    collections: Vec<ChunkCollection>,
     page: u64,
     pool: web::Data<Pool>,
     dataset_id: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
 ) -> Result<SearchCollectionsResult, actix_web::Error> {
     let data_inner = data.clone();
     let pool1 = pool.clone();
 
     let search_chunk_query_results = search_full_text_collection_query(
-        data_inner.query.clone(),
+        parsed_query.query.clone(),
         page,
         pool,
         data_inner.filters.clone(),
+        data_inner.case_sensitive_filters,
         data_inner.link.clone(),
         data_inner.tag_set.clone(),
-        data_inner.collection_id,
+        collections.iter().map(|collection| collection.id).collect(),
         parsed_query,
         dataset_id,
+        acl_groups,
     )
     .await
-    .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+    .map_err(qdrant_error_to_service_error)?;
 
     let mut result_chunks = retrieve_chunks_from_point_ids(
         search_chunk_query_results,
@@ -1268,7 +2600,469 @@ pub async fn search_full_text_collections(
 
     Ok(SearchCollectionsResult {
         bookmarks: result_chunks.score_chunks,
-        collection,
+        collection: collections[0].clone(),
+        collections,
         total_pages: result_chunks.total_chunk_pages,
     })
 }
+
+/// Buckets ranked results by the value of a metadata key. Groups are ordered by the rank of their
+/// first member and members keep their relative order.
+pub fn group_score_chunks(
+    score_chunks: Vec<ScoreChunkDTO>,
+    group_by: &str,
+) -> Vec<ScoreChunkGroup> {
+    let mut groups: Vec<ScoreChunkGroup> = vec![];
+
+    for score_chunk in score_chunks {
+        let group_key = score_chunk
+            .metadata
+            .first()
+            .and_then(|chunk| chunk.metadata.as_ref())
+            .and_then(|metadata| metadata.get(group_by))
+            .filter(|value| !value.is_null())
+            .map(|value| match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            });
+
+        match groups.iter_mut().find(|group| group.group_key == group_key) {
+            Some(group) => group.score_chunks.push(score_chunk),
+            None => groups.push(ScoreChunkGroup {
+                group_key,
+                score_chunks: vec![score_chunk],
+            }),
+        }
+    }
+
+    groups
+}
+
+const SEMANTIC_HIGHLIGHT_EMBEDDING_BATCH_SIZE: usize = 32;
+const SEMANTIC_HIGHLIGHT_EMBEDDING_CONCURRENCY: usize = 4;
+
+/// Highlights the sentences of each result which are closest to the query by embedding, in place
+/// of the lexical highlights. Only the first chunk of each result is highlighted, like lexical
+/// highlighting. `query_vector` is the embedding the search ran with, the query is only embedded
+/// when the search did not embed it.
+pub async fn add_semantic_highlights(
+    score_chunks: &mut [ScoreChunkDTO],
+    query_vector: Option<Vec<f32>>,
+    query: &str,
+    highlight_max_num: Option<usize>,
+    highlight_window: Option<usize>,
+    dataset_config: &ServerDatasetConfiguration,
+) -> Result<(), actix_web::Error> {
+    let query_vector = match query_vector {
+        Some(query_vector) => query_vector,
+        None => create_query_embedding(query, dataset_config.clone()).await?,
+    };
+
+    let chunk_sentences = score_chunks
+        .iter()
+        .filter_map(|score_chunk| score_chunk.metadata.first())
+        .map(get_semantic_highlight_sentences)
+        .collect::<Vec<(Vec<String>, Vec<usize>)>>();
+
+    let candidate_sentences = chunk_sentences
+        .iter()
+        .flat_map(|(sentences, candidate_indices)| {
+            candidate_indices.iter().map(|idx| sentences[*idx].clone())
+        })
+        .collect::<Vec<String>>();
+
+    let embedding_batches = candidate_sentences
+        .chunks(SEMANTIC_HIGHLIGHT_EMBEDDING_BATCH_SIZE)
+        .map(|batch| create_embeddings(batch, dataset_config.clone()));
+    let embedding_batches: Vec<Vec<Vec<f32>>> =
+        futures::TryStreamExt::try_collect(futures::StreamExt::buffered(
+            futures::stream::iter(embedding_batches),
+            SEMANTIC_HIGHLIGHT_EMBEDDING_CONCURRENCY,
+        ))
+        .await?;
+    let mut sentence_vectors = embedding_batches.into_iter().flatten();
+
+    for (score_chunk, (sentences, candidate_indices)) in score_chunks
+        .iter_mut()
+        .filter(|score_chunk| !score_chunk.metadata.is_empty())
+        .zip(chunk_sentences)
+    {
+        let candidate_vectors = candidate_indices
+            .into_iter()
+            .zip(sentence_vectors.by_ref())
+            .collect::<Vec<(usize, Vec<f32>)>>();
+
+        score_chunk.metadata[0] = find_semantically_relevant_sentences(
+            score_chunk.metadata[0].clone(),
+            sentences,
+            candidate_vectors,
+            &query_vector,
+            highlight_max_num,
+            highlight_window,
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets each result's result_id to a hash of the query, the chunk and the page, so the same search
+/// always produces the same ids and feedback on a result can be tied back to the query.
+pub fn add_result_ids(score_chunks: &mut [ScoreChunkDTO], query: &str, page: u64) {
+    for score_chunk in score_chunks.iter_mut() {
+        let chunk_id = score_chunk
+            .metadata
+            .first()
+            .map(|chunk| chunk.id)
+            .unwrap_or_default();
+        score_chunk.result_id = Some(uuid::Uuid::new_v5(
+            &uuid::Uuid::NAMESPACE_OID,
+            format!("{}:{}:{}", chunk_id, page, query).as_bytes(),
+        ));
+    }
+}
+
+/// Sets each result's matched_tags to the tags of its chunk which are in the requested tag_set.
+pub fn add_matched_tags(score_chunks: &mut [ScoreChunkDTO], tag_set: &Option<Vec<String>>) {
+    let requested_tags = tag_set
+        .iter()
+        .flatten()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect::<HashSet<String>>();
+    if requested_tags.is_empty() {
+        return;
+    }
+
+    for score_chunk in score_chunks.iter_mut() {
+        score_chunk.matched_tags = score_chunk
+            .metadata
+            .first()
+            .and_then(|chunk| chunk.tag_set.as_ref())
+            .map(|chunk_tags| {
+                chunk_tags
+                    .split(',')
+                    .map(|tag| tag.trim())
+                    .filter(|tag| requested_tags.contains(&tag.to_lowercase()))
+                    .unique()
+                    .map(|tag| tag.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+}
+
+/// Sets each result's relevance_percent by min-max scaling its score against the other results on
+/// the page, so the best result is 100 and the worst is 0. The order of the results is unchanged.
+pub fn add_relevance_percents(score_chunks: &mut [ScoreChunkDTO]) {
+    let max_score = score_chunks
+        .iter()
+        .map(|chunk| chunk.score)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let min_score = score_chunks
+        .iter()
+        .map(|chunk| chunk.score)
+        .fold(f64::INFINITY, f64::min);
+
+    score_chunks.iter_mut().for_each(|chunk| {
+        chunk.relevance_percent = if max_score > min_score {
+            Some(((chunk.score - min_score) / (max_score - min_score) * 100.0).round())
+        } else {
+            Some(100.0)
+        };
+    });
+}
+
+/// Sets each result's embedding_model from the payload of its qdrant point.
+pub async fn add_embedding_models(score_chunks: &mut [ScoreChunkDTO]) -> Result<(), ServiceError> {
+    let point_ids = score_chunks
+        .iter()
+        .filter_map(|score_chunk| score_chunk.metadata.first())
+        .map(|chunk| chunk.qdrant_point_id)
+        .unique()
+        .collect::<Vec<uuid::Uuid>>();
+
+    let embedding_models = get_point_embedding_models_query(point_ids)
+        .await
+        .map_err(qdrant_error_to_service_error)?;
+
+    for score_chunk in score_chunks.iter_mut() {
+        score_chunk.embedding_model = score_chunk
+            .metadata
+            .first()
+            .and_then(|chunk| embedding_models.get(&chunk.qdrant_point_id))
+            .cloned();
+    }
+
+    Ok(())
+}
+
+/// Attaches the chunks surrounding each result in the file it came from. Neighbors which the
+/// acl_groups do not grant access to are left out.
+pub async fn add_neighbor_chunks(
+    score_chunks: &mut [ScoreChunkDTO],
+    n: usize,
+    dataset_id: uuid::Uuid,
+    acl_groups: Option<Vec<String>>,
+    pool: web::Data<Pool>,
+) -> Result<(), ServiceError> {
+    let chunk_ids = score_chunks
+        .iter()
+        .filter_map(|score_chunk| score_chunk.metadata.first())
+        .map(|chunk| chunk.id)
+        .filter(|chunk_id| !chunk_id.is_nil())
+        .unique()
+        .collect::<Vec<uuid::Uuid>>();
+
+    let (mut neighbors, neighbor_point_ids) =
+        web::block(move || get_neighbor_chunks_query(chunk_ids, n, dataset_id, pool))
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?
+            .map_err(|err| ServiceError::BadRequest(err.message.into()))?;
+
+    if let Some(acl_groups) = acl_groups {
+        let allowed_point_ids = filter_point_ids_by_acl_query(
+            neighbor_point_ids.values().copied().unique().collect(),
+            acl_groups,
+        )
+        .await
+        .map_err(qdrant_error_to_service_error)?;
+
+        let is_allowed = |chunk: &ChunkMetadata| {
+            neighbor_point_ids
+                .get(&chunk.id)
+                .is_some_and(|point_id| allowed_point_ids.contains(point_id))
+        };
+        for chunk_neighbors in neighbors.values_mut() {
+            chunk_neighbors.previous.retain(is_allowed);
+            chunk_neighbors.next.retain(is_allowed);
+        }
+    }
+
+    for score_chunk in score_chunks.iter_mut() {
+        score_chunk.neighbors = Some(
+            score_chunk
+                .metadata
+                .first()
+                .and_then(|chunk| neighbors.get(&chunk.id))
+                .cloned()
+                .unwrap_or(ChunkNeighbors {
+                    previous: vec![],
+                    next: vec![],
+                }),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score_chunk(id: u128, score: f64, weight: f64) -> ScoreChunkDTO {
+        let now = chrono::Utc::now().naive_local();
+        ScoreChunkDTO::new(
+            vec![ChunkMetadataWithFileData {
+                id: uuid::Uuid::from_u128(id),
+                author: None,
+                content: String::new(),
+                chunk_html: None,
+                link: None,
+                qdrant_point_id: uuid::Uuid::from_u128(id),
+                created_at: now,
+                updated_at: now,
+                tag_set: None,
+                file_id: None,
+                file_name: None,
+                metadata: None,
+                tracking_id: None,
+                time_stamp: None,
+                weight,
+            }],
+            score,
+        )
+    }
+
+    fn chunk_ids(score_chunks: &[ScoreChunkDTO]) -> Vec<uuid::Uuid> {
+        score_chunks
+            .iter()
+            .map(|score_chunk| score_chunk.metadata[0].id)
+            .collect()
+    }
+
+    fn metadata_filter_sql(key: &str, value: &str, case_sensitive: bool) -> String {
+        diesel::debug_query::<diesel::pg::Pg, _>(&metadata_contains_filter(
+            key,
+            value,
+            case_sensitive,
+        ))
+        .to_string()
+    }
+
+    #[test]
+    fn metadata_filter_ignores_case_by_default() {
+        assert_eq!(
+            metadata_filter_sql("status", "Active", false),
+            r#"chunk_metadata.metadata->>$1 ILIKE $2 -- binds: ["status", "%Active%"]"#
+        );
+        assert_eq!(
+            metadata_filter_sql("Status", "aCTIVE", false),
+            r#"chunk_metadata.metadata->>$1 ILIKE $2 -- binds: ["Status", "%aCTIVE%"]"#
+        );
+    }
+
+    #[test]
+    fn metadata_filter_matches_case_when_case_sensitive() {
+        assert_eq!(
+            metadata_filter_sql("status", "Active", true),
+            r#"chunk_metadata.metadata->>$1 LIKE $2 -- binds: ["status", "%Active%"]"#
+        );
+    }
+
+    #[test]
+    fn metadata_filter_binds_key() {
+        let filter_sql = metadata_filter_sql("status' OR '1'='1", "Active", false);
+
+        assert!(filter_sql.starts_with("chunk_metadata.metadata->>$1 ILIKE $2 -- binds: "));
+        assert!(filter_sql.contains(r#""status' OR '1'='1""#));
+    }
+
+    #[test]
+    fn rerank_chunks_ranks_low_weight_below_equal_similarity() {
+        let reranked = rerank_chunks(
+            vec![score_chunk(1, 0.8, 0.1), score_chunk(2, 0.8, 1.0)],
+            None,
+        );
+
+        assert_eq!(
+            chunk_ids(&reranked),
+            vec![uuid::Uuid::from_u128(2), uuid::Uuid::from_u128(1)]
+        );
+        assert!(reranked[1].score < reranked[0].score);
+    }
+
+    #[test]
+    fn rerank_chunks_zero_weight_ranks_last() {
+        let reranked = rerank_chunks(
+            vec![
+                score_chunk(1, 0.9, 0.0),
+                score_chunk(2, 0.5, 1.0),
+                score_chunk(3, 0.2, 1.0),
+            ],
+            None,
+        );
+
+        assert_eq!(reranked[2].metadata[0].id, uuid::Uuid::from_u128(1));
+        assert_eq!(reranked[2].score, 0.0);
+    }
+
+    #[test]
+    fn dedupe_score_chunks_keeps_highest_score_for_chunk_in_both_lists() {
+        let semantic_results = vec![score_chunk(1, 0.9, 1.0), score_chunk(2, 0.7, 1.0)];
+        let full_text_results = vec![score_chunk(1, 0.95, 1.0), score_chunk(3, 0.6, 1.0)];
+
+        let deduped = dedupe_score_chunks(
+            semantic_results
+                .into_iter()
+                .chain(full_text_results)
+                .collect(),
+        );
+
+        assert_eq!(
+            chunk_ids(&deduped),
+            vec![
+                uuid::Uuid::from_u128(1),
+                uuid::Uuid::from_u128(2),
+                uuid::Uuid::from_u128(3)
+            ]
+        );
+        assert_eq!(deduped[0].score, 0.95);
+    }
+
+    #[test]
+    fn reciprocal_rank_fusion_returns_chunk_in_both_lists_once() {
+        let semantic_results = vec![score_chunk(1, 0.9, 1.0), score_chunk(2, 0.7, 1.0)];
+        let full_text_results = vec![score_chunk(1, 12.0, 1.0), score_chunk(3, 8.0, 1.0)];
+
+        let fused = dedupe_score_chunks(reciprocal_rank_fusion(
+            semantic_results,
+            full_text_results,
+            None,
+            None,
+        ));
+
+        assert_eq!(
+            chunk_ids(&fused)
+                .iter()
+                .filter(|id| **id == uuid::Uuid::from_u128(1))
+                .count(),
+            1
+        );
+        assert_eq!(fused[0].metadata[0].id, uuid::Uuid::from_u128(1));
+        assert_eq!(fused.len(), 3);
+    }
+
+    fn search_result(id: u128, score: f32) -> SearchResult {
+        SearchResult {
+            score,
+            point_id: uuid::Uuid::from_u128(id),
+        }
+    }
+
+    #[test]
+    fn cmp_score_chunks_orders_equal_scores_by_chunk_id() {
+        let score_chunks = (1..=25)
+            .map(|id| score_chunk(id, 0.5, 1.0))
+            .collect::<Vec<ScoreChunkDTO>>();
+
+        let mut forward = score_chunks.clone();
+        forward.sort_by(cmp_score_chunks);
+        let mut reversed = score_chunks.into_iter().rev().collect::<Vec<_>>();
+        reversed.sort_by(cmp_score_chunks);
+
+        assert_eq!(chunk_ids(&forward), chunk_ids(&reversed));
+        assert_eq!(
+            chunk_ids(&forward),
+            (1..=25).map(uuid::Uuid::from_u128).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn page_search_results_pages_equal_scores_without_duplicates() {
+        // 12 results tie across the end of the first page, which fits in the overfetch
+        // Qdrant returns tied points in no particular order, so each page sees them differently
+        let qdrant_results = |page: u64| {
+            let mut results = (1..=30)
+                .map(|id| match id {
+                    1..=8 => search_result(id, 1.0 - id as f32 * 0.01),
+                    9..=20 => search_result(id, 0.5),
+                    _ => search_result(id, 0.4 - id as f32 * 0.01),
+                })
+                .collect::<Vec<SearchResult>>();
+            if page == 2 {
+                results.reverse();
+            }
+            results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            results.truncate((page * 10 + 10) as usize);
+            results
+        };
+
+        let paged_point_ids = (1..=3)
+            .flat_map(|page| page_search_results(qdrant_results(page), page))
+            .map(|search_result| search_result.point_id)
+            .collect::<Vec<uuid::Uuid>>();
+
+        assert_eq!(paged_point_ids.len(), 30);
+        assert_eq!(paged_point_ids.iter().unique().count(), 30);
+        assert_eq!(
+            page_search_results(qdrant_results(1), 1)
+                .iter()
+                .map(|search_result| search_result.point_id)
+                .collect::<Vec<_>>(),
+            page_search_results(qdrant_results(2), 1)
+                .iter()
+                .map(|search_result| search_result.point_id)
+                .collect::<Vec<_>>()
+        );
+    }
+}
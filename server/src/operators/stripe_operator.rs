@@ -133,6 +133,7 @@ pub fn create_stripe_plan_query(
         10000,
         amount,
         "Project".to_string(),
+        1000,
     );
 
     let mut conn = pool.get().expect("Failed to get connection from pool");